@@ -0,0 +1,384 @@
+use axum::{
+    body::{Body, HttpBody},
+    extract::{ConnectInfo, Request, State},
+    http::{header, HeaderMap},
+    middleware::Next,
+    response::Response,
+};
+use chrono::{DateTime, Utc};
+use futures::StreamExt;
+use percent_encoding::{utf8_percent_encode, AsciiSet, NON_ALPHANUMERIC};
+use serde::{Deserialize, Serialize};
+use std::collections::BTreeMap;
+use std::io;
+use std::sync::atomic::{AtomicU64, Ordering};
+use std::sync::{Arc, Mutex};
+use std::time::{Duration, Instant};
+
+use crate::error::{ErrorCode, S3Error};
+use crate::handlers::{AppState, RequestIds};
+use crate::operation;
+use crate::server::PeerAddr;
+use crate::xml;
+
+// the bucket setting a logging configuration is stored under
+pub const SETTING: &str = "logging";
+
+// the one owner every bucket has, as ListBuckets reports it
+const OWNER: &str = "local";
+
+// keys in log records are url-encoded, keeping their slashes
+const KEY_ENCODE: &AsciiSet = &NON_ALPHANUMERIC
+    .remove(b'-')
+    .remove(b'_')
+    .remove(b'.')
+    .remove(b'~')
+    .remove(b'/');
+
+// where a bucket's access logs go, stored as json under the logging setting
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct LoggingConfiguration {
+    pub target_bucket: String,
+    #[serde(default)]
+    pub target_prefix: String,
+}
+
+impl LoggingConfiguration {
+    // a PutBucketLogging body; None when it has no LoggingEnabled, which turns logging off
+    pub fn parse(body: &str) -> Result<Option<Self>, S3Error> {
+        let root = xml::element(body, "BucketLoggingStatus").ok_or_else(S3Error::malformed_xml)?;
+        let Some(enabled) = xml::element(root, "LoggingEnabled") else {
+            return Ok(None);
+        };
+
+        let target_bucket = xml::text(enabled, "TargetBucket").ok_or_else(S3Error::malformed_xml)?;
+        let target_prefix = xml::text(enabled, "TargetPrefix").unwrap_or_default();
+        Ok(Some(LoggingConfiguration {
+            target_bucket,
+            target_prefix,
+        }))
+    }
+
+    pub fn to_xml(configuration: Option<&Self>) -> String {
+        let enabled = match configuration {
+            Some(c) => format!(
+                "<LoggingEnabled><TargetBucket>{}</TargetBucket><TargetPrefix>{}</TargetPrefix></LoggingEnabled>",
+                xml::escape(&c.target_bucket),
+                xml::escape(&c.target_prefix)
+            ),
+            None => String::new(),
+        };
+        format!(
+            r#"<?xml version="1.0" encoding="UTF-8"?>
+<BucketLoggingStatus xmlns="http://doc.s3.amazonaws.com/2006-03-01">{}</BucketLoggingStatus>"#,
+            enabled
+        )
+    }
+}
+
+pub async fn load(state: &AppState, bucket: &str) -> Result<Option<LoggingConfiguration>, io::Error> {
+    match state.storage.get_bucket_setting(bucket, SETTING).await? {
+        Some(stored) => Ok(Some(serde_json::from_str(&stored)?)),
+        None => Ok(None),
+    }
+}
+
+// records waiting to be written, by target bucket and prefix
+#[derive(Default)]
+pub struct AccessLogs {
+    pending: Mutex<BTreeMap<(String, String), Vec<String>>>,
+}
+
+impl AccessLogs {
+    pub fn new() -> Self {
+        AccessLogs::default()
+    }
+
+    fn push(&self, target: &LoggingConfiguration, record: String) {
+        self.pending
+            .lock()
+            .unwrap()
+            .entry((target.target_bucket.clone(), target.target_prefix.clone()))
+            .or_default()
+            .push(record);
+    }
+}
+
+// write what's pending as one log object per target, named the way s3 names them:
+// TargetPrefixYYYY-mm-DD-HH-MM-SS-UniqueString
+pub async fn flush(state: &AppState) {
+    let pending = std::mem::take(&mut *state.access_logs.pending.lock().unwrap());
+    for ((bucket, prefix), records) in pending {
+        // not from the seeded entropy: flushes happen on a timer, and would shift the ids a
+        // deterministic run hands out
+        let unique = uuid::Uuid::new_v4().simple().to_string()[..16].to_ascii_uppercase();
+        let key = format!("{}{}-{}", prefix, state.clock().now().format("%Y-%m-%d-%H-%M-%S"), unique);
+        let mut body = records.join("\n");
+        body.push('\n');
+
+        let written = state
+            .storage
+            .put_object(&bucket, &key, body.into(), Some("text/plain".to_string()))
+            .await;
+        if let Err(e) = written {
+            tracing::warn!("access logs: could not write {}/{}: {}", bucket, key, e);
+        }
+    }
+}
+
+// flush on the configured interval, for as long as the server runs
+pub async fn flush_periodically(state: Arc<AppState>) {
+    loop {
+        let interval = Duration::from_secs(state.config().access_logs.flush_secs);
+        tokio::time::sleep(interval).await;
+        flush(&state).await;
+    }
+}
+
+// buffers a record for every s3 request to a bucket with logging on, once its response
+// body is done
+pub async fn record(State(state): State<Arc<AppState>>, request: Request, next: Next) -> Response {
+    let Some(op) = operation::classify(request.method(), request.uri(), request.headers()) else {
+        return next.run(request).await;
+    };
+    let Some(bucket) = op.bucket.clone() else {
+        return next.run(request).await;
+    };
+    // a bucket that doesn't exist yet has no configuration either
+    let Ok(Some(target)) = load(&state, &bucket).await else {
+        return next.run(request).await;
+    };
+
+    let started = Instant::now();
+    let headers = request.headers();
+    let (signature, auth) = authentication(headers, request.uri().query().unwrap_or(""));
+    let header = |name: header::HeaderName| headers.get(name).and_then(|v| v.to_str().ok()).map(str::to_string);
+    let fields = Fields {
+        bucket,
+        time: state.clock().now(),
+        remote_ip: request
+            .extensions()
+            .get::<ConnectInfo<PeerAddr>>()
+            .map(|ConnectInfo(PeerAddr(addr))| addr.ip().to_string())
+            .unwrap_or_else(|| "127.0.0.1".to_string()),
+        requester: auth.map(|_| OWNER),
+        ids: request.extensions().get::<RequestIds>().cloned().unwrap_or_default(),
+        operation: rest_operation(op.name),
+        key: op.key.clone(),
+        request_uri: format!(
+            "{} {} {:?}",
+            request.method(),
+            request.uri().path_and_query().map(|pq| pq.as_str()).unwrap_or("/"),
+            request.version()
+        ),
+        referer: header(header::REFERER),
+        user_agent: header(header::USER_AGENT),
+        signature,
+        auth,
+        host: header(header::HOST),
+        uploads: matches!(op.name, "PutObject" | "UploadPart"),
+    };
+
+    let bytes_in = Arc::new(AtomicU64::new(0));
+    let (parts, body) = request.into_parts();
+    let counted = bytes_in.clone();
+    let body = body.into_data_stream().map(move |chunk| {
+        if let Ok(chunk) = &chunk {
+            counted.fetch_add(chunk.len() as u64, Ordering::Relaxed);
+        }
+        chunk
+    });
+
+    let response = next.run(Request::from_parts(parts, Body::from_stream(body))).await;
+    let turnaround = started.elapsed();
+    let (mut parts, body) = response.into_parts();
+    // the body is re-wrapped below, which would lose its length and switch to chunked
+    if let Some(len) = body.size_hint().exact() {
+        parts.headers.entry(header::CONTENT_LENGTH).or_insert(len.into());
+    }
+    let content_length = parts
+        .headers
+        .get(header::CONTENT_LENGTH)
+        .and_then(|v| v.to_str().ok())
+        .and_then(|v| v.parse::<u64>().ok());
+
+    let mut pending = Pending {
+        state: state.clone(),
+        target,
+        fields: Some(fields),
+        status: parts.status.as_u16(),
+        error_code: parts.extensions.get::<ErrorCode>().map(|ErrorCode(code)| *code),
+        // what a download sends is the object; what an upload receives is
+        object_size: parts.status.is_success().then_some(content_length).flatten(),
+        bytes_in,
+        bytes_sent: 0,
+        started,
+        turnaround,
+    };
+    let stream = body.into_data_stream().map(move |chunk| {
+        if let Ok(chunk) = &chunk {
+            pending.observe(chunk.len());
+        }
+        chunk
+    });
+    Response::from_parts(parts, Body::from_stream(stream))
+}
+
+// what's known about a request before it runs
+struct Fields {
+    bucket: String,
+    time: DateTime<Utc>,
+    remote_ip: String,
+    requester: Option<&'static str>,
+    ids: RequestIds,
+    operation: &'static str,
+    key: Option<String>,
+    request_uri: String,
+    referer: Option<String>,
+    user_agent: Option<String>,
+    signature: Option<&'static str>,
+    auth: Option<&'static str>,
+    host: Option<String>,
+    // whether the object size is what was sent rather than what's returned
+    uploads: bool,
+}
+
+// a record waiting for its response body to be sent; buffered when the body is dropped
+struct Pending {
+    state: Arc<AppState>,
+    target: LoggingConfiguration,
+    fields: Option<Fields>,
+    status: u16,
+    error_code: Option<&'static str>,
+    object_size: Option<u64>,
+    bytes_in: Arc<AtomicU64>,
+    bytes_sent: u64,
+    started: Instant,
+    turnaround: Duration,
+}
+
+impl Pending {
+    fn observe(&mut self, len: usize) {
+        self.bytes_sent += len as u64;
+    }
+}
+
+impl Drop for Pending {
+    fn drop(&mut self) {
+        let Some(fields) = self.fields.take() else {
+            return;
+        };
+        let object_size = match fields.uploads {
+            true => Some(self.bytes_in.load(Ordering::Relaxed)).filter(|_| self.status < 300),
+            false if fields.key.is_some() => self.object_size,
+            false => None,
+        };
+        let record = format_record(
+            &fields,
+            self.status,
+            self.error_code,
+            self.bytes_sent,
+            object_size,
+            self.started.elapsed(),
+            self.turnaround,
+        );
+        self.state.access_logs.push(&self.target, record);
+    }
+}
+
+// one line in the s3 server access log format
+fn format_record(
+    fields: &Fields,
+    status: u16,
+    error_code: Option<&str>,
+    bytes_sent: u64,
+    object_size: Option<u64>,
+    total: Duration,
+    turnaround: Duration,
+) -> String {
+    let or_dash = |value: Option<String>| value.filter(|v| !v.is_empty()).unwrap_or_else(|| "-".to_string());
+    let quoted = |value: Option<&String>| match value {
+        Some(v) => format!("\"{}\"", v.replace('"', "\\\"")),
+        None => "\"-\"".to_string(),
+    };
+
+    [
+        OWNER.to_string(),
+        fields.bucket.clone(),
+        fields.time.format("[%d/%b/%Y:%H:%M:%S +0000]").to_string(),
+        fields.remote_ip.clone(),
+        or_dash(fields.requester.map(str::to_string)),
+        or_dash(Some(fields.ids.request_id.clone())),
+        fields.operation.to_string(),
+        or_dash(fields.key.as_ref().map(|k| utf8_percent_encode(k, KEY_ENCODE).to_string())),
+        quoted(Some(&fields.request_uri)),
+        status.to_string(),
+        or_dash(error_code.map(str::to_string)),
+        or_dash((bytes_sent > 0).then(|| bytes_sent.to_string())),
+        or_dash(object_size.map(|s| s.to_string())),
+        total.as_millis().to_string(),
+        turnaround.as_millis().to_string(),
+        quoted(fields.referer.as_ref()),
+        quoted(fields.user_agent.as_ref()),
+        // version id
+        "-".to_string(),
+        or_dash(Some(fields.ids.host_id.clone())),
+        or_dash(fields.signature.map(str::to_string)),
+        // cipher suite
+        "-".to_string(),
+        or_dash(fields.auth.map(str::to_string)),
+        or_dash(fields.host.clone()),
+        // tls version, access point arn, acl required
+        "-".to_string(),
+        "-".to_string(),
+        "-".to_string(),
+    ]
+    .join(" ")
+}
+
+// signature version and authentication type, or neither for anonymous requests
+fn authentication(headers: &HeaderMap, query: &str) -> (Option<&'static str>, Option<&'static str>) {
+    let authorization = headers
+        .get(header::AUTHORIZATION)
+        .and_then(|v| v.to_str().ok())
+        .unwrap_or("");
+    if authorization.starts_with("AWS4-") {
+        return (Some("SigV4"), Some("AuthHeader"));
+    }
+    if authorization.starts_with("AWS ") {
+        return (Some("SigV2"), Some("AuthHeader"));
+    }
+    let has = |param: &str| query.split('&').any(|pair| pair.split('=').next() == Some(param));
+    if has("X-Amz-Algorithm") {
+        return (Some("SigV4"), Some("QueryString"));
+    }
+    if has("Signature") {
+        return (Some("SigV2"), Some("QueryString"));
+    }
+    (None, None)
+}
+
+// the operation as s3 logs it
+fn rest_operation(name: &str) -> &'static str {
+    match name {
+        "CreateBucket" => "REST.PUT.BUCKET",
+        "HeadBucket" => "REST.HEAD.BUCKET",
+        "DeleteBucket" => "REST.DELETE.BUCKET",
+        "ListObjects" | "ListObjectsV2" => "REST.GET.BUCKET",
+        "ListMultipartUploads" => "REST.GET.UPLOADS",
+        "PutBucketNotificationConfiguration" => "REST.PUT.NOTIFICATION",
+        "GetBucketNotificationConfiguration" => "REST.GET.NOTIFICATION",
+        "PutBucketLogging" => "REST.PUT.LOGGING_STATUS",
+        "GetBucketLogging" => "REST.GET.LOGGING_STATUS",
+        "PutObject" => "REST.PUT.OBJECT",
+        "CopyObject" => "REST.COPY.OBJECT",
+        "UploadPart" => "REST.PUT.PART",
+        "GetObject" => "REST.GET.OBJECT",
+        "HeadObject" => "REST.HEAD.OBJECT",
+        "DeleteObject" => "REST.DELETE.OBJECT",
+        "CreateMultipartUpload" => "REST.POST.UPLOADS",
+        "CompleteMultipartUpload" => "REST.POST.UPLOAD",
+        "AbortMultipartUpload" => "REST.DELETE.UPLOAD",
+        _ => "REST.GET.SERVICE",
+    }
+}
@@ -0,0 +1,465 @@
+use axum::{
+    body::Bytes,
+    extract::{Path, Query, Request, State},
+    http::{header, HeaderMap, StatusCode},
+    middleware::{self, Next},
+    response::{
+        sse::{Event, KeepAlive, Sse},
+        IntoResponse, Response,
+    },
+    routing::{delete, get, post, put},
+    Json, Router,
+};
+use chrono::{DateTime, TimeDelta, Utc};
+use futures::stream::{self, Stream};
+use serde::{Deserialize, Serialize};
+use std::convert::Infallible;
+use std::sync::Arc;
+use std::time::Duration;
+use tokio::sync::broadcast::error::RecvError;
+
+use crate::access_log;
+use crate::clock::OutOfRange;
+use crate::error::S3Error;
+use crate::faults::FaultRule;
+use crate::handlers::{self, AppState};
+use crate::health;
+use crate::journal::JournalFilter;
+use crate::storage::{self, FsckOptions, ObjectMetadata};
+use crate::ui;
+use crate::watch::WatchFilter;
+
+// everything under /_six7/; the s3 routes can never collide since '_' isn't valid in
+// a bucket name
+pub const PREFIX: &str = "/_six7";
+
+pub fn router(state: Arc<AppState>) -> Router<Arc<AppState>> {
+    Router::new()
+        .route("/reset", post(reset_all))
+        .route("/reset/{bucket}", post(reset_bucket))
+        .route("/buckets", get(list_buckets))
+        .route("/buckets/{bucket}", put(create_bucket).delete(delete_bucket))
+        .route("/objects", get(list_objects))
+        .route("/objects/{bucket}/{*key}", get(fetch_object).put(seed_object).delete(remove_object))
+        .route("/config", get(dump_config))
+        .route("/snapshots", get(list_snapshots))
+        .route("/snapshots/{name}", post(create_snapshot).delete(delete_snapshot))
+        .route("/snapshots/{name}/restore", post(restore_snapshot))
+        .route("/faults", get(list_faults).post(add_fault).delete(clear_faults))
+        .route("/faults/{id}", delete(remove_fault))
+        .route("/journal", get(query_journal).delete(clear_journal))
+        .route("/events", get(watch_events))
+        .route("/access-logs/flush", post(flush_access_logs))
+        .route("/fsck", get(check_storage).post(repair_storage))
+        .route("/clock", get(clock_status).put(set_clock).delete(reset_clock))
+        .route("/clock/freeze", post(freeze_clock))
+        .route("/clock/resume", post(resume_clock))
+        .route("/clock/advance", post(advance_clock))
+        .route_layer(middleware::from_fn_with_state(state, require_token))
+        .merge(health::router())
+        // the page itself holds no data; what it shows comes from the routes above
+        .route("/ui", get(ui::page))
+}
+
+// 404 while the api is disabled, so it looks like it isn't there at all
+async fn require_token(State(state): State<Arc<AppState>>, request: Request, next: Next) -> Response {
+    let config = state.config();
+    if !config.admin.enabled {
+        return json_error(StatusCode::NOT_FOUND, "NotFound", "admin api is disabled");
+    }
+
+    let presented = request
+        .headers()
+        .get(header::AUTHORIZATION)
+        .and_then(|h| h.to_str().ok())
+        .and_then(|h| h.strip_prefix("Bearer "));
+    match (presented, config.admin.token.as_deref()) {
+        (Some(presented), Some(token)) if constant_time_eq(presented.as_bytes(), token.as_bytes()) => {
+            next.run(request).await
+        }
+        _ => {
+            let mut response = json_error(StatusCode::UNAUTHORIZED, "Unauthorized", "missing or wrong admin token");
+            response
+                .headers_mut()
+                .insert(header::WWW_AUTHENTICATE, "Bearer".parse().unwrap());
+            response
+        }
+    }
+}
+
+fn constant_time_eq(a: &[u8], b: &[u8]) -> bool {
+    a.len() == b.len() && a.iter().zip(b).fold(0u8, |acc, (x, y)| acc | (x ^ y)) == 0
+}
+
+pub(crate) fn json_error(status: StatusCode, code: &str, message: &str) -> Response {
+    let body = serde_json::json!({ "code": code, "message": message });
+    (status, Json(body)).into_response()
+}
+
+fn storage_error(err: std::io::Error) -> Response {
+    let err = S3Error::from(err);
+    json_error(err.status, err.code, &err.message)
+}
+
+// empty every bucket, drop the ones that aren't configured and recreate the ones that are
+async fn reset_all(State(state): State<Arc<AppState>>) -> Response {
+    let config = state.config();
+
+    let keep: Vec<String> = config.buckets.iter().map(|b| b.name.clone()).collect();
+    if let Err(e) = state.storage.reset(&keep).await {
+        return storage_error(e);
+    }
+    state.queues.clear();
+
+    StatusCode::NO_CONTENT.into_response()
+}
+
+async fn reset_bucket(State(state): State<Arc<AppState>>, Path(bucket): Path<String>) -> Response {
+    match state.storage.empty_bucket(&bucket).await {
+        Ok(()) => StatusCode::NO_CONTENT.into_response(),
+        Err(e) => storage_error(e),
+    }
+}
+
+#[derive(Deserialize)]
+struct ObjectsQuery {
+    bucket: Option<String>,
+    prefix: Option<String>,
+}
+
+#[derive(Deserialize)]
+struct FetchQuery {
+    download: Option<String>,
+}
+
+#[derive(Serialize)]
+struct ObjectEntry {
+    bucket: String,
+    #[serde(flatten)]
+    metadata: ObjectMetadata,
+}
+
+async fn list_objects(State(state): State<Arc<AppState>>, Query(query): Query<ObjectsQuery>) -> Response {
+    let buckets = match query.bucket {
+        Some(bucket) => vec![bucket],
+        None => match state.storage.list_buckets().await {
+            Ok(buckets) => buckets.into_iter().map(|b| b.name).collect(),
+            Err(e) => return storage_error(e),
+        },
+    };
+
+    let mut entries = Vec::new();
+    for bucket in buckets {
+        let objects = match state.storage.list_objects(&bucket, query.prefix.as_deref()).await {
+            Ok(objects) => objects,
+            Err(e) => return storage_error(e),
+        };
+        entries.extend(objects.into_iter().map(|metadata| ObjectEntry {
+            bucket: bucket.clone(),
+            metadata,
+        }));
+    }
+
+    Json(entries).into_response()
+}
+
+async fn list_buckets(State(state): State<Arc<AppState>>) -> Response {
+    match state.storage.list_buckets().await {
+        Ok(buckets) => Json(buckets).into_response(),
+        Err(e) => storage_error(e),
+    }
+}
+
+// succeeds for a bucket that's already there
+async fn create_bucket(State(state): State<Arc<AppState>>, Path(bucket): Path<String>) -> Response {
+    match state.storage.create_bucket(&bucket).await {
+        Ok(()) => StatusCode::CREATED.into_response(),
+        Err(e) => storage_error(e),
+    }
+}
+
+// only an empty bucket can go
+async fn delete_bucket(State(state): State<Arc<AppState>>, Path(bucket): Path<String>) -> Response {
+    match state.storage.delete_bucket(&bucket).await {
+        Ok(()) => StatusCode::NO_CONTENT.into_response(),
+        Err(e) => storage_error(e),
+    }
+}
+
+// the object's body with its content type; with ?download, as an attachment
+async fn fetch_object(
+    State(state): State<Arc<AppState>>,
+    Path((bucket, key)): Path<(String, String)>,
+    Query(query): Query<FetchQuery>,
+) -> Response {
+    let metadata = match state.storage.head_object(&bucket, &key).await {
+        Ok(metadata) => metadata,
+        Err(e) => return storage_error(e),
+    };
+    let data = match state.storage.get_object(&bucket, &key).await {
+        Ok(data) => data,
+        Err(e) => return storage_error(e),
+    };
+
+    let content_type = metadata.content_type.as_deref().unwrap_or("application/octet-stream");
+    let mut response = ([(header::CONTENT_TYPE, content_type.to_string())], data).into_response();
+    if query.download.is_some() {
+        let name = key.rsplit('/').next().unwrap_or(&key).replace('"', "");
+        let disposition = format!("attachment; filename=\"{}\"", name);
+        if let Ok(value) = disposition.parse() {
+            response.headers_mut().insert(header::CONTENT_DISPOSITION, value);
+        }
+    }
+    response
+}
+
+// like PutObject, but creates the bucket if it doesn't exist yet
+async fn seed_object(
+    State(state): State<Arc<AppState>>,
+    Path((bucket, key)): Path<(String, String)>,
+    headers: HeaderMap,
+    body: Bytes,
+) -> Response {
+    if let Err(e) = storage::validate_bucket_name(&bucket) {
+        return json_error(e.status, e.code, &e.message);
+    }
+    if !state.storage.bucket_exists(&bucket).await {
+        if let Err(e) = state.storage.create_bucket(&bucket).await {
+            return storage_error(e);
+        }
+    }
+
+    match state
+        .storage
+        .put_object(&bucket, &key, body, handlers::content_type(&headers))
+        .await
+    {
+        Ok(metadata) => (StatusCode::CREATED, Json(ObjectEntry { bucket, metadata })).into_response(),
+        Err(e) => storage_error(e),
+    }
+}
+
+async fn remove_object(State(state): State<Arc<AppState>>, Path((bucket, key)): Path<(String, String)>) -> Response {
+    match state.storage.delete_object(&bucket, &key).await {
+        Ok(()) => StatusCode::NO_CONTENT.into_response(),
+        Err(e) => storage_error(e),
+    }
+}
+
+// the config currently in effect, after env, flags and reloads; the admin token is redacted
+async fn dump_config(State(state): State<Arc<AppState>>) -> Response {
+    let mut config = (*state.config()).clone();
+    if config.admin.token.is_some() {
+        config.admin.token = Some("***".to_string());
+    }
+    for bucket in &mut config.buckets {
+        bucket.secret_key = "***".to_string();
+    }
+    Json(config).into_response()
+}
+
+async fn list_snapshots(State(state): State<Arc<AppState>>) -> Response {
+    match state.storage.list_snapshots().await {
+        Ok(snapshots) => Json(snapshots).into_response(),
+        Err(e) => storage_error(e),
+    }
+}
+
+async fn create_snapshot(State(state): State<Arc<AppState>>, Path(name): Path<String>) -> Response {
+    match state.storage.create_snapshot(&name).await {
+        Ok(info) => (StatusCode::CREATED, Json(info)).into_response(),
+        Err(e) => storage_error(e),
+    }
+}
+
+async fn restore_snapshot(State(state): State<Arc<AppState>>, Path(name): Path<String>) -> Response {
+    match state.storage.restore_snapshot(&name).await {
+        Ok(()) => StatusCode::NO_CONTENT.into_response(),
+        Err(e) => storage_error(e),
+    }
+}
+
+async fn delete_snapshot(State(state): State<Arc<AppState>>, Path(name): Path<String>) -> Response {
+    match state.storage.delete_snapshot(&name).await {
+        Ok(()) => StatusCode::NO_CONTENT.into_response(),
+        Err(e) => storage_error(e),
+    }
+}
+
+async fn list_faults(State(state): State<Arc<AppState>>) -> Response {
+    Json(state.faults.list()).into_response()
+}
+
+// the body is parsed by hand so a bad rule gets a json error like everything else here
+async fn add_fault(State(state): State<Arc<AppState>>, body: Bytes) -> Response {
+    let rule: FaultRule = match serde_json::from_slice(&body) {
+        Ok(rule) => rule,
+        Err(e) => return json_error(StatusCode::BAD_REQUEST, "InvalidFault", &e.to_string()),
+    };
+    if let Err(message) = rule.validate() {
+        return json_error(StatusCode::BAD_REQUEST, "InvalidFault", &message);
+    }
+
+    let id = state.faults.add(rule);
+    match state.faults.list().into_iter().find(|r| r.id == id) {
+        Some(status) => (StatusCode::CREATED, Json(status)).into_response(),
+        // removed again before we got to answer
+        None => StatusCode::CREATED.into_response(),
+    }
+}
+
+async fn clear_faults(State(state): State<Arc<AppState>>) -> Response {
+    state.faults.clear();
+    StatusCode::NO_CONTENT.into_response()
+}
+
+async fn remove_fault(State(state): State<Arc<AppState>>, Path(id): Path<u64>) -> Response {
+    if state.faults.remove(id) {
+        StatusCode::NO_CONTENT.into_response()
+    } else {
+        json_error(StatusCode::NOT_FOUND, "NoSuchFault", "no fault rule with that id")
+    }
+}
+
+// journaled requests still in memory, oldest first, filtered by the query string
+async fn query_journal(State(state): State<Arc<AppState>>, Query(filter): Query<JournalFilter>) -> Response {
+    Json(state.journal.entries(&filter)).into_response()
+}
+
+async fn clear_journal(State(state): State<Arc<AppState>>) -> Response {
+    state.journal.clear();
+    StatusCode::NO_CONTENT.into_response()
+}
+
+// write buffered access log records now rather than on the next interval
+async fn flush_access_logs(State(state): State<Arc<AppState>>) -> Response {
+    access_log::flush(&state).await;
+    StatusCode::NO_CONTENT.into_response()
+}
+
+// server-sent events, one `activity` per matching s3 request from now on; a watcher that
+// falls behind gets a `lagged` event saying how many it missed. the stream ends when the
+// server starts shutting down
+async fn watch_events(
+    State(state): State<Arc<AppState>>,
+    Query(filter): Query<WatchFilter>,
+) -> Sse<impl Stream<Item = Result<Event, Infallible>>> {
+    let receiver = state.watchers.subscribe();
+    let closed = Box::pin(state.watchers.closed());
+    let events = stream::unfold((receiver, filter, closed), |(mut receiver, filter, mut closed)| async move {
+        loop {
+            let received = tokio::select! {
+                received = receiver.recv() => received,
+                _ = &mut closed => return None,
+            };
+            let event = match received {
+                Ok(activity) if filter.matches(&activity) => Event::default()
+                    .event("activity")
+                    .id(activity.seq.to_string())
+                    .json_data(&*activity)
+                    .unwrap(),
+                Ok(_) => continue,
+                Err(RecvError::Lagged(missed)) => Event::default().event("lagged").data(missed.to_string()),
+                Err(RecvError::Closed) => return None,
+            };
+            return Some((Ok(event), (receiver, filter, closed)));
+        }
+    });
+    Sse::new(events).keep_alive(KeepAlive::default())
+}
+
+#[derive(Deserialize, Default)]
+struct FsckQuery {
+    // seconds
+    abandoned_after: Option<u64>,
+}
+
+impl FsckQuery {
+    fn options(&self, repair: bool) -> FsckOptions {
+        FsckOptions {
+            repair,
+            abandoned_after: self
+                .abandoned_after
+                .map(Duration::from_secs)
+                .unwrap_or(storage::DEFAULT_ABANDONED_AFTER),
+        }
+    }
+}
+
+// what's drifted from its metadata, without touching anything
+async fn check_storage(State(state): State<Arc<AppState>>, Query(query): Query<FsckQuery>) -> Response {
+    match state.storage.fsck(&query.options(false)).await {
+        Ok(report) => Json(report).into_response(),
+        Err(e) => storage_error(e),
+    }
+}
+
+async fn repair_storage(State(state): State<Arc<AppState>>, Query(query): Query<FsckQuery>) -> Response {
+    match state.storage.fsck(&query.options(true)).await {
+        Ok(report) => Json(report).into_response(),
+        Err(e) => storage_error(e),
+    }
+}
+
+fn clock_response(state: &AppState) -> Response {
+    Json(state.clock().status()).into_response()
+}
+
+async fn clock_status(State(state): State<Arc<AppState>>) -> Response {
+    clock_response(&state)
+}
+
+#[derive(Deserialize)]
+struct SetClock {
+    time: DateTime<Utc>,
+}
+
+async fn set_clock(State(state): State<Arc<AppState>>, body: Bytes) -> Response {
+    match serde_json::from_slice::<SetClock>(&body) {
+        Ok(request) => {
+            state.clock().set(request.time);
+            clock_response(&state)
+        }
+        Err(e) => json_error(StatusCode::BAD_REQUEST, "InvalidArgument", &e.to_string()),
+    }
+}
+
+async fn freeze_clock(State(state): State<Arc<AppState>>) -> Response {
+    state.clock().freeze();
+    clock_response(&state)
+}
+
+async fn resume_clock(State(state): State<Arc<AppState>>) -> Response {
+    state.clock().resume();
+    clock_response(&state)
+}
+
+#[derive(Deserialize)]
+struct AdvanceClock {
+    seconds: f64,
+}
+
+async fn advance_clock(State(state): State<Arc<AppState>>, body: Bytes) -> Response {
+    match serde_json::from_slice::<AdvanceClock>(&body) {
+        Ok(request) => {
+            // anything past what a TimeDelta holds, NaN included, fails the comparison
+            let by = Some(request.seconds * 1000.0)
+                .filter(|ms| ms.abs() < i64::MAX as f64)
+                .and_then(|ms| TimeDelta::try_milliseconds(ms as i64));
+            match by.ok_or(OutOfRange).and_then(|by| state.clock().advance(by)) {
+                Ok(()) => clock_response(&state),
+                Err(e) => json_error(
+                    StatusCode::BAD_REQUEST,
+                    "InvalidArgument",
+                    &format!("can't advance the clock {} seconds: {}", request.seconds, e),
+                ),
+            }
+        }
+        Err(e) => json_error(StatusCode::BAD_REQUEST, "InvalidArgument", &e.to_string()),
+    }
+}
+
+async fn reset_clock(State(state): State<Arc<AppState>>) -> Response {
+    state.clock().reset();
+    clock_response(&state)
+}
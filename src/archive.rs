@@ -0,0 +1,191 @@
+use bytes::Bytes;
+use chrono::{DateTime, Datelike, Timelike, Utc};
+use serde::{Deserialize, Serialize};
+use std::fs::File;
+use std::io::{self, Read, Write};
+use std::path::Path;
+
+use crate::client;
+use crate::storage::ObjectMetadata;
+
+// the entry in every export that describes the objects in it
+pub const MANIFEST: &str = ".six7-manifest.json";
+
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum Format {
+    Tar,
+    Zip,
+}
+
+impl Format {
+    // from the file name: .tar or .zip
+    pub fn for_path(path: &Path) -> Option<Self> {
+        let extension = path.extension()?.to_str()?.to_ascii_lowercase();
+        match extension.as_str() {
+            "tar" => Some(Format::Tar),
+            "zip" => Some(Format::Zip),
+            _ => None,
+        }
+    }
+}
+
+// what a bucket held when it was exported
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct Manifest {
+    pub bucket: String,
+    pub exported: DateTime<Utc>,
+    pub objects: Vec<ObjectMetadata>,
+}
+
+impl Manifest {
+    pub fn get(&self, key: &str) -> Option<&ObjectMetadata> {
+        self.objects.iter().find(|o| o.key == key)
+    }
+}
+
+// an export being written, one object at a time
+pub enum Writer {
+    Tar(tar::Builder<File>),
+    Zip(Box<zip::ZipWriter<File>>),
+}
+
+impl Writer {
+    pub fn create(path: &Path, format: Format) -> io::Result<Self> {
+        let file = File::create(path)?;
+        Ok(match format {
+            Format::Tar => Writer::Tar(tar::Builder::new(file)),
+            Format::Zip => Writer::Zip(Box::new(zip::ZipWriter::new(file))),
+        })
+    }
+
+    // keys ending in / can't be files, so they go in as directory entries
+    pub fn add(&mut self, name: &str, data: &[u8], modified: DateTime<Utc>) -> io::Result<()> {
+        match self {
+            Writer::Tar(builder) => {
+                let mut header = tar::Header::new_gnu();
+                header.set_entry_type(match name.ends_with('/') {
+                    true => tar::EntryType::Directory,
+                    false => tar::EntryType::Regular,
+                });
+                header.set_mode(if name.ends_with('/') { 0o755 } else { 0o644 });
+                header.set_size(data.len() as u64);
+                header.set_mtime(modified.timestamp().max(0) as u64);
+                builder.append_data(&mut header, name, data)
+            }
+            Writer::Zip(writer) => {
+                let options = zip::write::SimpleFileOptions::default().last_modified_time(zip_time(modified));
+                match name.ends_with('/') {
+                    true => writer.add_directory(name, options)?,
+                    false => {
+                        writer.start_file(name, options)?;
+                        writer.write_all(data)?;
+                    }
+                }
+                Ok(())
+            }
+        }
+    }
+
+    // the manifest goes last, once everything it lists is in
+    pub fn finish(mut self, manifest: &Manifest) -> io::Result<()> {
+        let json = serde_json::to_vec_pretty(manifest)?;
+        self.add(MANIFEST, &json, manifest.exported)?;
+        match self {
+            Writer::Tar(builder) => builder.into_inner()?.sync_all(),
+            Writer::Zip(writer) => writer.finish()?.sync_all(),
+        }
+    }
+}
+
+// zip timestamps have two-second resolution and start in 1980
+fn zip_time(time: DateTime<Utc>) -> zip::DateTime {
+    zip::DateTime::from_date_and_time(
+        time.year().clamp(1980, 2107) as u16,
+        time.month() as u8,
+        time.day() as u8,
+        time.hour() as u8,
+        time.minute() as u8,
+        time.second() as u8,
+    )
+    .unwrap_or_default()
+}
+
+// what's in a directory, tar or zip: every file by key, and the manifest if there is one
+#[derive(Debug, Default)]
+pub struct Contents {
+    pub manifest: Option<Manifest>,
+    pub entries: Vec<(String, Bytes)>,
+}
+
+pub fn read(path: &Path) -> io::Result<Contents> {
+    let mut entries = match path.is_dir() {
+        true => read_dir(path)?,
+        false => match Format::for_path(path) {
+            Some(Format::Tar) => read_tar(path)?,
+            Some(Format::Zip) => read_zip(path)?,
+            None => {
+                return Err(io::Error::new(
+                    io::ErrorKind::InvalidInput,
+                    "expected a directory, .tar or .zip",
+                ))
+            }
+        },
+    };
+
+    let mut contents = Contents::default();
+    if let Some(at) = entries.iter().position(|(name, _)| name == MANIFEST) {
+        let (_, json) = entries.remove(at);
+        contents.manifest = Some(serde_json::from_slice(&json)?);
+    }
+    // a directory entry is an object only when the manifest says so
+    entries.retain(|(name, _)| {
+        !name.is_empty() && (!name.ends_with('/') || contents.manifest.as_ref().is_some_and(|m| m.get(name).is_some()))
+    });
+    contents.entries = entries;
+    Ok(contents)
+}
+
+fn read_dir(dir: &Path) -> io::Result<Vec<(String, Bytes)>> {
+    client::walk(dir)?
+        .into_iter()
+        .map(|(key, path)| Ok((key, Bytes::from(std::fs::read(path)?))))
+        .collect()
+}
+
+fn read_tar(path: &Path) -> io::Result<Vec<(String, Bytes)>> {
+    let mut entries = Vec::new();
+    let mut archive = tar::Archive::new(File::open(path)?);
+    for entry in archive.entries()? {
+        let mut entry = entry?;
+        let kind = entry.header().entry_type();
+        if !kind.is_file() && !kind.is_dir() {
+            continue;
+        }
+        let mut name = String::from_utf8_lossy(&entry.path_bytes()).to_string();
+        if kind.is_dir() && !name.ends_with('/') {
+            name.push('/');
+        }
+        let mut data = Vec::new();
+        entry.read_to_end(&mut data)?;
+        entries.push((entry_name(&name), Bytes::from(data)));
+    }
+    Ok(entries)
+}
+
+fn read_zip(path: &Path) -> io::Result<Vec<(String, Bytes)>> {
+    let mut entries = Vec::new();
+    let mut archive = zip::ZipArchive::new(File::open(path)?)?;
+    for i in 0..archive.len() {
+        let mut file = archive.by_index(i)?;
+        let name = entry_name(file.name());
+        let mut data = Vec::new();
+        file.read_to_end(&mut data)?;
+        entries.push((name, Bytes::from(data)));
+    }
+    Ok(entries)
+}
+
+// tars made with `tar -C dir .` name everything ./like/this
+fn entry_name(name: &str) -> String {
+    name.trim_start_matches("./").to_string()
+}
@@ -1,7 +1,14 @@
 use axum::http::HeaderMap;
+use chrono::{DateTime, NaiveDateTime, TimeDelta, Utc};
 use hmac::{Hmac, Mac};
 use sha2::{Digest, Sha256};
 
+use crate::clock::Clock;
+use crate::error::S3Error;
+
+// the longest a presigned url can be valid for: seven days
+const MAX_PRESIGNED_EXPIRES: i64 = 604800;
+
 #[allow(dead_code)]
 type HmacSha256 = Hmac<Sha256>;
 
@@ -85,3 +92,44 @@ pub fn sign(key: &[u8], data: &[u8]) -> Vec<u8> {
     mac.update(data);
     mac.finalize().into_bytes().to_vec()
 }
+
+// x-amz-date and X-Amz-Date use the iso 8601 basic format, e.g. 20130524T000000Z
+pub fn parse_amz_date(value: &str) -> Option<DateTime<Utc>> {
+    NaiveDateTime::parse_from_str(value, "%Y%m%dT%H%M%SZ")
+        .ok()
+        .map(|t| t.and_utc())
+}
+
+// when a presigned url stops working: X-Amz-Date plus X-Amz-Expires seconds. None when
+// the url isn't presigned, an error when it is but the two can't be used
+pub fn presigned_expiry(query: &str) -> Result<Option<DateTime<Utc>>, S3Error> {
+    let Ok(params) = serde_urlencoded::from_str::<Vec<(String, String)>>(query) else {
+        return Ok(None);
+    };
+    let param = |name: &str| params.iter().find(|(k, _)| k == name).map(|(_, v)| v.as_str());
+
+    let Some(expires) = param("X-Amz-Expires") else {
+        return Ok(None);
+    };
+    let expires = expires.parse::<i64>().map_err(|_| {
+        S3Error::authorization_query_parameters_error("X-Amz-Expires should be a number")
+    })?;
+    if expires < 0 {
+        return Err(S3Error::authorization_query_parameters_error("X-Amz-Expires must be non-negative"));
+    }
+    if !(1..=MAX_PRESIGNED_EXPIRES).contains(&expires) {
+        return Err(S3Error::authorization_query_parameters_error(format!(
+            "X-Amz-Expires must be less than a week (in seconds) that is {}",
+            MAX_PRESIGNED_EXPIRES
+        )));
+    }
+    let date = param("X-Amz-Date").and_then(parse_amz_date).ok_or_else(|| {
+        S3Error::authorization_query_parameters_error("X-Amz-Date must be in the ISO8601 Long Format \"yyyyMMdd'T'HHmmss'Z'\"")
+    })?;
+    Ok(Some(date + TimeDelta::seconds(expires)))
+}
+
+// urls that aren't presigned never expire
+pub fn presigned_url_expired(query: &str, clock: &Clock) -> Result<bool, S3Error> {
+    Ok(presigned_expiry(query)?.is_some_and(|expiry| clock.now() >= expiry))
+}
@@ -0,0 +1,440 @@
+use clap::{Args, Parser, Subcommand};
+use std::path::{Path, PathBuf};
+
+use crate::client::{Client, Location};
+use crate::config::{self, BucketConfig, Config, ConfigError, DeterministicConfig, TlsConfig};
+use crate::storage;
+
+const DEFAULT_CONFIG: &str = "six7.yaml";
+
+#[derive(Parser, Debug)]
+#[command(name = "six7", version, about = "lightweight, s3-compatible mock service")]
+#[command(args_conflicts_with_subcommands = true)]
+pub struct Cli {
+    #[command(flatten)]
+    pub serve: ServeArgs,
+
+    #[command(subcommand)]
+    pub command: Option<Command>,
+}
+
+#[derive(Subcommand, Debug)]
+pub enum Command {
+    /// run the s3 server (the default when no subcommand is given)
+    Serve(ServeArgs),
+    /// save, restore and list named copies of the stored state
+    Snapshot(SnapshotArgs),
+    /// send the requests in a journal file to a server and compare the responses
+    Replay(ReplayArgs),
+    /// print the requests a running server handles as they happen
+    Watch(WatchArgs),
+    /// list buckets, or the objects under s3://bucket/prefix
+    Ls(LsArgs),
+    /// copy files and objects: local to s3, s3 to local, or s3 to s3
+    Cp(CpArgs),
+    /// delete an object, or with --recursive everything under a prefix
+    Rm(RmArgs),
+    /// create a bucket
+    Mb(BucketArgs),
+    /// delete a bucket
+    Rb(RbArgs),
+    /// print an object to stdout
+    Cat(CatArgs),
+    /// copy what's new or changed, comparing md5 checksums
+    Sync(SyncArgs),
+    /// load a directory, .tar or .zip into a bucket
+    Import(ImportArgs),
+    /// write a bucket's objects and a manifest of their metadata to a .tar or .zip
+    Export(ExportArgs),
+    /// check stored objects against their metadata, and with --repair fix what's found
+    Fsck(FsckArgs),
+}
+
+#[derive(Args, Debug)]
+pub struct LsArgs {
+    /// s3://bucket/prefix [default: every bucket]
+    #[arg(value_name = "S3URL", value_parser = Location::parse)]
+    pub path: Option<Location>,
+
+    /// every object under the prefix rather than one level of it
+    #[arg(short, long)]
+    pub recursive: bool,
+
+    #[command(flatten)]
+    pub target: AdminTarget,
+}
+
+#[derive(Args, Debug)]
+pub struct CpArgs {
+    /// file, directory or s3://bucket/key
+    #[arg(value_name = "SOURCE", value_parser = Location::parse)]
+    pub source: Location,
+
+    /// file, directory or s3://bucket/key
+    #[arg(value_name = "DEST", value_parser = Location::parse)]
+    pub dest: Location,
+
+    /// copy everything under a directory or prefix
+    #[arg(short, long)]
+    pub recursive: bool,
+
+    #[command(flatten)]
+    pub target: AdminTarget,
+}
+
+#[derive(Args, Debug)]
+pub struct RmArgs {
+    /// s3://bucket/key
+    #[arg(value_name = "S3URL", value_parser = Location::parse)]
+    pub path: Location,
+
+    /// delete everything under the prefix
+    #[arg(short, long)]
+    pub recursive: bool,
+
+    #[command(flatten)]
+    pub target: AdminTarget,
+}
+
+#[derive(Args, Debug)]
+pub struct BucketArgs {
+    /// bucket name or s3://bucket
+    #[arg(value_name = "BUCKET", value_parser = Location::parse_bucket)]
+    pub bucket: String,
+
+    #[command(flatten)]
+    pub target: AdminTarget,
+}
+
+#[derive(Args, Debug)]
+pub struct RbArgs {
+    /// bucket name or s3://bucket
+    #[arg(value_name = "BUCKET", value_parser = Location::parse_bucket)]
+    pub bucket: String,
+
+    /// delete the bucket's objects first
+    #[arg(long)]
+    pub force: bool,
+
+    #[command(flatten)]
+    pub target: AdminTarget,
+}
+
+#[derive(Args, Debug)]
+pub struct CatArgs {
+    /// s3://bucket/key
+    #[arg(value_name = "S3URL", value_parser = Location::parse)]
+    pub path: Location,
+
+    #[command(flatten)]
+    pub target: AdminTarget,
+}
+
+#[derive(Args, Debug)]
+pub struct SyncArgs {
+    /// directory or s3://bucket/prefix
+    #[arg(value_name = "SOURCE", value_parser = Location::parse)]
+    pub source: Location,
+
+    /// directory or s3://bucket/prefix
+    #[arg(value_name = "DEST", value_parser = Location::parse)]
+    pub dest: Location,
+
+    /// delete what's in the destination but not the source
+    #[arg(long)]
+    pub delete: bool,
+
+    #[command(flatten)]
+    pub target: AdminTarget,
+}
+
+#[derive(Args, Debug)]
+pub struct ImportArgs {
+    /// bucket to load into, created if it's missing
+    #[arg(short, long, value_name = "NAME", value_parser = Location::parse_bucket)]
+    pub bucket: String,
+
+    /// directory, or a .tar or .zip such as one written by export
+    #[arg(value_name = "PATH")]
+    pub source: PathBuf,
+
+    #[command(flatten)]
+    pub target: AdminTarget,
+}
+
+#[derive(Args, Debug)]
+pub struct ExportArgs {
+    /// bucket to export
+    #[arg(short, long, value_name = "NAME", value_parser = Location::parse_bucket)]
+    pub bucket: String,
+
+    /// file to write, a .tar or .zip
+    #[arg(value_name = "PATH")]
+    pub dest: PathBuf,
+
+    #[command(flatten)]
+    pub target: AdminTarget,
+}
+
+#[derive(Args, Debug)]
+pub struct FsckArgs {
+    /// fix what's found instead of only reporting it
+    #[arg(long)]
+    pub repair: bool,
+
+    /// multipart uploads and staged writes older than this are abandoned
+    #[arg(long, value_name = "SECS", default_value_t = storage::DEFAULT_ABANDONED_AFTER.as_secs())]
+    pub abandoned_after: u64,
+
+    #[command(flatten)]
+    pub target: AdminTarget,
+}
+
+#[derive(Args, Debug)]
+pub struct WatchArgs {
+    /// url of the six7 to watch
+    #[arg(long, value_name = "URL", default_value = "http://localhost:4040")]
+    pub endpoint: String,
+
+    /// admin token [default: $SIX7_ADMIN_TOKEN]
+    #[arg(long, value_name = "TOKEN")]
+    pub admin_token: Option<String>,
+
+    /// only requests for this bucket
+    #[arg(short, long, value_name = "NAME")]
+    pub bucket: Option<String>,
+
+    /// only requests for keys starting with this
+    #[arg(long, value_name = "PREFIX")]
+    pub prefix: Option<String>,
+
+    /// only this operation, e.g. PutObject
+    #[arg(long, value_name = "NAME")]
+    pub operation: Option<String>,
+
+    /// print each request as a line of json
+    #[arg(long)]
+    pub json: bool,
+}
+
+impl WatchArgs {
+    pub fn admin_token(&self) -> Option<String> {
+        self.admin_token
+            .clone()
+            .or_else(|| std::env::var(config::ENV_ADMIN_TOKEN).ok())
+    }
+}
+
+#[derive(Args, Debug)]
+pub struct ReplayArgs {
+    /// journal file written by a server with the journal enabled
+    #[arg(value_name = "PATH")]
+    pub journal: PathBuf,
+
+    /// url of the six7 to replay against [default: a fresh in-memory instance]
+    #[arg(long, value_name = "URL")]
+    pub endpoint: Option<String>,
+
+    /// config for the fresh instance, for its buckets [default: six7.yaml, skipped if missing]
+    #[arg(short, long, value_name = "PATH")]
+    pub config: Option<PathBuf>,
+}
+
+#[derive(Args, Debug)]
+pub struct SnapshotArgs {
+    #[command(subcommand)]
+    pub action: SnapshotAction,
+
+    #[command(flatten)]
+    pub target: AdminTarget,
+}
+
+#[derive(Subcommand, Debug)]
+pub enum SnapshotAction {
+    /// snapshot every bucket and object, replacing a snapshot of the same name
+    Create { name: String },
+    /// replace the stored state with a snapshot
+    Restore { name: String },
+    /// list snapshots
+    List,
+    /// delete a snapshot
+    Delete { name: String },
+}
+
+// where an admin command acts: a running server, or the data dir directly
+#[derive(Args, Debug, Clone, Default)]
+pub struct AdminTarget {
+    /// url of a running six7; without it the data dir is used directly
+    #[arg(long, global = true, value_name = "URL")]
+    pub endpoint: Option<String>,
+
+    /// admin token for --endpoint [default: $SIX7_ADMIN_TOKEN]
+    #[arg(long, global = true, value_name = "TOKEN")]
+    pub admin_token: Option<String>,
+
+    /// config file naming the data dir [default: six7.yaml, skipped if missing]
+    #[arg(short, long, global = true, value_name = "PATH")]
+    pub config: Option<PathBuf>,
+
+    /// data dir to act on, when not using --endpoint
+    #[arg(long, global = true, value_name = "PATH")]
+    pub data_dir: Option<String>,
+}
+
+impl AdminTarget {
+    pub fn admin_token(&self) -> Option<String> {
+        self.admin_token
+            .clone()
+            .or_else(|| std::env::var(config::ENV_ADMIN_TOKEN).ok())
+    }
+
+    // a running server with --endpoint, or else the data dir
+    pub fn client(&self) -> Result<Client, Box<dyn std::error::Error>> {
+        match &self.endpoint {
+            Some(endpoint) => Ok(Client::remote(endpoint, self.admin_token())),
+            None => Ok(Client::local(self.data_dir()?)?),
+        }
+    }
+
+    // the storage path a server started with the same config and flags would use
+    pub fn data_dir(&self) -> Result<PathBuf, ConfigError> {
+        let serve = ServeArgs {
+            config: self.config.clone(),
+            data_dir: self.data_dir.clone(),
+            ..ServeArgs::default()
+        };
+        Ok(PathBuf::from(serve.load_config()?.storage.path))
+    }
+}
+
+#[derive(Args, Debug, Clone, Default)]
+pub struct ServeArgs {
+    /// config file to load [default: six7.yaml, skipped if missing]
+    #[arg(short, long, value_name = "PATH")]
+    pub config: Option<PathBuf>,
+
+    /// address to bind
+    #[arg(long)]
+    pub host: Option<String>,
+
+    /// port to listen on
+    #[arg(short, long)]
+    pub port: Option<u16>,
+
+    /// base domain for virtual-hosted-style requests; repeatable
+    #[arg(long = "domain", value_name = "DOMAIN")]
+    pub domains: Vec<String>,
+
+    /// also serve https on this port
+    #[arg(long, value_name = "PORT")]
+    pub tls_port: Option<u16>,
+
+    /// pem certificate chain for https [default: issued by a generated local ca]
+    #[arg(long, value_name = "PATH", requires = "tls_key")]
+    pub tls_cert: Option<String>,
+
+    /// pem private key for --tls-cert
+    #[arg(long, value_name = "PATH", requires = "tls_cert")]
+    pub tls_key: Option<String>,
+
+    /// directory objects are stored in
+    #[arg(long, value_name = "PATH")]
+    pub data_dir: Option<String>,
+
+    /// bucket to create on start, optionally with credentials; repeatable
+    #[arg(short, long = "bucket", value_name = "NAME[:KEY:SECRET]", value_parser = BucketConfig::parse)]
+    pub buckets: Vec<BucketConfig>,
+
+    /// enable the /_six7/ admin api, protected by this bearer token
+    #[arg(long, value_name = "TOKEN")]
+    pub admin_token: Option<String>,
+
+    /// keep everything in memory instead of under the data dir
+    #[arg(long)]
+    pub in_memory: bool,
+
+    /// deterministic mode: generated ids from this seed and a frozen clock
+    #[arg(long, value_name = "N")]
+    pub seed: Option<u64>,
+
+    /// journal every s3 request, appending them to this jsonl file
+    #[arg(long, value_name = "PATH")]
+    pub journal: Option<String>,
+}
+
+impl Cli {
+    // the subcommand to run, with bare flags meaning `serve`
+    pub fn into_command(self) -> Command {
+        self.command.unwrap_or(Command::Serve(self.serve))
+    }
+}
+
+impl ServeArgs {
+    // the file the config comes from, if there is one
+    pub fn config_path(&self) -> Option<PathBuf> {
+        match &self.config {
+            Some(path) => Some(path.clone()),
+            None if Path::new(DEFAULT_CONFIG).exists() => Some(PathBuf::from(DEFAULT_CONFIG)),
+            None => None,
+        }
+    }
+
+    // config file (if any) and SIX7_* variables, with the command-line flags on top
+    pub fn load_config(&self) -> Result<Config, ConfigError> {
+        let mut config = match self.config_path() {
+            Some(path) => Config::load(path)?,
+            None => Config::from_env()?,
+        };
+
+        self.apply(&mut config);
+        config.validate()?;
+        Ok(config)
+    }
+
+    pub fn apply(&self, config: &mut Config) {
+        if let Some(host) = &self.host {
+            config.server.host = host.clone();
+        }
+        if let Some(port) = self.port {
+            config.server.port = port;
+        }
+        if !self.domains.is_empty() {
+            config.server.domains = self.domains.clone();
+        }
+        if self.tls_port.is_some() || self.tls_cert.is_some() {
+            let tls = config.server.tls.get_or_insert_with(TlsConfig::default);
+            if let Some(port) = self.tls_port {
+                tls.port = port;
+            }
+            if self.tls_cert.is_some() {
+                tls.cert = self.tls_cert.clone();
+                tls.key = self.tls_key.clone();
+            }
+        }
+        if let Some(data_dir) = &self.data_dir {
+            config.storage.path = data_dir.clone();
+        }
+
+        if let Some(seed) = self.seed {
+            config
+                .deterministic
+                .get_or_insert_with(|| DeterministicConfig::new(seed))
+                .seed = seed;
+        }
+
+        if let Some(path) = &self.journal {
+            config.journal.enabled = true;
+            config.journal.path = Some(path.clone());
+        }
+
+        if let Some(token) = &self.admin_token {
+            config.admin.enabled = true;
+            config.admin.token = Some(token.clone());
+        }
+
+        for bucket in &self.buckets {
+            config.buckets.retain(|b| b.name != bucket.name);
+            config.buckets.push(bucket.clone());
+        }
+    }
+}
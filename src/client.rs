@@ -0,0 +1,527 @@
+use bytes::Bytes;
+use chrono::Utc;
+use std::collections::{BTreeMap, BTreeSet};
+use std::fmt;
+use std::io;
+use std::path::{Path, PathBuf};
+use std::sync::Arc;
+
+use crate::archive::{self, Format, Manifest, Writer};
+use crate::error::S3Error;
+use crate::remote::{AdminClient, RemoteError};
+use crate::storage::{BucketInfo, ObjectMetadata, Storage, StorageBackend};
+
+const SCHEME: &str = "s3://";
+
+// a place objects are copied from or to: a key or prefix in a bucket, or a local path
+#[derive(Debug, Clone, PartialEq)]
+pub enum Location {
+    S3 { bucket: String, key: String },
+    File(PathBuf),
+}
+
+impl Location {
+    // s3://bucket/key for objects, anything else for local files
+    pub fn parse(value: &str) -> Result<Self, String> {
+        let Some(rest) = value.strip_prefix(SCHEME) else {
+            return Ok(Location::File(PathBuf::from(value)));
+        };
+        let (bucket, key) = rest.split_once('/').unwrap_or((rest, ""));
+        if bucket.is_empty() {
+            return Err(format!("'{}' names no bucket", value));
+        }
+        Ok(Location::S3 {
+            bucket: bucket.to_string(),
+            key: key.to_string(),
+        })
+    }
+
+    // the bucket and key, for commands that only act on objects
+    pub fn s3(&self) -> Result<(&str, &str), ClientError> {
+        match self {
+            Location::S3 { bucket, key } => Ok((bucket, key)),
+            Location::File(path) => Err(ClientError::Usage(format!("{} is not an s3:// url", path.display()))),
+        }
+    }
+
+    // a bare bucket name or s3://bucket
+    pub fn parse_bucket(value: &str) -> Result<String, String> {
+        let name = value.strip_prefix(SCHEME).unwrap_or(value).trim_end_matches('/');
+        match name.is_empty() || name.contains('/') {
+            true => Err(format!("'{}' is not a bucket", value)),
+            false => Ok(name.to_string()),
+        }
+    }
+}
+
+impl fmt::Display for Location {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            Location::S3 { bucket, key } => write!(f, "{}{}/{}", SCHEME, bucket, key),
+            Location::File(path) => write!(f, "{}", path.display()),
+        }
+    }
+}
+
+#[derive(Debug)]
+pub enum ClientError {
+    Storage(S3Error),
+    Remote(RemoteError),
+    File(PathBuf, io::Error),
+    Usage(String),
+}
+
+impl fmt::Display for ClientError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            ClientError::Storage(e) => write!(f, "{}", e),
+            ClientError::Remote(e) => write!(f, "{}", e),
+            ClientError::File(path, e) => write!(f, "{}: {}", path.display(), e),
+            ClientError::Usage(message) => write!(f, "{}", message),
+        }
+    }
+}
+
+impl std::error::Error for ClientError {}
+
+impl From<io::Error> for ClientError {
+    fn from(err: io::Error) -> Self {
+        ClientError::Storage(S3Error::from(err))
+    }
+}
+
+impl From<RemoteError> for ClientError {
+    fn from(err: RemoteError) -> Self {
+        ClientError::Remote(err)
+    }
+}
+
+// what's under a prefix, split at the next / unless listed recursively
+#[derive(Debug, Default)]
+pub struct Listing {
+    pub prefixes: Vec<String>,
+    pub objects: Vec<ObjectMetadata>,
+}
+
+// one object or file copied
+#[derive(Debug, Clone, PartialEq)]
+pub struct Transfer {
+    pub from: Location,
+    pub to: Location,
+}
+
+#[derive(Debug, Default)]
+pub struct SyncReport {
+    pub copied: Vec<Transfer>,
+    pub deleted: Vec<Location>,
+    pub unchanged: usize,
+}
+
+// where client commands act: the data dir directly, or a running six7 through its admin api
+pub enum Client {
+    Local(Arc<dyn StorageBackend>),
+    Remote(AdminClient),
+}
+
+impl Client {
+    pub fn local(data_dir: impl AsRef<Path>) -> Result<Self, ClientError> {
+        let storage = Storage::new(data_dir.as_ref()).map_err(|e| ClientError::File(data_dir.as_ref().into(), e))?;
+        Ok(Client::Local(Arc::new(storage)))
+    }
+
+    pub fn remote(endpoint: &str, token: Option<String>) -> Self {
+        Client::Remote(AdminClient::new(endpoint, token))
+    }
+
+    pub async fn list_buckets(&self) -> Result<Vec<BucketInfo>, ClientError> {
+        match self {
+            Client::Local(storage) => Ok(storage.list_buckets().await?),
+            Client::Remote(client) => Ok(client.list_buckets().await?),
+        }
+    }
+
+    pub async fn create_bucket(&self, bucket: &str) -> Result<(), ClientError> {
+        match self {
+            Client::Local(storage) => Ok(storage.create_bucket(bucket).await?),
+            Client::Remote(client) => Ok(client.create_bucket(bucket).await?),
+        }
+    }
+
+    // with force, whatever's in the bucket goes first
+    pub async fn remove_bucket(&self, bucket: &str, force: bool) -> Result<Vec<Location>, ClientError> {
+        let removed = match force {
+            true => self.remove(bucket, "", true).await?,
+            false => Vec::new(),
+        };
+        match self {
+            Client::Local(storage) => storage.delete_bucket(bucket).await?,
+            Client::Remote(client) => client.delete_bucket(bucket).await?,
+        }
+        Ok(removed)
+    }
+
+    async fn objects(&self, bucket: &str, prefix: &str) -> Result<Vec<ObjectMetadata>, ClientError> {
+        match self {
+            Client::Local(storage) => {
+                if !storage.bucket_exists(bucket).await {
+                    return Err(ClientError::Storage(S3Error::no_such_bucket()));
+                }
+                Ok(storage.list_objects(bucket, Some(prefix)).await?)
+            }
+            Client::Remote(client) => Ok(client.list_objects(bucket, prefix).await?),
+        }
+    }
+
+    pub async fn list(&self, bucket: &str, prefix: &str, recursive: bool) -> Result<Listing, ClientError> {
+        let mut listing = Listing::default();
+        let mut prefixes = BTreeSet::new();
+        for object in self.objects(bucket, prefix).await? {
+            match object.key[prefix.len()..].find('/') {
+                Some(slash) if !recursive => {
+                    prefixes.insert(object.key[..prefix.len() + slash + 1].to_string());
+                }
+                _ => listing.objects.push(object),
+            }
+        }
+        listing.prefixes = prefixes.into_iter().collect();
+        Ok(listing)
+    }
+
+    pub async fn get(&self, bucket: &str, key: &str) -> Result<Bytes, ClientError> {
+        Ok(self.fetch(bucket, key).await?.0)
+    }
+
+    // the object with its content type
+    async fn fetch(&self, bucket: &str, key: &str) -> Result<(Bytes, Option<String>), ClientError> {
+        match self {
+            Client::Local(storage) => {
+                let content_type = storage.head_object(bucket, key).await?.content_type;
+                Ok((storage.get_object(bucket, key).await?, content_type))
+            }
+            Client::Remote(client) => Ok(client.get_object(bucket, key).await?),
+        }
+    }
+
+    async fn put(&self, bucket: &str, key: &str, data: Bytes, content_type: Option<&str>) -> Result<(), ClientError> {
+        let content_type = content_type.map(str::to_string);
+        match self {
+            Client::Local(storage) => {
+                storage.put_object(bucket, key, data, content_type).await?;
+            }
+            Client::Remote(client) => {
+                client.put_object(bucket, key, data, content_type.as_deref()).await?;
+            }
+        }
+        Ok(())
+    }
+
+    async fn delete(&self, bucket: &str, key: &str) -> Result<(), ClientError> {
+        match self {
+            Client::Local(storage) => Ok(storage.delete_object(bucket, key).await?),
+            Client::Remote(client) => Ok(client.delete_object(bucket, key).await?),
+        }
+    }
+
+    // one object, or with recursive everything under key as a prefix
+    pub async fn remove(&self, bucket: &str, key: &str, recursive: bool) -> Result<Vec<Location>, ClientError> {
+        let keys = match recursive {
+            true => self.objects(bucket, key).await?.into_iter().map(|o| o.key).collect(),
+            false => vec![key.to_string()],
+        };
+        let mut removed = Vec::new();
+        for key in keys {
+            self.delete(bucket, &key).await?;
+            removed.push(Location::S3 {
+                bucket: bucket.to_string(),
+                key,
+            });
+        }
+        Ok(removed)
+    }
+
+    // one object or file to another place; with recursive, everything under a prefix or
+    // directory to the same relative paths under the destination
+    pub async fn copy(&self, from: &Location, to: &Location, recursive: bool) -> Result<Vec<Transfer>, ClientError> {
+        if let (Location::File(_), Location::File(_)) = (from, to) {
+            return Err(ClientError::Usage("at least one side has to be s3://".to_string()));
+        }
+
+        self.check_destination(to).await?;
+        let sources = match recursive {
+            true => self.entries(from).await?.into_keys().collect(),
+            false => {
+                let name = match from {
+                    Location::S3 { key, .. } => key.rsplit('/').next().unwrap_or_default(),
+                    Location::File(path) => path.file_name().and_then(|n| n.to_str()).unwrap_or_default(),
+                };
+                if name.is_empty() {
+                    return Err(ClientError::Usage(format!("{} is not an object; copy it with --recursive", from)));
+                }
+                vec![name.to_string()]
+            }
+        };
+
+        let mut transfers = Vec::new();
+        for relative in sources {
+            let source = match recursive {
+                true => join(from, &relative),
+                false => from.clone(),
+            };
+            let destination = match recursive || is_directory(to) {
+                true => join(to, &relative),
+                false => to.clone(),
+            };
+            self.transfer(&source, &destination).await?;
+            transfers.push(Transfer {
+                from: source,
+                to: destination,
+            });
+        }
+        Ok(transfers)
+    }
+
+    // make the destination match the source, copying only what's missing or whose md5
+    // differs; with delete, what the source doesn't have goes
+    pub async fn sync(&self, from: &Location, to: &Location, delete: bool) -> Result<SyncReport, ClientError> {
+        if let (Location::File(_), Location::File(_)) = (from, to) {
+            return Err(ClientError::Usage("at least one side has to be s3://".to_string()));
+        }
+
+        self.check_destination(to).await?;
+        let sources = self.entries(from).await?;
+        let existing = self.entries(to).await?;
+        let mut report = SyncReport::default();
+        for (relative, checksum) in &sources {
+            if existing.get(relative).is_some_and(|c| c == checksum) {
+                report.unchanged += 1;
+                continue;
+            }
+            let (source, destination) = (join(from, relative), join(to, relative));
+            self.transfer(&source, &destination).await?;
+            report.copied.push(Transfer {
+                from: source,
+                to: destination,
+            });
+        }
+
+        if delete {
+            for relative in existing.keys().filter(|r| !sources.contains_key(*r)) {
+                let target = join(to, relative);
+                match &target {
+                    Location::S3 { bucket, key } => self.delete(bucket, key).await?,
+                    Location::File(path) => {
+                        tokio::fs::remove_file(path)
+                            .await
+                            .map_err(|e| ClientError::File(path.clone(), e))?;
+                    }
+                }
+                report.deleted.push(target);
+            }
+        }
+        Ok(report)
+    }
+
+    // a directory, tar or zip into a bucket, creating it if it's missing. content types
+    // come from the manifest if there is one, or else from file extensions; an archive's
+    // files have to match the etags in its manifest before anything is stored
+    pub async fn import(&self, bucket: &str, source: &Path) -> Result<Vec<Location>, ClientError> {
+        let file_error = |e| ClientError::File(source.to_path_buf(), e);
+        let contents = archive::read(source).map_err(file_error)?;
+        if let Some(manifest) = contents.manifest.as_ref().filter(|_| !source.is_dir()) {
+            verify(manifest, &contents.entries).map_err(file_error)?;
+        }
+
+        self.create_bucket(bucket).await?;
+        let mut imported = Vec::new();
+        for (key, data) in contents.entries {
+            let content_type = match contents.manifest.as_ref().and_then(|m| m.get(&key)) {
+                Some(object) => object.content_type.clone(),
+                None => content_type_for(Path::new(&key)).map(str::to_string),
+            };
+            self.put(bucket, &key, data, content_type.as_deref()).await?;
+            imported.push(Location::S3 {
+                bucket: bucket.to_string(),
+                key,
+            });
+        }
+        Ok(imported)
+    }
+
+    // every object in a bucket into a tar or zip, followed by a manifest of their metadata
+    pub async fn export(&self, bucket: &str, dest: &Path) -> Result<Manifest, ClientError> {
+        let format = Format::for_path(dest)
+            .ok_or_else(|| ClientError::Usage(format!("{} should end in .tar or .zip", dest.display())))?;
+        let objects = self.objects(bucket, "").await?;
+        if objects.iter().any(|o| o.key == archive::MANIFEST) {
+            return Err(ClientError::Usage(format!(
+                "the bucket has an object named {}, which exports keep for the manifest",
+                archive::MANIFEST
+            )));
+        }
+
+        let file_error = |e| ClientError::File(dest.to_path_buf(), e);
+        let mut writer = Writer::create(dest, format).map_err(file_error)?;
+        for object in &objects {
+            let data = self.get(bucket, &object.key).await?;
+            writer.add(&object.key, &data, object.last_modified).map_err(file_error)?;
+        }
+        let manifest = Manifest {
+            bucket: bucket.to_string(),
+            exported: Utc::now(),
+            objects,
+        };
+        writer.finish(&manifest).map_err(file_error)?;
+        Ok(manifest)
+    }
+
+    // copies never create buckets, wherever they run
+    async fn check_destination(&self, to: &Location) -> Result<(), ClientError> {
+        let Location::S3 { bucket, .. } = to else {
+            return Ok(());
+        };
+        match self.list_buckets().await?.iter().any(|b| &b.name == bucket) {
+            true => Ok(()),
+            false => Err(ClientError::Storage(S3Error::no_such_bucket())),
+        }
+    }
+
+    async fn transfer(&self, from: &Location, to: &Location) -> Result<(), ClientError> {
+        let (data, content_type) = match from {
+            Location::S3 { bucket, key } => self.fetch(bucket, key).await?,
+            Location::File(path) => {
+                let data = tokio::fs::read(path).await.map_err(|e| ClientError::File(path.clone(), e))?;
+                (Bytes::from(data), content_type_for(path).map(str::to_string))
+            }
+        };
+
+        match to {
+            Location::S3 { bucket, key } => self.put(bucket, key, data, content_type.as_deref()).await,
+            Location::File(path) => {
+                if let Some(parent) = path.parent().filter(|p| !p.as_os_str().is_empty()) {
+                    tokio::fs::create_dir_all(parent)
+                        .await
+                        .map_err(|e| ClientError::File(parent.to_path_buf(), e))?;
+                }
+                tokio::fs::write(path, &data).await.map_err(|e| ClientError::File(path.clone(), e))
+            }
+        }
+    }
+
+    // everything under a prefix or directory, by path relative to it, with its md5; a
+    // multipart etag never matches a file's md5, so those objects are always copied
+    async fn entries(&self, location: &Location) -> Result<BTreeMap<String, String>, ClientError> {
+        match location {
+            Location::S3 { bucket, key } => {
+                let prefix = as_prefix(key);
+                let objects = self.objects(bucket, &prefix).await?;
+                Ok(objects
+                    .into_iter()
+                    .map(|o| (o.key[prefix.len()..].to_string(), o.etag))
+                    .collect())
+            }
+            Location::File(dir) => {
+                let mut entries = BTreeMap::new();
+                if !dir.exists() {
+                    return Ok(entries);
+                }
+                for (relative, path) in walk(dir).map_err(|e| ClientError::File(dir.clone(), e))? {
+                    let data = tokio::fs::read(&path).await.map_err(|e| ClientError::File(path.clone(), e))?;
+                    entries.insert(relative, format!("{:x}", md5::compute(&data)));
+                }
+                Ok(entries)
+            }
+        }
+    }
+}
+
+// every object the manifest lists is in the archive, with the same md5 unless it was a
+// multipart upload
+fn verify(manifest: &Manifest, entries: &[(String, Bytes)]) -> io::Result<()> {
+    for object in &manifest.objects {
+        let Some((_, data)) = entries.iter().find(|(key, _)| *key == object.key) else {
+            return Err(io::Error::new(
+                io::ErrorKind::InvalidData,
+                format!("{} is in the manifest but not the archive", object.key),
+            ));
+        };
+        if !object.etag.contains('-') && format!("{:x}", md5::compute(data)) != object.etag {
+            return Err(io::Error::new(
+                io::ErrorKind::InvalidData,
+                format!("{} doesn't match its etag in the manifest", object.key),
+            ));
+        }
+    }
+    Ok(())
+}
+
+// a key as a prefix of what's under it: "dir" means "dir/", and "" the whole bucket
+fn as_prefix(key: &str) -> String {
+    match key.is_empty() || key.ends_with('/') {
+        true => key.to_string(),
+        false => format!("{}/", key),
+    }
+}
+
+fn join(location: &Location, relative: &str) -> Location {
+    match location {
+        Location::S3 { bucket, key } => Location::S3 {
+            bucket: bucket.clone(),
+            key: format!("{}{}", as_prefix(key), relative),
+        },
+        Location::File(dir) => Location::File(relative.split('/').fold(dir.clone(), |path, part| path.join(part))),
+    }
+}
+
+// a destination that a single copy goes into rather than replaces
+fn is_directory(location: &Location) -> bool {
+    match location {
+        Location::S3 { key, .. } => key.is_empty() || key.ends_with('/'),
+        Location::File(path) => path.is_dir() || path.to_string_lossy().ends_with(std::path::MAIN_SEPARATOR),
+    }
+}
+
+// every file under dir, with its path relative to dir joined by /
+pub(crate) fn walk(dir: &Path) -> io::Result<Vec<(String, PathBuf)>> {
+    let mut files = Vec::new();
+    let mut pending = vec![(String::new(), dir.to_path_buf())];
+    while let Some((relative, path)) = pending.pop() {
+        for entry in std::fs::read_dir(&path)? {
+            let entry = entry?;
+            let name = entry.file_name().to_string_lossy().to_string();
+            let relative = format!("{}{}", relative, name);
+            if entry.file_type()?.is_dir() {
+                pending.push((format!("{}/", relative), entry.path()));
+            } else {
+                files.push((relative, entry.path()));
+            }
+        }
+    }
+    files.sort();
+    Ok(files)
+}
+
+// enough to preview what's uploaded from the command line; anything else is left unset
+fn content_type_for(path: &Path) -> Option<&'static str> {
+    let extension = path.extension()?.to_str()?.to_ascii_lowercase();
+    Some(match extension.as_str() {
+        "txt" | "log" => "text/plain",
+        "html" | "htm" => "text/html",
+        "css" => "text/css",
+        "csv" => "text/csv",
+        "md" => "text/markdown",
+        "js" | "mjs" => "text/javascript",
+        "json" => "application/json",
+        "xml" => "application/xml",
+        "yaml" | "yml" => "application/yaml",
+        "pdf" => "application/pdf",
+        "zip" => "application/zip",
+        "tar" => "application/x-tar",
+        "gz" => "application/gzip",
+        "png" => "image/png",
+        "jpg" | "jpeg" => "image/jpeg",
+        "gif" => "image/gif",
+        "svg" => "image/svg+xml",
+        "webp" => "image/webp",
+        _ => return None,
+    })
+}
@@ -0,0 +1,124 @@
+use chrono::{DateTime, TimeDelta, Utc};
+use serde::Serialize;
+use std::fmt;
+use std::sync::{Arc, RwLock};
+
+// where storage and auth get the time from. it follows the system clock until it's frozen,
+// set or advanced, so tests can move time instead of waiting for it; clones share state
+#[derive(Debug, Clone, Default)]
+pub struct Clock {
+    state: Arc<RwLock<ClockState>>,
+}
+
+#[derive(Debug, Default)]
+struct ClockState {
+    // added to the system time while the clock runs
+    offset: TimeDelta,
+    frozen: Option<DateTime<Utc>>,
+}
+
+#[derive(Debug, Clone, PartialEq, Serialize)]
+pub struct ClockStatus {
+    pub now: DateTime<Utc>,
+    pub frozen: bool,
+    // how far the clock is from the system clock
+    pub offset_ms: i64,
+}
+
+// a move that would take the clock past the times it can represent
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct OutOfRange;
+
+impl fmt::Display for OutOfRange {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "time out of range")
+    }
+}
+
+impl std::error::Error for OutOfRange {}
+
+impl ClockState {
+    // a running clock pinned to the last representable time rather than overflowing,
+    // should it run past it
+    fn now(&self) -> DateTime<Utc> {
+        self.frozen.unwrap_or_else(|| {
+            Utc::now()
+                .checked_add_signed(self.offset)
+                .unwrap_or(match self.offset > TimeDelta::zero() {
+                    true => DateTime::<Utc>::MAX_UTC,
+                    false => DateTime::<Utc>::MIN_UTC,
+                })
+        })
+    }
+}
+
+impl Clock {
+    pub fn new() -> Self {
+        Clock::default()
+    }
+
+    // a clock stopped at the given time
+    pub fn frozen_at(time: DateTime<Utc>) -> Self {
+        let clock = Clock::new();
+        clock.freeze();
+        clock.set(time);
+        clock
+    }
+
+    pub fn now(&self) -> DateTime<Utc> {
+        self.state.read().unwrap().now()
+    }
+
+    // stop at the current time
+    pub fn freeze(&self) {
+        let mut state = self.state.write().unwrap();
+        if state.frozen.is_none() {
+            state.frozen = Some(state.now());
+        }
+    }
+
+    // run again from wherever the clock was stopped
+    pub fn resume(&self) {
+        let mut state = self.state.write().unwrap();
+        if let Some(frozen) = state.frozen.take() {
+            state.offset = frozen - Utc::now();
+        }
+    }
+
+    // jump to a time; a frozen clock stays frozen there, a running one runs on from it
+    pub fn set(&self, time: DateTime<Utc>) {
+        let mut state = self.state.write().unwrap();
+        match &mut state.frozen {
+            Some(frozen) => *frozen = time,
+            None => state.offset = time - Utc::now(),
+        }
+    }
+
+    // the clock is left where it was if the move would take it out of range
+    pub fn advance(&self, by: TimeDelta) -> Result<(), OutOfRange> {
+        let mut state = self.state.write().unwrap();
+        match &mut state.frozen {
+            Some(frozen) => *frozen = frozen.checked_add_signed(by).ok_or(OutOfRange)?,
+            None => {
+                let offset = state.offset.checked_add(&by).ok_or(OutOfRange)?;
+                Utc::now().checked_add_signed(offset).ok_or(OutOfRange)?;
+                state.offset = offset;
+            }
+        }
+        Ok(())
+    }
+
+    // back to following the system clock
+    pub fn reset(&self) {
+        *self.state.write().unwrap() = ClockState::default();
+    }
+
+    pub fn status(&self) -> ClockStatus {
+        let now = self.now();
+        ClockStatus {
+            now,
+            frozen: self.state.read().unwrap().frozen.is_some(),
+            offset_ms: (now - Utc::now()).num_milliseconds(),
+        }
+    }
+}
@@ -1,18 +1,153 @@
+use chrono::{DateTime, Utc};
 use serde::{Deserialize, Serialize};
+use std::collections::HashMap;
+use std::fmt;
 use std::fs;
-use std::path::Path;
+use std::path::{Path, PathBuf};
+
+use crate::faults::FaultRule;
+use crate::storage;
+
+// environment variables layered over the file, in the order they're applied
+pub const ENV_HOST: &str = "SIX7_HOST";
+pub const ENV_PORT: &str = "SIX7_PORT";
+pub const ENV_STORAGE_PATH: &str = "SIX7_STORAGE_PATH";
+pub const ENV_BUCKETS: &str = "SIX7_BUCKETS";
+pub const ENV_ACCESS_KEY: &str = "SIX7_ACCESS_KEY";
+pub const ENV_SECRET_KEY: &str = "SIX7_SECRET_KEY";
+pub const ENV_REGION: &str = "SIX7_REGION";
+pub const ENV_DOMAINS: &str = "SIX7_DOMAINS";
+pub const ENV_ADMIN_TOKEN: &str = "SIX7_ADMIN_TOKEN";
+pub const ENV_SEED: &str = "SIX7_SEED";
 
 #[derive(Debug, Clone, Serialize, Deserialize)]
 pub struct Config {
     pub server: ServerConfig,
     pub storage: StorageConfig,
     pub buckets: Vec<BucketConfig>,
+    #[serde(default)]
+    pub admin: AdminConfig,
+    #[serde(default, skip_serializing_if = "Vec::is_empty")]
+    pub faults: Vec<FaultRule>,
+    #[serde(default)]
+    pub journal: JournalConfig,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub deterministic: Option<DeterministicConfig>,
+    #[serde(default)]
+    pub notifications: NotificationsConfig,
+    #[serde(default)]
+    pub metrics: MetricsConfig,
+    #[serde(default)]
+    pub access_logs: AccessLogConfig,
+    #[serde(default)]
+    pub cors: CorsConfig,
+}
+
+// generated ids from a fixed seed and a clock frozen at a fixed start, so the same
+// requests get byte-identical responses on every run
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct DeterministicConfig {
+    #[serde(default)]
+    pub seed: u64,
+    #[serde(default = "default_start")]
+    pub start: DateTime<Utc>,
+}
+
+// the /_six7/ admin api; off unless enabled, and then only with the bearer token
+#[derive(Debug, Clone, Default, Serialize, Deserialize)]
+pub struct AdminConfig {
+    #[serde(default)]
+    pub enabled: bool,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub token: Option<String>,
+}
+
+// a record of every s3 request and response, queryable through the admin api and
+// optionally appended to a jsonl file
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct JournalConfig {
+    #[serde(default)]
+    pub enabled: bool,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub path: Option<String>,
+    // sha-256 of request and response bodies
+    #[serde(default)]
+    pub body_hashes: bool,
+    // keep request bodies too, so the journal can be replayed exactly
+    #[serde(default)]
+    pub request_bodies: bool,
+    // entries kept in memory for the admin api; the file keeps everything
+    #[serde(default = "default_journal_limit")]
+    pub limit: usize,
+}
+
+// how bucket notifications are delivered. a destination arn in a bucket's notification
+// configuration is looked up in targets; an http(s) url in its place is posted to directly
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct NotificationsConfig {
+    #[serde(default, skip_serializing_if = "Vec::is_empty")]
+    pub targets: Vec<NotificationTarget>,
+    // attempts after the first one fails
+    #[serde(default = "default_notification_retries")]
+    pub retries: u32,
+    // wait before the first retry, doubled for each one after
+    #[serde(default = "default_notification_backoff_ms")]
+    pub backoff_ms: u64,
+    #[serde(default = "default_notification_timeout_ms")]
+    pub timeout_ms: u64,
+    // events that couldn't be delivered, appended as lines of json
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub dead_letter: Option<String>,
+}
+
+// prometheus metrics, scraped from path; it takes the place of a bucket with that name
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct MetricsConfig {
+    #[serde(default = "default_true")]
+    pub enabled: bool,
+    #[serde(default = "default_metrics_path")]
+    pub path: String,
+}
+
+// how often buffered access log records are written to their target buckets
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct AccessLogConfig {
+    #[serde(default = "default_flush_secs")]
+    pub flush_secs: u64,
+}
+
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct NotificationTarget {
+    pub arn: String,
+    pub url: String,
 }
 
 #[derive(Debug, Clone, Serialize, Deserialize)]
 pub struct ServerConfig {
     pub host: String,
     pub port: u16,
+    // base domains for virtual-hosted-style requests, e.g. bucket.s3.localhost
+    #[serde(default = "default_domains")]
+    pub domains: Vec<String>,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub tls: Option<TlsConfig>,
+    // how long requests in flight get to finish once shutdown starts
+    #[serde(default = "default_drain_secs")]
+    pub drain_secs: u64,
+}
+
+// an https listener next to the plain one; without cert and key a local ca issues one
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct TlsConfig {
+    #[serde(default = "default_tls_port")]
+    pub port: u16,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub cert: Option<String>,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub key: Option<String>,
+    // where the generated ca and certificate are kept between runs
+    #[serde(default = "default_tls_dir")]
+    pub dir: String,
 }
 
 #[derive(Debug, Clone, Serialize, Deserialize)]
@@ -23,19 +158,522 @@ pub struct StorageConfig {
 #[derive(Debug, Clone, Serialize, Deserialize)]
 pub struct BucketConfig {
     pub name: String,
+    #[serde(default = "default_access_key")]
     pub access_key: String,
+    #[serde(default = "default_secret_key")]
     pub secret_key: String,
+    #[serde(default = "default_region")]
     pub region: String,
 }
 
+pub const DEFAULT_ACCESS_KEY: &str = "minioadmin";
+pub const DEFAULT_SECRET_KEY: &str = "minioadmin";
+pub const DEFAULT_REGION: &str = "us-east-1";
+pub const DEFAULT_DOMAIN: &str = "localhost";
+pub const DEFAULT_TLS_PORT: u16 = 4443;
+pub const DEFAULT_TLS_DIR: &str = "./tls";
+pub const DEFAULT_JOURNAL_LIMIT: usize = 10_000;
+pub const DEFAULT_NOTIFICATION_RETRIES: u32 = 3;
+pub const DEFAULT_NOTIFICATION_BACKOFF_MS: u64 = 500;
+pub const DEFAULT_NOTIFICATION_TIMEOUT_MS: u64 = 5000;
+pub const DEFAULT_METRICS_PATH: &str = "/metrics";
+pub const DEFAULT_ACCESS_LOG_FLUSH_SECS: u64 = 60;
+pub const DEFAULT_DRAIN_SECS: u64 = 30;
+
+fn default_access_key() -> String {
+    DEFAULT_ACCESS_KEY.to_string()
+}
+
+fn default_secret_key() -> String {
+    DEFAULT_SECRET_KEY.to_string()
+}
+
+fn default_region() -> String {
+    DEFAULT_REGION.to_string()
+}
+
+fn default_domains() -> Vec<String> {
+    vec![DEFAULT_DOMAIN.to_string()]
+}
+
+fn default_tls_port() -> u16 {
+    DEFAULT_TLS_PORT
+}
+
+fn default_tls_dir() -> String {
+    DEFAULT_TLS_DIR.to_string()
+}
+
+// 2000-01-01T00:00:00Z
+pub fn default_start() -> DateTime<Utc> {
+    DateTime::from_timestamp(946_684_800, 0).unwrap()
+}
+
+impl DeterministicConfig {
+    pub fn new(seed: u64) -> Self {
+        DeterministicConfig {
+            seed,
+            start: default_start(),
+        }
+    }
+}
+
+fn default_journal_limit() -> usize {
+    DEFAULT_JOURNAL_LIMIT
+}
+
+impl Default for JournalConfig {
+    fn default() -> Self {
+        JournalConfig {
+            enabled: false,
+            path: None,
+            body_hashes: false,
+            request_bodies: false,
+            limit: default_journal_limit(),
+        }
+    }
+}
+
+fn default_notification_retries() -> u32 {
+    DEFAULT_NOTIFICATION_RETRIES
+}
+
+fn default_notification_backoff_ms() -> u64 {
+    DEFAULT_NOTIFICATION_BACKOFF_MS
+}
+
+fn default_notification_timeout_ms() -> u64 {
+    DEFAULT_NOTIFICATION_TIMEOUT_MS
+}
+
+impl Default for NotificationsConfig {
+    fn default() -> Self {
+        NotificationsConfig {
+            targets: Vec::new(),
+            retries: default_notification_retries(),
+            backoff_ms: default_notification_backoff_ms(),
+            timeout_ms: default_notification_timeout_ms(),
+            dead_letter: None,
+        }
+    }
+}
+
+// cross-origin requests browsers may make; "*" in a list allows anything
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct CorsConfig {
+    #[serde(default = "default_any")]
+    pub allowed_origins: Vec<String>,
+    #[serde(default = "default_any")]
+    pub allowed_methods: Vec<String>,
+    #[serde(default = "default_any")]
+    pub allowed_headers: Vec<String>,
+    #[serde(default, skip_serializing_if = "Vec::is_empty")]
+    pub expose_headers: Vec<String>,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub max_age_secs: Option<u64>,
+}
+
+fn default_any() -> Vec<String> {
+    vec!["*".to_string()]
+}
+
+impl Default for CorsConfig {
+    fn default() -> Self {
+        CorsConfig {
+            allowed_origins: default_any(),
+            allowed_methods: default_any(),
+            allowed_headers: default_any(),
+            expose_headers: Vec::new(),
+            max_age_secs: None,
+        }
+    }
+}
+
+fn default_true() -> bool {
+    true
+}
+
+fn default_metrics_path() -> String {
+    DEFAULT_METRICS_PATH.to_string()
+}
+
+impl Default for MetricsConfig {
+    fn default() -> Self {
+        MetricsConfig {
+            enabled: true,
+            path: default_metrics_path(),
+        }
+    }
+}
+
+fn default_drain_secs() -> u64 {
+    DEFAULT_DRAIN_SECS
+}
+
+fn default_flush_secs() -> u64 {
+    DEFAULT_ACCESS_LOG_FLUSH_SECS
+}
+
+impl Default for AccessLogConfig {
+    fn default() -> Self {
+        AccessLogConfig {
+            flush_secs: default_flush_secs(),
+        }
+    }
+}
+
+impl NotificationsConfig {
+    pub fn target(&self, arn: &str) -> Option<&NotificationTarget> {
+        self.targets.iter().find(|t| t.arn == arn)
+    }
+}
+
+impl CorsConfig {
+    // what Access-Control-Allow-Origin answers a request from origin with, if it's allowed
+    pub fn allow_origin<'a>(&self, origin: &'a str) -> Option<&'a str> {
+        if self.allowed_origins.iter().any(|o| o == "*") {
+            Some("*")
+        } else if self.allowed_origins.iter().any(|o| o == origin) {
+            Some(origin)
+        } else {
+            None
+        }
+    }
+
+    pub fn allows_method(&self, method: &str) -> bool {
+        self.allowed_methods.iter().any(|m| m == "*" || m.eq_ignore_ascii_case(method))
+    }
+}
+
+impl Default for TlsConfig {
+    fn default() -> Self {
+        TlsConfig {
+            port: default_tls_port(),
+            cert: None,
+            key: None,
+            dir: default_tls_dir(),
+        }
+    }
+}
+
+impl Default for Config {
+    fn default() -> Self {
+        Config {
+            server: ServerConfig {
+                host: "127.0.0.1".to_string(),
+                port: 4040,
+                domains: default_domains(),
+                tls: None,
+                drain_secs: default_drain_secs(),
+            },
+            storage: StorageConfig {
+                path: "./data".to_string(),
+            },
+            buckets: Vec::new(),
+            admin: AdminConfig::default(),
+            faults: Vec::new(),
+            journal: JournalConfig::default(),
+            deterministic: None,
+            notifications: NotificationsConfig::default(),
+            metrics: MetricsConfig::default(),
+            access_logs: AccessLogConfig::default(),
+            cors: CorsConfig::default(),
+        }
+    }
+}
+
+impl BucketConfig {
+    pub fn new(name: impl Into<String>) -> Self {
+        BucketConfig {
+            name: name.into(),
+            access_key: default_access_key(),
+            secret_key: default_secret_key(),
+            region: default_region(),
+        }
+    }
+
+    // "name" or "name:key:secret", as taken by --bucket and SIX7_BUCKETS
+    pub fn parse(spec: &str) -> Result<Self, String> {
+        let parts: Vec<&str> = spec.split(':').collect();
+        match parts.as_slice() {
+            [name] if !name.is_empty() => Ok(BucketConfig::new(*name)),
+            [name, key, secret] if !name.is_empty() && !key.is_empty() && !secret.is_empty() => {
+                Ok(BucketConfig {
+                    access_key: key.to_string(),
+                    secret_key: secret.to_string(),
+                    ..BucketConfig::new(*name)
+                })
+            }
+            _ => Err(format!("expected NAME or NAME:KEY:SECRET, got '{}'", spec)),
+        }
+    }
+}
+
+#[derive(Debug)]
+pub enum ConfigError {
+    Read { path: PathBuf, source: std::io::Error },
+    Parse(serde_yaml::Error),
+    Interpolation { line: usize, message: String },
+    Env { var: &'static str, message: String },
+    Invalid { field: String, message: String },
+}
+
+impl fmt::Display for ConfigError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            ConfigError::Read { path, source } => {
+                write!(f, "could not read config {}: {}", path.display(), source)
+            }
+            ConfigError::Parse(e) => write!(f, "invalid config: {}", e),
+            ConfigError::Interpolation { line, message } => {
+                write!(f, "invalid config: line {}: {}", line, message)
+            }
+            ConfigError::Env { var, message } => write!(f, "invalid {}: {}", var, message),
+            ConfigError::Invalid { field, message } => {
+                write!(f, "invalid config: {}: {}", field, message)
+            }
+        }
+    }
+}
+
+impl std::error::Error for ConfigError {
+    fn source(&self) -> Option<&(dyn std::error::Error + 'static)> {
+        match self {
+            ConfigError::Read { source, .. } => Some(source),
+            ConfigError::Parse(e) => Some(e),
+            _ => None,
+        }
+    }
+}
+
+fn invalid(field: impl Into<String>, message: impl Into<String>) -> ConfigError {
+    ConfigError::Invalid {
+        field: field.into(),
+        message: message.into(),
+    }
+}
+
 impl Config {
-    pub fn load<P: AsRef<Path>>(path: P) -> Result<Self, Box<dyn std::error::Error>> {
-        let content = fs::read_to_string(path)?;
-        let config: Config = serde_yaml::from_str(&content)?;
+    // read the file, expand ${VAR}, layer SIX7_* variables on top and validate
+    pub fn load<P: AsRef<Path>>(path: P) -> Result<Self, ConfigError> {
+        Self::load_with_env(path, |name| std::env::var(name).ok())
+    }
+
+    pub fn load_with_env<P, F>(path: P, env: F) -> Result<Self, ConfigError>
+    where
+        P: AsRef<Path>,
+        F: Fn(&str) -> Option<String>,
+    {
+        let path = path.as_ref();
+        let content = fs::read_to_string(path).map_err(|source| ConfigError::Read {
+            path: path.to_path_buf(),
+            source,
+        })?;
+        Self::parse_with_env(&content, env)
+    }
+
+    pub fn parse_with_env<F>(content: &str, env: F) -> Result<Self, ConfigError>
+    where
+        F: Fn(&str) -> Option<String>,
+    {
+        let expanded = interpolate(content, &env)?;
+        let mut config: Config = serde_yaml::from_str(&expanded).map_err(ConfigError::Parse)?;
+        config.apply_env(&env)?;
+        config.validate()?;
         Ok(config)
     }
 
-    #[allow(dead_code)]
+    // built-in defaults with SIX7_* variables applied, for running without a file
+    pub fn from_env() -> Result<Self, ConfigError> {
+        let mut config = Config::default();
+        config.apply_env(&|name: &str| std::env::var(name).ok())?;
+        config.validate()?;
+        Ok(config)
+    }
+
+    pub fn apply_env<F>(&mut self, env: &F) -> Result<(), ConfigError>
+    where
+        F: Fn(&str) -> Option<String>,
+    {
+        if let Some(host) = env(ENV_HOST) {
+            self.server.host = host;
+        }
+        if let Some(port) = env(ENV_PORT) {
+            self.server.port = parse_port(&port).map_err(|message| ConfigError::Env {
+                var: ENV_PORT,
+                message,
+            })?;
+        }
+        if let Some(path) = env(ENV_STORAGE_PATH) {
+            self.storage.path = path;
+        }
+        if let Some(domains) = env(ENV_DOMAINS) {
+            self.server.domains = split_list(&domains);
+        }
+        // setting a token is enough to turn the admin api on
+        if let Some(token) = env(ENV_ADMIN_TOKEN) {
+            self.admin.enabled = true;
+            self.admin.token = Some(token);
+        }
+
+        if let Some(seed) = env(ENV_SEED) {
+            let seed = seed.trim().parse::<u64>().map_err(|_| ConfigError::Env {
+                var: ENV_SEED,
+                message: format!("'{}' is not a number", seed),
+            })?;
+            self.deterministic
+                .get_or_insert_with(|| DeterministicConfig::new(seed))
+                .seed = seed;
+        }
+
+        if let Some(buckets) = env(ENV_BUCKETS) {
+            for spec in split_list(&buckets) {
+                let bucket = BucketConfig::parse(&spec).map_err(|message| ConfigError::Env {
+                    var: ENV_BUCKETS,
+                    message,
+                })?;
+                self.buckets.retain(|b| b.name != bucket.name);
+                self.buckets.push(bucket);
+            }
+        }
+
+        // credential and region overrides apply to every bucket
+        for bucket in &mut self.buckets {
+            if let Some(key) = env(ENV_ACCESS_KEY) {
+                bucket.access_key = key;
+            }
+            if let Some(secret) = env(ENV_SECRET_KEY) {
+                bucket.secret_key = secret;
+            }
+            if let Some(region) = env(ENV_REGION) {
+                bucket.region = region;
+            }
+        }
+
+        Ok(())
+    }
+
+    pub fn validate(&self) -> Result<(), ConfigError> {
+        if self.server.host.trim().is_empty() {
+            return Err(invalid("server.host", "must not be empty"));
+        }
+        if self.server.port == 0 {
+            return Err(invalid("server.port", "must be between 1 and 65535"));
+        }
+        for (i, domain) in self.server.domains.iter().enumerate() {
+            if !valid_domain(domain) {
+                return Err(invalid(
+                    format!("server.domains[{}]", i),
+                    format!("'{}' is not a domain name like s3.localhost", domain),
+                ));
+            }
+        }
+        if let Some(tls) = &self.server.tls {
+            if tls.port == 0 {
+                return Err(invalid("server.tls.port", "must be between 1 and 65535"));
+            }
+            if tls.port == self.server.port {
+                return Err(invalid("server.tls.port", "must differ from server.port"));
+            }
+            match (&tls.cert, &tls.key) {
+                (Some(_), None) => return Err(invalid("server.tls.key", "required when cert is set")),
+                (None, Some(_)) => return Err(invalid("server.tls.cert", "required when key is set")),
+                (None, None) if tls.dir.trim().is_empty() => {
+                    return Err(invalid("server.tls.dir", "must not be empty"));
+                }
+                _ => {}
+            }
+        }
+        if self.storage.path.trim().is_empty() {
+            return Err(invalid("storage.path", "must not be empty"));
+        }
+        if self.admin.enabled && self.admin.token.as_deref().is_none_or(|t| t.trim().is_empty()) {
+            return Err(invalid("admin.token", "required when the admin api is enabled"));
+        }
+
+        if self.journal.path.as_deref().is_some_and(|p| p.trim().is_empty()) {
+            return Err(invalid("journal.path", "must not be empty"));
+        }
+
+        let mut arns = HashMap::new();
+        for (i, target) in self.notifications.targets.iter().enumerate() {
+            let field = |name: &str| format!("notifications.targets[{}].{}", i, name);
+            if !target.arn.starts_with("arn:") {
+                return Err(invalid(field("arn"), format!("'{}' is not an arn", target.arn)));
+            }
+            if let Some(first) = arns.insert(target.arn.as_str(), i) {
+                return Err(invalid(
+                    field("arn"),
+                    format!("'{}' is already defined at notifications.targets[{}]", target.arn, first),
+                ));
+            }
+            if !is_http_url(&target.url) {
+                return Err(invalid(field("url"), format!("'{}' is not an http or https url", target.url)));
+            }
+        }
+        if self.notifications.timeout_ms == 0 {
+            return Err(invalid("notifications.timeout_ms", "must be at least 1"));
+        }
+        if self.notifications.dead_letter.as_deref().is_some_and(|p| p.trim().is_empty()) {
+            return Err(invalid("notifications.dead_letter", "must not be empty"));
+        }
+
+        if !self.metrics.path.starts_with('/') || self.metrics.path == "/" {
+            return Err(invalid("metrics.path", format!("'{}' is not a path like /metrics", self.metrics.path)));
+        }
+        if self.access_logs.flush_secs == 0 {
+            return Err(invalid("access_logs.flush_secs", "must be at least 1"));
+        }
+
+        for (i, rule) in self.faults.iter().enumerate() {
+            rule.validate().map_err(|message| invalid(format!("faults[{}]", i), message))?;
+        }
+
+        let mut names = HashMap::new();
+        let mut secrets: HashMap<&str, &str> = HashMap::new();
+        for (i, bucket) in self.buckets.iter().enumerate() {
+            let field = |name: &str| format!("buckets[{}].{}", i, name);
+
+            if let Err(e) = storage::validate_bucket_name(&bucket.name) {
+                return Err(invalid(field("name"), format!("{} ({})", e.message, e.code)));
+            }
+            if let Some(first) = names.insert(bucket.name.as_str(), i) {
+                return Err(invalid(
+                    field("name"),
+                    format!("bucket '{}' is already defined at buckets[{}]", bucket.name, first),
+                ));
+            }
+
+            if bucket.access_key.is_empty() {
+                return Err(invalid(field("access_key"), "must not be empty"));
+            }
+            if bucket.secret_key.is_empty() {
+                return Err(invalid(field("secret_key"), "must not be empty"));
+            }
+            // one access key can't resolve to two different secrets
+            if let Some(secret) = secrets.insert(&bucket.access_key, &bucket.secret_key) {
+                if secret != bucket.secret_key {
+                    return Err(invalid(
+                        field("access_key"),
+                        format!(
+                            "access key '{}' is used by another bucket with a different secret",
+                            bucket.access_key
+                        ),
+                    ));
+                }
+            }
+
+            if !valid_region(&bucket.region) {
+                return Err(invalid(
+                    field("region"),
+                    format!("'{}' is not a region name like us-east-1", bucket.region),
+                ));
+            }
+        }
+
+        Ok(())
+    }
+
     pub fn get_bucket(&self, name: &str) -> Option<&BucketConfig> {
         self.buckets.iter().find(|b| b.name == name)
     }
@@ -45,3 +683,99 @@ impl Config {
         self.buckets.iter().find(|b| b.access_key == access_key)
     }
 }
+
+fn parse_port(value: &str) -> Result<u16, String> {
+    match value.trim().parse::<u32>() {
+        Ok(port) if (1..=65535).contains(&port) => Ok(port as u16),
+        _ => Err(format!("'{}' is not a port between 1 and 65535", value)),
+    }
+}
+
+// "a, b,,c" -> ["a", "b", "c"]
+fn split_list(value: &str) -> Vec<String> {
+    value
+        .split(',')
+        .map(str::trim)
+        .filter(|s| !s.is_empty())
+        .map(str::to_string)
+        .collect()
+}
+
+// a bare host name, optionally written as a wildcard: s3.localhost or *.s3.localhost
+fn valid_domain(domain: &str) -> bool {
+    let domain = domain.strip_prefix("*.").unwrap_or(domain);
+    !domain.is_empty()
+        && domain.split('.').all(|label| {
+            !label.is_empty()
+                && label
+                    .bytes()
+                    .all(|b| b.is_ascii_lowercase() || b.is_ascii_digit() || b == b'-')
+        })
+}
+
+pub fn is_http_url(value: &str) -> bool {
+    value
+        .strip_prefix("http://")
+        .or_else(|| value.strip_prefix("https://"))
+        .is_some_and(|rest| !rest.is_empty())
+}
+
+// region names look like us-east-1, eu-central-2 or us-gov-west-1
+fn valid_region(region: &str) -> bool {
+    let parts: Vec<&str> = region.split('-').collect();
+    if parts.len() < 3 {
+        return false;
+    }
+
+    let (number, words) = parts.split_last().unwrap();
+    !number.is_empty()
+        && number.bytes().all(|b| b.is_ascii_digit())
+        && words
+            .iter()
+            .all(|w| !w.is_empty() && w.bytes().all(|b| b.is_ascii_lowercase()))
+}
+
+// replace ${VAR} and ${VAR:-default} before the yaml is parsed
+fn interpolate<F>(content: &str, env: &F) -> Result<String, ConfigError>
+where
+    F: Fn(&str) -> Option<String>,
+{
+    let mut output = String::with_capacity(content.len());
+
+    for (i, line) in content.split_inclusive('\n').enumerate() {
+        let error = |message: String| ConfigError::Interpolation { line: i + 1, message };
+
+        // comments are left alone so a commented-out ${VAR} doesn't have to be set
+        if line.trim_start().starts_with('#') {
+            output.push_str(line);
+            continue;
+        }
+
+        let mut rest = line;
+        while let Some(start) = rest.find("${") {
+            output.push_str(&rest[..start]);
+            let after = &rest[start + 2..];
+            let end = after
+                .find('}')
+                .ok_or_else(|| error("unterminated ${".to_string()))?;
+
+            let expr = &after[..end];
+            let (name, default) = match expr.split_once(":-") {
+                Some((name, default)) => (name, Some(default)),
+                None => (expr, None),
+            };
+            if name.is_empty() || !name.bytes().all(|b| b.is_ascii_alphanumeric() || b == b'_') {
+                return Err(error(format!("invalid variable name '{}'", name)));
+            }
+
+            match env(name).or_else(|| default.map(str::to_string)) {
+                Some(value) => output.push_str(&value),
+                None => return Err(error(format!("environment variable {} is not set", name))),
+            }
+            rest = &after[end + 1..];
+        }
+        output.push_str(rest);
+    }
+
+    Ok(output)
+}
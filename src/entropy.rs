@@ -0,0 +1,58 @@
+use rand::rngs::StdRng;
+use rand::{Rng, SeedableRng};
+use std::sync::{Arc, Mutex};
+use uuid::Uuid;
+
+// where generated ids and random choices come from. normally seeded by the os; in
+// deterministic mode from a fixed seed, so the same requests get the same ids on every
+// run. clones share one generator
+#[derive(Debug, Clone)]
+pub struct Entropy {
+    rng: Arc<Mutex<StdRng>>,
+}
+
+impl Default for Entropy {
+    fn default() -> Self {
+        Entropy {
+            rng: Arc::new(Mutex::new(StdRng::from_entropy())),
+        }
+    }
+}
+
+impl Entropy {
+    pub fn new() -> Self {
+        Entropy::default()
+    }
+
+    pub fn seeded(seed: u64) -> Self {
+        Entropy {
+            rng: Arc::new(Mutex::new(StdRng::seed_from_u64(seed))),
+        }
+    }
+
+    // a version 4 uuid, drawn from this generator rather than the os
+    pub fn uuid(&self) -> Uuid {
+        let mut bytes = [0u8; 16];
+        self.rng.lock().unwrap().fill(&mut bytes);
+        uuid::Builder::from_random_bytes(bytes).into_uuid()
+    }
+
+    // uppercase hex, like s3's request ids
+    pub fn hex_id(&self, len: usize) -> String {
+        let mut rng = self.rng.lock().unwrap();
+        (0..len)
+            .map(|_| char::from_digit(rng.gen_range(0..16), 16).unwrap().to_ascii_uppercase())
+            .collect()
+    }
+
+    pub fn bytes(&self, len: usize) -> Vec<u8> {
+        let mut bytes = vec![0u8; len];
+        self.rng.lock().unwrap().fill(&mut bytes[..]);
+        bytes
+    }
+
+    // true with probability p
+    pub fn chance(&self, p: f64) -> bool {
+        self.rng.lock().unwrap().gen::<f64>() < p
+    }
+}
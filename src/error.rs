@@ -0,0 +1,206 @@
+use axum::{
+    body::Body,
+    http::StatusCode,
+    response::{IntoResponse, Response},
+};
+use std::fmt;
+use std::io;
+
+// an s3 error code plus the http status it is returned with
+#[derive(Debug, Clone)]
+pub struct S3Error {
+    pub status: StatusCode,
+    pub code: &'static str,
+    pub message: String,
+}
+
+impl S3Error {
+    pub fn new(status: StatusCode, code: &'static str, message: impl Into<String>) -> Self {
+        S3Error {
+            status,
+            code,
+            message: message.into(),
+        }
+    }
+
+    pub fn invalid_bucket_name(bucket: &str) -> Self {
+        Self::new(
+            StatusCode::BAD_REQUEST,
+            "InvalidBucketName",
+            format!("The specified bucket is not valid: {}", bucket),
+        )
+    }
+
+    pub fn invalid_argument(message: impl Into<String>) -> Self {
+        Self::new(StatusCode::BAD_REQUEST, "InvalidArgument", message)
+    }
+
+    pub fn malformed_xml() -> Self {
+        Self::new(
+            StatusCode::BAD_REQUEST,
+            "MalformedXML",
+            "The XML you provided was not well-formed or did not validate against our published schema.",
+        )
+    }
+
+    pub fn invalid_part() -> Self {
+        Self::new(
+            StatusCode::BAD_REQUEST,
+            "InvalidPart",
+            "One or more of the specified parts could not be found.",
+        )
+    }
+
+    pub fn invalid_part_order() -> Self {
+        Self::new(
+            StatusCode::BAD_REQUEST,
+            "InvalidPartOrder",
+            "The list of parts was not in ascending order.",
+        )
+    }
+
+    pub fn key_too_long() -> Self {
+        Self::new(
+            StatusCode::BAD_REQUEST,
+            "KeyTooLongError",
+            "Your key is too long",
+        )
+    }
+
+    pub fn no_such_bucket() -> Self {
+        Self::new(
+            StatusCode::NOT_FOUND,
+            "NoSuchBucket",
+            "The specified bucket does not exist",
+        )
+    }
+
+    pub fn invalid_target_bucket_for_logging() -> Self {
+        Self::new(
+            StatusCode::BAD_REQUEST,
+            "InvalidTargetBucketForLogging",
+            "The target bucket for logging does not exist",
+        )
+    }
+
+    pub fn bucket_not_empty() -> Self {
+        Self::new(
+            StatusCode::CONFLICT,
+            "BucketNotEmpty",
+            "The bucket you tried to delete is not empty",
+        )
+    }
+
+    pub fn no_such_upload() -> Self {
+        Self::new(
+            StatusCode::NOT_FOUND,
+            "NoSuchUpload",
+            "The specified multipart upload does not exist.",
+        )
+    }
+
+    pub fn no_such_key() -> Self {
+        Self::new(
+            StatusCode::NOT_FOUND,
+            "NoSuchKey",
+            "The specified key does not exist.",
+        )
+    }
+
+    pub fn no_such_snapshot() -> Self {
+        Self::new(
+            StatusCode::NOT_FOUND,
+            "NoSuchSnapshot",
+            "The specified snapshot does not exist.",
+        )
+    }
+
+    pub fn authorization_query_parameters_error(message: impl Into<String>) -> Self {
+        Self::new(StatusCode::BAD_REQUEST, "AuthorizationQueryParametersError", message)
+    }
+
+    pub fn request_expired() -> Self {
+        Self::new(StatusCode::FORBIDDEN, "AccessDenied", "Request has expired")
+    }
+
+    pub fn not_implemented(message: impl Into<String>) -> Self {
+        Self::new(StatusCode::NOT_IMPLEMENTED, "NotImplemented", message)
+    }
+
+    pub fn slow_down() -> Self {
+        Self::new(
+            StatusCode::SERVICE_UNAVAILABLE,
+            "SlowDown",
+            "Please reduce your request rate.",
+        )
+    }
+
+    pub fn service_unavailable() -> Self {
+        Self::new(
+            StatusCode::SERVICE_UNAVAILABLE,
+            "ServiceUnavailable",
+            "Service is unable to handle request.",
+        )
+    }
+
+    pub fn internal_error() -> Self {
+        Self::new(
+            StatusCode::INTERNAL_SERVER_ERROR,
+            "InternalError",
+            "We encountered an internal error. Please try again.",
+        )
+    }
+
+    // wrap as an io error so storage can keep returning std::io::Result
+    pub fn into_io(self) -> io::Error {
+        io::Error::new(io::ErrorKind::InvalidInput, self)
+    }
+
+    pub fn to_xml(&self) -> String {
+        format!(
+            r#"<?xml version="1.0" encoding="UTF-8"?>
+<Error>
+    <Code>{}</Code>
+    <Message>{}</Message>
+</Error>"#,
+            self.code,
+            crate::xml::escape(&self.message)
+        )
+    }
+}
+
+impl fmt::Display for S3Error {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "{}: {}", self.code, self.message)
+    }
+}
+
+impl std::error::Error for S3Error {}
+
+impl From<io::Error> for S3Error {
+    fn from(err: io::Error) -> Self {
+        if let Some(inner) = err.get_ref().and_then(|e| e.downcast_ref::<S3Error>()) {
+            return inner.clone();
+        }
+
+        match err.kind() {
+            io::ErrorKind::NotFound => S3Error::no_such_key(),
+            _ => S3Error::internal_error(),
+        }
+    }
+}
+
+// the code an error response was for, for middleware that logs it
+#[derive(Debug, Clone, Copy)]
+pub struct ErrorCode(pub &'static str);
+
+impl IntoResponse for S3Error {
+    fn into_response(self) -> Response {
+        Response::builder()
+            .status(self.status)
+            .header("Content-Type", "application/xml")
+            .extension(ErrorCode(self.code))
+            .body(Body::from(self.to_xml()))
+            .unwrap()
+    }
+}
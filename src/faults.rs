@@ -0,0 +1,355 @@
+use axum::{
+    body::{Body, Bytes},
+    extract::{Request, State},
+    http::header,
+    middleware::Next,
+    response::{IntoResponse, Response},
+};
+use futures::stream::{self, StreamExt};
+use serde::{Deserialize, Serialize};
+use std::io;
+use std::sync::atomic::{AtomicU64, Ordering};
+use std::sync::{Arc, RwLock};
+use std::time::Duration;
+
+use crate::entropy::Entropy;
+use crate::error::S3Error;
+use crate::handlers::AppState;
+use crate::operation::{self, Operation};
+
+// which requests a fault applies to and how often; unset filters match everything, and
+// without probability or every it fires on every matching request
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct FaultRule {
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub bucket: Option<String>,
+    // glob over the object key: '*' is any run of characters including '/', '?' is one
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub key: Option<String>,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub operation: Option<String>,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub probability: Option<f64>,
+    // fire on every nth matching request
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub every: Option<u64>,
+    // stop after firing this many times
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub times: Option<u64>,
+    #[serde(flatten)]
+    pub fault: Fault,
+}
+
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+#[serde(tag = "fault", rename_all = "snake_case")]
+pub enum Fault {
+    // answer with an s3 error instead of running the request
+    Error {
+        #[serde(default = "default_error_code")]
+        code: String,
+    },
+    // wait before running the request
+    Latency { delay_ms: u64 },
+    // send the response body at this rate
+    Throttle { bytes_per_sec: u64 },
+    // drop the connection without a usable response; the request isn't run
+    Reset,
+    // send only the first bytes of the body, then drop the connection
+    Truncate {
+        #[serde(default)]
+        bytes: u64,
+    },
+    // dribble the body out a few bytes at a time
+    SlowLoris {
+        #[serde(default = "default_chunk_bytes")]
+        chunk_bytes: u64,
+        interval_ms: u64,
+    },
+}
+
+impl Fault {
+    // its name in rules and metrics
+    pub fn kind(&self) -> &'static str {
+        match self {
+            Fault::Error { .. } => "error",
+            Fault::Latency { .. } => "latency",
+            Fault::Throttle { .. } => "throttle",
+            Fault::Reset => "reset",
+            Fault::Truncate { .. } => "truncate",
+            Fault::SlowLoris { .. } => "slow_loris",
+        }
+    }
+}
+
+pub const ERROR_CODES: &[&str] = &["InternalError", "SlowDown", "ServiceUnavailable"];
+
+fn default_error_code() -> String {
+    "InternalError".to_string()
+}
+
+fn default_chunk_bytes() -> u64 {
+    1
+}
+
+impl FaultRule {
+    pub fn validate(&self) -> Result<(), String> {
+        if let Some(operation) = &self.operation {
+            if !operation::OPERATIONS.contains(&operation.as_str()) {
+                return Err(format!("unknown operation '{}'", operation));
+            }
+        }
+        if self.probability.is_some() && self.every.is_some() {
+            return Err("set probability or every, not both".to_string());
+        }
+        if let Some(p) = self.probability {
+            if !(p > 0.0 && p <= 1.0) {
+                return Err(format!("probability must be in (0, 1], got {}", p));
+            }
+        }
+        if self.every == Some(0) {
+            return Err("every must be at least 1".to_string());
+        }
+
+        match &self.fault {
+            Fault::Error { code } if !ERROR_CODES.contains(&code.as_str()) => {
+                Err(format!("error code must be one of {}, got '{}'", ERROR_CODES.join(", "), code))
+            }
+            Fault::Throttle { bytes_per_sec: 0 } => Err("bytes_per_sec must be at least 1".to_string()),
+            Fault::SlowLoris { chunk_bytes: 0, .. } => Err("chunk_bytes must be at least 1".to_string()),
+            _ => Ok(()),
+        }
+    }
+
+    fn matches(&self, op: &Operation) -> bool {
+        let bucket = self.bucket.as_ref().is_none_or(|b| op.bucket.as_ref() == Some(b));
+        let operation = self.operation.as_deref().is_none_or(|o| o == op.name);
+        let key = match (&self.key, &op.key) {
+            (None, _) => true,
+            (Some(pattern), Some(key)) => glob_match(pattern, key),
+            (Some(_), None) => false,
+        };
+        bucket && operation && key
+    }
+}
+
+struct ActiveRule {
+    id: u64,
+    rule: FaultRule,
+    matched: AtomicU64,
+    fired: AtomicU64,
+}
+
+#[derive(Debug, Clone, Serialize)]
+pub struct RuleStatus {
+    pub id: u64,
+    #[serde(flatten)]
+    pub rule: FaultRule,
+    pub matched: u64,
+    pub fired: u64,
+}
+
+// the rules in effect, checked in order; the first one that fires wins
+#[derive(Default)]
+pub struct FaultInjector {
+    rules: RwLock<Vec<Arc<ActiveRule>>>,
+    next_id: AtomicU64,
+    injected: AtomicU64,
+}
+
+impl FaultInjector {
+    pub fn new(rules: Vec<FaultRule>) -> Self {
+        let injector = FaultInjector::default();
+        injector.replace(rules);
+        injector
+    }
+
+    pub fn add(&self, rule: FaultRule) -> u64 {
+        let id = self.next_id.fetch_add(1, Ordering::Relaxed) + 1;
+        self.rules.write().unwrap().push(Arc::new(ActiveRule {
+            id,
+            rule,
+            matched: AtomicU64::new(0),
+            fired: AtomicU64::new(0),
+        }));
+        id
+    }
+
+    pub fn remove(&self, id: u64) -> bool {
+        let mut rules = self.rules.write().unwrap();
+        let before = rules.len();
+        rules.retain(|r| r.id != id);
+        rules.len() != before
+    }
+
+    pub fn clear(&self) {
+        self.rules.write().unwrap().clear();
+    }
+
+    pub fn replace(&self, rules: Vec<FaultRule>) {
+        self.clear();
+        for rule in rules {
+            self.add(rule);
+        }
+    }
+
+    pub fn list(&self) -> Vec<RuleStatus> {
+        self.rules
+            .read()
+            .unwrap()
+            .iter()
+            .map(|r| RuleStatus {
+                id: r.id,
+                rule: r.rule.clone(),
+                matched: r.matched.load(Ordering::Relaxed),
+                fired: r.fired.load(Ordering::Relaxed),
+            })
+            .collect()
+    }
+
+    // faults injected since start, across every rule ever added
+    pub fn injected(&self) -> u64 {
+        self.injected.load(Ordering::Relaxed)
+    }
+
+    pub fn pick(&self, op: &Operation, entropy: &Entropy) -> Option<Fault> {
+        let rules = self.rules.read().unwrap().clone();
+        for active in rules {
+            if !active.rule.matches(op) {
+                continue;
+            }
+
+            let n = active.matched.fetch_add(1, Ordering::Relaxed) + 1;
+            if active
+                .rule
+                .times
+                .is_some_and(|times| active.fired.load(Ordering::Relaxed) >= times)
+            {
+                continue;
+            }
+            let fires = match (active.rule.every, active.rule.probability) {
+                (Some(every), _) => n % every == 0,
+                (None, Some(p)) => entropy.chance(p),
+                (None, None) => true,
+            };
+            if fires {
+                active.fired.fetch_add(1, Ordering::Relaxed);
+                self.injected.fetch_add(1, Ordering::Relaxed);
+                return Some(active.rule.fault.clone());
+            }
+        }
+        None
+    }
+}
+
+// runs ahead of the handlers; admin requests are never faulted
+pub async fn inject(State(state): State<Arc<AppState>>, request: Request, next: Next) -> Response {
+    let Some(op) = operation::classify(request.method(), request.uri(), request.headers()) else {
+        return next.run(request).await;
+    };
+    let Some(fault) = state.faults.pick(&op, state.entropy()) else {
+        return next.run(request).await;
+    };
+    tracing::debug!("injecting {:?} into {}", fault, op.name);
+    state.metrics.fault_injected(op.name, fault.kind());
+
+    match fault {
+        Fault::Error { code } => error(&code).into_response(),
+        Fault::Latency { delay_ms } => {
+            tokio::time::sleep(Duration::from_millis(delay_ms)).await;
+            next.run(request).await
+        }
+        Fault::Reset => broken(Vec::new()),
+        Fault::Truncate { bytes } => {
+            let (response, body) = buffered(next.run(request).await).await;
+            let kept = body.slice(..(bytes as usize).min(body.len()));
+            with_body(response, body.len(), broken(vec![kept]).into_body())
+        }
+        Fault::Throttle { bytes_per_sec } => {
+            // ten chunks a second reads as a steady rate without a timer per byte
+            let chunk = (bytes_per_sec / 10).max(1);
+            let (response, body) = buffered(next.run(request).await).await;
+            with_body(response, body.len(), trickle(body, chunk, 100))
+        }
+        Fault::SlowLoris { chunk_bytes, interval_ms } => {
+            let (response, body) = buffered(next.run(request).await).await;
+            with_body(response, body.len(), trickle(body, chunk_bytes, interval_ms))
+        }
+    }
+}
+
+fn error(code: &str) -> S3Error {
+    match code {
+        "SlowDown" => S3Error::slow_down(),
+        "ServiceUnavailable" => S3Error::service_unavailable(),
+        _ => S3Error::internal_error(),
+    }
+}
+
+async fn buffered(response: Response) -> (Response, Bytes) {
+    let (parts, body) = response.into_parts();
+    let bytes = axum::body::to_bytes(body, usize::MAX).await.unwrap_or_default();
+    (Response::from_parts(parts, Body::empty()), bytes)
+}
+
+// keep the real content length, so a client can tell the body came up short
+fn with_body(response: Response, len: usize, body: Body) -> Response {
+    let (mut parts, _) = response.into_parts();
+    parts.headers.insert(header::CONTENT_LENGTH, len.into());
+    Response::from_parts(parts, body)
+}
+
+// a body that sends the given chunks and then fails, which makes the server drop the
+// connection mid-response
+fn broken(chunks: Vec<Bytes>) -> Response {
+    let chunks = stream::iter(chunks.into_iter().map(Ok));
+    let failure = stream::once(async {
+        Err::<Bytes, io::Error>(io::Error::new(io::ErrorKind::ConnectionReset, "injected fault"))
+    });
+    Response::new(Body::from_stream(chunks.chain(failure)))
+}
+
+fn trickle(body: Bytes, chunk: u64, interval_ms: u64) -> Body {
+    let chunks: Vec<Bytes> = body
+        .chunks(chunk as usize)
+        .map(|c| body.slice_ref(c))
+        .collect();
+    let stream = stream::iter(chunks.into_iter().enumerate()).then(move |(i, chunk)| async move {
+        if i > 0 {
+            tokio::time::sleep(Duration::from_millis(interval_ms)).await;
+        }
+        Ok::<_, io::Error>(chunk)
+    });
+    Body::from_stream(stream)
+}
+
+// '*' matches any run of characters (including '/'), '?' exactly one
+pub fn glob_match(pattern: &str, text: &str) -> bool {
+    let pattern: Vec<char> = pattern.chars().collect();
+    let text: Vec<char> = text.chars().collect();
+    let (mut p, mut t) = (0, 0);
+    let mut backtrack: Option<(usize, usize)> = None;
+
+    while t < text.len() {
+        match pattern.get(p) {
+            Some('*') => {
+                backtrack = Some((p, t));
+                p += 1;
+            }
+            Some(&c) if c == '?' || c == text[t] => {
+                p += 1;
+                t += 1;
+            }
+            _ => match backtrack {
+                // let the last '*' swallow one more character and try again
+                Some((star, matched)) => {
+                    p = star + 1;
+                    t = matched + 1;
+                    backtrack = Some((star, matched + 1));
+                }
+                None => return false,
+            },
+        }
+    }
+
+    pattern[p..].iter().all(|&c| c == '*')
+}
@@ -1,17 +1,83 @@
 use axum::{
     body::Body,
-    extract::{Path, RawQuery, State},
+    extract::{Extension, Path, RawQuery, State},
     http::{HeaderMap, StatusCode},
     response::{IntoResponse, Response},
 };
 use bytes::Bytes;
 use serde::Deserialize;
-use std::sync::Arc;
+use std::sync::{Arc, RwLock};
 
-use crate::storage::Storage;
+use crate::access_log::{self, AccessLogs, LoggingConfiguration};
+use crate::clock::Clock;
+use crate::config::Config;
+use crate::entropy::Entropy;
+use crate::error::S3Error;
+use crate::faults::FaultInjector;
+use crate::journal::Journal;
+use crate::metrics::Metrics;
+use crate::notifications::{self, Event, EventName, NotificationConfiguration, Notifier};
+use crate::sqs::Queues;
+use crate::storage::{self, ObjectMetadata, StorageBackend};
+use crate::watch::Watchers;
+use crate::xml;
 
 pub struct AppState {
-    pub storage: Storage,
+    pub storage: Arc<dyn StorageBackend>,
+    pub faults: FaultInjector,
+    pub journal: Journal,
+    pub notifier: Notifier,
+    pub queues: Queues,
+    pub watchers: Watchers,
+    pub metrics: Metrics,
+    pub access_logs: AccessLogs,
+    config: RwLock<Arc<Config>>,
+}
+
+// the ids s3_headers gave a request, for responses and events that quote them back
+#[derive(Debug, Clone, Default)]
+pub struct RequestIds {
+    pub request_id: String,
+    pub host_id: String,
+}
+
+impl AppState {
+    pub fn new(storage: impl StorageBackend + 'static) -> Self {
+        Self::from_parts(Arc::new(storage), Config::default())
+    }
+
+    pub fn from_parts(storage: Arc<dyn StorageBackend>, config: Config) -> Self {
+        AppState {
+            storage,
+            faults: FaultInjector::new(config.faults.clone()),
+            journal: Journal::new(),
+            notifier: Notifier::new(),
+            queues: Queues::new(),
+            watchers: Watchers::new(),
+            metrics: Metrics::new(),
+            access_logs: AccessLogs::new(),
+            config: RwLock::new(Arc::new(config)),
+        }
+    }
+
+    // the storage's clock, so anything that stamps a time agrees with the objects
+    pub fn clock(&self) -> &Clock {
+        self.storage.clock()
+    }
+
+    pub fn entropy(&self) -> &Entropy {
+        self.storage.entropy()
+    }
+
+    // a consistent view of the config; a reload never changes it under a running request
+    pub fn config(&self) -> Arc<Config> {
+        self.config.read().unwrap().clone()
+    }
+
+    // swap in a new config, returning the one it replaced
+    pub fn set_config(&self, config: Config) -> Arc<Config> {
+        std::mem::replace(&mut *self.config.write().unwrap(), Arc::new(config))
+    }
 }
 
 #[derive(Deserialize, Default, Debug)]
@@ -24,30 +90,86 @@ pub struct ListObjectsQuery {
     pub max_keys: Option<usize>,
     #[serde(rename = "continuation-token")]
     pub continuation_token: Option<String>,
+    pub uploads: Option<String>,
+    pub notification: Option<String>,
+    pub logging: Option<String>,
 }
 
-pub async fn list_buckets(State(_state): State<Arc<AppState>>) -> impl IntoResponse {
-    let xml = r#"<?xml version="1.0" encoding="UTF-8"?>
-<ListAllMyBucketsResult xmlns="http://s3.amazonaws.com/doc/2006-03-01/">
-    <Owner>
-        <ID>local</ID>
-        <DisplayName>local</DisplayName>
-    </Owner>
-    <Buckets/>
-</ListAllMyBucketsResult>"#;
+#[derive(Deserialize, Default, Debug)]
+pub struct BucketQuery {
+    pub notification: Option<String>,
+    pub logging: Option<String>,
+}
+
+#[derive(Deserialize, Default, Debug)]
+pub struct ObjectQuery {
+    pub uploads: Option<String>,
+    #[serde(rename = "uploadId")]
+    pub upload_id: Option<String>,
+    #[serde(rename = "partNumber")]
+    pub part_number: Option<u32>,
+}
 
+fn parse_query<T: Default + serde::de::DeserializeOwned>(query: Option<String>) -> T {
+    query
+        .and_then(|q| serde_urlencoded::from_str(&q).ok())
+        .unwrap_or_default()
+}
+
+fn xml_response(status: StatusCode, xml: String) -> Response {
     Response::builder()
-        .status(StatusCode::OK)
+        .status(status)
         .header("Content-Type", "application/xml")
         .body(Body::from(xml))
         .unwrap()
 }
 
+pub async fn list_buckets(State(state): State<Arc<AppState>>) -> Response {
+    let buckets = match state.storage.list_buckets().await {
+        Ok(buckets) => buckets,
+        Err(e) => return S3Error::from(e).into_response(),
+    };
+
+    let mut buckets_xml = String::new();
+    for bucket in &buckets {
+        buckets_xml.push_str(&format!(
+            r#"<Bucket>
+            <Name>{}</Name>
+            <CreationDate>{}</CreationDate>
+        </Bucket>"#,
+            xml::escape(&bucket.name),
+            bucket.creation_date.to_rfc3339()
+        ));
+    }
+
+    let xml = format!(
+        r#"<?xml version="1.0" encoding="UTF-8"?>
+<ListAllMyBucketsResult xmlns="http://s3.amazonaws.com/doc/2006-03-01/">
+    <Owner>
+        <ID>local</ID>
+        <DisplayName>local</DisplayName>
+    </Owner>
+    <Buckets>{}</Buckets>
+</ListAllMyBucketsResult>"#,
+        buckets_xml
+    );
+
+    xml_response(StatusCode::OK, xml)
+}
 
 pub async fn create_bucket(
     State(state): State<Arc<AppState>>,
     Path(bucket): Path<String>,
+    RawQuery(query): RawQuery,
+    body: Bytes,
 ) -> Response {
+    let params: BucketQuery = parse_query(query);
+    if params.notification.is_some() {
+        return put_bucket_notification_impl(&state, &bucket, &body).await;
+    }
+    if params.logging.is_some() {
+        return put_bucket_logging_impl(&state, &bucket, &body).await;
+    }
     create_bucket_impl(&state, &bucket).await
 }
 
@@ -58,24 +180,131 @@ pub async fn head_bucket(
     head_bucket_impl(&state, &bucket).await
 }
 
+pub async fn delete_bucket(
+    State(state): State<Arc<AppState>>,
+    Path(bucket): Path<String>,
+) -> Response {
+    match state.storage.delete_bucket(&bucket).await {
+        Ok(_) => StatusCode::NO_CONTENT.into_response(),
+        Err(e) => S3Error::from(e).into_response(),
+    }
+}
+
 pub async fn list_bucket_objects(
     State(state): State<Arc<AppState>>,
     Path(bucket): Path<String>,
     RawQuery(query): RawQuery,
 ) -> Response {
-    let params = query
-        .and_then(|q| serde_urlencoded::from_str(&q).ok())
-        .unwrap_or_default();
+    let params: ListObjectsQuery = parse_query(query);
+    if params.uploads.is_some() {
+        return list_multipart_uploads_impl(&state, &bucket).await;
+    }
+    if params.notification.is_some() {
+        return get_bucket_notification_impl(&state, &bucket).await;
+    }
+    if params.logging.is_some() {
+        return get_bucket_logging_impl(&state, &bucket).await;
+    }
     list_objects_impl(&state, &bucket, params).await
 }
 
 async fn create_bucket_impl(state: &AppState, bucket: &str) -> Response {
+    if let Err(e) = storage::validate_bucket_name(bucket) {
+        return e.into_response();
+    }
+
     match state.storage.create_bucket(bucket).await {
         Ok(_) => StatusCode::OK.into_response(),
-        Err(_) => StatusCode::INTERNAL_SERVER_ERROR.into_response(),
+        Err(e) => S3Error::from(e).into_response(),
+    }
+}
+
+async fn put_bucket_notification_impl(state: &AppState, bucket: &str, body: &[u8]) -> Response {
+    let body = String::from_utf8_lossy(body);
+    let configuration = match NotificationConfiguration::parse(&body, || state.entropy().uuid().to_string()) {
+        Ok(configuration) => configuration,
+        Err(e) => return e.into_response(),
+    };
+    if let Err(e) = configuration.validate_destinations(state) {
+        return e.into_response();
+    }
+
+    // an empty configuration turns notifications off
+    let stored = match configuration.rules.is_empty() {
+        true => None,
+        false => match serde_json::to_string(&configuration) {
+            Ok(json) => Some(json),
+            Err(e) => return S3Error::from(std::io::Error::from(e)).into_response(),
+        },
+    };
+    match state.storage.put_bucket_setting(bucket, notifications::SETTING, stored).await {
+        Ok(_) => StatusCode::OK.into_response(),
+        Err(e) => S3Error::from(e).into_response(),
     }
 }
 
+async fn get_bucket_notification_impl(state: &AppState, bucket: &str) -> Response {
+    match notifications::load(state, bucket).await {
+        Ok(configuration) => xml_response(StatusCode::OK, configuration.to_xml()),
+        Err(e) => S3Error::from(e).into_response(),
+    }
+}
+
+async fn put_bucket_logging_impl(state: &AppState, bucket: &str, body: &[u8]) -> Response {
+    let body = String::from_utf8_lossy(body);
+    let configuration = match LoggingConfiguration::parse(&body) {
+        Ok(configuration) => configuration,
+        Err(e) => return e.into_response(),
+    };
+    if let Some(configuration) = &configuration {
+        if !state.storage.bucket_exists(&configuration.target_bucket).await {
+            return S3Error::invalid_target_bucket_for_logging().into_response();
+        }
+    }
+
+    // no LoggingEnabled turns logging off
+    let stored = match configuration.map(|c| serde_json::to_string(&c)).transpose() {
+        Ok(stored) => stored,
+        Err(e) => return S3Error::from(std::io::Error::from(e)).into_response(),
+    };
+    match state.storage.put_bucket_setting(bucket, access_log::SETTING, stored).await {
+        Ok(_) => StatusCode::OK.into_response(),
+        Err(e) => S3Error::from(e).into_response(),
+    }
+}
+
+async fn get_bucket_logging_impl(state: &AppState, bucket: &str) -> Response {
+    match access_log::load(state, bucket).await {
+        Ok(configuration) => xml_response(StatusCode::OK, LoggingConfiguration::to_xml(configuration.as_ref())),
+        Err(e) => S3Error::from(e).into_response(),
+    }
+}
+
+// tell the bucket's notification destinations about a change to an object; the event is
+// handed back for the response, where watchers pick it up
+async fn notify(
+    state: &AppState,
+    ids: &RequestIds,
+    name: EventName,
+    bucket: &str,
+    key: &str,
+    metadata: Option<&ObjectMetadata>,
+) -> Extension<Event> {
+    let event = Event {
+        name,
+        bucket: bucket.to_string(),
+        key: key.to_string(),
+        size: metadata.map(|m| m.size),
+        etag: metadata.map(|m| m.etag.clone()),
+        time: state.clock().now(),
+        request_id: ids.request_id.clone(),
+        host_id: ids.host_id.clone(),
+        sequencer: state.notifier.next_sequencer(),
+    };
+    notifications::notify(state, event.clone()).await;
+    Extension(event)
+}
+
 async fn head_bucket_impl(state: &AppState, bucket: &str) -> Response {
     if state.storage.bucket_exists(bucket).await {
         StatusCode::OK.into_response()
@@ -86,21 +315,19 @@ async fn head_bucket_impl(state: &AppState, bucket: &str) -> Response {
 
 async fn list_objects_impl(state: &AppState, bucket: &str, params: ListObjectsQuery) -> Response {
     let prefix = params.prefix.as_deref();
-    let objects = match state.storage.list_objects(&bucket, prefix).await {
+    let objects = match state.storage.list_objects(bucket, prefix).await {
         Ok(objs) => objs,
-        Err(_) => {
-            return Response::builder()
-                .status(StatusCode::INTERNAL_SERVER_ERROR)
-                .body(Body::empty())
-                .unwrap();
+        Err(e) if e.kind() == std::io::ErrorKind::NotFound => {
+            return S3Error::no_such_bucket().into_response();
         }
+        Err(e) => return S3Error::from(e).into_response(),
     };
 
     let max_keys = params.max_keys.unwrap_or(1000).min(1000);
     let delimiter = params.delimiter.as_deref();
 
     let mut filtered_objects = Vec::new();
-    let mut common_prefixes = std::collections::HashSet::new();
+    let mut common_prefixes = std::collections::BTreeSet::new();
 
     for obj in objects {
         if let Some(delim) = delimiter {
@@ -134,7 +361,7 @@ async fn list_objects_impl(state: &AppState, bucket: &str, params: ListObjectsQu
             <Size>{}</Size>
             <StorageClass>STANDARD</StorageClass>
         </Contents>"#,
-            xml_escape(&obj.key),
+            xml::escape(&obj.key),
             obj.last_modified.to_rfc3339(),
             obj.etag,
             obj.size
@@ -147,7 +374,7 @@ async fn list_objects_impl(state: &AppState, bucket: &str, params: ListObjectsQu
             r#"<CommonPrefixes>
             <Prefix>{}</Prefix>
         </CommonPrefixes>"#,
-            xml_escape(&cp)
+            xml::escape(&cp)
         ));
     }
 
@@ -162,10 +389,10 @@ async fn list_objects_impl(state: &AppState, bucket: &str, params: ListObjectsQu
     {}
     {}
 </ListBucketResult>"#,
-        xml_escape(&bucket),
-        xml_escape(prefix.unwrap_or("")),
+        xml::escape(bucket),
+        xml::escape(prefix.unwrap_or("")),
         max_keys,
-        xml_escape(delimiter.unwrap_or("")),
+        xml::escape(delimiter.unwrap_or("")),
         is_truncated,
         contents,
         common_prefixes_xml
@@ -178,27 +405,226 @@ async fn list_objects_impl(state: &AppState, bucket: &str, params: ListObjectsQu
         .unwrap()
 }
 
+async fn list_multipart_uploads_impl(state: &AppState, bucket: &str) -> Response {
+    let uploads = match state.storage.list_multipart_uploads(bucket).await {
+        Ok(uploads) => uploads,
+        Err(e) => return S3Error::from(e).into_response(),
+    };
+
+    let mut uploads_xml = String::new();
+    for upload in &uploads {
+        uploads_xml.push_str(&format!(
+            r#"<Upload>
+            <Key>{}</Key>
+            <UploadId>{}</UploadId>
+            <Initiated>{}</Initiated>
+            <StorageClass>STANDARD</StorageClass>
+        </Upload>"#,
+            xml::escape(&upload.key),
+            upload.upload_id,
+            upload.initiated.to_rfc3339()
+        ));
+    }
+
+    let xml = format!(
+        r#"<?xml version="1.0" encoding="UTF-8"?>
+<ListMultipartUploadsResult xmlns="http://s3.amazonaws.com/doc/2006-03-01/">
+    <Bucket>{}</Bucket>
+    <IsTruncated>false</IsTruncated>
+    {}
+</ListMultipartUploadsResult>"#,
+        xml::escape(bucket),
+        uploads_xml
+    );
+
+    xml_response(StatusCode::OK, xml)
+}
+
+pub(crate) fn content_type(headers: &HeaderMap) -> Option<String> {
+    headers
+        .get("content-type")
+        .and_then(|v| v.to_str().ok())
+        .map(|s| s.to_string())
+}
+
 pub async fn put_object(
     State(state): State<Arc<AppState>>,
     Path((bucket, key)): Path<(String, String)>,
+    RawQuery(query): RawQuery,
+    ids: Option<Extension<RequestIds>>,
     headers: HeaderMap,
     body: Bytes,
-) -> impl IntoResponse {
-    let content_type = headers
-        .get("content-type")
-        .and_then(|v| v.to_str().ok())
-        .map(|s| s.to_string());
+) -> Response {
+    let Extension(ids) = ids.unwrap_or_default();
+    let params: ObjectQuery = parse_query(query);
+    if let (Some(upload_id), Some(part_number)) = (params.upload_id, params.part_number) {
+        return upload_part_impl(&state, &bucket, &key, &upload_id, part_number, body).await;
+    }
+
+    if let Some(source) = headers.get("x-amz-copy-source").and_then(|v| v.to_str().ok()) {
+        return copy_object_impl(&state, &ids, source, &bucket, &key).await;
+    }
+
+    let content_type = content_type(&headers);
 
     match state.storage.put_object(&bucket, &key, body, content_type).await {
-        Ok(metadata) => Response::builder()
+        Ok(metadata) => {
+            let event = notify(&state, &ids, EventName::ObjectCreatedPut, &bucket, &key, Some(&metadata)).await;
+            let response = Response::builder()
+                .status(StatusCode::OK)
+                .header("ETag", format!("\"{}\"", metadata.etag))
+                .body(Body::empty())
+                .unwrap();
+            (event, response).into_response()
+        }
+        Err(e) => S3Error::from(e).into_response(),
+    }
+}
+
+async fn upload_part_impl(
+    state: &AppState,
+    bucket: &str,
+    key: &str,
+    upload_id: &str,
+    part_number: u32,
+    body: Bytes,
+) -> Response {
+    match state.storage.upload_part(bucket, key, upload_id, part_number, body).await {
+        Ok(part) => Response::builder()
             .status(StatusCode::OK)
-            .header("ETag", format!("\"{}\"", metadata.etag))
-            .body(Body::empty())
-            .unwrap(),
-        Err(_) => Response::builder()
-            .status(StatusCode::INTERNAL_SERVER_ERROR)
+            .header("ETag", format!("\"{}\"", part.etag))
             .body(Body::empty())
             .unwrap(),
+        Err(e) => S3Error::from(e).into_response(),
+    }
+}
+
+// x-amz-copy-source is "bucket/key" or "/bucket/key", url-encoded, optionally with ?versionId
+fn parse_copy_source(source: &str) -> Option<(String, String)> {
+    let source = source.split('?').next()?;
+    let decoded = percent_encoding::percent_decode_str(source.trim_start_matches('/'))
+        .decode_utf8()
+        .ok()?;
+    let (bucket, key) = decoded.split_once('/')?;
+    if key.is_empty() {
+        return None;
+    }
+    Some((bucket.to_string(), key.to_string()))
+}
+
+async fn copy_object_impl(state: &AppState, ids: &RequestIds, source: &str, bucket: &str, key: &str) -> Response {
+    let Some((src_bucket, src_key)) = parse_copy_source(source) else {
+        return S3Error::invalid_argument("Copy Source must mention the source bucket and key: sourcebucket/sourcekey")
+            .into_response();
+    };
+
+    match state.storage.copy_object(&src_bucket, &src_key, bucket, key).await {
+        Ok(metadata) => {
+            let event = notify(state, ids, EventName::ObjectCreatedCopy, bucket, key, Some(&metadata)).await;
+            let response = xml_response(
+                StatusCode::OK,
+                format!(
+                    r#"<?xml version="1.0" encoding="UTF-8"?>
+<CopyObjectResult xmlns="http://s3.amazonaws.com/doc/2006-03-01/">
+    <LastModified>{}</LastModified>
+    <ETag>"{}"</ETag>
+</CopyObjectResult>"#,
+                    metadata.last_modified.to_rfc3339(),
+                    metadata.etag
+                ),
+            );
+            (event, response).into_response()
+        }
+        Err(e) => S3Error::from(e).into_response(),
+    }
+}
+
+pub async fn post_object(
+    State(state): State<Arc<AppState>>,
+    Path((bucket, key)): Path<(String, String)>,
+    RawQuery(query): RawQuery,
+    ids: Option<Extension<RequestIds>>,
+    headers: HeaderMap,
+    body: Bytes,
+) -> Response {
+    let Extension(ids) = ids.unwrap_or_default();
+    let params: ObjectQuery = parse_query(query);
+
+    if params.uploads.is_some() {
+        return create_multipart_upload_impl(&state, &bucket, &key, content_type(&headers)).await;
+    }
+    if let Some(upload_id) = params.upload_id {
+        return complete_multipart_upload_impl(&state, &ids, &bucket, &key, &upload_id, &body).await;
+    }
+
+    S3Error::invalid_argument("unsupported POST operation").into_response()
+}
+
+async fn create_multipart_upload_impl(
+    state: &AppState,
+    bucket: &str,
+    key: &str,
+    content_type: Option<String>,
+) -> Response {
+    match state.storage.create_multipart_upload(bucket, key, content_type).await {
+        Ok(upload) => xml_response(
+            StatusCode::OK,
+            format!(
+                r#"<?xml version="1.0" encoding="UTF-8"?>
+<InitiateMultipartUploadResult xmlns="http://s3.amazonaws.com/doc/2006-03-01/">
+    <Bucket>{}</Bucket>
+    <Key>{}</Key>
+    <UploadId>{}</UploadId>
+</InitiateMultipartUploadResult>"#,
+                xml::escape(bucket),
+                xml::escape(key),
+                upload.upload_id
+            ),
+        ),
+        Err(e) => S3Error::from(e).into_response(),
+    }
+}
+
+async fn complete_multipart_upload_impl(
+    state: &AppState,
+    ids: &RequestIds,
+    bucket: &str,
+    key: &str,
+    upload_id: &str,
+    body: &[u8],
+) -> Response {
+    let body = String::from_utf8_lossy(body);
+    let mut parts = Vec::new();
+    for part in xml::elements(&body, "Part") {
+        let number = xml::text(part, "PartNumber").and_then(|n| n.parse::<u32>().ok());
+        let etag = xml::text(part, "ETag");
+        match (number, etag) {
+            (Some(number), Some(etag)) => parts.push((number, etag)),
+            _ => return S3Error::malformed_xml().into_response(),
+        }
+    }
+
+    match state.storage.complete_multipart_upload(bucket, key, upload_id, &parts).await {
+        Ok(metadata) => {
+            let name = EventName::ObjectCreatedCompleteMultipartUpload;
+            let event = notify(state, ids, name, bucket, key, Some(&metadata)).await;
+            let response = xml_response(
+                StatusCode::OK,
+                format!(
+                    r#"<?xml version="1.0" encoding="UTF-8"?>
+<CompleteMultipartUploadResult xmlns="http://s3.amazonaws.com/doc/2006-03-01/">
+    <Bucket>{}</Bucket>
+    <Key>{}</Key>
+    <ETag>"{}"</ETag>
+</CompleteMultipartUploadResult>"#,
+                    xml::escape(bucket),
+                    xml::escape(key),
+                    metadata.etag
+                ),
+            );
+            (event, response).into_response()
+        }
+        Err(e) => S3Error::from(e).into_response(),
     }
 }
 
@@ -212,10 +638,7 @@ pub async fn get_object(
             .header("Content-Type", "application/octet-stream")
             .body(Body::from(data))
             .unwrap(),
-        Err(_) => Response::builder()
-            .status(StatusCode::NOT_FOUND)
-            .body(Body::empty())
-            .unwrap(),
+        Err(e) => S3Error::from(e).into_response(),
     }
 }
 
@@ -232,27 +655,32 @@ pub async fn head_object(
             .header("Last-Modified", metadata.last_modified.to_rfc2822())
             .body(Body::empty())
             .unwrap(),
-        Err(_) => Response::builder()
-            .status(StatusCode::NOT_FOUND)
-            .body(Body::empty())
-            .unwrap(),
+        Err(e) => S3Error::from(e).status.into_response(),
     }
 }
 
 pub async fn delete_object(
     State(state): State<Arc<AppState>>,
     Path((bucket, key)): Path<(String, String)>,
-) -> impl IntoResponse {
+    RawQuery(query): RawQuery,
+    ids: Option<Extension<RequestIds>>,
+) -> Response {
+    let Extension(ids) = ids.unwrap_or_default();
+    let params: ObjectQuery = parse_query(query);
+    if let Some(upload_id) = params.upload_id {
+        return match state.storage.abort_multipart_upload(&bucket, &key, &upload_id).await {
+            Ok(_) => StatusCode::NO_CONTENT.into_response(),
+            Err(e) => S3Error::from(e).into_response(),
+        };
+    }
+
     match state.storage.delete_object(&bucket, &key).await {
-        Ok(_) => StatusCode::NO_CONTENT,
-        Err(_) => StatusCode::INTERNAL_SERVER_ERROR,
+        // deleting a key that doesn't exist is not an error in s3, and still notifies
+        Ok(_) => {}
+        Err(e) if e.kind() == std::io::ErrorKind::NotFound => {}
+        Err(e) => return S3Error::from(e).into_response(),
     }
+    let event = notify(&state, &ids, EventName::ObjectRemovedDelete, &bucket, &key, None).await;
+    (event, StatusCode::NO_CONTENT).into_response()
 }
 
-fn xml_escape(s: &str) -> String {
-    s.replace('&', "&amp;")
-        .replace('<', "&lt;")
-        .replace('>', "&gt;")
-        .replace('"', "&quot;")
-        .replace('\'', "&apos;")
-}
@@ -0,0 +1,41 @@
+use axum::{
+    extract::State,
+    http::StatusCode,
+    response::{IntoResponse, Response},
+    routing::get,
+    Json, Router,
+};
+use serde_json::json;
+use std::sync::Arc;
+
+use crate::handlers::AppState;
+
+// probes for orchestrators; they need no token and answer even with the admin api
+// disabled, since that's how docker and kubernetes call them
+pub fn router() -> Router<Arc<AppState>> {
+    Router::new()
+        .route("/health", get(health))
+        .route("/ready", get(ready))
+        .route("/version", get(version))
+}
+
+// the process is up and serving
+async fn health() -> Response {
+    Json(json!({ "status": "ok" })).into_response()
+}
+
+// requests can be served: the storage takes writes
+async fn ready(State(state): State<Arc<AppState>>) -> Response {
+    match state.storage.check_writable().await {
+        Ok(()) => Json(json!({ "status": "ready" })).into_response(),
+        Err(e) => {
+            tracing::warn!("readiness: storage is not writable: {}", e);
+            let body = json!({ "status": "unavailable", "message": format!("storage is not writable: {}", e) });
+            (StatusCode::SERVICE_UNAVAILABLE, Json(body)).into_response()
+        }
+    }
+}
+
+async fn version() -> Response {
+    Json(json!({ "name": env!("CARGO_PKG_NAME"), "version": env!("CARGO_PKG_VERSION") })).into_response()
+}
@@ -0,0 +1,284 @@
+use axum::{
+    body::{Body, Bytes, HttpBody},
+    extract::{Request, State},
+    http::{header, HeaderMap, StatusCode},
+    middleware::Next,
+    response::{IntoResponse, Response},
+};
+use base64::Engine;
+use chrono::{DateTime, Utc};
+use futures::StreamExt;
+use serde::{Deserialize, Serialize};
+use sha2::{Digest, Sha256};
+use std::collections::{BTreeMap, VecDeque};
+use std::fs::{self, File, OpenOptions};
+use std::io::{self, BufRead, Write};
+use std::path::Path;
+use std::sync::atomic::{AtomicU64, Ordering};
+use std::sync::{Arc, Mutex};
+use std::time::Instant;
+
+use crate::config::JournalConfig;
+use crate::error::S3Error;
+use crate::handlers::AppState;
+use crate::operation;
+
+pub mod replay;
+
+// one s3 request and the response it got, as a line of the journal
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct JournalEntry {
+    // order the requests arrived in
+    pub seq: u64,
+    pub time: DateTime<Utc>,
+    pub operation: String,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub bucket: Option<String>,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub key: Option<String>,
+    pub method: String,
+    // path and query, path style even when the request came in virtual-hosted style
+    pub uri: String,
+    pub request_headers: BTreeMap<String, String>,
+    pub status: u16,
+    pub response_headers: BTreeMap<String, String>,
+    // until the last byte of the response body was sent
+    pub duration_ms: f64,
+    pub request_bytes: u64,
+    pub response_bytes: u64,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub request_sha256: Option<String>,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub response_sha256: Option<String>,
+    // base64
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub request_body: Option<String>,
+}
+
+// which entries a query wants; unset fields match everything
+#[derive(Debug, Clone, Default, Deserialize)]
+pub struct JournalFilter {
+    pub operation: Option<String>,
+    pub bucket: Option<String>,
+    pub key: Option<String>,
+    pub method: Option<String>,
+    pub status: Option<u16>,
+    // only entries after this sequence number
+    pub since: Option<u64>,
+}
+
+impl JournalFilter {
+    pub fn matches(&self, entry: &JournalEntry) -> bool {
+        self.operation.as_ref().is_none_or(|o| *o == entry.operation)
+            && self.bucket.as_ref().is_none_or(|b| entry.bucket.as_ref() == Some(b))
+            && self.key.as_ref().is_none_or(|k| entry.key.as_ref() == Some(k))
+            && self.method.as_ref().is_none_or(|m| m.eq_ignore_ascii_case(&entry.method))
+            && self.status.is_none_or(|s| s == entry.status)
+            && self.since.is_none_or(|s| entry.seq > s)
+    }
+}
+
+#[derive(Default)]
+pub struct Journal {
+    entries: Mutex<VecDeque<JournalEntry>>,
+    next_seq: AtomicU64,
+    // the open journal file and the path it was opened from, reopened when the path changes
+    file: Mutex<Option<(String, File)>>,
+}
+
+impl Journal {
+    pub fn new() -> Self {
+        Journal::default()
+    }
+
+    fn next_seq(&self) -> u64 {
+        self.next_seq.fetch_add(1, Ordering::Relaxed) + 1
+    }
+
+    pub fn push(&self, settings: &JournalConfig, entry: JournalEntry) {
+        if let Some(path) = &settings.path {
+            if let Err(e) = self.append(path, &entry) {
+                tracing::warn!("journal: could not write to {}: {}", path, e);
+            }
+        }
+
+        let mut entries = self.entries.lock().unwrap();
+        entries.push_back(entry);
+        while entries.len() > settings.limit {
+            entries.pop_front();
+        }
+    }
+
+    fn append(&self, path: &str, entry: &JournalEntry) -> io::Result<()> {
+        let mut line = serde_json::to_vec(entry)?;
+        line.push(b'\n');
+
+        let mut file = self.file.lock().unwrap();
+        if file.as_ref().is_none_or(|(open, _)| open != path) {
+            if let Some(parent) = Path::new(path).parent().filter(|p| !p.as_os_str().is_empty()) {
+                fs::create_dir_all(parent)?;
+            }
+            let opened = OpenOptions::new().create(true).append(true).open(path)?;
+            *file = Some((path.to_string(), opened));
+        }
+        // one write per line, so concurrent servers appending to a file don't interleave
+        file.as_mut().unwrap().1.write_all(&line)
+    }
+
+    // matching entries still in memory, oldest first
+    pub fn entries(&self, filter: &JournalFilter) -> Vec<JournalEntry> {
+        let mut found: Vec<JournalEntry> = self
+            .entries
+            .lock()
+            .unwrap()
+            .iter()
+            .filter(|e| filter.matches(e))
+            .cloned()
+            .collect();
+        // entries are pushed as their responses finish; report them in arrival order
+        found.sort_by_key(|e| e.seq);
+        found
+    }
+
+    // forget what's in memory; the file is left alone
+    pub fn clear(&self) {
+        self.entries.lock().unwrap().clear();
+    }
+}
+
+// every line of a journal file, in the order they were written
+pub fn read(path: impl AsRef<Path>) -> io::Result<Vec<JournalEntry>> {
+    let file = File::open(path)?;
+    let mut entries = Vec::new();
+    for line in io::BufReader::new(file).lines() {
+        let line = line?;
+        if line.trim().is_empty() {
+            continue;
+        }
+        entries.push(serde_json::from_str(&line)?);
+    }
+    entries.sort_by_key(|e: &JournalEntry| e.seq);
+    Ok(entries)
+}
+
+fn hash(data: &[u8]) -> String {
+    format!("{:x}", Sha256::digest(data))
+}
+
+fn header_map(headers: &HeaderMap) -> BTreeMap<String, String> {
+    let mut map = BTreeMap::new();
+    for (name, value) in headers {
+        let value = String::from_utf8_lossy(value.as_bytes()).into_owned();
+        map.entry(name.as_str().to_string())
+            .and_modify(|v: &mut String| {
+                v.push_str(", ");
+                v.push_str(&value);
+            })
+            .or_insert(value);
+    }
+    map
+}
+
+// runs outside fault injection, so the journal shows what clients actually got
+pub async fn record(State(state): State<Arc<AppState>>, request: Request, next: Next) -> Response {
+    let settings = state.config().journal.clone();
+    if !settings.enabled {
+        return next.run(request).await;
+    }
+    let Some(op) = operation::classify(request.method(), request.uri(), request.headers()) else {
+        return next.run(request).await;
+    };
+
+    let started = Instant::now();
+    let seq = state.journal.next_seq();
+    let (parts, body) = request.into_parts();
+    let body = match axum::body::to_bytes(body, usize::MAX).await {
+        Ok(body) => body,
+        Err(e) => {
+            return S3Error::new(StatusCode::BAD_REQUEST, "IncompleteBody", e.to_string()).into_response();
+        }
+    };
+
+    let entry = JournalEntry {
+        seq,
+        time: state.clock().now(),
+        operation: op.name.to_string(),
+        bucket: op.bucket,
+        key: op.key,
+        method: parts.method.to_string(),
+        uri: parts
+            .uri
+            .path_and_query()
+            .map(|pq| pq.to_string())
+            .unwrap_or_else(|| parts.uri.path().to_string()),
+        request_headers: header_map(&parts.headers),
+        status: 0,
+        response_headers: BTreeMap::new(),
+        duration_ms: 0.0,
+        request_bytes: body.len() as u64,
+        response_bytes: 0,
+        request_sha256: settings.body_hashes.then(|| hash(&body)),
+        response_sha256: None,
+        request_body: (settings.request_bodies && !body.is_empty())
+            .then(|| base64::engine::general_purpose::STANDARD.encode(&body)),
+    };
+
+    let response = next.run(Request::from_parts(parts, Body::from(body))).await;
+    let (mut parts, body) = response.into_parts();
+
+    // the body is re-wrapped below, which would lose its length and switch to chunked
+    if let Some(len) = body.size_hint().exact() {
+        parts.headers.entry(header::CONTENT_LENGTH).or_insert(len.into());
+    }
+
+    let mut pending = Pending {
+        state: state.clone(),
+        entry: Some(JournalEntry {
+            status: parts.status.as_u16(),
+            response_headers: header_map(&parts.headers),
+            ..entry
+        }),
+        hasher: settings.body_hashes.then(Sha256::new),
+        settings,
+        started,
+    };
+    let stream = body.into_data_stream().map(move |chunk| {
+        if let Ok(chunk) = &chunk {
+            pending.observe(chunk);
+        }
+        chunk
+    });
+    Response::from_parts(parts, Body::from_stream(stream))
+}
+
+// an entry waiting for its response body to be sent; it's recorded when the body is
+// dropped, whether it was sent in full, failed or the client went away
+struct Pending {
+    state: Arc<AppState>,
+    settings: JournalConfig,
+    entry: Option<JournalEntry>,
+    hasher: Option<Sha256>,
+    started: Instant,
+}
+
+impl Pending {
+    fn observe(&mut self, chunk: &Bytes) {
+        if let Some(entry) = &mut self.entry {
+            entry.response_bytes += chunk.len() as u64;
+        }
+        if let Some(hasher) = &mut self.hasher {
+            hasher.update(chunk);
+        }
+    }
+}
+
+impl Drop for Pending {
+    fn drop(&mut self) {
+        let Some(mut entry) = self.entry.take() else {
+            return;
+        };
+        entry.duration_ms = self.started.elapsed().as_secs_f64() * 1000.0;
+        entry.response_sha256 = self.hasher.take().map(|h| format!("{:x}", h.finalize()));
+        self.state.journal.push(&self.settings, entry);
+    }
+}
@@ -0,0 +1,133 @@
+use base64::Engine;
+use reqwest::Method;
+use serde::Serialize;
+use sha2::{Digest, Sha256};
+use std::collections::HashMap;
+
+use super::JournalEntry;
+use crate::xml;
+
+// headers that describe the original connection rather than the request
+const SKIPPED_HEADERS: &[&str] = &["host", "content-length", "transfer-encoding", "connection"];
+
+#[derive(Debug, Default, Serialize)]
+pub struct ReplayReport {
+    pub replayed: usize,
+    // entries with a body that wasn't recorded, so they couldn't be sent again
+    pub skipped: Vec<u64>,
+    pub mismatches: Vec<Mismatch>,
+}
+
+impl ReplayReport {
+    pub fn is_clean(&self) -> bool {
+        self.skipped.is_empty() && self.mismatches.is_empty()
+    }
+}
+
+#[derive(Debug, Clone, Serialize)]
+pub struct Mismatch {
+    pub seq: u64,
+    pub operation: String,
+    pub uri: String,
+    pub expected: String,
+    pub actual: String,
+}
+
+// send the journaled requests to endpoint one at a time, in their original order, and
+// compare what comes back with what was recorded
+pub async fn replay(entries: &[JournalEntry], endpoint: &str) -> Result<ReplayReport, reqwest::Error> {
+    let endpoint = endpoint.trim_end_matches('/');
+    let http = reqwest::Client::new();
+    let mut report = ReplayReport::default();
+
+    // upload ids are generated, so the ones in the journal have to be swapped for the ones
+    // the new server hands out: each recorded id takes the next new one for its key
+    let mut fresh_uploads: HashMap<(Option<String>, Option<String>), Vec<String>> = HashMap::new();
+    let mut upload_ids: HashMap<String, String> = HashMap::new();
+
+    for entry in entries {
+        let body = match &entry.request_body {
+            Some(encoded) => match base64::engine::general_purpose::STANDARD.decode(encoded) {
+                Ok(body) => body,
+                Err(_) => {
+                    report.skipped.push(entry.seq);
+                    continue;
+                }
+            },
+            None if entry.request_bytes == 0 => Vec::new(),
+            None => {
+                report.skipped.push(entry.seq);
+                continue;
+            }
+        };
+
+        let upload_key = (entry.bucket.clone(), entry.key.clone());
+        let mut uri = entry.uri.clone();
+        if let Some(recorded) = query_param(&uri, "uploadId") {
+            if !upload_ids.contains_key(&recorded) {
+                if let Some(fresh) = fresh_uploads.get_mut(&upload_key).filter(|ids| !ids.is_empty()) {
+                    upload_ids.insert(recorded.clone(), fresh.remove(0));
+                }
+            }
+            if let Some(fresh) = upload_ids.get(&recorded) {
+                uri = uri.replace(&format!("uploadId={}", recorded), &format!("uploadId={}", fresh));
+            }
+        }
+
+        let method = Method::from_bytes(entry.method.as_bytes()).unwrap_or(Method::GET);
+        let mut request = http.request(method, format!("{}{}", endpoint, uri)).body(body);
+        for (name, value) in &entry.request_headers {
+            if !SKIPPED_HEADERS.contains(&name.as_str()) {
+                request = request.header(name, value);
+            }
+        }
+
+        let response = request.send().await?;
+        let status = response.status().as_u16();
+        let body = response.bytes().await?;
+        report.replayed += 1;
+
+        if entry.operation == "CreateMultipartUpload" && status == 200 {
+            if let Some(id) = xml::text(&String::from_utf8_lossy(&body), "UploadId") {
+                fresh_uploads.entry(upload_key).or_default().push(id);
+            }
+        }
+
+        let mismatch = |expected: String, actual: String| Mismatch {
+            seq: entry.seq,
+            operation: entry.operation.clone(),
+            uri: entry.uri.clone(),
+            expected,
+            actual,
+        };
+        if status != entry.status {
+            report
+                .mismatches
+                .push(mismatch(format!("status {}", entry.status), format!("status {}", status)));
+            continue;
+        }
+        // only object contents are compared: listings and upload responses carry
+        // timestamps and generated ids that differ from run to run
+        if entry.operation == "GetObject" {
+            if let Some(expected) = &entry.response_sha256 {
+                let actual = format!("{:x}", Sha256::digest(&body));
+                if *expected != actual {
+                    report.mismatches.push(mismatch(
+                        format!("body sha256 {}", expected),
+                        format!("body sha256 {}", actual),
+                    ));
+                }
+            }
+        }
+    }
+
+    Ok(report)
+}
+
+fn query_param(uri: &str, name: &str) -> Option<String> {
+    let (_, query) = uri.split_once('?')?;
+    query.split('&').find_map(|pair| {
+        let (key, value) = pair.split_once('=')?;
+        (key == name).then(|| value.to_string())
+    })
+}
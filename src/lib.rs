@@ -1,4 +1,28 @@
+pub mod access_log;
+pub mod admin;
+pub mod archive;
 pub mod auth;
+pub mod cli;
+pub mod client;
+pub mod clock;
 pub mod config;
+pub mod entropy;
+pub mod error;
+pub mod faults;
 pub mod handlers;
+pub mod health;
+pub mod journal;
+pub mod metrics;
+pub mod notifications;
+pub mod operation;
+pub mod reload;
+pub mod remote;
+pub mod server;
+pub mod sqs;
 pub mod storage;
+pub mod tls;
+pub mod ui;
+pub mod watch;
+pub mod xml;
+
+pub use server::Server;
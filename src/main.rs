@@ -1,61 +1,364 @@
-mod auth;
-mod config;
-mod handlers;
-mod storage;
-
-use axum::{
-    routing::{delete, get, head, put},
-    Router,
-};
-use std::sync::Arc;
-use tower_http::cors::{Any, CorsLayer};
-use tracing_subscriber;
+use clap::Parser;
+use std::io::Write;
+use std::time::Duration;
 
-use crate::config::Config;
-use crate::handlers::AppState;
-use crate::storage::Storage;
+use six7::cli::{
+    BucketArgs, CatArgs, Cli, Command, CpArgs, ExportArgs, FsckArgs, ImportArgs, LsArgs, RbArgs, ReplayArgs, RmArgs,
+    ServeArgs, SnapshotAction, SnapshotArgs, SyncArgs, WatchArgs,
+};
+use six7::journal::{self, replay};
+use six7::remote::{AdminClient, WatchEvent};
+use six7::storage::{snapshot, FsckOptions, SnapshotInfo, Storage, StorageBackend};
+use six7::watch::{Activity, WatchFilter};
+use six7::Server;
 
 #[tokio::main]
 async fn main() {
     tracing_subscriber::fmt::init();
 
-    let config = Config::load("six7.yaml").expect("failed to load config");
-    let storage = Storage::new(&config.storage.path).expect("failed to initialize storage");
-
-    for bucket in &config.buckets {
-        let _ = storage.create_bucket(&bucket.name).await;
-    }
-
-    let state = Arc::new(AppState { storage });
-
-    let cors = CorsLayer::new()
-        .allow_origin(Any)
-        .allow_methods(Any)
-        .allow_headers(Any);
-
-    let app = Router::new()
-        .route("/", get(handlers::list_buckets))
-        .route("/{bucket}",
-            put(handlers::create_bucket)
-                .head(handlers::head_bucket)
-        )
-        .route("/{bucket}/{*key}",
-            put(handlers::put_object)
-                .get(handlers::get_object)
-                .delete(handlers::delete_object)
-                .head(handlers::head_object)
-        )
-        .layer(cors)
-        .with_state(state);
-
-    let addr = format!("{}:{}", config.server.host, config.server.port);
-    let listener = tokio::net::TcpListener::bind(&addr)
-        .await
-        .expect("failed to bind");
-
-    tracing::info!("six7 listening on {}", addr);
-
-    axum::serve(listener, app)
-        .await
-        .expect("server error");
+    let result = match Cli::parse().into_command() {
+        Command::Serve(args) => serve(args).await,
+        Command::Snapshot(args) => snapshot(args).await,
+        Command::Replay(args) => replay(args).await,
+        Command::Watch(args) => watch(args).await,
+        Command::Ls(args) => ls(args).await,
+        Command::Cp(args) => cp(args).await,
+        Command::Rm(args) => rm(args).await,
+        Command::Mb(args) => mb(args).await,
+        Command::Rb(args) => rb(args).await,
+        Command::Cat(args) => cat(args).await,
+        Command::Sync(args) => sync(args).await,
+        Command::Import(args) => import(args).await,
+        Command::Export(args) => export(args).await,
+        Command::Fsck(args) => fsck(args).await,
+    };
+
+    if let Err(e) = result {
+        eprintln!("six7: {}", e);
+        std::process::exit(1);
+    }
+}
+
+async fn serve(args: ServeArgs) -> Result<(), Box<dyn std::error::Error>> {
+    let config = args.load_config()?;
+
+    let mut server = Server::builder().config(config);
+    if args.in_memory {
+        server = server.in_memory();
+    }
+    if let Some(path) = args.config_path() {
+        let overrides = args.clone();
+        server = server.watch_config(path, move |config| overrides.apply(config));
+    }
+
+    server.serve_with_shutdown(shutdown_signal()).await?;
+    Ok(())
+}
+
+// SIGTERM, as docker and kubernetes stop containers, or ctrl-c
+async fn shutdown_signal() {
+    let interrupt = async {
+        let _ = tokio::signal::ctrl_c().await;
+    };
+
+    #[cfg(unix)]
+    let terminate = async {
+        match tokio::signal::unix::signal(tokio::signal::unix::SignalKind::terminate()) {
+            Ok(mut terminate) => {
+                terminate.recv().await;
+            }
+            Err(e) => {
+                tracing::warn!("no SIGTERM handler: {}", e);
+                std::future::pending::<()>().await;
+            }
+        }
+    };
+    #[cfg(not(unix))]
+    let terminate = std::future::pending::<()>();
+
+    tokio::select! {
+        _ = interrupt => {}
+        _ = terminate => {}
+    }
+}
+
+async fn snapshot(args: SnapshotArgs) -> Result<(), Box<dyn std::error::Error>> {
+    let snapshots = match &args.target.endpoint {
+        Some(endpoint) => {
+            let client = AdminClient::new(endpoint, args.target.admin_token());
+            match &args.action {
+                SnapshotAction::Create { name } => vec![client.create_snapshot(name).await?],
+                SnapshotAction::Restore { name } => {
+                    client.restore_snapshot(name).await?;
+                    Vec::new()
+                }
+                SnapshotAction::List => client.list_snapshots().await?,
+                SnapshotAction::Delete { name } => {
+                    client.delete_snapshot(name).await?;
+                    Vec::new()
+                }
+            }
+        }
+        // straight on the data dir, for when no server is running
+        None => {
+            let base = args.target.data_dir()?;
+            match &args.action {
+                SnapshotAction::Create { name } => vec![snapshot::create(&base, name, chrono::Utc::now())?],
+                SnapshotAction::Restore { name } => {
+                    snapshot::restore(&base, name)?;
+                    Vec::new()
+                }
+                SnapshotAction::List => snapshot::list(&base)?,
+                SnapshotAction::Delete { name } => {
+                    snapshot::delete(&base, name)?;
+                    Vec::new()
+                }
+            }
+        }
+    };
+
+    match &args.action {
+        SnapshotAction::Restore { name } => println!("restored {}", name),
+        SnapshotAction::Delete { name } => println!("deleted {}", name),
+        _ => print_snapshots(&snapshots),
+    }
+    Ok(())
+}
+
+async fn replay(args: ReplayArgs) -> Result<(), Box<dyn std::error::Error>> {
+    let entries = journal::read(&args.journal)?;
+
+    // without an endpoint, a fresh server with the same buckets and nothing else; it's
+    // kept alive until the replay is done
+    let (endpoint, _server) = match &args.endpoint {
+        Some(endpoint) => (endpoint.clone(), None),
+        None => {
+            let serve = ServeArgs {
+                config: args.config.clone(),
+                ..ServeArgs::default()
+            };
+            let mut config = serve.load_config()?;
+            config.server.tls = None;
+            config.journal = Default::default();
+            config.faults.clear();
+            let server = Server::builder()
+                .config(config)
+                .port(0)
+                .in_memory()
+                .spawn()
+                .await?;
+            (server.url(), Some(server))
+        }
+    };
+
+    let report = replay::replay(&entries, &endpoint).await?;
+    for seq in &report.skipped {
+        println!("#{:<6} skipped: request body wasn't recorded", seq);
+    }
+    for m in &report.mismatches {
+        println!("#{:<6} {} {}: expected {}, got {}", m.seq, m.operation, m.uri, m.expected, m.actual);
+    }
+    println!(
+        "replayed {} of {} requests, {} mismatched",
+        report.replayed,
+        entries.len(),
+        report.mismatches.len()
+    );
+
+    if !report.is_clean() {
+        return Err("replay didn't match the journal".into());
+    }
+    Ok(())
+}
+
+async fn watch(args: WatchArgs) -> Result<(), Box<dyn std::error::Error>> {
+    let client = AdminClient::new(&args.endpoint, args.admin_token());
+    let filter = WatchFilter {
+        bucket: args.bucket.clone(),
+        prefix: args.prefix.clone(),
+        operation: args.operation.clone(),
+    };
+
+    client
+        .watch(&filter, |event| {
+            match event {
+                WatchEvent::Activity(activity) if args.json => {
+                    println!("{}", serde_json::to_string(&activity).unwrap());
+                }
+                WatchEvent::Activity(activity) => print_activity(&activity),
+                WatchEvent::Lagged(missed) => eprintln!("six7: fell behind, {} requests not shown", missed),
+            }
+            true
+        })
+        .await?;
+    Err("the server closed the stream".into())
+}
+
+async fn ls(args: LsArgs) -> Result<(), Box<dyn std::error::Error>> {
+    let client = args.target.client()?;
+    let Some(path) = &args.path else {
+        for bucket in client.list_buckets().await? {
+            println!("{}  {}", bucket.creation_date.format("%Y-%m-%d %H:%M:%S"), bucket.name);
+        }
+        return Ok(());
+    };
+
+    let (bucket, prefix) = path.s3()?;
+    let listing = client.list(bucket, prefix, args.recursive).await?;
+    // one level is shown relative to it, like a directory listing; recursive shows whole keys
+    let dir = match args.recursive {
+        true => "",
+        false => &prefix[..prefix.rfind('/').map_or(0, |i| i + 1)],
+    };
+    for prefix in &listing.prefixes {
+        println!("{:>30} {}", "PRE", &prefix[dir.len()..]);
+    }
+    for object in &listing.objects {
+        println!(
+            "{} {:>10} {}",
+            object.last_modified.format("%Y-%m-%d %H:%M:%S"),
+            object.size,
+            &object.key[dir.len()..]
+        );
+    }
+    Ok(())
+}
+
+async fn cp(args: CpArgs) -> Result<(), Box<dyn std::error::Error>> {
+    let client = args.target.client()?;
+    for transfer in client.copy(&args.source, &args.dest, args.recursive).await? {
+        println!("copy: {} to {}", transfer.from, transfer.to);
+    }
+    Ok(())
+}
+
+async fn rm(args: RmArgs) -> Result<(), Box<dyn std::error::Error>> {
+    let client = args.target.client()?;
+    let (bucket, key) = args.path.s3()?;
+    for removed in client.remove(bucket, key, args.recursive).await? {
+        println!("delete: {}", removed);
+    }
+    Ok(())
+}
+
+async fn mb(args: BucketArgs) -> Result<(), Box<dyn std::error::Error>> {
+    args.target.client()?.create_bucket(&args.bucket).await?;
+    println!("make_bucket: {}", args.bucket);
+    Ok(())
+}
+
+async fn rb(args: RbArgs) -> Result<(), Box<dyn std::error::Error>> {
+    for removed in args.target.client()?.remove_bucket(&args.bucket, args.force).await? {
+        println!("delete: {}", removed);
+    }
+    println!("remove_bucket: {}", args.bucket);
+    Ok(())
+}
+
+async fn cat(args: CatArgs) -> Result<(), Box<dyn std::error::Error>> {
+    let (bucket, key) = args.path.s3()?;
+    let data = args.target.client()?.get(bucket, key).await?;
+    let mut stdout = std::io::stdout().lock();
+    stdout.write_all(&data)?;
+    stdout.flush()?;
+    Ok(())
+}
+
+async fn sync(args: SyncArgs) -> Result<(), Box<dyn std::error::Error>> {
+    let client = args.target.client()?;
+    let report = client.sync(&args.source, &args.dest, args.delete).await?;
+    for transfer in &report.copied {
+        println!("copy: {} to {}", transfer.from, transfer.to);
+    }
+    for deleted in &report.deleted {
+        println!("delete: {}", deleted);
+    }
+    println!(
+        "{} copied, {} deleted, {} unchanged",
+        report.copied.len(),
+        report.deleted.len(),
+        report.unchanged
+    );
+    Ok(())
+}
+
+async fn import(args: ImportArgs) -> Result<(), Box<dyn std::error::Error>> {
+    let client = args.target.client()?;
+    let imported = client.import(&args.bucket, &args.source).await?;
+    for location in &imported {
+        println!("import: {}", location);
+    }
+    println!("{} objects imported into {}", imported.len(), args.bucket);
+    Ok(())
+}
+
+async fn export(args: ExportArgs) -> Result<(), Box<dyn std::error::Error>> {
+    let client = args.target.client()?;
+    let manifest = client.export(&args.bucket, &args.dest).await?;
+    println!("{} objects exported to {}", manifest.objects.len(), args.dest.display());
+    Ok(())
+}
+
+async fn fsck(args: FsckArgs) -> Result<(), Box<dyn std::error::Error>> {
+    let abandoned_after = Duration::from_secs(args.abandoned_after);
+    let report = match &args.target.endpoint {
+        Some(endpoint) => {
+            let client = AdminClient::new(endpoint, args.target.admin_token());
+            client.fsck(args.repair, abandoned_after).await?
+        }
+        // straight on the data dir, as it was left
+        None => {
+            let options = FsckOptions {
+                repair: args.repair,
+                abandoned_after,
+            };
+            Storage::open(args.target.data_dir()?)?.fsck(&options).await?
+        }
+    };
+
+    for finding in &report.findings {
+        println!("{:<18} {}: {}", finding.problem, finding.path, finding.detail);
+    }
+    println!(
+        "checked {} keys and {} uploads, {} problems{}",
+        report.keys,
+        report.uploads,
+        report.findings.len(),
+        if report.repaired { " repaired" } else { "" }
+    );
+
+    if !report.repaired && !report.findings.is_empty() {
+        return Err("run with --repair to fix them".into());
+    }
+    Ok(())
+}
+
+fn print_activity(activity: &Activity) {
+    let target = match (&activity.bucket, &activity.key) {
+        (Some(bucket), Some(key)) => format!("{}/{}", bucket, key),
+        (Some(bucket), None) => bucket.clone(),
+        _ => String::new(),
+    };
+    let event = activity.event.as_ref().map(|e| e.name.as_str()).unwrap_or_default();
+    println!(
+        "{}  {:<26} {}  {:<40} {:>8.1}ms  {}",
+        activity.time.format("%H:%M:%S%.3f"),
+        activity.operation,
+        activity.status,
+        target,
+        activity.duration_ms,
+        event
+    );
+}
+
+fn print_snapshots(snapshots: &[SnapshotInfo]) {
+    for info in snapshots {
+        println!(
+            "{:<24} {}  {:>8} objects  {:>12} bytes",
+            info.name,
+            info.created.format("%Y-%m-%d %H:%M:%S"),
+            info.objects,
+            info.bytes
+        );
+    }
 }
@@ -0,0 +1,251 @@
+use axum::{
+    body::{Body, HttpBody},
+    extract::{Request, State},
+    http::{header, Method},
+    middleware::Next,
+    response::Response,
+};
+use futures::StreamExt;
+use std::collections::BTreeMap;
+use std::fmt::Write;
+use std::sync::atomic::{AtomicU64, Ordering};
+use std::sync::{Arc, Mutex};
+use std::time::Instant;
+
+use crate::handlers::AppState;
+use crate::operation;
+
+pub const CONTENT_TYPE: &str = "text/plain; version=0.0.4; charset=utf-8";
+
+// upper bounds of the latency histogram in seconds, prometheus' defaults
+const LATENCY_BUCKETS: &[f64] = &[0.005, 0.01, 0.025, 0.05, 0.1, 0.25, 0.5, 1.0, 2.5, 5.0, 10.0];
+
+#[derive(Debug, Clone, PartialEq, Eq, PartialOrd, Ord)]
+struct RequestLabels {
+    operation: &'static str,
+    // empty for requests that aren't about a bucket
+    bucket: String,
+    status: u16,
+}
+
+#[derive(Debug, Clone, Default)]
+struct RequestStats {
+    count: u64,
+    // per entry of LATENCY_BUCKETS, not cumulative
+    latency: [u64; LATENCY_BUCKETS.len()],
+    latency_sum: f64,
+    bytes_in: u64,
+    bytes_out: u64,
+}
+
+// counters for everything s3 clients did since the server started
+#[derive(Default)]
+pub struct Metrics {
+    requests: Mutex<BTreeMap<RequestLabels, RequestStats>>,
+    // by operation and kind of fault
+    faults: Mutex<BTreeMap<(&'static str, &'static str), u64>>,
+}
+
+impl Metrics {
+    pub fn new() -> Self {
+        Metrics::default()
+    }
+
+    fn record(&self, labels: RequestLabels, seconds: f64, bytes_in: u64, bytes_out: u64) {
+        let mut requests = self.requests.lock().unwrap();
+        let stats = requests.entry(labels).or_default();
+        stats.count += 1;
+        if let Some(i) = LATENCY_BUCKETS.iter().position(|&le| seconds <= le) {
+            stats.latency[i] += 1;
+        }
+        stats.latency_sum += seconds;
+        stats.bytes_in += bytes_in;
+        stats.bytes_out += bytes_out;
+    }
+
+    pub fn fault_injected(&self, operation: &'static str, fault: &'static str) {
+        *self.faults.lock().unwrap().entry((operation, fault)).or_default() += 1;
+    }
+}
+
+// counts every s3 request once its response body is done, so latency and bytes out cover
+// the whole transfer
+pub async fn track(State(state): State<Arc<AppState>>, request: Request, next: Next) -> Response {
+    let Some(op) = operation::classify(request.method(), request.uri(), request.headers()) else {
+        return next.run(request).await;
+    };
+
+    let started = Instant::now();
+    let bytes_in = Arc::new(AtomicU64::new(0));
+    let (parts, body) = request.into_parts();
+    let counted = bytes_in.clone();
+    let body = body.into_data_stream().map(move |chunk| {
+        if let Ok(chunk) = &chunk {
+            counted.fetch_add(chunk.len() as u64, Ordering::Relaxed);
+        }
+        chunk
+    });
+
+    let response = next.run(Request::from_parts(parts, Body::from_stream(body))).await;
+    let (mut parts, body) = response.into_parts();
+    // the body is re-wrapped below, which would lose its length and switch to chunked
+    if let Some(len) = body.size_hint().exact() {
+        parts.headers.entry(header::CONTENT_LENGTH).or_insert(len.into());
+    }
+
+    let mut pending = Pending {
+        state: state.clone(),
+        labels: Some(RequestLabels {
+            operation: op.name,
+            bucket: op.bucket.unwrap_or_default(),
+            status: parts.status.as_u16(),
+        }),
+        started,
+        bytes_in,
+        bytes_out: 0,
+    };
+    let stream = body.into_data_stream().map(move |chunk| {
+        if let Ok(chunk) = &chunk {
+            pending.observe(chunk.len());
+        }
+        chunk
+    });
+    Response::from_parts(parts, Body::from_stream(stream))
+}
+
+// a request waiting for its response body to be sent; counted when the body is dropped
+struct Pending {
+    state: Arc<AppState>,
+    labels: Option<RequestLabels>,
+    started: Instant,
+    bytes_in: Arc<AtomicU64>,
+    bytes_out: u64,
+}
+
+impl Pending {
+    fn observe(&mut self, len: usize) {
+        self.bytes_out += len as u64;
+    }
+}
+
+impl Drop for Pending {
+    fn drop(&mut self) {
+        if let Some(labels) = self.labels.take() {
+            let seconds = self.started.elapsed().as_secs_f64();
+            let bytes_in = self.bytes_in.load(Ordering::Relaxed);
+            self.state.metrics.record(labels, seconds, bytes_in, self.bytes_out);
+        }
+    }
+}
+
+// answers scrapes at the configured path, ahead of the s3 routes
+pub async fn serve(State(state): State<Arc<AppState>>, request: Request, next: Next) -> Response {
+    let config = state.config();
+    if !config.metrics.enabled || request.method() != Method::GET || request.uri().path() != config.metrics.path {
+        return next.run(request).await;
+    }
+
+    Response::builder()
+        .header(header::CONTENT_TYPE, CONTENT_TYPE)
+        .body(Body::from(render(&state).await))
+        .unwrap()
+}
+
+// everything in prometheus' text format
+pub async fn render(state: &AppState) -> String {
+    let mut out = String::new();
+    let requests = state.metrics.requests.lock().unwrap().clone();
+
+    family(&mut out, "six7_requests_total", "counter", "s3 requests handled");
+    for (labels, stats) in &requests {
+        sample(&mut out, "six7_requests_total", &request_labels(labels), stats.count);
+    }
+
+    family(&mut out, "six7_request_duration_seconds", "histogram", "time until the response body was sent");
+    for (labels, stats) in &requests {
+        let base = request_labels(labels);
+        let mut cumulative = 0;
+        for (le, count) in LATENCY_BUCKETS.iter().zip(stats.latency) {
+            cumulative += count;
+            let labels = format!("{},le=\"{}\"", base, le);
+            sample(&mut out, "six7_request_duration_seconds_bucket", &labels, cumulative);
+        }
+        let labels = format!("{},le=\"+Inf\"", base);
+        sample(&mut out, "six7_request_duration_seconds_bucket", &labels, stats.count);
+        sample(&mut out, "six7_request_duration_seconds_sum", &base, stats.latency_sum);
+        sample(&mut out, "six7_request_duration_seconds_count", &base, stats.count);
+    }
+
+    family(&mut out, "six7_received_bytes_total", "counter", "request body bytes received");
+    for (labels, stats) in &requests {
+        sample(&mut out, "six7_received_bytes_total", &request_labels(labels), stats.bytes_in);
+    }
+    family(&mut out, "six7_sent_bytes_total", "counter", "response body bytes sent");
+    for (labels, stats) in &requests {
+        sample(&mut out, "six7_sent_bytes_total", &request_labels(labels), stats.bytes_out);
+    }
+
+    let faults = state.metrics.faults.lock().unwrap().clone();
+    family(&mut out, "six7_faults_injected_total", "counter", "faults injected into s3 requests");
+    for ((operation, fault), count) in faults {
+        let labels = format!("operation=\"{}\",fault=\"{}\"", operation, fault);
+        sample(&mut out, "six7_faults_injected_total", &labels, count);
+    }
+
+    // bucket contents are read from storage on every scrape rather than tracked, so
+    // they're right however the objects got there
+    let mut buckets = Vec::new();
+    match state.storage.list_buckets().await {
+        Ok(list) => {
+            for bucket in list {
+                let objects = state.storage.list_objects(&bucket.name, None).await.unwrap_or_default();
+                let uploads = state.storage.list_multipart_uploads(&bucket.name).await.unwrap_or_default();
+                let bytes: u64 = objects.iter().map(|o| o.size).sum();
+                buckets.push((bucket.name, objects.len(), bytes, uploads.len()));
+            }
+        }
+        Err(e) => tracing::warn!("metrics: could not list buckets: {}", e),
+    }
+
+    family(&mut out, "six7_bucket_objects", "gauge", "objects stored in a bucket");
+    for (name, objects, _, _) in &buckets {
+        sample(&mut out, "six7_bucket_objects", &bucket_label(name), objects);
+    }
+    family(&mut out, "six7_bucket_bytes", "gauge", "bytes stored in a bucket's objects");
+    for (name, _, bytes, _) in &buckets {
+        sample(&mut out, "six7_bucket_bytes", &bucket_label(name), bytes);
+    }
+    family(&mut out, "six7_multipart_uploads", "gauge", "multipart uploads started and not yet completed or aborted");
+    for (name, _, _, uploads) in &buckets {
+        sample(&mut out, "six7_multipart_uploads", &bucket_label(name), uploads);
+    }
+
+    out
+}
+
+fn family(out: &mut String, name: &str, kind: &str, help: &str) {
+    let _ = writeln!(out, "# HELP {} {}", name, help);
+    let _ = writeln!(out, "# TYPE {} {}", name, kind);
+}
+
+fn sample(out: &mut String, name: &str, labels: &str, value: impl std::fmt::Display) {
+    let _ = writeln!(out, "{}{{{}}} {}", name, labels, value);
+}
+
+fn request_labels(labels: &RequestLabels) -> String {
+    format!(
+        "operation=\"{}\",bucket=\"{}\",status=\"{}\"",
+        labels.operation,
+        escape(&labels.bucket),
+        labels.status
+    )
+}
+
+fn bucket_label(bucket: &str) -> String {
+    format!("bucket=\"{}\"", escape(bucket))
+}
+
+// label values escape backslashes, quotes and newlines
+fn escape(value: &str) -> String {
+    value.replace('\\', "\\\\").replace('"', "\\\"").replace('\n', "\\n")
+}
@@ -0,0 +1,480 @@
+use chrono::{DateTime, Utc};
+use percent_encoding::{utf8_percent_encode, AsciiSet, NON_ALPHANUMERIC};
+use serde::{Deserialize, Serialize};
+use serde_json::{json, Value};
+use std::fs::{self, File, OpenOptions};
+use std::io::{self, Write};
+use std::path::Path;
+use std::sync::atomic::{AtomicU64, Ordering};
+use std::sync::{Arc, Mutex};
+use std::time::Duration;
+
+use crate::config::{self, NotificationsConfig, DEFAULT_REGION};
+use crate::error::S3Error;
+use crate::handlers::AppState;
+use crate::sqs::{self, Queues};
+use crate::xml;
+
+// the bucket setting a notification configuration is stored under
+pub const SETTING: &str = "notification";
+
+// the event types a configuration can subscribe to
+pub const EVENT_TYPES: &[&str] = &[
+    "s3:ObjectCreated:*",
+    "s3:ObjectCreated:Put",
+    "s3:ObjectCreated:Post",
+    "s3:ObjectCreated:Copy",
+    "s3:ObjectCreated:CompleteMultipartUpload",
+    "s3:ObjectRemoved:*",
+    "s3:ObjectRemoved:Delete",
+    "s3:ObjectRemoved:DeleteMarkerCreated",
+];
+
+// keys in event records are url-encoded with spaces as '+', as s3 sends them
+const KEY_ENCODE: &AsciiSet = &NON_ALPHANUMERIC
+    .remove(b'-')
+    .remove(b'_')
+    .remove(b'.')
+    .remove(b'~')
+    .remove(b'/');
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+pub enum EventName {
+    #[serde(rename = "ObjectCreated:Put")]
+    ObjectCreatedPut,
+    #[serde(rename = "ObjectCreated:Copy")]
+    ObjectCreatedCopy,
+    #[serde(rename = "ObjectCreated:CompleteMultipartUpload")]
+    ObjectCreatedCompleteMultipartUpload,
+    #[serde(rename = "ObjectRemoved:Delete")]
+    ObjectRemovedDelete,
+}
+
+impl EventName {
+    pub fn as_str(&self) -> &'static str {
+        match self {
+            EventName::ObjectCreatedPut => "ObjectCreated:Put",
+            EventName::ObjectCreatedCopy => "ObjectCreated:Copy",
+            EventName::ObjectCreatedCompleteMultipartUpload => "ObjectCreated:CompleteMultipartUpload",
+            EventName::ObjectRemovedDelete => "ObjectRemoved:Delete",
+        }
+    }
+}
+
+// something that happened to an object
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct Event {
+    pub name: EventName,
+    pub bucket: String,
+    pub key: String,
+    // size and etag of the object created; removals have neither
+    pub size: Option<u64>,
+    pub etag: Option<String>,
+    pub time: DateTime<Utc>,
+    pub request_id: String,
+    pub host_id: String,
+    // orders events for one key: a later event has a greater sequencer
+    pub sequencer: String,
+}
+
+impl Event {
+    // one entry of the Records array in s3's event message format
+    pub fn record(&self, configuration_id: &str, region: &str) -> Value {
+        let mut object = json!({
+            "key": utf8_percent_encode(&self.key, KEY_ENCODE).to_string().replace("%20", "+"),
+            "sequencer": self.sequencer,
+        });
+        if let Some(size) = self.size {
+            object["size"] = json!(size);
+        }
+        if let Some(etag) = &self.etag {
+            object["eTag"] = json!(etag);
+        }
+
+        json!({
+            "eventVersion": "2.1",
+            "eventSource": "aws:s3",
+            "awsRegion": region,
+            "eventTime": self.time.format("%Y-%m-%dT%H:%M:%S%.3fZ").to_string(),
+            "eventName": self.name.as_str(),
+            "userIdentity": { "principalId": "local" },
+            "requestParameters": { "sourceIPAddress": "127.0.0.1" },
+            "responseElements": {
+                "x-amz-request-id": self.request_id,
+                "x-amz-id-2": self.host_id,
+            },
+            "s3": {
+                "s3SchemaVersion": "1.0",
+                "configurationId": configuration_id,
+                "bucket": {
+                    "name": self.bucket,
+                    "ownerIdentity": { "principalId": "local" },
+                    "arn": format!("arn:aws:s3:::{}", self.bucket),
+                },
+                "object": object,
+            },
+        })
+    }
+}
+
+// the three kinds of destination s3 knows; all of them are delivered the same way here
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+pub enum DestinationKind {
+    Topic,
+    Queue,
+    CloudFunction,
+}
+
+impl DestinationKind {
+    const ALL: [DestinationKind; 3] = [DestinationKind::Topic, DestinationKind::Queue, DestinationKind::CloudFunction];
+
+    // the element naming the arn; the rule itself is <{tag}Configuration>
+    fn tag(&self) -> &'static str {
+        match self {
+            DestinationKind::Topic => "Topic",
+            DestinationKind::Queue => "Queue",
+            DestinationKind::CloudFunction => "CloudFunction",
+        }
+    }
+}
+
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct NotificationRule {
+    pub id: String,
+    pub kind: DestinationKind,
+    pub arn: String,
+    pub events: Vec<String>,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub prefix: Option<String>,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub suffix: Option<String>,
+}
+
+impl NotificationRule {
+    pub fn matches(&self, event: &Event) -> bool {
+        let name = format!("s3:{}", event.name.as_str());
+        let subscribed = self.events.iter().any(|pattern| match pattern.strip_suffix('*') {
+            Some(family) => name.starts_with(family),
+            None => *pattern == name,
+        });
+        subscribed
+            && self.prefix.as_deref().is_none_or(|p| event.key.starts_with(p))
+            && self.suffix.as_deref().is_none_or(|s| event.key.ends_with(s))
+    }
+}
+
+// a bucket's notification configuration, stored as json under the notification setting
+#[derive(Debug, Clone, Default, PartialEq, Serialize, Deserialize)]
+pub struct NotificationConfiguration {
+    pub rules: Vec<NotificationRule>,
+}
+
+impl NotificationConfiguration {
+    // a PutBucketNotificationConfiguration body; rules without an id get one from new_id
+    pub fn parse(body: &str, mut new_id: impl FnMut() -> String) -> Result<Self, S3Error> {
+        let root = xml::element(body, "NotificationConfiguration").ok_or_else(S3Error::malformed_xml)?;
+
+        let mut rules = Vec::new();
+        for kind in DestinationKind::ALL {
+            for rule in xml::elements(root, &format!("{}Configuration", kind.tag())) {
+                let arn = xml::text(rule, kind.tag()).ok_or_else(S3Error::malformed_xml)?;
+                let events: Vec<String> = xml::elements(rule, "Event")
+                    .into_iter()
+                    .map(|e| xml::unescape(e.trim()))
+                    .collect();
+                if events.is_empty() {
+                    return Err(S3Error::malformed_xml());
+                }
+                if let Some(unknown) = events.iter().find(|e| !EVENT_TYPES.contains(&e.as_str())) {
+                    return Err(S3Error::invalid_argument(format!(
+                        "The event is not supported for notifications: {}",
+                        unknown
+                    )));
+                }
+
+                let (mut prefix, mut suffix) = (None, None);
+                for filter in xml::elements(rule, "FilterRule") {
+                    let name = xml::text(filter, "Name").unwrap_or_default();
+                    let value = xml::text(filter, "Value").unwrap_or_default();
+                    let slot = match name.to_ascii_lowercase().as_str() {
+                        "prefix" => &mut prefix,
+                        "suffix" => &mut suffix,
+                        _ => {
+                            return Err(S3Error::invalid_argument(format!(
+                                "filter rule name must be either prefix or suffix: {}",
+                                name
+                            )))
+                        }
+                    };
+                    if slot.replace(value).is_some() {
+                        return Err(S3Error::invalid_argument(format!(
+                            "Cannot specify more than one {} rule in a filter.",
+                            name.to_ascii_lowercase()
+                        )));
+                    }
+                }
+
+                rules.push(NotificationRule {
+                    id: xml::text(rule, "Id").filter(|id| !id.is_empty()).unwrap_or_else(&mut new_id),
+                    kind,
+                    arn,
+                    events,
+                    prefix,
+                    suffix,
+                });
+            }
+        }
+
+        Ok(NotificationConfiguration { rules })
+    }
+
+    pub fn to_xml(&self) -> String {
+        let mut rules = String::new();
+        for kind in DestinationKind::ALL {
+            for rule in self.rules.iter().filter(|r| r.kind == kind) {
+                let events: String = rule
+                    .events
+                    .iter()
+                    .map(|e| format!("<Event>{}</Event>", xml::escape(e)))
+                    .collect();
+                let filters: String = [("prefix", &rule.prefix), ("suffix", &rule.suffix)]
+                    .into_iter()
+                    .filter_map(|(name, value)| {
+                        value.as_ref().map(|v| {
+                            format!("<FilterRule><Name>{}</Name><Value>{}</Value></FilterRule>", name, xml::escape(v))
+                        })
+                    })
+                    .collect();
+                let filter = if filters.is_empty() {
+                    String::new()
+                } else {
+                    format!("<Filter><S3Key>{}</S3Key></Filter>", filters)
+                };
+                rules.push_str(&format!(
+                    "<{tag}Configuration><Id>{}</Id><{tag}>{}</{tag}>{}{}</{tag}Configuration>",
+                    xml::escape(&rule.id),
+                    xml::escape(&rule.arn),
+                    events,
+                    filter,
+                    tag = kind.tag()
+                ));
+            }
+        }
+
+        format!(
+            r#"<?xml version="1.0" encoding="UTF-8"?>
+<NotificationConfiguration xmlns="http://s3.amazonaws.com/doc/2006-03-01/">{}</NotificationConfiguration>"#,
+            rules
+        )
+    }
+
+    // every destination has to resolve now, as s3 checks them when the configuration is put
+    pub fn validate_destinations(&self, state: &AppState) -> Result<(), S3Error> {
+        let config = state.config();
+        let unresolved: Vec<&str> = self
+            .rules
+            .iter()
+            .filter(|r| resolve(&r.arn, &config.notifications, &state.queues).is_none())
+            .map(|r| r.arn.as_str())
+            .collect();
+        if unresolved.is_empty() {
+            return Ok(());
+        }
+        Err(S3Error::invalid_argument(format!(
+            "Unable to validate the following destination configurations: {}",
+            unresolved.join(", ")
+        )))
+    }
+}
+
+#[derive(Debug, Clone, PartialEq)]
+pub enum Destination {
+    Webhook(String),
+    // one of the built-in queues, by name
+    Queue(String),
+}
+
+// where a destination arn is delivered: a configured target, one of our own queues, or
+// the arn itself when a url was given in its place
+pub fn resolve(arn: &str, settings: &NotificationsConfig, queues: &Queues) -> Option<Destination> {
+    if let Some(target) = settings.target(arn) {
+        return Some(Destination::Webhook(target.url.clone()));
+    }
+    if let Some(name) = sqs::queue_name_from_arn(arn).filter(|name| queues.exists(name)) {
+        return Some(Destination::Queue(name.to_string()));
+    }
+    config::is_http_url(arn).then(|| Destination::Webhook(arn.to_string()))
+}
+
+pub async fn load(state: &AppState, bucket: &str) -> Result<NotificationConfiguration, io::Error> {
+    match state.storage.get_bucket_setting(bucket, SETTING).await? {
+        Some(stored) => Ok(serde_json::from_str(&stored)?),
+        None => Ok(NotificationConfiguration::default()),
+    }
+}
+
+// hands out sequencers and delivers events in the background
+#[derive(Default)]
+pub struct Notifier {
+    sequence: AtomicU64,
+    http: reqwest::Client,
+    dead_letters: Arc<DeadLetters>,
+}
+
+impl Notifier {
+    pub fn new() -> Self {
+        Notifier::default()
+    }
+
+    pub fn next_sequencer(&self) -> String {
+        format!("{:016X}", self.sequence.fetch_add(1, Ordering::Relaxed) + 1)
+    }
+}
+
+// send an event to every destination of the bucket's configuration that wants it. delivery
+// happens on its own task, so the request that caused the event doesn't wait for it
+pub async fn notify(state: &AppState, event: Event) {
+    let configuration = match load(state, &event.bucket).await {
+        Ok(configuration) => configuration,
+        Err(e) => {
+            tracing::warn!("notifications: could not read the configuration of {}: {}", event.bucket, e);
+            return;
+        }
+    };
+
+    let config = state.config();
+    let region = config
+        .get_bucket(&event.bucket)
+        .map(|b| b.region.as_str())
+        .unwrap_or(DEFAULT_REGION);
+
+    for rule in configuration.rules.iter().filter(|r| r.matches(&event)) {
+        let message = json!({ "Records": [event.record(&rule.id, region)] });
+        let time = state.clock().now();
+        let url = match resolve(&rule.arn, &config.notifications, &state.queues) {
+            // in-process, so there's nothing to retry and consumers see it before the response
+            Some(Destination::Queue(name)) => {
+                let sent = state
+                    .queues
+                    .send(&name, message.to_string(), 0, state.clock(), state.entropy());
+                if let Err(e) = sent {
+                    let delivery = Delivery { arn: rule.arn.clone(), url: None, message };
+                    state.notifier.dead_letters.push(&config.notifications, time, delivery, 1, e.to_string());
+                }
+                continue;
+            }
+            Some(Destination::Webhook(url)) => Some(url),
+            None => None,
+        };
+
+        let delivery = Delivery {
+            arn: rule.arn.clone(),
+            url,
+            message,
+        };
+        let settings = config.notifications.clone();
+        let http = state.notifier.http.clone();
+        let dead_letters = state.notifier.dead_letters.clone();
+        tokio::spawn(async move {
+            if let Err((attempts, error)) = delivery.send(&http, &settings).await {
+                dead_letters.push(&settings, time, delivery, attempts, error);
+            }
+        });
+    }
+}
+
+struct Delivery {
+    arn: String,
+    // None once the target or queue is gone
+    url: Option<String>,
+    message: Value,
+}
+
+impl Delivery {
+    // post the message until it's accepted or the retries run out
+    async fn send(&self, http: &reqwest::Client, settings: &NotificationsConfig) -> Result<(), (u32, String)> {
+        let Some(url) = &self.url else {
+            return Err((0, format!("no notification target for {}", self.arn)));
+        };
+
+        let body = self.message.to_string();
+        let mut backoff = Duration::from_millis(settings.backoff_ms);
+        let mut attempts = 0;
+        loop {
+            attempts += 1;
+            let response = http
+                .post(url)
+                .header("content-type", "application/json")
+                .timeout(Duration::from_millis(settings.timeout_ms))
+                .body(body.clone())
+                .send()
+                .await;
+            let error = match response {
+                Ok(response) if response.status().is_success() => return Ok(()),
+                Ok(response) => format!("{} answered {}", url, response.status()),
+                Err(e) => format!("could not post to {}: {}", url, e),
+            };
+
+            if attempts > settings.retries {
+                return Err((attempts, error));
+            }
+            tracing::debug!("notifications: {}, retrying in {:?}", error, backoff);
+            tokio::time::sleep(backoff).await;
+            backoff *= 2;
+        }
+    }
+}
+
+// a message that couldn't be delivered, as a line of the dead-letter log
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct DeadLetter {
+    pub time: DateTime<Utc>,
+    pub arn: String,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub url: Option<String>,
+    pub attempts: u32,
+    pub error: String,
+    pub message: Value,
+}
+
+#[derive(Default)]
+struct DeadLetters {
+    // the open log and the path it was opened from, reopened when the path changes
+    file: Mutex<Option<(String, File)>>,
+}
+
+impl DeadLetters {
+    fn push(&self, settings: &NotificationsConfig, time: DateTime<Utc>, delivery: Delivery, attempts: u32, error: String) {
+        tracing::warn!("notifications: giving up on {} after {} attempts: {}", delivery.arn, attempts, error);
+        let Some(path) = &settings.dead_letter else {
+            return;
+        };
+
+        let letter = DeadLetter {
+            time,
+            arn: delivery.arn,
+            url: delivery.url,
+            attempts,
+            error,
+            message: delivery.message,
+        };
+        if let Err(e) = self.append(path, &letter) {
+            tracing::warn!("notifications: could not write to {}: {}", path, e);
+        }
+    }
+
+    fn append(&self, path: &str, letter: &DeadLetter) -> io::Result<()> {
+        let mut line = serde_json::to_vec(letter)?;
+        line.push(b'\n');
+
+        let mut file = self.file.lock().unwrap();
+        if file.as_ref().is_none_or(|(open, _)| open != path) {
+            if let Some(parent) = Path::new(path).parent().filter(|p| !p.as_os_str().is_empty()) {
+                fs::create_dir_all(parent)?;
+            }
+            let opened = OpenOptions::new().create(true).append(true).open(path)?;
+            *file = Some((path.to_string(), opened));
+        }
+        file.as_mut().unwrap().1.write_all(&line)
+    }
+}
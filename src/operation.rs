@@ -0,0 +1,86 @@
+use axum::http::{HeaderMap, Method, Uri};
+use percent_encoding::percent_decode_str;
+
+use crate::admin;
+
+// every operation the s3 routes serve, by its name in the s3 api reference
+pub const OPERATIONS: &[&str] = &[
+    "ListBuckets",
+    "CreateBucket",
+    "HeadBucket",
+    "DeleteBucket",
+    "ListObjects",
+    "ListObjectsV2",
+    "ListMultipartUploads",
+    "PutBucketNotificationConfiguration",
+    "GetBucketNotificationConfiguration",
+    "PutBucketLogging",
+    "GetBucketLogging",
+    "PutObject",
+    "CopyObject",
+    "UploadPart",
+    "GetObject",
+    "HeadObject",
+    "DeleteObject",
+    "CreateMultipartUpload",
+    "CompleteMultipartUpload",
+    "AbortMultipartUpload",
+];
+
+// what a request does, worked out the same way the routes and handlers dispatch it
+#[derive(Debug, Clone, PartialEq)]
+pub struct Operation {
+    pub name: &'static str,
+    pub bucket: Option<String>,
+    pub key: Option<String>,
+}
+
+// None for admin requests and anything the s3 routes wouldn't serve
+pub fn classify(method: &Method, uri: &Uri, headers: &HeaderMap) -> Option<Operation> {
+    let path = uri.path();
+    if path == admin::PREFIX || path.starts_with(&format!("{}/", admin::PREFIX)) {
+        return None;
+    }
+
+    let has = |param: &str| {
+        uri.query().is_some_and(|q| {
+            q.split('&')
+                .any(|pair| pair.split('=').next() == Some(param))
+        })
+    };
+    let decode = |s: &str| percent_decode_str(s).decode_utf8_lossy().into_owned();
+
+    let trimmed = path.trim_start_matches('/');
+    let (bucket, key) = match trimmed.split_once('/') {
+        Some((bucket, key)) if !key.is_empty() => (Some(decode(bucket)), Some(decode(key))),
+        Some((bucket, _)) => (Some(decode(bucket)), None),
+        None if trimmed.is_empty() => (None, None),
+        None => (Some(decode(trimmed)), None),
+    };
+
+    let name = match (&bucket, &key, method.as_str()) {
+        (None, _, "GET") => "ListBuckets",
+        (Some(_), None, "PUT") if has("notification") => "PutBucketNotificationConfiguration",
+        (Some(_), None, "PUT") if has("logging") => "PutBucketLogging",
+        (Some(_), None, "PUT") => "CreateBucket",
+        (Some(_), None, "HEAD") => "HeadBucket",
+        (Some(_), None, "DELETE") => "DeleteBucket",
+        (Some(_), None, "GET") if has("uploads") => "ListMultipartUploads",
+        (Some(_), None, "GET") if has("notification") => "GetBucketNotificationConfiguration",
+        (Some(_), None, "GET") if has("logging") => "GetBucketLogging",
+        (Some(_), None, "GET") if has("list-type") => "ListObjectsV2",
+        (Some(_), None, "GET") => "ListObjects",
+        (Some(_), Some(_), "PUT") if has("uploadId") && has("partNumber") => "UploadPart",
+        (Some(_), Some(_), "PUT") if headers.contains_key("x-amz-copy-source") => "CopyObject",
+        (Some(_), Some(_), "PUT") => "PutObject",
+        (Some(_), Some(_), "GET") => "GetObject",
+        (Some(_), Some(_), "HEAD") => "HeadObject",
+        (Some(_), Some(_), "POST") if has("uploads") => "CreateMultipartUpload",
+        (Some(_), Some(_), "POST") if has("uploadId") => "CompleteMultipartUpload",
+        (Some(_), Some(_), "DELETE") if has("uploadId") => "AbortMultipartUpload",
+        (Some(_), Some(_), "DELETE") => "DeleteObject",
+        _ => return None,
+    };
+
+    Some(Operation { name, bucket, key })
+}
@@ -0,0 +1,227 @@
+use notify::{RecursiveMode, Watcher};
+use serde::Serialize;
+use std::fmt;
+use std::path::{Path, PathBuf};
+use std::sync::Arc;
+use std::time::Duration;
+use tokio::sync::mpsc;
+use tokio::task::JoinHandle;
+
+use crate::config::{Config, ConfigError};
+use crate::handlers::AppState;
+
+// editors tend to write a file in several steps; wait for them to settle
+const DEBOUNCE: Duration = Duration::from_millis(250);
+
+// applied to every freshly loaded config, e.g. to keep command-line flags winning
+pub type Overrides = Arc<dyn Fn(&mut Config) + Send + Sync>;
+
+#[derive(Debug, Default, PartialEq)]
+pub struct ConfigDiff {
+    pub added_buckets: Vec<String>,
+    pub removed_buckets: Vec<String>,
+    pub changed_credentials: Vec<String>,
+    pub changed_regions: Vec<String>,
+    pub faults_replaced: bool,
+    // settings read as they're used, which take effect on reload
+    pub changed_settings: Vec<String>,
+    // settings that only take effect after a restart
+    pub restart_required: Vec<String>,
+}
+
+impl ConfigDiff {
+    pub fn between(old: &Config, new: &Config) -> Self {
+        let mut diff = ConfigDiff::default();
+
+        for bucket in &new.buckets {
+            match old.get_bucket(&bucket.name) {
+                None => diff.added_buckets.push(bucket.name.clone()),
+                Some(previous) => {
+                    if previous.access_key != bucket.access_key || previous.secret_key != bucket.secret_key {
+                        diff.changed_credentials.push(bucket.name.clone());
+                    }
+                    if previous.region != bucket.region {
+                        diff.changed_regions.push(bucket.name.clone());
+                    }
+                }
+            }
+        }
+        for bucket in &old.buckets {
+            if new.get_bucket(&bucket.name).is_none() {
+                diff.removed_buckets.push(bucket.name.clone());
+            }
+        }
+
+        diff.faults_replaced = old.faults != new.faults;
+
+        let live = [
+            ("server.domains", differs(&old.server.domains, &new.server.domains)),
+            ("server.drain_secs", differs(&old.server.drain_secs, &new.server.drain_secs)),
+            ("admin", differs(&old.admin, &new.admin)),
+            ("journal", differs(&old.journal, &new.journal)),
+            ("notifications", differs(&old.notifications, &new.notifications)),
+            ("metrics", differs(&old.metrics, &new.metrics)),
+            ("access_logs", differs(&old.access_logs, &new.access_logs)),
+            ("cors", differs(&old.cors, &new.cors)),
+        ];
+        let restart = [
+            ("server.host", differs(&old.server.host, &new.server.host)),
+            ("server.port", differs(&old.server.port, &new.server.port)),
+            ("server.tls", differs(&old.server.tls, &new.server.tls)),
+            ("storage", differs(&old.storage, &new.storage)),
+            ("deterministic", differs(&old.deterministic, &new.deterministic)),
+        ];
+        for (name, _) in live.iter().filter(|(_, changed)| *changed) {
+            diff.changed_settings.push(name.to_string());
+        }
+        for (name, _) in restart.iter().filter(|(_, changed)| *changed) {
+            diff.restart_required.push(name.to_string());
+        }
+
+        diff
+    }
+
+    pub fn is_empty(&self) -> bool {
+        *self == ConfigDiff::default()
+    }
+}
+
+impl fmt::Display for ConfigDiff {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        if self.is_empty() {
+            return write!(f, "no changes");
+        }
+
+        let sections = [
+            ("+bucket", &self.added_buckets),
+            ("-bucket", &self.removed_buckets),
+            ("~credentials", &self.changed_credentials),
+            ("~region", &self.changed_regions),
+            ("~settings", &self.changed_settings),
+            ("needs restart", &self.restart_required),
+        ];
+        let mut parts: Vec<String> = sections
+            .iter()
+            .filter(|(_, items)| !items.is_empty())
+            .map(|(label, items)| format!("{}: {}", label, items.join(", ")))
+            .collect();
+        if self.faults_replaced {
+            parts.push("faults replaced".to_string());
+        }
+        write!(f, "{}", parts.join("; "))
+    }
+}
+
+// sections are compared by what they serialize to, so a field added to one later
+// can't change without showing up in the diff
+fn differs<T: Serialize>(old: &T, new: &T) -> bool {
+    serde_json::to_value(old).ok() != serde_json::to_value(new).ok()
+}
+
+// load the file again and swap it into the running server
+pub async fn reload(state: &AppState, path: &Path, overrides: &Overrides) -> Result<ConfigDiff, ConfigError> {
+    let mut config = Config::load(path)?;
+    overrides(&mut config);
+    config.validate()?;
+
+    let diff = ConfigDiff::between(&state.config(), &config);
+
+    // buckets first, so by the time the new credentials are visible the bucket exists
+    for name in &diff.added_buckets {
+        if let Err(e) = state.storage.create_bucket(name).await {
+            tracing::warn!("config reload: could not create bucket {}: {}", name, e);
+        }
+    }
+
+    // rules added through the admin api survive a reload unless the file's rules changed
+    if diff.faults_replaced {
+        state.faults.replace(config.faults.clone());
+    }
+
+    state.set_config(config);
+    Ok(diff)
+}
+
+// reload on SIGHUP and whenever the file changes; the tasks doing it run until aborted
+pub fn watch(state: Arc<AppState>, path: PathBuf, overrides: Overrides) -> Vec<JoinHandle<()>> {
+    let (tx, rx) = mpsc::unbounded_channel::<()>();
+    let mut tasks = Vec::new();
+
+    #[cfg(unix)]
+    match tokio::signal::unix::signal(tokio::signal::unix::SignalKind::hangup()) {
+        Ok(mut hup) => {
+            let hup_tx = tx.clone();
+            tasks.push(tokio::spawn(async move {
+                while hup.recv().await.is_some() {
+                    if hup_tx.send(()).is_err() {
+                        break;
+                    }
+                }
+            }));
+        }
+        Err(e) => tracing::warn!("config reload: no SIGHUP handler: {}", e),
+    }
+
+    tasks.push(tokio::spawn(follow(state, path, overrides, tx, rx)));
+    tasks
+}
+
+async fn follow(
+    state: Arc<AppState>,
+    path: PathBuf,
+    overrides: Overrides,
+    tx: mpsc::UnboundedSender<()>,
+    mut rx: mpsc::UnboundedReceiver<()>,
+) {
+    // watch the directory, since editors often replace the file rather than write it
+    let file_name = path.file_name().map(|n| n.to_os_string());
+    let watch_dir = path
+        .parent()
+        .filter(|p| !p.as_os_str().is_empty())
+        .unwrap_or(Path::new("."))
+        .to_path_buf();
+
+    let watch_tx = tx.clone();
+    let watcher = notify::recommended_watcher(move |event: notify::Result<notify::Event>| {
+        if let Ok(event) = event {
+            let ours = event.paths.iter().any(|p| p.file_name() == file_name.as_deref());
+            if ours && !event.kind.is_access() {
+                let _ = watch_tx.send(());
+            }
+        }
+    })
+    .and_then(|mut watcher| {
+        watcher.watch(&watch_dir, RecursiveMode::NonRecursive)?;
+        Ok(watcher)
+    });
+    let _watcher = match watcher {
+        Ok(watcher) => Some(watcher),
+        Err(e) => {
+            tracing::warn!("config reload: not watching {}: {}", path.display(), e);
+            None
+        }
+    };
+    drop(tx);
+
+    while rx.recv().await.is_some() {
+        tokio::time::sleep(DEBOUNCE).await;
+        while rx.try_recv().is_ok() {}
+
+        match reload(&state, &path, &overrides).await {
+            Ok(diff) if diff.is_empty() => {
+                tracing::debug!("config reload: {} unchanged", path.display());
+            }
+            Ok(diff) => {
+                tracing::info!("config reloaded from {}: {}", path.display(), diff);
+                if !diff.restart_required.is_empty() {
+                    tracing::warn!(
+                        "config reload: {} changed but only applies after a restart",
+                        diff.restart_required.join(", ")
+                    );
+                }
+            }
+            // keep serving with the old config rather than half-applying a broken one
+            Err(e) => tracing::error!("config reload failed, keeping previous config: {}", e),
+        }
+    }
+}
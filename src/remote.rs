@@ -0,0 +1,241 @@
+use bytes::Bytes;
+use percent_encoding::{utf8_percent_encode, AsciiSet, NON_ALPHANUMERIC};
+use reqwest::Method;
+use serde::de::DeserializeOwned;
+use serde::Deserialize;
+use std::fmt;
+use std::time::Duration;
+
+use crate::admin;
+use crate::storage::{BucketInfo, FsckReport, ObjectMetadata, SnapshotInfo};
+use crate::watch::{Activity, WatchFilter};
+
+// what's escaped in a path segment
+const PATH_SEGMENT: &AsciiSet = &NON_ALPHANUMERIC.remove(b'-').remove(b'_').remove(b'.').remove(b'~');
+
+// talks to the admin api of a running six7
+pub struct AdminClient {
+    endpoint: String,
+    token: Option<String>,
+    http: reqwest::Client,
+}
+
+#[derive(Debug)]
+pub enum RemoteError {
+    Http(reqwest::Error),
+    Api { status: u16, code: String, message: String },
+    Decode(serde_json::Error),
+}
+
+impl fmt::Display for RemoteError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            RemoteError::Http(e) => write!(f, "request failed: {}", e),
+            RemoteError::Api { status, code, message } => write!(f, "{} ({}): {}", code, status, message),
+            RemoteError::Decode(e) => write!(f, "unexpected response: {}", e),
+        }
+    }
+}
+
+impl std::error::Error for RemoteError {}
+
+#[derive(Deserialize)]
+struct ApiError {
+    code: String,
+    message: String,
+}
+
+impl AdminClient {
+    pub fn new(endpoint: impl Into<String>, token: Option<String>) -> Self {
+        AdminClient {
+            endpoint: endpoint.into().trim_end_matches('/').to_string(),
+            token,
+            http: reqwest::Client::new(),
+        }
+    }
+
+    fn build(&self, method: Method, path: &str) -> reqwest::RequestBuilder {
+        let mut request = self
+            .http
+            .request(method, format!("{}{}{}", self.endpoint, admin::PREFIX, path));
+        if let Some(token) = &self.token {
+            request = request.bearer_auth(token);
+        }
+        request
+    }
+
+    async fn request(&self, method: Method, path: &str) -> Result<reqwest::Response, RemoteError> {
+        self.execute(self.build(method, path)).await
+    }
+
+    async fn execute(&self, request: reqwest::RequestBuilder) -> Result<reqwest::Response, RemoteError> {
+        let response = request.send().await.map_err(RemoteError::Http)?;
+        let status = response.status();
+        if status.is_success() {
+            return Ok(response);
+        }
+
+        let body = response.text().await.map_err(RemoteError::Http)?;
+        let (code, message) = match serde_json::from_str::<ApiError>(&body) {
+            Ok(e) => (e.code, e.message),
+            Err(_) => (status.canonical_reason().unwrap_or("Error").to_string(), body),
+        };
+        Err(RemoteError::Api {
+            status: status.as_u16(),
+            code,
+            message,
+        })
+    }
+
+    async fn send(&self, method: Method, path: &str) -> Result<String, RemoteError> {
+        let response = self.request(method, path).await?;
+        response.text().await.map_err(RemoteError::Http)
+    }
+
+    async fn send_json<T: DeserializeOwned>(&self, method: Method, path: &str) -> Result<T, RemoteError> {
+        let body = self.send(method, path).await?;
+        serde_json::from_str(&body).map_err(RemoteError::Decode)
+    }
+
+    pub async fn create_snapshot(&self, name: &str) -> Result<SnapshotInfo, RemoteError> {
+        self.send_json(Method::POST, &format!("/snapshots/{}", name)).await
+    }
+
+    pub async fn restore_snapshot(&self, name: &str) -> Result<(), RemoteError> {
+        self.send(Method::POST, &format!("/snapshots/{}/restore", name)).await?;
+        Ok(())
+    }
+
+    pub async fn list_snapshots(&self) -> Result<Vec<SnapshotInfo>, RemoteError> {
+        self.send_json(Method::GET, "/snapshots").await
+    }
+
+    pub async fn delete_snapshot(&self, name: &str) -> Result<(), RemoteError> {
+        self.send(Method::DELETE, &format!("/snapshots/{}", name)).await?;
+        Ok(())
+    }
+
+    // with repair, what's found is fixed as well as reported
+    pub async fn fsck(&self, repair: bool, abandoned_after: Duration) -> Result<FsckReport, RemoteError> {
+        let method = if repair { Method::POST } else { Method::GET };
+        let path = format!("/fsck?abandoned_after={}", abandoned_after.as_secs());
+        self.send_json(method, &path).await
+    }
+
+    pub async fn list_buckets(&self) -> Result<Vec<BucketInfo>, RemoteError> {
+        self.send_json(Method::GET, "/buckets").await
+    }
+
+    pub async fn create_bucket(&self, bucket: &str) -> Result<(), RemoteError> {
+        self.send(Method::PUT, &format!("/buckets/{}", encode(bucket))).await?;
+        Ok(())
+    }
+
+    pub async fn delete_bucket(&self, bucket: &str) -> Result<(), RemoteError> {
+        self.send(Method::DELETE, &format!("/buckets/{}", encode(bucket))).await?;
+        Ok(())
+    }
+
+    pub async fn list_objects(&self, bucket: &str, prefix: &str) -> Result<Vec<ObjectMetadata>, RemoteError> {
+        let query = serde_urlencoded::to_string([("bucket", bucket), ("prefix", prefix)]).unwrap_or_default();
+        self.send_json(Method::GET, &format!("/objects?{}", query)).await
+    }
+
+    // the body with its content type
+    pub async fn get_object(&self, bucket: &str, key: &str) -> Result<(Bytes, Option<String>), RemoteError> {
+        let response = self.request(Method::GET, &object_path(bucket, key)).await?;
+        let content_type = response
+            .headers()
+            .get(reqwest::header::CONTENT_TYPE)
+            .and_then(|v| v.to_str().ok())
+            .map(str::to_string);
+        let data = response.bytes().await.map_err(RemoteError::Http)?;
+        Ok((data, content_type))
+    }
+
+    pub async fn put_object(
+        &self,
+        bucket: &str,
+        key: &str,
+        data: Bytes,
+        content_type: Option<&str>,
+    ) -> Result<ObjectMetadata, RemoteError> {
+        let mut request = self.build(Method::PUT, &object_path(bucket, key)).body(data);
+        if let Some(content_type) = content_type {
+            request = request.header(reqwest::header::CONTENT_TYPE, content_type);
+        }
+        let body = self.execute(request).await?.text().await.map_err(RemoteError::Http)?;
+        serde_json::from_str(&body).map_err(RemoteError::Decode)
+    }
+
+    pub async fn delete_object(&self, bucket: &str, key: &str) -> Result<(), RemoteError> {
+        self.send(Method::DELETE, &object_path(bucket, key)).await?;
+        Ok(())
+    }
+
+    // follow the server's activity, handing each event to on_event until the server goes
+    // away or on_event returns false
+    pub async fn watch(
+        &self,
+        filter: &WatchFilter,
+        mut on_event: impl FnMut(WatchEvent) -> bool,
+    ) -> Result<(), RemoteError> {
+        let query = serde_urlencoded::to_string(filter).unwrap_or_default();
+        let mut response = self.request(Method::GET, &format!("/events?{}", query)).await?;
+
+        let mut buffer = String::new();
+        while let Some(chunk) = response.chunk().await.map_err(RemoteError::Http)? {
+            buffer.push_str(&String::from_utf8_lossy(&chunk));
+            // events end with a blank line
+            while let Some(end) = buffer.find("\n\n") {
+                let block: String = buffer.drain(..end + 2).collect();
+                let Some(event) = parse_event(&block)? else {
+                    continue;
+                };
+                if !on_event(event) {
+                    return Ok(());
+                }
+            }
+        }
+        Ok(())
+    }
+}
+
+fn encode(segment: &str) -> String {
+    utf8_percent_encode(segment, PATH_SEGMENT).to_string()
+}
+
+// keys keep their slashes, with each segment encoded on its own
+fn object_path(bucket: &str, key: &str) -> String {
+    let key: Vec<String> = key.split('/').map(encode).collect();
+    format!("/objects/{}/{}", encode(bucket), key.join("/"))
+}
+
+#[derive(Debug, Clone, PartialEq)]
+pub enum WatchEvent {
+    Activity(Box<Activity>),
+    // the watcher fell behind and this many requests went by unseen
+    Lagged(u64),
+}
+
+// one server-sent event; keep-alive comments and unknown events are skipped
+fn parse_event(block: &str) -> Result<Option<WatchEvent>, RemoteError> {
+    let mut name = "message";
+    let mut data = String::new();
+    for line in block.lines() {
+        if let Some(value) = line.strip_prefix("event:") {
+            name = value.trim();
+        } else if let Some(value) = line.strip_prefix("data:") {
+            data.push_str(value.strip_prefix(' ').unwrap_or(value));
+        }
+    }
+
+    match name {
+        "activity" => {
+            let activity = serde_json::from_str(&data).map_err(RemoteError::Decode)?;
+            Ok(Some(WatchEvent::Activity(Box::new(activity))))
+        }
+        "lagged" => Ok(data.trim().parse().ok().map(WatchEvent::Lagged)),
+        _ => Ok(None),
+    }
+}
@@ -0,0 +1,580 @@
+use axum::{
+    extract::{Request, State},
+    http::{header, uri::PathAndQuery, HeaderMap, HeaderName, HeaderValue, Method, StatusCode, Uri},
+    middleware::{self, Next},
+    response::{IntoResponse, Response},
+    routing::{get, put},
+    extract::connect_info::Connected,
+    serve::{IncomingStream, Listener},
+    Router,
+};
+use base64::{engine::general_purpose::STANDARD as BASE64, Engine};
+use futures::FutureExt;
+use std::future::{Future, IntoFuture};
+use std::net::SocketAddr;
+use std::path::PathBuf;
+use std::sync::Arc;
+use std::time::Duration;
+use tokio::net::TcpListener;
+use tokio::sync::oneshot;
+use tokio::task::JoinHandle;
+
+use crate::access_log;
+use crate::admin;
+use crate::auth;
+use crate::clock::Clock;
+use crate::config::{BucketConfig, Config, CorsConfig, TlsConfig};
+use crate::entropy::Entropy;
+use crate::error::S3Error;
+use crate::faults;
+use crate::handlers::{self, AppState, RequestIds};
+use crate::journal;
+use crate::metrics;
+use crate::operation;
+use crate::reload::{self, Overrides};
+use crate::sqs;
+use crate::storage::{self, MemoryStorage, Storage, StorageBackend};
+use crate::tls::{self, TlsListener};
+use crate::watch;
+
+// the full s3 route table, shared by the binary, embedders and tests
+pub fn router(state: Arc<AppState>) -> Router {
+    let s3 = Router::new()
+        .route("/", get(handlers::list_buckets))
+        .route("/{bucket}",
+            put(handlers::create_bucket)
+                .head(handlers::head_bucket)
+                .get(handlers::list_bucket_objects)
+                .delete(handlers::delete_bucket)
+        )
+        .route("/{bucket}/{*key}",
+            put(handlers::put_object)
+                .get(handlers::get_object)
+                .post(handlers::post_object)
+                .delete(handlers::delete_object)
+                .head(handlers::head_object)
+        )
+        .nest(admin::PREFIX, admin::router(state.clone()))
+        .layer(middleware::from_fn_with_state(state.clone(), cors))
+        .with_state(state.clone());
+
+    // scrapes and sqs requests are answered before anything s3 sees them,
+    // virtual-hosted-style requests have to be rewritten before the routes are matched, the
+    // journal, watchers and metrics see what clients get, faults included, and faults are
+    // injected once the request is known but before a handler runs
+    let service = tower::ServiceBuilder::new()
+        .layer(middleware::from_fn_with_state(state.clone(), metrics::serve))
+        .layer(middleware::from_fn_with_state(state.clone(), sqs::protocol::intercept))
+        .layer(middleware::from_fn_with_state(state.clone(), virtual_host))
+        .layer(middleware::from_fn_with_state(state.clone(), s3_headers))
+        .layer(middleware::from_fn_with_state(state.clone(), journal::record))
+        .layer(middleware::from_fn_with_state(state.clone(), access_log::record))
+        .layer(middleware::from_fn_with_state(state.clone(), watch::observe))
+        .layer(middleware::from_fn_with_state(state.clone(), metrics::track))
+        .layer(middleware::from_fn_with_state(state.clone(), faults::inject))
+        .layer(middleware::from_fn_with_state(state, reject_expired))
+        .service(s3);
+    Router::new().fallback_service(service)
+}
+
+// cors headers from the config as it is now, so a reload applies to the next request.
+// preflights are answered here; the browser refuses what they don't allow
+async fn cors(State(state): State<Arc<AppState>>, request: Request, next: Next) -> Response {
+    let config = state.config();
+    let cors = &config.cors;
+    let origin = request
+        .headers()
+        .get(header::ORIGIN)
+        .and_then(|o| o.to_str().ok())
+        .map(str::to_string);
+    let allowed = cors.allow_origin(origin.as_deref().unwrap_or_default()).map(str::to_string);
+
+    if request.method() == Method::OPTIONS {
+        let mut response = StatusCode::OK.into_response();
+        let requested = request
+            .headers()
+            .get(header::ACCESS_CONTROL_REQUEST_METHOD)
+            .and_then(|m| m.to_str().ok())
+            .unwrap_or_default();
+        if let Some(allowed) = allowed.filter(|_| cors.allows_method(requested)) {
+            let headers = response.headers_mut();
+            set_header(headers, header::ACCESS_CONTROL_ALLOW_ORIGIN, &allowed);
+            set_header(headers, header::ACCESS_CONTROL_ALLOW_METHODS, &cors.allowed_methods.join(","));
+            set_header(headers, header::ACCESS_CONTROL_ALLOW_HEADERS, &cors.allowed_headers.join(","));
+            if let Some(max_age) = cors.max_age_secs {
+                set_header(headers, header::ACCESS_CONTROL_MAX_AGE, &max_age.to_string());
+            }
+        }
+        vary_on_origin(&mut response, cors);
+        return response;
+    }
+
+    let mut response = next.run(request).await;
+    if let Some(allowed) = allowed {
+        let headers = response.headers_mut();
+        set_header(headers, header::ACCESS_CONTROL_ALLOW_ORIGIN, &allowed);
+        if !cors.expose_headers.is_empty() {
+            set_header(headers, header::ACCESS_CONTROL_EXPOSE_HEADERS, &cors.expose_headers.join(","));
+        }
+    }
+    vary_on_origin(&mut response, cors);
+    response
+}
+
+fn set_header(headers: &mut HeaderMap, name: HeaderName, value: &str) {
+    if let Ok(value) = HeaderValue::from_str(value) {
+        headers.insert(name, value);
+    }
+}
+
+// an answer that depends on the origin mustn't be cached for another one
+fn vary_on_origin(response: &mut Response, cors: &CorsConfig) {
+    if !cors.allowed_origins.iter().any(|o| o == "*") {
+        response.headers_mut().append(header::VARY, HeaderValue::from_static("origin"));
+    }
+}
+
+// bucket.s3.localhost/key -> /bucket/key; anything else is left as path style
+async fn virtual_host(State(state): State<Arc<AppState>>, mut request: Request, next: Next) -> Response {
+    let host = request
+        .headers()
+        .get(header::HOST)
+        .and_then(|h| h.to_str().ok())
+        .or_else(|| request.uri().host())
+        .map(str::to_string);
+
+    let bucket = host.and_then(|host| bucket_from_host(&host, &state.config().server.domains));
+    if let Some(bucket) = bucket {
+        if let Some(uri) = prefix_path(request.uri(), &bucket) {
+            *request.uri_mut() = uri;
+        }
+    }
+
+    next.run(request).await
+}
+
+// x-amz-request-id and x-amz-id-2 on every s3 response, as clients log them for support,
+// and a Date from the virtual clock; hyper only adds its own when there's none
+async fn s3_headers(State(state): State<Arc<AppState>>, mut request: Request, next: Next) -> Response {
+    if operation::classify(request.method(), request.uri(), request.headers()).is_none() {
+        return next.run(request).await;
+    }
+
+    // drawn before the request runs, so concurrent requests can't reorder what a
+    // deterministic run hands out within one request
+    let request_id = state.entropy().hex_id(16);
+    let host_id = BASE64.encode(state.entropy().bytes(48));
+    request.extensions_mut().insert(RequestIds {
+        request_id: request_id.clone(),
+        host_id: host_id.clone(),
+    });
+    let mut response = next.run(request).await;
+    let headers = response.headers_mut();
+    headers.insert("x-amz-request-id", HeaderValue::from_str(&request_id).unwrap());
+    headers.insert("x-amz-id-2", HeaderValue::from_str(&host_id).unwrap());
+    let date = state.clock().now().format("%a, %d %b %Y %H:%M:%S GMT").to_string();
+    headers.insert(header::DATE, HeaderValue::from_str(&date).unwrap());
+    response
+}
+
+// presigned urls stop working once the clock passes X-Amz-Date + X-Amz-Expires
+async fn reject_expired(State(state): State<Arc<AppState>>, request: Request, next: Next) -> Response {
+    let query = request.uri().query().unwrap_or("");
+    match auth::presigned_url_expired(query, state.clock()) {
+        Ok(true) => return S3Error::request_expired().into_response(),
+        Err(e) => return e.into_response(),
+        Ok(false) => {}
+    }
+    next.run(request).await
+}
+
+// the bucket named by a host under one of the base domains, if any
+pub fn bucket_from_host(host: &str, domains: &[String]) -> Option<String> {
+    let host = host.to_ascii_lowercase();
+    // strip the port, leaving ipv6 literals alone since they can't carry a bucket anyway
+    let host = match host.rsplit_once(':') {
+        Some((name, port)) if !name.contains(':') && port.bytes().all(|b| b.is_ascii_digit()) => name,
+        _ => host.as_str(),
+    };
+    let host = host.trim_end_matches('.');
+
+    domains.iter().find_map(|domain| {
+        let domain = domain.strip_prefix("*.").unwrap_or(domain);
+        let bucket = host.strip_suffix(domain)?.strip_suffix('.')?;
+        storage::validate_bucket_name(bucket).ok()?;
+        Some(bucket.to_string())
+    })
+}
+
+fn prefix_path(uri: &Uri, bucket: &str) -> Option<Uri> {
+    let path = match uri.path() {
+        "/" => format!("/{}", bucket),
+        path => format!("/{}{}", bucket, path),
+    };
+    let path_and_query = match uri.query() {
+        Some(query) => format!("{}?{}", path, query),
+        None => path,
+    };
+
+    let mut parts = uri.clone().into_parts();
+    parts.path_and_query = Some(PathAndQuery::try_from(path_and_query).ok()?);
+    Uri::from_parts(parts).ok()
+}
+
+pub struct Server;
+
+impl Server {
+    pub fn builder() -> ServerBuilder {
+        ServerBuilder::default()
+    }
+}
+
+// defaults to an in-memory store on 127.0.0.1 with an os-assigned port
+pub struct ServerBuilder {
+    host: String,
+    port: u16,
+    config: Option<Config>,
+    storage: Option<Arc<dyn StorageBackend>>,
+    buckets: Vec<String>,
+    tls: Option<TlsConfig>,
+    watch: Option<(PathBuf, Overrides)>,
+    in_memory: bool,
+}
+
+fn add_buckets(config: &mut Config, names: &[String]) {
+    for name in names {
+        if config.get_bucket(name).is_none() {
+            config.buckets.push(BucketConfig::new(name.as_str()));
+        }
+    }
+}
+
+// a port asked for and the one bound, which differ when the request was for port 0
+#[derive(Debug, Clone, Copy)]
+struct Ports {
+    requested: u16,
+    bound: u16,
+}
+
+impl Ports {
+    fn resolve(&self, port: u16) -> u16 {
+        match port == self.requested {
+            true => self.bound,
+            false => port,
+        }
+    }
+}
+
+// the address a connection came from, whichever listener accepted it
+#[derive(Debug, Clone, Copy)]
+pub struct PeerAddr(pub SocketAddr);
+
+impl Connected<IncomingStream<'_, TcpListener>> for PeerAddr {
+    fn connect_info(stream: IncomingStream<'_, TcpListener>) -> Self {
+        PeerAddr(*stream.remote_addr())
+    }
+}
+
+impl Connected<IncomingStream<'_, TlsListener>> for PeerAddr {
+    fn connect_info(stream: IncomingStream<'_, TlsListener>) -> Self {
+        PeerAddr(*stream.remote_addr())
+    }
+}
+
+// what a built server needs to run: the sockets, the app and tasks that live alongside it
+struct Built {
+    listener: TcpListener,
+    tls: Option<TlsListener>,
+    app: Router,
+    state: Arc<AppState>,
+    tasks: Vec<JoinHandle<()>>,
+}
+
+impl Built {
+    async fn run<F>(self, shutdown: F) -> Result<(), std::io::Error>
+    where
+        F: Future<Output = ()> + Send + 'static,
+    {
+        // watchers are told first, as their streams would otherwise hold the drain open
+        let state = self.state.clone();
+        let shutdown = async move {
+            shutdown.await;
+            state.watchers.close();
+        }
+        .shared();
+        // the peer address is what access logs record as the remote ip
+        let app = self.app.into_make_service_with_connect_info::<PeerAddr>();
+        let plain = axum::serve(self.listener, app.clone())
+            .with_graceful_shutdown(shutdown.clone())
+            .into_future();
+        let secure = self.tls.map(|tls| {
+            axum::serve(tls, app)
+                .with_graceful_shutdown(shutdown.clone())
+                .into_future()
+        });
+        let serving = async move {
+            match secure {
+                Some(secure) => {
+                    let (plain, secure) = tokio::join!(plain, secure);
+                    plain.and(secure)
+                }
+                None => plain.await,
+            }
+        };
+
+        // once shutdown starts nothing new is accepted, and requests in flight get the
+        // drain timeout to finish
+        let state = self.state.clone();
+        let drained = async move {
+            shutdown.await;
+            let drain = Duration::from_secs(state.config().server.drain_secs);
+            tracing::info!("six7 shutting down, draining requests for up to {:?}", drain);
+            tokio::time::sleep(drain).await;
+        };
+        let result = tokio::select! {
+            result = serving => result,
+            _ = drained => {
+                tracing::warn!("six7 stopped with requests still in flight after the drain timeout");
+                Ok(())
+            }
+        };
+
+        for task in self.tasks {
+            task.abort();
+        }
+        // whatever was logged since the last flush
+        access_log::flush(&self.state).await;
+        result
+    }
+}
+
+impl Default for ServerBuilder {
+    fn default() -> Self {
+        ServerBuilder {
+            host: "127.0.0.1".to_string(),
+            port: 0,
+            config: None,
+            storage: None,
+            buckets: Vec::new(),
+            tls: None,
+            watch: None,
+            in_memory: false,
+        }
+    }
+}
+
+impl ServerBuilder {
+    // takes host, port, storage path and buckets from the config
+    pub fn config(mut self, config: Config) -> Self {
+        self.host = config.server.host.clone();
+        self.port = config.server.port;
+        self.tls = config.server.tls.clone();
+        self.config = Some(config);
+        self
+    }
+
+    pub fn host(mut self, host: impl Into<String>) -> Self {
+        self.host = host.into();
+        self
+    }
+
+    pub fn port(mut self, port: u16) -> Self {
+        self.port = port;
+        self
+    }
+
+    pub fn bucket(mut self, name: impl Into<String>) -> Self {
+        self.buckets.push(name.into());
+        self
+    }
+
+    // a storage built elsewhere keeps its own clock and ids, whatever the config says
+    pub fn storage(mut self, storage: impl StorageBackend + 'static) -> Self {
+        self.storage = Some(Arc::new(storage));
+        self
+    }
+
+    // keep objects in memory even though the config names a storage path
+    pub fn in_memory(mut self) -> Self {
+        self.in_memory = true;
+        self
+    }
+
+    // also serve https, on tls.port (0 for any free port)
+    pub fn tls(mut self, tls: TlsConfig) -> Self {
+        self.tls = Some(tls);
+        self
+    }
+
+    // reload the config from this file on change or SIGHUP; overrides run on every load
+    pub fn watch_config<F>(mut self, path: impl Into<PathBuf>, overrides: F) -> Self
+    where
+        F: Fn(&mut Config) + Send + Sync + 'static,
+    {
+        self.watch = Some((path.into(), Arc::new(overrides)));
+        self
+    }
+
+    async fn build(self) -> Result<Built, std::io::Error> {
+        let deterministic = self.config.as_ref().and_then(|c| c.deterministic.as_ref());
+        let (clock, entropy) = match deterministic {
+            Some(settings) => (Clock::frozen_at(settings.start), Entropy::seeded(settings.seed)),
+            None => (Clock::new(), Entropy::new()),
+        };
+        let storage: Arc<dyn StorageBackend> = match (self.storage, &self.config) {
+            (Some(storage), _) => storage,
+            (None, Some(config)) if !self.in_memory => Arc::new(
+                Storage::new(&config.storage.path)?
+                    .with_clock(clock)
+                    .with_entropy(entropy),
+            ),
+            (None, _) => Arc::new(MemoryStorage::new().with_clock(clock).with_entropy(entropy)),
+        };
+
+        let mut config = self.config.unwrap_or_default();
+        config.server.host = self.host;
+        config.server.tls = self.tls;
+        // buckets added on the builder are in the config like any other, so a reset keeps
+        // them and the config dump shows them
+        add_buckets(&mut config, &self.buckets);
+
+        let buckets: Vec<String> = config.buckets.iter().map(|b| b.name.clone()).collect();
+        for bucket in &buckets {
+            storage.create_bucket(bucket).await?;
+        }
+
+        // the config has the ports actually bound, which differ when port 0 was asked for
+        let listener = TcpListener::bind((config.server.host.as_str(), self.port)).await?;
+        let port = Ports {
+            requested: self.port,
+            bound: listener.local_addr()?.port(),
+        };
+        config.server.port = port.bound;
+        let (tls, tls_port) = match config.server.tls.clone() {
+            Some(settings) => {
+                let rustls = tls::server_config(&settings, &tls::subject_names(&config.server, &buckets))?;
+                let socket = TcpListener::bind((config.server.host.as_str(), settings.port)).await?;
+                let ports = Ports {
+                    requested: settings.port,
+                    bound: socket.local_addr()?.port(),
+                };
+                if let Some(settings) = &mut config.server.tls {
+                    settings.port = ports.bound;
+                }
+                (Some(TlsListener::new(socket, rustls)?), Some(ports))
+            }
+            None => (None, None),
+        };
+
+        let state = Arc::new(AppState::from_parts(storage, config));
+
+        let mut tasks = Vec::new();
+        if let Some((path, overrides)) = self.watch {
+            // a reloaded file says the same as it did at start, so what the builder added
+            // and the ports it bound stay as they are
+            let added = self.buckets;
+            let overrides: Overrides = Arc::new(move |config: &mut Config| {
+                overrides(config);
+                add_buckets(config, &added);
+                config.server.port = port.resolve(config.server.port);
+                if let (Some(settings), Some(tls_port)) = (&mut config.server.tls, tls_port) {
+                    settings.port = tls_port.resolve(settings.port);
+                }
+            });
+            tasks.extend(reload::watch(state.clone(), path, overrides));
+        }
+        tasks.push(tokio::spawn(access_log::flush_periodically(state.clone())));
+
+        Ok(Built {
+            listener,
+            tls,
+            app: router(state.clone()),
+            state,
+            tasks,
+        })
+    }
+
+    // bind and serve on the current task until the process exits
+    pub async fn serve(self) -> Result<(), std::io::Error> {
+        self.serve_with_shutdown(std::future::pending()).await
+    }
+
+    // bind and serve on the current task until the shutdown future resolves
+    pub async fn serve_with_shutdown<F>(self, shutdown: F) -> Result<(), std::io::Error>
+    where
+        F: Future<Output = ()> + Send + 'static,
+    {
+        let built = self.build().await?;
+        tracing::info!("six7 listening on http://{}", built.listener.local_addr()?);
+        if let Some(tls) = &built.tls {
+            tracing::info!("six7 listening on https://{}", tls.local_addr()?);
+        }
+
+        built.run(shutdown).await
+    }
+
+    // bind and serve on a background task; dropping the handle stops the server
+    pub async fn spawn(self) -> Result<RunningServer, std::io::Error> {
+        let built = self.build().await?;
+        let addr = built.listener.local_addr()?;
+        let tls_addr = match &built.tls {
+            Some(tls) => Some(tls.local_addr()?),
+            None => None,
+        };
+        let (shutdown, signal) = oneshot::channel::<()>();
+
+        let task = tokio::spawn(built.run(async move {
+            let _ = signal.await;
+        }));
+
+        Ok(RunningServer {
+            addr,
+            tls_addr,
+            shutdown: Some(shutdown),
+            task,
+        })
+    }
+}
+
+pub struct RunningServer {
+    addr: SocketAddr,
+    tls_addr: Option<SocketAddr>,
+    shutdown: Option<oneshot::Sender<()>>,
+    task: JoinHandle<Result<(), std::io::Error>>,
+}
+
+impl RunningServer {
+    pub fn addr(&self) -> SocketAddr {
+        self.addr
+    }
+
+    pub fn url(&self) -> String {
+        format!("http://{}", self.addr)
+    }
+
+    pub fn tls_addr(&self) -> Option<SocketAddr> {
+        self.tls_addr
+    }
+
+    pub fn tls_url(&self) -> Option<String> {
+        self.tls_addr.map(|addr| format!("https://{}", addr))
+    }
+
+    // stop accepting connections and wait for in-flight requests to finish
+    pub async fn shutdown(mut self) -> Result<(), std::io::Error> {
+        if let Some(shutdown) = self.shutdown.take() {
+            let _ = shutdown.send(());
+        }
+        (&mut self.task)
+            .await
+            .map_err(std::io::Error::other)?
+    }
+}
+
+impl Drop for RunningServer {
+    fn drop(&mut self) {
+        if let Some(shutdown) = self.shutdown.take() {
+            let _ = shutdown.send(());
+        }
+    }
+}
@@ -0,0 +1,413 @@
+use axum::http::StatusCode;
+use chrono::{DateTime, TimeDelta, Utc};
+use std::collections::{BTreeMap, VecDeque};
+use std::fmt;
+use std::sync::Mutex;
+use tokio::sync::Notify;
+
+use crate::clock::Clock;
+use crate::entropy::Entropy;
+
+pub mod protocol;
+
+// queues live under one made-up account, as in their urls and arns
+pub const ACCOUNT_ID: &str = "000000000000";
+pub const DEFAULT_VISIBILITY_TIMEOUT: u32 = 30;
+pub const MAX_VISIBILITY_TIMEOUT: u32 = 43200;
+pub const MAX_WAIT_SECONDS: u32 = 20;
+pub const MAX_RECEIVE: usize = 10;
+pub const MAX_MESSAGE_BYTES: usize = 262_144;
+pub const MAX_DELAY_SECONDS: u32 = 900;
+const MAX_QUEUE_NAME_LEN: usize = 80;
+
+// an sqs error: its code in the json protocol, and the one the query protocol uses
+#[derive(Debug, Clone)]
+pub struct SqsError {
+    pub status: StatusCode,
+    pub code: &'static str,
+    pub query_code: &'static str,
+    pub message: String,
+}
+
+impl SqsError {
+    fn new(code: &'static str, query_code: &'static str, message: impl Into<String>) -> Self {
+        SqsError {
+            status: StatusCode::BAD_REQUEST,
+            code,
+            query_code,
+            message: message.into(),
+        }
+    }
+
+    pub fn no_such_queue() -> Self {
+        Self::new(
+            "QueueDoesNotExist",
+            "AWS.SimpleQueueService.NonExistentQueue",
+            "The specified queue does not exist.",
+        )
+    }
+
+    pub fn queue_exists(name: &str) -> Self {
+        Self::new(
+            "QueueNameExists",
+            "QueueAlreadyExists",
+            format!("A queue already exists with the same name and a different value for attribute(s): {}", name),
+        )
+    }
+
+    pub fn invalid_parameter(message: impl Into<String>) -> Self {
+        Self::new("InvalidParameterValue", "InvalidParameterValue", message)
+    }
+
+    pub fn missing_parameter(name: &str) -> Self {
+        Self::new(
+            "MissingParameter",
+            "MissingParameter",
+            format!("The request must contain the parameter {}.", name),
+        )
+    }
+
+    pub fn invalid_receipt_handle() -> Self {
+        Self::new(
+            "ReceiptHandleIsInvalid",
+            "ReceiptHandleIsInvalid",
+            "The input receipt handle is invalid.",
+        )
+    }
+
+    pub fn message_not_inflight() -> Self {
+        Self::new(
+            "MessageNotInflight",
+            "AWS.SimpleQueueService.MessageNotInflight",
+            "The message referred to isn't in flight.",
+        )
+    }
+
+    pub fn unsupported(action: &str) -> Self {
+        Self::new(
+            "UnsupportedOperation",
+            "AWS.SimpleQueueService.UnsupportedOperation",
+            format!("six7 doesn't support the {} action", action),
+        )
+    }
+}
+
+impl fmt::Display for SqsError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "{}: {}", self.code, self.message)
+    }
+}
+
+impl std::error::Error for SqsError {}
+
+#[derive(Debug, Clone)]
+pub struct Message {
+    pub id: String,
+    pub body: String,
+    pub md5: String,
+    pub sent: DateTime<Utc>,
+    // hidden from receivers until then
+    pub visible_at: DateTime<Utc>,
+    // from the latest receive; only this one can delete the message
+    pub receipt_handle: Option<String>,
+    pub receive_count: u32,
+    pub first_received: Option<DateTime<Utc>>,
+}
+
+#[derive(Debug, Clone)]
+pub struct Queue {
+    pub name: String,
+    pub created: DateTime<Utc>,
+    pub visibility_timeout: u32,
+    pub messages: VecDeque<Message>,
+}
+
+impl Queue {
+    pub fn arn(&self, region: &str) -> String {
+        queue_arn(&self.name, region)
+    }
+
+    pub fn visible(&self, now: DateTime<Utc>) -> usize {
+        self.messages.iter().filter(|m| m.visible_at <= now).count()
+    }
+}
+
+pub fn queue_arn(name: &str, region: &str) -> String {
+    format!("arn:aws:sqs:{}:{}:{}", region, ACCOUNT_ID, name)
+}
+
+// the queue an arn names, whatever region it claims
+pub fn queue_name_from_arn(arn: &str) -> Option<&str> {
+    let rest = arn.strip_prefix("arn:aws:sqs:")?;
+    let mut parts = rest.splitn(3, ':');
+    let (_region, account, name) = (parts.next()?, parts.next()?, parts.next()?);
+    (account == ACCOUNT_ID && validate_queue_name(name).is_ok()).then_some(name)
+}
+
+// the last path segment of a queue url, e.g. http://localhost:4040/000000000000/jobs
+pub fn queue_name_from_url(url: &str) -> &str {
+    url.trim_end_matches('/').rsplit('/').next().unwrap_or(url)
+}
+
+pub fn validate_queue_name(name: &str) -> Result<(), SqsError> {
+    if name.ends_with(".fifo") {
+        return Err(SqsError::invalid_parameter("six7 doesn't support FIFO queues"));
+    }
+    let valid = !name.is_empty()
+        && name.len() <= MAX_QUEUE_NAME_LEN
+        && name.bytes().all(|b| b.is_ascii_alphanumeric() || b == b'-' || b == b'_');
+    if !valid {
+        return Err(SqsError::invalid_parameter(
+            "Can only include alphanumeric characters, hyphens, or underscores. 1 to 80 in length",
+        ));
+    }
+    Ok(())
+}
+
+pub fn validate_visibility_timeout(seconds: u32) -> Result<(), SqsError> {
+    if seconds > MAX_VISIBILITY_TIMEOUT {
+        return Err(SqsError::invalid_parameter(format!(
+            "Value {} for parameter VisibilityTimeout is invalid. Reason: Must be between 0 and {}.",
+            seconds, MAX_VISIBILITY_TIMEOUT
+        )));
+    }
+    Ok(())
+}
+
+// every queue, in memory only; receivers waiting on an empty queue are woken by sends
+#[derive(Default)]
+pub struct Queues {
+    queues: Mutex<BTreeMap<String, Queue>>,
+    sent: Notify,
+}
+
+impl Queues {
+    pub fn new() -> Self {
+        Queues::default()
+    }
+
+    // creating a queue that exists is fine as long as the attributes agree
+    pub fn create(&self, name: &str, visibility_timeout: Option<u32>, now: DateTime<Utc>) -> Result<(), SqsError> {
+        validate_queue_name(name)?;
+        if let Some(seconds) = visibility_timeout {
+            validate_visibility_timeout(seconds)?;
+        }
+
+        let mut queues = self.queues.lock().unwrap();
+        if let Some(queue) = queues.get(name) {
+            if visibility_timeout.is_some_and(|v| v != queue.visibility_timeout) {
+                return Err(SqsError::queue_exists(name));
+            }
+            return Ok(());
+        }
+        queues.insert(
+            name.to_string(),
+            Queue {
+                name: name.to_string(),
+                created: now,
+                visibility_timeout: visibility_timeout.unwrap_or(DEFAULT_VISIBILITY_TIMEOUT),
+                messages: VecDeque::new(),
+            },
+        );
+        Ok(())
+    }
+
+    pub fn delete(&self, name: &str) -> Result<(), SqsError> {
+        match self.queues.lock().unwrap().remove(name) {
+            Some(_) => Ok(()),
+            None => Err(SqsError::no_such_queue()),
+        }
+    }
+
+    pub fn exists(&self, name: &str) -> bool {
+        self.queues.lock().unwrap().contains_key(name)
+    }
+
+    // a copy of the queue as it is now
+    pub fn get(&self, name: &str) -> Result<Queue, SqsError> {
+        self.queues
+            .lock()
+            .unwrap()
+            .get(name)
+            .cloned()
+            .ok_or_else(SqsError::no_such_queue)
+    }
+
+    pub fn names(&self, prefix: Option<&str>) -> Vec<String> {
+        self.queues
+            .lock()
+            .unwrap()
+            .keys()
+            .filter(|name| prefix.is_none_or(|p| name.starts_with(p)))
+            .cloned()
+            .collect()
+    }
+
+    pub fn set_visibility_timeout(&self, name: &str, seconds: u32) -> Result<(), SqsError> {
+        validate_visibility_timeout(seconds)?;
+        let mut queues = self.queues.lock().unwrap();
+        let queue = queues.get_mut(name).ok_or_else(SqsError::no_such_queue)?;
+        queue.visibility_timeout = seconds;
+        Ok(())
+    }
+
+    // delay keeps the message hidden for that many seconds after it's sent
+    pub fn send(
+        &self,
+        name: &str,
+        body: String,
+        delay: u32,
+        clock: &Clock,
+        entropy: &Entropy,
+    ) -> Result<Message, SqsError> {
+        if delay > MAX_DELAY_SECONDS {
+            return Err(SqsError::invalid_parameter(format!(
+                "Value {} for parameter DelaySeconds is invalid. Reason: Must be between 0 and {}.",
+                delay, MAX_DELAY_SECONDS
+            )));
+        }
+        if body.is_empty() {
+            return Err(SqsError::missing_parameter("MessageBody"));
+        }
+        if body.len() > MAX_MESSAGE_BYTES {
+            return Err(SqsError::invalid_parameter(format!(
+                "One or more parameters are invalid. Reason: Message must be shorter than {} bytes.",
+                MAX_MESSAGE_BYTES
+            )));
+        }
+
+        let now = clock.now();
+        let message = Message {
+            id: entropy.uuid().to_string(),
+            md5: format!("{:x}", md5::compute(&body)),
+            body,
+            sent: now,
+            visible_at: now + TimeDelta::seconds(delay as i64),
+            receipt_handle: None,
+            receive_count: 0,
+            first_received: None,
+        };
+
+        let mut queues = self.queues.lock().unwrap();
+        let queue = queues.get_mut(name).ok_or_else(SqsError::no_such_queue)?;
+        queue.messages.push_back(message.clone());
+        drop(queues);
+
+        self.sent.notify_waiters();
+        Ok(message)
+    }
+
+    // hand out up to max visible messages, oldest first, hiding each for the visibility
+    // timeout; waits up to wait for one to turn up if none are visible
+    pub async fn receive(
+        &self,
+        name: &str,
+        max: usize,
+        visibility_timeout: Option<u32>,
+        wait: std::time::Duration,
+        clock: &Clock,
+        entropy: &Entropy,
+    ) -> Result<Vec<Message>, SqsError> {
+        if !(1..=MAX_RECEIVE).contains(&max) {
+            return Err(SqsError::invalid_parameter(format!(
+                "Value {} for parameter MaxNumberOfMessages is invalid. Reason: Must be between 1 and {}.",
+                max, MAX_RECEIVE
+            )));
+        }
+        if let Some(seconds) = visibility_timeout {
+            validate_visibility_timeout(seconds)?;
+        }
+
+        let deadline = tokio::time::Instant::now() + wait;
+        loop {
+            // registered before looking, so a send in between still wakes us
+            let sent = self.sent.notified();
+            let received = self.take_visible(name, max, visibility_timeout, clock, entropy)?;
+            if !received.is_empty() || tokio::time::Instant::now() >= deadline {
+                return Ok(received);
+            }
+            // messages also come back when their visibility timeout runs out, which
+            // nothing announces, so look again every so often
+            let next_look = deadline.min(tokio::time::Instant::now() + std::time::Duration::from_millis(100));
+            let _ = tokio::time::timeout_at(next_look, sent).await;
+        }
+    }
+
+    fn take_visible(
+        &self,
+        name: &str,
+        max: usize,
+        visibility_timeout: Option<u32>,
+        clock: &Clock,
+        entropy: &Entropy,
+    ) -> Result<Vec<Message>, SqsError> {
+        let now = clock.now();
+        let mut queues = self.queues.lock().unwrap();
+        let queue = queues.get_mut(name).ok_or_else(SqsError::no_such_queue)?;
+        let hidden_for = TimeDelta::seconds(visibility_timeout.unwrap_or(queue.visibility_timeout) as i64);
+
+        let mut received = Vec::new();
+        for message in queue.messages.iter_mut().filter(|m| m.visible_at <= now).take(max) {
+            message.visible_at = now + hidden_for;
+            message.receipt_handle = Some(receipt_handle(entropy));
+            message.receive_count += 1;
+            message.first_received.get_or_insert(now);
+            received.push(message.clone());
+        }
+        Ok(received)
+    }
+
+    // a handle from an earlier receive, or for a message that's gone, is accepted and
+    // does nothing, as in sqs
+    pub fn delete_message(&self, name: &str, receipt_handle: &str) -> Result<(), SqsError> {
+        if receipt_handle.is_empty() {
+            return Err(SqsError::invalid_receipt_handle());
+        }
+        let mut queues = self.queues.lock().unwrap();
+        let queue = queues.get_mut(name).ok_or_else(SqsError::no_such_queue)?;
+        queue
+            .messages
+            .retain(|m| m.receipt_handle.as_deref() != Some(receipt_handle));
+        Ok(())
+    }
+
+    pub fn change_visibility(
+        &self,
+        name: &str,
+        receipt_handle: &str,
+        seconds: u32,
+        clock: &Clock,
+    ) -> Result<(), SqsError> {
+        validate_visibility_timeout(seconds)?;
+        let now = clock.now();
+        let mut queues = self.queues.lock().unwrap();
+        let queue = queues.get_mut(name).ok_or_else(SqsError::no_such_queue)?;
+        let message = queue
+            .messages
+            .iter_mut()
+            .find(|m| m.receipt_handle.as_deref() == Some(receipt_handle))
+            .filter(|m| m.visible_at > now)
+            .ok_or_else(SqsError::message_not_inflight)?;
+        message.visible_at = now + TimeDelta::seconds(seconds as i64);
+        drop(queues);
+
+        self.sent.notify_waiters();
+        Ok(())
+    }
+
+    pub fn purge(&self, name: &str) -> Result<(), SqsError> {
+        let mut queues = self.queues.lock().unwrap();
+        queues.get_mut(name).ok_or_else(SqsError::no_such_queue)?.messages.clear();
+        Ok(())
+    }
+
+    // drop every queue, for a full reset
+    pub fn clear(&self) {
+        self.queues.lock().unwrap().clear();
+    }
+}
+
+fn receipt_handle(entropy: &Entropy) -> String {
+    use base64::Engine;
+    base64::engine::general_purpose::URL_SAFE_NO_PAD.encode(entropy.bytes(32))
+}
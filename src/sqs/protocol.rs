@@ -0,0 +1,462 @@
+use axum::{
+    body::{Body, Bytes},
+    extract::{Request, State},
+    http::{header, HeaderMap, Method},
+    middleware::Next,
+    response::{IntoResponse, Response},
+};
+use serde_json::{json, Map, Value};
+use std::collections::BTreeMap;
+use std::sync::Arc;
+use std::time::Duration;
+
+use super::{queue_name_from_url, Message, SqsError, ACCOUNT_ID, MAX_WAIT_SECONDS};
+use crate::config::DEFAULT_REGION;
+use crate::handlers::AppState;
+use crate::xml;
+
+const JSON_CONTENT_TYPE: &str = "application/x-amz-json-1.0";
+const TARGET_PREFIX: &str = "AmazonSQS.";
+const QUERY_NAMESPACE: &str = "http://queue.amazonaws.com/doc/2012-11-05/";
+
+// current sdks send json with an X-Amz-Target header; older ones and the query api send
+// form-encoded parameters, to the endpoint or to the queue's url
+#[derive(Debug, Clone, Copy, PartialEq)]
+enum Protocol {
+    Json,
+    Query,
+}
+
+fn detect(method: &Method, headers: &HeaderMap) -> Option<Protocol> {
+    if method != Method::POST {
+        return None;
+    }
+    let header = |name| headers.get(name).and_then(|v| v.to_str().ok()).unwrap_or("");
+    if header("x-amz-target").starts_with(TARGET_PREFIX) {
+        return Some(Protocol::Json);
+    }
+    // s3 never takes form-encoded posts; browser uploads are multipart/form-data
+    header(header::CONTENT_TYPE.as_str())
+        .starts_with("application/x-www-form-urlencoded")
+        .then_some(Protocol::Query)
+}
+
+// answers sqs requests before they reach the s3 routes
+pub async fn intercept(State(state): State<Arc<AppState>>, request: Request, next: Next) -> Response {
+    let Some(protocol) = detect(request.method(), request.headers()) else {
+        return next.run(request).await;
+    };
+
+    let base_url = base_url(request.headers());
+    let target = request
+        .headers()
+        .get("x-amz-target")
+        .and_then(|v| v.to_str().ok())
+        .and_then(|t| t.strip_prefix(TARGET_PREFIX))
+        .map(str::to_string);
+    let path = request.uri().path().to_string();
+    let body = match axum::body::to_bytes(request.into_body(), super::MAX_MESSAGE_BYTES * 2).await {
+        Ok(body) => body,
+        Err(e) => return render_error(protocol, &SqsError::invalid_parameter(e.to_string()), &state),
+    };
+
+    let parsed = match protocol {
+        Protocol::Json => parse_json(target, &body),
+        Protocol::Query => parse_query(&path, &body),
+    };
+    let result = match parsed {
+        Ok((action, params)) => handle(&state, &action, &Params(params), &base_url)
+            .await
+            .map(|result| (action, result)),
+        Err(e) => Err(e),
+    };
+
+    match result {
+        Ok((action, result)) => render(protocol, &action, result, &state),
+        Err(e) => render_error(protocol, &e, &state),
+    }
+}
+
+fn base_url(headers: &HeaderMap) -> String {
+    let host = headers
+        .get(header::HOST)
+        .and_then(|h| h.to_str().ok())
+        .unwrap_or("localhost");
+    format!("http://{}", host)
+}
+
+fn parse_json(target: Option<String>, body: &[u8]) -> Result<(String, Map<String, Value>), SqsError> {
+    let action = target.ok_or_else(|| SqsError::missing_parameter("X-Amz-Target"))?;
+    if body.is_empty() {
+        return Ok((action, Map::new()));
+    }
+    match serde_json::from_slice(body) {
+        Ok(Value::Object(params)) => Ok((action, params)),
+        _ => Err(SqsError::invalid_parameter("the request body must be a json object")),
+    }
+}
+
+// form parameters in the json protocol's shape: Attribute.N.Name/Value pairs become an
+// Attributes object and AttributeName.N entries an AttributeNames list
+fn parse_query(path: &str, body: &[u8]) -> Result<(String, Map<String, Value>), SqsError> {
+    let pairs: Vec<(String, String)> = serde_urlencoded::from_bytes(body)
+        .map_err(|e| SqsError::invalid_parameter(e.to_string()))?;
+
+    let mut action = None;
+    let mut params = Map::new();
+    let mut attributes: BTreeMap<u32, (Option<String>, Option<String>)> = BTreeMap::new();
+    let mut lists: BTreeMap<&str, BTreeMap<u32, String>> = BTreeMap::new();
+    for (name, value) in pairs {
+        if name == "Action" {
+            action = Some(value);
+            continue;
+        }
+        if let Some(rest) = name.strip_prefix("Attribute.") {
+            if let Some((index, field)) = rest.split_once('.').and_then(|(i, f)| Some((i.parse().ok()?, f))) {
+                let entry = attributes.entry(index).or_default();
+                match field {
+                    "Name" => entry.0 = Some(value),
+                    "Value" => entry.1 = Some(value),
+                    _ => {}
+                }
+                continue;
+            }
+        }
+        let listed = ["AttributeName", "MessageSystemAttributeName", "MessageAttributeName"]
+            .into_iter()
+            .find_map(|list| Some((list, name.strip_prefix(list)?.strip_prefix('.')?.parse::<u32>().ok()?)));
+        if let Some((list, index)) = listed {
+            lists.entry(list).or_default().insert(index, value);
+            continue;
+        }
+        params.insert(name, Value::String(value));
+    }
+
+    if !attributes.is_empty() {
+        let attributes: Map<String, Value> = attributes
+            .into_values()
+            .filter_map(|(name, value)| Some((name?, Value::String(value?))))
+            .collect();
+        params.insert("Attributes".to_string(), Value::Object(attributes));
+    }
+    for (list, values) in lists {
+        params.insert(format!("{}s", list), values.into_values().collect());
+    }
+    // the query api can also be sent to the queue url itself
+    if !params.contains_key("QueueUrl") {
+        if let Some((_, name)) = path.trim_matches('/').split_once('/') {
+            params.insert("QueueUrl".to_string(), Value::String(name.to_string()));
+        }
+    }
+
+    let action = action.ok_or_else(|| SqsError::missing_parameter("Action"))?;
+    Ok((action, params))
+}
+
+// request parameters; numbers arrive as numbers in json and as strings in the query api
+struct Params(Map<String, Value>);
+
+impl Params {
+    fn str(&self, name: &str) -> Option<&str> {
+        self.0.get(name).and_then(Value::as_str)
+    }
+
+    fn required(&self, name: &str) -> Result<&str, SqsError> {
+        self.str(name).ok_or_else(|| SqsError::missing_parameter(name))
+    }
+
+    fn number(&self, name: &str) -> Result<Option<u32>, SqsError> {
+        let invalid = || SqsError::invalid_parameter(format!("Value for parameter {} is invalid.", name));
+        match self.0.get(name) {
+            None | Some(Value::Null) => Ok(None),
+            Some(Value::Number(n)) => n.as_u64().and_then(|n| u32::try_from(n).ok()).map(Some).ok_or_else(invalid),
+            Some(Value::String(s)) => s.trim().parse().map(Some).map_err(|_| invalid()),
+            Some(_) => Err(invalid()),
+        }
+    }
+
+    fn attributes(&self) -> BTreeMap<String, String> {
+        let Some(Value::Object(attributes)) = self.0.get("Attributes") else {
+            return BTreeMap::new();
+        };
+        attributes
+            .iter()
+            .filter_map(|(name, value)| Some((name.clone(), value.as_str()?.to_string())))
+            .collect()
+    }
+
+    fn list(&self, name: &str) -> Vec<String> {
+        match self.0.get(name) {
+            Some(Value::Array(values)) => values.iter().filter_map(|v| Some(v.as_str()?.to_string())).collect(),
+            _ => Vec::new(),
+        }
+    }
+
+    fn queue(&self) -> Result<&str, SqsError> {
+        Ok(queue_name_from_url(self.required("QueueUrl")?))
+    }
+}
+
+fn queue_url(base_url: &str, name: &str) -> String {
+    format!("{}/{}/{}", base_url, ACCOUNT_ID, name)
+}
+
+fn visibility_timeout(attributes: &BTreeMap<String, String>) -> Result<Option<u32>, SqsError> {
+    attributes
+        .get("VisibilityTimeout")
+        .map(|v| {
+            v.trim()
+                .parse::<u32>()
+                .map_err(|_| SqsError::invalid_parameter(format!("Invalid value for the parameter VisibilityTimeout: {}", v)))
+        })
+        .transpose()
+}
+
+fn millis(time: chrono::DateTime<chrono::Utc>) -> String {
+    time.timestamp_millis().to_string()
+}
+
+// the result of an action in the json protocol's shape
+async fn handle(state: &AppState, action: &str, params: &Params, base_url: &str) -> Result<Value, SqsError> {
+    let queues = &state.queues;
+    let (clock, entropy) = (state.clock(), state.entropy());
+
+    match action {
+        "CreateQueue" => {
+            let name = params.required("QueueName")?;
+            let attributes = params.attributes();
+            if let Some(unknown) = attributes.keys().find(|k| *k != "VisibilityTimeout") {
+                return Err(SqsError::invalid_parameter(format!("six7 doesn't support the queue attribute {}", unknown)));
+            }
+            queues.create(name, visibility_timeout(&attributes)?, clock.now())?;
+            Ok(json!({ "QueueUrl": queue_url(base_url, name) }))
+        }
+        "GetQueueUrl" => {
+            let name = params.required("QueueName")?;
+            queues.get(name)?;
+            Ok(json!({ "QueueUrl": queue_url(base_url, name) }))
+        }
+        "ListQueues" => {
+            let names = queues.names(params.str("QueueNamePrefix"));
+            let urls: Vec<String> = names.iter().map(|name| queue_url(base_url, name)).collect();
+            if urls.is_empty() {
+                return Ok(json!({}));
+            }
+            Ok(json!({ "QueueUrls": urls }))
+        }
+        "DeleteQueue" => {
+            queues.delete(params.queue()?)?;
+            Ok(json!({}))
+        }
+        "GetQueueAttributes" => {
+            let queue = queues.get(params.queue()?)?;
+            let now = clock.now();
+            let all = [
+                ("QueueArn", queue.arn(DEFAULT_REGION)),
+                ("VisibilityTimeout", queue.visibility_timeout.to_string()),
+                ("ApproximateNumberOfMessages", queue.visible(now).to_string()),
+                (
+                    "ApproximateNumberOfMessagesNotVisible",
+                    (queue.messages.len() - queue.visible(now)).to_string(),
+                ),
+                ("CreatedTimestamp", queue.created.timestamp().to_string()),
+            ];
+            let wanted = params.list("AttributeNames");
+            let attributes: Map<String, Value> = all
+                .into_iter()
+                .filter(|(name, _)| wanted.iter().any(|w| w == "All" || w == name))
+                .map(|(name, value)| (name.to_string(), Value::String(value)))
+                .collect();
+            Ok(json!({ "Attributes": attributes }))
+        }
+        "SetQueueAttributes" => {
+            let name = params.queue()?;
+            let attributes = params.attributes();
+            if let Some(unknown) = attributes.keys().find(|k| *k != "VisibilityTimeout") {
+                return Err(SqsError::invalid_parameter(format!("six7 doesn't support the queue attribute {}", unknown)));
+            }
+            match visibility_timeout(&attributes)? {
+                Some(seconds) => queues.set_visibility_timeout(name, seconds)?,
+                None => {
+                    queues.get(name)?;
+                }
+            }
+            Ok(json!({}))
+        }
+        "SendMessage" => {
+            let name = params.queue()?;
+            let body = params.required("MessageBody")?.to_string();
+            let delay = params.number("DelaySeconds")?.unwrap_or(0);
+            let message = queues.send(name, body, delay, clock, entropy)?;
+            Ok(json!({ "MessageId": message.id, "MD5OfMessageBody": message.md5 }))
+        }
+        "ReceiveMessage" => {
+            let name = params.queue()?;
+            let max = params.number("MaxNumberOfMessages")?.unwrap_or(1) as usize;
+            let wait = params.number("WaitTimeSeconds")?.unwrap_or(0);
+            if wait > MAX_WAIT_SECONDS {
+                return Err(SqsError::invalid_parameter(format!(
+                    "Value {} for parameter WaitTimeSeconds is invalid. Reason: Must be >= 0 and <= {}.",
+                    wait, MAX_WAIT_SECONDS
+                )));
+            }
+            let wait = Duration::from_secs(wait as u64);
+            let visibility = params.number("VisibilityTimeout")?;
+            let messages = queues.receive(name, max, visibility, wait, clock, entropy).await?;
+
+            let mut wanted = params.list("AttributeNames");
+            wanted.extend(params.list("MessageSystemAttributeNames"));
+            let messages: Vec<Value> = messages.iter().map(|m| message_json(m, &wanted)).collect();
+            if messages.is_empty() {
+                return Ok(json!({}));
+            }
+            Ok(json!({ "Messages": messages }))
+        }
+        "DeleteMessage" => {
+            queues.delete_message(params.queue()?, params.required("ReceiptHandle")?)?;
+            Ok(json!({}))
+        }
+        "ChangeMessageVisibility" => {
+            let name = params.queue()?;
+            let handle = params.required("ReceiptHandle")?;
+            let seconds = params
+                .number("VisibilityTimeout")?
+                .ok_or_else(|| SqsError::missing_parameter("VisibilityTimeout"))?;
+            queues.change_visibility(name, handle, seconds, clock)?;
+            Ok(json!({}))
+        }
+        "PurgeQueue" => {
+            queues.purge(params.queue()?)?;
+            Ok(json!({}))
+        }
+        _ => Err(SqsError::unsupported(action)),
+    }
+}
+
+fn message_json(message: &Message, wanted: &[String]) -> Value {
+    let all = [
+        ("SenderId", ACCOUNT_ID.to_string()),
+        ("SentTimestamp", millis(message.sent)),
+        ("ApproximateReceiveCount", message.receive_count.to_string()),
+        (
+            "ApproximateFirstReceiveTimestamp",
+            millis(message.first_received.unwrap_or(message.sent)),
+        ),
+    ];
+    let attributes: Map<String, Value> = all
+        .into_iter()
+        .filter(|(name, _)| wanted.iter().any(|w| w == "All" || w == name))
+        .map(|(name, value)| (name.to_string(), Value::String(value)))
+        .collect();
+
+    let mut value = json!({
+        "MessageId": message.id,
+        "ReceiptHandle": message.receipt_handle,
+        "MD5OfBody": message.md5,
+        "Body": message.body,
+    });
+    if !attributes.is_empty() {
+        value["Attributes"] = Value::Object(attributes);
+    }
+    value
+}
+
+fn render(protocol: Protocol, action: &str, result: Value, state: &AppState) -> Response {
+    let request_id = state.entropy().uuid().to_string();
+    let (content_type, body) = match protocol {
+        Protocol::Json => (JSON_CONTENT_TYPE, result.to_string()),
+        Protocol::Query => {
+            let inner = query_xml(&result);
+            let result = match inner.is_empty() {
+                true => String::new(),
+                false => format!("<{action}Result>{}</{action}Result>", inner, action = action),
+            };
+            let body = format!(
+                r#"<?xml version="1.0" encoding="UTF-8"?>
+<{action}Response xmlns="{}">{}<ResponseMetadata><RequestId>{}</RequestId></ResponseMetadata></{action}Response>"#,
+                QUERY_NAMESPACE,
+                result,
+                request_id,
+                action = action
+            );
+            ("text/xml", body)
+        }
+    };
+
+    Response::builder()
+        .header(header::CONTENT_TYPE, content_type)
+        .header("x-amzn-RequestId", request_id)
+        .body(Body::from(body))
+        .unwrap()
+}
+
+// the query api's xml for a json result: lists repeat their singular element and
+// attribute maps become Name/Value pairs
+fn query_xml(value: &Value) -> String {
+    let Value::Object(fields) = value else {
+        return String::new();
+    };
+
+    let mut out = String::new();
+    for (name, value) in fields {
+        match (name.as_str(), value) {
+            ("Attributes", Value::Object(attributes)) => {
+                for (name, value) in attributes {
+                    out.push_str(&format!(
+                        "<Attribute><Name>{}</Name><Value>{}</Value></Attribute>",
+                        xml::escape(name),
+                        xml::escape(value.as_str().unwrap_or_default())
+                    ));
+                }
+            }
+            (_, Value::Array(items)) => {
+                let singular = name.strip_suffix('s').unwrap_or(name);
+                for item in items {
+                    let inner = match item {
+                        Value::String(s) => xml::escape(s),
+                        item => query_xml(item),
+                    };
+                    out.push_str(&format!("<{0}>{1}</{0}>", singular, inner));
+                }
+            }
+            (_, Value::String(s)) => out.push_str(&format!("<{0}>{1}</{0}>", name, xml::escape(s))),
+            (_, Value::Null) => {}
+            (_, other) => out.push_str(&format!("<{0}>{1}</{0}>", name, other)),
+        }
+    }
+    out
+}
+
+fn render_error(protocol: Protocol, error: &SqsError, state: &AppState) -> Response {
+    let request_id = state.entropy().uuid().to_string();
+    let (content_type, body) = match protocol {
+        Protocol::Json => {
+            let body = json!({
+                "__type": format!("com.amazonaws.sqs#{}", error.code),
+                "message": error.message,
+            });
+            (JSON_CONTENT_TYPE, body.to_string())
+        }
+        Protocol::Query => {
+            let body = format!(
+                r#"<?xml version="1.0" encoding="UTF-8"?>
+<ErrorResponse xmlns="{}"><Error><Type>Sender</Type><Code>{}</Code><Message>{}</Message><Detail/></Error><RequestId>{}</RequestId></ErrorResponse>"#,
+                QUERY_NAMESPACE,
+                error.query_code,
+                xml::escape(&error.message),
+                request_id
+            );
+            ("text/xml", body)
+        }
+    };
+
+    let mut response = (error.status, Bytes::from(body)).into_response();
+    let headers = response.headers_mut();
+    headers.insert(header::CONTENT_TYPE, content_type.parse().unwrap());
+    headers.insert("x-amzn-RequestId", request_id.parse().unwrap());
+    headers.insert(
+        "x-amzn-query-error",
+        format!("{};Sender", error.query_code).parse().unwrap(),
+    );
+    response
+}
@@ -1,12 +1,37 @@
+use async_trait::async_trait;
 use bytes::Bytes;
 use chrono::{DateTime, Utc};
 use serde::{Deserialize, Serialize};
-use std::fs;
+use sha2::{Digest, Sha256};
 use std::path::{Path, PathBuf};
-use std::pin::Pin;
-use std::future::Future;
-use tokio::fs as async_fs;
-use tokio::io::AsyncWriteExt;
+
+use crate::clock::Clock;
+use crate::entropy::Entropy;
+use crate::error::S3Error;
+
+mod fs;
+mod fsck;
+mod memory;
+pub mod snapshot;
+
+pub use fs::Storage;
+pub use fsck::{Finding, FsckOptions, FsckReport, Problem, DEFAULT_ABANDONED_AFTER};
+pub use memory::MemoryStorage;
+
+// on disk a key is split on '/', each segment is percent-encoded, and every directory
+// gets this suffix so "a" (a file) and "a/b" (a file under directory "a%2F") can coexist
+const DIR_SUFFIX: &str = "%2F";
+const EMPTY_SEGMENT: &str = "%";
+// keys whose encoded path won't fit the filesystem's limits are stored under a single
+// name made from their hash instead; '%' is always escaped, so no encoded key starts
+// with it, and the key itself is kept in the metadata
+const HASHED_PREFIX: &str = "%H";
+// names are at most 255 bytes on most filesystems, less ".json" for the metadata file
+const MAX_SEGMENT_LEN: usize = 250;
+// leaves room under PATH_MAX for the data dir and bucket
+const MAX_ENCODED_LEN: usize = 2048;
+const MAX_KEY_LEN: usize = 1024;
+const MAX_PART_NUMBER: u32 = 10000;
 
 #[derive(Debug, Clone, Serialize, Deserialize)]
 pub struct ObjectMetadata {
@@ -17,161 +42,400 @@ pub struct ObjectMetadata {
     pub content_type: Option<String>,
 }
 
-pub struct Storage {
-    base_path: PathBuf,
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct BucketInfo {
+    pub name: String,
+    pub creation_date: DateTime<Utc>,
 }
 
-impl Storage {
-    pub fn new<P: AsRef<Path>>(base_path: P) -> Result<Self, std::io::Error> {
-        let base_path = base_path.as_ref().to_path_buf();
-        fs::create_dir_all(&base_path)?;
-        Ok(Storage { base_path })
-    }
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct MultipartUpload {
+    pub upload_id: String,
+    pub bucket: String,
+    pub key: String,
+    pub content_type: Option<String>,
+    pub initiated: DateTime<Utc>,
+}
 
-    pub fn bucket_path(&self, bucket: &str) -> PathBuf {
-        self.base_path.join(bucket)
-    }
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct PartInfo {
+    pub part_number: u32,
+    pub etag: String,
+    pub size: u64,
+}
 
-    pub fn object_path(&self, bucket: &str, key: &str) -> PathBuf {
-        self.bucket_path(bucket).join(key)
-    }
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct SnapshotInfo {
+    pub name: String,
+    pub created: DateTime<Utc>,
+    pub objects: u64,
+    pub bytes: u64,
+}
 
-    pub async fn create_bucket(&self, bucket: &str) -> Result<(), std::io::Error> {
-        let bucket_path = self.bucket_path(bucket);
-        async_fs::create_dir_all(bucket_path).await
-    }
+// everything the handlers need from a place to keep buckets and objects
+#[async_trait]
+pub trait StorageBackend: Send + Sync {
+    // the time timestamps are taken from; the admin api moves it through this
+    fn clock(&self) -> &Clock;
 
-    #[allow(dead_code)]
-    pub async fn bucket_exists(&self, bucket: &str) -> bool {
-        self.bucket_path(bucket).exists()
-    }
+    // where generated ids come from
+    fn entropy(&self) -> &Entropy;
+
+    async fn create_bucket(&self, bucket: &str) -> Result<(), std::io::Error>;
+
+    async fn delete_bucket(&self, bucket: &str) -> Result<(), std::io::Error>;
 
-    pub async fn put_object(
+    async fn bucket_exists(&self, bucket: &str) -> bool;
+
+    async fn list_buckets(&self) -> Result<Vec<BucketInfo>, std::io::Error>;
+
+    async fn put_object(
         &self,
         bucket: &str,
         key: &str,
         data: Bytes,
         content_type: Option<String>,
+    ) -> Result<ObjectMetadata, std::io::Error>;
+
+    async fn get_object(&self, bucket: &str, key: &str) -> Result<Bytes, std::io::Error>;
+
+    async fn head_object(&self, bucket: &str, key: &str) -> Result<ObjectMetadata, std::io::Error>;
+
+    async fn delete_object(&self, bucket: &str, key: &str) -> Result<(), std::io::Error>;
+
+    async fn list_objects(
+        &self,
+        bucket: &str,
+        prefix: Option<&str>,
+    ) -> Result<Vec<ObjectMetadata>, std::io::Error>;
+
+    async fn copy_object(
+        &self,
+        src_bucket: &str,
+        src_key: &str,
+        dst_bucket: &str,
+        dst_key: &str,
     ) -> Result<ObjectMetadata, std::io::Error> {
-        let object_path = self.object_path(bucket, key);
+        let source = self.head_object(src_bucket, src_key).await?;
+        let data = self.get_object(src_bucket, src_key).await?;
+        self.put_object(dst_bucket, dst_key, data, source.content_type)
+            .await
+    }
 
-        if let Some(parent) = object_path.parent() {
-            async_fs::create_dir_all(parent).await?;
+    async fn create_multipart_upload(
+        &self,
+        bucket: &str,
+        key: &str,
+        content_type: Option<String>,
+    ) -> Result<MultipartUpload, std::io::Error>;
+
+    async fn upload_part(
+        &self,
+        bucket: &str,
+        key: &str,
+        upload_id: &str,
+        part_number: u32,
+        data: Bytes,
+    ) -> Result<PartInfo, std::io::Error>;
+
+    // parts are (part number, etag) pairs as sent by the client, in ascending order
+    async fn complete_multipart_upload(
+        &self,
+        bucket: &str,
+        key: &str,
+        upload_id: &str,
+        parts: &[(u32, String)],
+    ) -> Result<ObjectMetadata, std::io::Error>;
+
+    async fn abort_multipart_upload(
+        &self,
+        bucket: &str,
+        key: &str,
+        upload_id: &str,
+    ) -> Result<(), std::io::Error>;
+
+    async fn list_multipart_uploads(&self, bucket: &str) -> Result<Vec<MultipartUpload>, std::io::Error>;
+
+    // a bucket subresource such as its notification configuration, stored as an opaque
+    // document under a name; None if it was never set
+    async fn get_bucket_setting(&self, bucket: &str, name: &str) -> Result<Option<String>, std::io::Error>;
+
+    // replace a bucket subresource, or remove it with None
+    async fn put_bucket_setting(
+        &self,
+        bucket: &str,
+        name: &str,
+        value: Option<String>,
+    ) -> Result<(), std::io::Error>;
+
+    // remove every object and pending upload but keep the bucket itself
+    async fn empty_bucket(&self, bucket: &str) -> Result<(), std::io::Error> {
+        for upload in self.list_multipart_uploads(bucket).await? {
+            self.abort_multipart_upload(bucket, &upload.key, &upload.upload_id)
+                .await?;
+        }
+        for object in self.list_objects(bucket, None).await? {
+            self.delete_object(bucket, &object.key).await?;
         }
+        Ok(())
+    }
 
-        let mut file = async_fs::File::create(&object_path).await?;
-        file.write_all(&data).await?;
-        file.flush().await?;
+    // empty every bucket, drop the ones not in keep and create the ones in keep that
+    // don't exist yet
+    async fn reset(&self, keep: &[String]) -> Result<(), std::io::Error> {
+        for bucket in self.list_buckets().await? {
+            self.empty_bucket(&bucket.name).await?;
+            if !keep.contains(&bucket.name) {
+                self.delete_bucket(&bucket.name).await?;
+            }
+        }
+        for bucket in keep {
+            self.create_bucket(bucket).await?;
+        }
+        Ok(())
+    }
 
-        let metadata = async_fs::metadata(&object_path).await?;
-        let etag = format!("{:x}", md5::compute(&data));
+    // a named copy of every bucket, object and upload; an existing snapshot is replaced
+    async fn create_snapshot(&self, _name: &str) -> Result<SnapshotInfo, std::io::Error> {
+        Err(S3Error::not_implemented("this storage backend has no snapshots").into_io())
+    }
 
-        Ok(ObjectMetadata {
-            key: key.to_string(),
-            size: metadata.len(),
-            last_modified: Utc::now(),
-            etag,
-            content_type,
-        })
+    // replace the whole state with a snapshot's; the snapshot itself is left as it was
+    async fn restore_snapshot(&self, _name: &str) -> Result<(), std::io::Error> {
+        Err(S3Error::not_implemented("this storage backend has no snapshots").into_io())
     }
 
-    pub async fn get_object(&self, bucket: &str, key: &str) -> Result<Bytes, std::io::Error> {
-        let object_path = self.object_path(bucket, key);
-        let data = async_fs::read(object_path).await?;
-        Ok(Bytes::from(data))
+    async fn list_snapshots(&self) -> Result<Vec<SnapshotInfo>, std::io::Error> {
+        Ok(Vec::new())
     }
 
-    pub async fn delete_object(&self, bucket: &str, key: &str) -> Result<(), std::io::Error> {
-        let object_path = self.object_path(bucket, key);
-        async_fs::remove_file(object_path).await
+    async fn delete_snapshot(&self, _name: &str) -> Result<(), std::io::Error> {
+        Err(S3Error::no_such_snapshot().into_io())
     }
 
-    #[allow(dead_code)]
-    pub async fn list_objects(
-        &self,
-        bucket: &str,
-        prefix: Option<&str>,
-    ) -> Result<Vec<ObjectMetadata>, std::io::Error> {
-        let bucket_path = self.bucket_path(bucket);
-        let mut objects = Vec::new();
-
-        self.scan_directory(&bucket_path, &bucket_path, prefix, &mut objects)
-            .await?;
-
-        Ok(objects)
-    }
-
-    #[allow(dead_code)]
-    fn scan_directory<'a>(
-        &'a self,
-        dir: &'a Path,
-        base: &'a Path,
-        prefix: Option<&'a str>,
-        objects: &'a mut Vec<ObjectMetadata>,
-    ) -> Pin<Box<dyn Future<Output = Result<(), std::io::Error>> + 'a>> {
-        Box::pin(async move {
-            let mut entries = async_fs::read_dir(dir).await?;
-
-            while let Some(entry) = entries.next_entry().await? {
-                let path = entry.path();
-                let metadata = entry.metadata().await?;
-
-                if metadata.is_file() {
-                    let relative_path = path.strip_prefix(base).unwrap();
-                    let key = relative_path.to_string_lossy().to_string();
-
-                    if let Some(p) = prefix {
-                        if !key.starts_with(p) {
-                            continue;
-                        }
-                    }
-
-                    let data = async_fs::read(&path).await?;
-                    let etag = format!("{:x}", md5::compute(&data));
-
-                    objects.push(ObjectMetadata {
-                        key,
-                        size: metadata.len(),
-                        last_modified: metadata
-                            .modified()
-                            .ok()
-                            .and_then(|t| DateTime::from_timestamp(
-                                t.duration_since(std::time::UNIX_EPOCH).unwrap().as_secs() as i64,
-                                0
-                            ))
-                            .unwrap_or_else(Utc::now),
-                        etag,
-                        content_type: None,
-                    });
-                } else if metadata.is_dir() {
-                    self.scan_directory(&path, base, prefix, objects).await?;
-                }
-            }
+    // fails when nothing could be written right now, e.g. the disk is full or read-only
+    async fn check_writable(&self) -> Result<(), std::io::Error> {
+        Ok(())
+    }
 
-            Ok(())
+    // compares what's stored with the metadata kept for it, repairing what it finds when
+    // asked; nothing drifts in memory, so by default there's nothing to find
+    async fn fsck(&self, options: &FsckOptions) -> Result<FsckReport, std::io::Error> {
+        Ok(FsckReport {
+            repaired: options.repair,
+            ..FsckReport::default()
         })
     }
+}
+
+pub fn validate_bucket_name(name: &str) -> Result<(), S3Error> {
+    let invalid = || Err(S3Error::invalid_bucket_name(name));
+
+    if name.len() < 3 || name.len() > 63 {
+        return invalid();
+    }
+    if !name
+        .bytes()
+        .all(|b| b.is_ascii_lowercase() || b.is_ascii_digit() || b == b'.' || b == b'-')
+    {
+        return invalid();
+    }
+
+    let first = name.as_bytes()[0];
+    let last = name.as_bytes()[name.len() - 1];
+    if !first.is_ascii_alphanumeric() || !last.is_ascii_alphanumeric() {
+        return invalid();
+    }
+    if name.contains("..") || name.parse::<std::net::Ipv4Addr>().is_ok() {
+        return invalid();
+    }
+
+    let reserved_prefixes = ["xn--", "sthree-", "amzn-s3-demo-"];
+    let reserved_suffixes = ["-s3alias", "--ol-s3", "--x-s3", ".mrap"];
+    if reserved_prefixes.iter().any(|p| name.starts_with(p))
+        || reserved_suffixes.iter().any(|s| name.ends_with(s))
+    {
+        return invalid();
+    }
+
+    Ok(())
+}
+
+// setting names become file names, so keep them to a safe alphabet
+pub fn validate_setting_name(name: &str) -> Result<(), S3Error> {
+    if name.is_empty() || !name.bytes().all(|b| b.is_ascii_lowercase() || b == b'-') {
+        return Err(S3Error::invalid_argument(format!("invalid bucket setting: {}", name)));
+    }
+    Ok(())
+}
+
+pub fn validate_key(key: &str) -> Result<(), S3Error> {
+    if key.is_empty() {
+        return Err(S3Error::invalid_argument("object key must not be empty"));
+    }
+    if key.len() > MAX_KEY_LEN {
+        return Err(S3Error::key_too_long());
+    }
+    Ok(())
+}
+
+// map a key to a relative path that stays inside the bucket directory
+pub fn encode_key(key: &str) -> PathBuf {
+    let segments: Vec<&str> = key.split('/').collect();
+    let mut path = PathBuf::new();
+    let mut len = 0;
+
+    for (i, segment) in segments.iter().enumerate() {
+        let mut name = encode_segment(segment);
+        if i + 1 < segments.len() {
+            name.push_str(DIR_SUFFIX);
+        }
+        if name.len() > MAX_SEGMENT_LEN {
+            return hashed_name(key);
+        }
+        len += name.len() + 1;
+        path.push(name);
+    }
+
+    match len > MAX_ENCODED_LEN {
+        true => hashed_name(key),
+        false => path,
+    }
+}
+
+fn hashed_name(key: &str) -> PathBuf {
+    PathBuf::from(format!("{}{:x}", HASHED_PREFIX, Sha256::digest(key.as_bytes())))
+}
+
+// the key a file in the layout from before keys were encoded (bucket/folder/file.txt)
+// was stored under: its path as it is
+pub fn legacy_key(path: &Path) -> Option<String> {
+    let segments: Vec<&str> = path
+        .components()
+        .map(|c| match c {
+            std::path::Component::Normal(name) => name.to_str(),
+            _ => None,
+        })
+        .collect::<Option<_>>()?;
+    let key = segments.join("/");
+    validate_key(&key).ok()?;
+    Some(key)
+}
 
-    #[allow(dead_code)]
-    pub async fn head_object(&self, bucket: &str, key: &str) -> Result<ObjectMetadata, std::io::Error> {
-        let object_path = self.object_path(bucket, key);
-        let metadata = async_fs::metadata(&object_path).await?;
-        let data = async_fs::read(&object_path).await?;
-        let etag = format!("{:x}", md5::compute(&data));
-
-        Ok(ObjectMetadata {
-            key: key.to_string(),
-            size: metadata.len(),
-            last_modified: metadata
-                .modified()
-                .ok()
-                .and_then(|t| DateTime::from_timestamp(
-                    t.duration_since(std::time::UNIX_EPOCH).unwrap().as_secs() as i64,
-                    0
-                ))
-                .unwrap_or_else(Utc::now),
-            etag,
-            content_type: None,
+// whether a path is a hashed name, whose key has to be read from its metadata
+pub fn is_hashed(path: &Path) -> bool {
+    path.to_str()
+        .and_then(|name| name.strip_prefix(HASHED_PREFIX))
+        .is_some_and(|hash| hash.len() == 64 && hash.bytes().all(|b| b.is_ascii_hexdigit()))
+}
+
+// inverse of encode_key; None for paths that were not written by encode_key, and for
+// hashed names
+pub fn decode_key(path: &Path) -> Option<String> {
+    let key = decode_path(path)?;
+    // a path that decodes but isn't how the key is written (a%41 for aA) isn't the key's
+    (encode_key(&key) == path).then_some(key)
+}
+
+fn decode_path(path: &Path) -> Option<String> {
+    let components: Vec<&str> = path
+        .components()
+        .map(|c| match c {
+            std::path::Component::Normal(name) => name.to_str(),
+            _ => None,
         })
+        .collect::<Option<_>>()?;
+
+    let (last, dirs) = components.split_last()?;
+    let mut segments = Vec::with_capacity(components.len());
+    for dir in dirs {
+        segments.push(decode_segment(dir.strip_suffix(DIR_SUFFIX)?)?);
+    }
+    if last.ends_with(DIR_SUFFIX) {
+        return None;
+    }
+    segments.push(decode_segment(last)?);
+
+    Some(segments.join("/"))
+}
+
+fn encode_segment(segment: &str) -> String {
+    match segment {
+        "" => return EMPTY_SEGMENT.to_string(),
+        "." => return "%2E".to_string(),
+        ".." => return "%2E%2E".to_string(),
+        _ => {}
+    }
+
+    let mut encoded = String::with_capacity(segment.len());
+    for c in segment.chars() {
+        if c == '%' || c == '\\' || c.is_control() {
+            let mut buf = [0u8; 4];
+            for b in c.encode_utf8(&mut buf).bytes() {
+                encoded.push_str(&format!("%{:02X}", b));
+            }
+        } else {
+            encoded.push(c);
+        }
+    }
+    encoded
+}
+
+fn decode_segment(name: &str) -> Option<String> {
+    if name == EMPTY_SEGMENT {
+        return Some(String::new());
     }
+
+    let bytes = name.as_bytes();
+    let mut decoded = Vec::with_capacity(bytes.len());
+    let mut i = 0;
+    while i < bytes.len() {
+        if bytes[i] == b'%' {
+            let hex = name.get(i + 1..i + 3)?;
+            decoded.push(u8::from_str_radix(hex, 16).ok()?);
+            i += 3;
+        } else {
+            decoded.push(bytes[i]);
+            i += 1;
+        }
+    }
+
+    String::from_utf8(decoded).ok()
+}
+
+pub fn validate_part_number(part_number: u32) -> Result<(), S3Error> {
+    if part_number == 0 || part_number > MAX_PART_NUMBER {
+        return Err(S3Error::invalid_argument(
+            "Part number must be an integer between 1 and 10000, inclusive",
+        ));
+    }
+    Ok(())
+}
+
+// check the client's part list against what was uploaded and work out the final etag
+pub fn resolve_parts(uploaded: &[PartInfo], requested: &[(u32, String)]) -> Result<String, S3Error> {
+    if requested.is_empty() {
+        return Err(S3Error::malformed_xml());
+    }
+
+    let mut digests = Vec::with_capacity(requested.len() * 16);
+    let mut previous = 0;
+    for (part_number, etag) in requested {
+        if *part_number <= previous {
+            return Err(S3Error::invalid_part_order());
+        }
+        previous = *part_number;
+
+        let part = uploaded
+            .iter()
+            .find(|p| p.part_number == *part_number)
+            .ok_or_else(S3Error::invalid_part)?;
+        if part.etag != etag.trim_matches('"') {
+            return Err(S3Error::invalid_part());
+        }
+
+        digests.extend(hex::decode(&part.etag).map_err(|_| S3Error::invalid_part())?);
+    }
+
+    Ok(format!("{:x}-{}", md5::compute(&digests), requested.len()))
 }
@@ -0,0 +1,1464 @@
+use async_trait::async_trait;
+use bytes::Bytes;
+use chrono::{DateTime, Utc};
+use serde::{Deserialize, Serialize};
+use std::collections::{BTreeSet, HashMap};
+use std::fs;
+use std::future::Future;
+use std::path::{Path, PathBuf};
+use std::pin::Pin;
+use std::sync::{Arc, Mutex};
+use std::time::Duration;
+use tokio::fs as async_fs;
+use tokio::io::AsyncWriteExt;
+use tokio::sync::{OwnedRwLockReadGuard, OwnedRwLockWriteGuard, RwLock};
+
+use super::{
+    decode_key, encode_key, is_hashed, legacy_key, resolve_parts, snapshot, validate_bucket_name, validate_key,
+    validate_part_number, validate_setting_name, BucketInfo, Finding, FsckOptions, FsckReport, MultipartUpload,
+    ObjectMetadata, PartInfo, Problem, SnapshotInfo, StorageBackend,
+};
+use crate::clock::Clock;
+use crate::entropy::Entropy;
+use crate::error::S3Error;
+
+// internal state lives next to the buckets; bucket names can't start with a dot
+pub(super) const INTERNAL_DIR: &str = ".six7";
+pub(super) const STAGING_DIR: &str = "tmp";
+const META_DIR: &str = "meta";
+const META_EXT: &str = "json";
+const MULTIPART_DIR: &str = "multipart";
+const BUCKETS_DIR: &str = "buckets";
+const SETTINGS_DIR: &str = "settings";
+const UPLOAD_FILE: &str = "upload.json";
+pub(super) const COMMITS_DIR: &str = "commits";
+const QUARANTINE_DIR: &str = "quarantine";
+
+// the renames of a write in progress, relative to the data dir, as (staged, destination)
+#[derive(Serialize, Deserialize)]
+struct Intent {
+    moves: Vec<(String, String)>,
+}
+
+// objects as plain files under <base>/<bucket>, metadata and staging under <base>/.six7
+pub struct Storage {
+    base_path: PathBuf,
+    locks: KeyLocks,
+    clock: Clock,
+    entropy: Entropy,
+}
+
+impl Storage {
+    pub fn new<P: AsRef<Path>>(base_path: P) -> Result<Self, std::io::Error> {
+        let base_path = base_path.as_ref().to_path_buf();
+        fs::create_dir_all(&base_path)?;
+
+        // finish the writes a crash stopped part way, while their staged files are still there
+        recover(&base_path)?;
+
+        // anything left in staging is a write that never got renamed into place
+        let staging = base_path.join(INTERNAL_DIR).join(STAGING_DIR);
+        if staging.exists() {
+            fs::remove_dir_all(&staging)?;
+        }
+        fs::create_dir_all(&staging)?;
+
+        let storage = Self::open(base_path)?;
+        storage.migrate()?;
+        Ok(storage)
+    }
+
+    // a data dir as it is, without clearing staging first; for fsck, which should see
+    // what's there before anything changes it
+    pub fn open<P: AsRef<Path>>(base_path: P) -> Result<Self, std::io::Error> {
+        let base_path = base_path.as_ref().to_path_buf();
+        if !base_path.is_dir() {
+            return Err(std::io::Error::new(
+                std::io::ErrorKind::NotFound,
+                format!("{} is not a directory", base_path.display()),
+            ));
+        }
+
+        Ok(Storage {
+            base_path,
+            locks: KeyLocks::default(),
+            clock: Clock::new(),
+            entropy: Entropy::new(),
+        })
+    }
+
+    // move files left in the layout from before keys were encoded to where their keys
+    // are stored now. anything that can't be moved stays where it is for fsck to report
+    fn migrate(&self) -> Result<(), std::io::Error> {
+        for entry in fs::read_dir(&self.base_path)? {
+            let entry = entry?;
+            let Some(bucket) = entry.file_name().to_str().map(str::to_string) else {
+                continue;
+            };
+            if !entry.file_type()?.is_dir() || validate_bucket_name(&bucket).is_err() {
+                continue;
+            }
+
+            let bucket_path = self.bucket_path(&bucket)?;
+            let mut pending = vec![PathBuf::new()];
+            let mut legacy = Vec::new();
+            while let Some(relative) = pending.pop() {
+                for entry in fs::read_dir(bucket_path.join(&relative))? {
+                    let entry = entry?;
+                    let path = relative.join(entry.file_name());
+                    if entry.file_type()?.is_dir() {
+                        pending.push(path);
+                    } else if !is_hashed(&path) && decode_key(&path).is_none() {
+                        legacy.push(path);
+                    }
+                }
+            }
+
+            for relative in legacy {
+                match self.relocate(&bucket, &relative) {
+                    Ok(Some(key)) => tracing::info!("moved {}/{} to where key {} is stored", bucket, relative.display(), key),
+                    Ok(None) => tracing::warn!("left {}/{} where it is: it isn't a key, or the key is taken", bucket, relative.display()),
+                    Err(e) => tracing::warn!("can't move {}/{}: {}", bucket, relative.display(), e),
+                }
+            }
+        }
+        Ok(())
+    }
+
+    // move a file that isn't where its key is stored, such as bucket/folder/file.txt from
+    // before keys were encoded, to where it should be, along with metadata kept for it at
+    // the same path. the key it now holds, or None if its path isn't a valid key or that
+    // key is already stored
+    fn relocate(&self, bucket: &str, relative: &Path) -> Result<Option<String>, std::io::Error> {
+        let Some(key) = legacy_key(relative) else {
+            return Ok(None);
+        };
+        let bucket_path = self.bucket_path(bucket)?;
+        let object_path = self.object_path(bucket, &key)?;
+        if object_path.exists() {
+            return Ok(None);
+        }
+
+        let from = bucket_path.join(relative);
+        if let Some(parent) = object_path.parent() {
+            fs::create_dir_all(parent)?;
+        }
+        fs::rename(&from, &object_path)?;
+        remove_empty_parents(&from, &bucket_path);
+
+        let metadata_dir = self.bucket_metadata_path(bucket)?;
+        let mut legacy_metadata = metadata_dir.join(relative).into_os_string();
+        legacy_metadata.push(".");
+        legacy_metadata.push(META_EXT);
+        let legacy_metadata = PathBuf::from(legacy_metadata);
+        let metadata_path = self.metadata_path(bucket, &key)?;
+        if legacy_metadata.is_file() && !metadata_path.exists() {
+            if let Some(parent) = metadata_path.parent() {
+                fs::create_dir_all(parent)?;
+            }
+            fs::rename(&legacy_metadata, &metadata_path)?;
+            remove_empty_parents(&legacy_metadata, &metadata_dir);
+        }
+
+        Ok(Some(key))
+    }
+
+    pub fn with_clock(mut self, clock: Clock) -> Self {
+        self.clock = clock;
+        self
+    }
+
+    pub fn with_entropy(mut self, entropy: Entropy) -> Self {
+        self.entropy = entropy;
+        self
+    }
+
+    pub fn base_path(&self) -> &Path {
+        &self.base_path
+    }
+
+    pub fn bucket_path(&self, bucket: &str) -> Result<PathBuf, std::io::Error> {
+        validate_bucket_name(bucket).map_err(S3Error::into_io)?;
+        Ok(self.base_path.join(bucket))
+    }
+
+    pub fn object_path(&self, bucket: &str, key: &str) -> Result<PathBuf, std::io::Error> {
+        validate_key(key).map_err(S3Error::into_io)?;
+        Ok(self.bucket_path(bucket)?.join(encode_key(key)))
+    }
+
+    pub fn metadata_path(&self, bucket: &str, key: &str) -> Result<PathBuf, std::io::Error> {
+        validate_key(key).map_err(S3Error::into_io)?;
+
+        let mut path = self.bucket_metadata_path(bucket)?.join(encode_key(key)).into_os_string();
+        path.push(".");
+        path.push(META_EXT);
+        Ok(PathBuf::from(path))
+    }
+
+    fn bucket_metadata_path(&self, bucket: &str) -> Result<PathBuf, std::io::Error> {
+        validate_bucket_name(bucket).map_err(S3Error::into_io)?;
+        Ok(self.base_path.join(INTERNAL_DIR).join(META_DIR).join(bucket))
+    }
+
+    // when a bucket was created, kept apart from the directory so it follows the clock
+    fn bucket_info_path(&self, bucket: &str) -> Result<PathBuf, std::io::Error> {
+        validate_bucket_name(bucket).map_err(S3Error::into_io)?;
+        Ok(self
+            .base_path
+            .join(INTERNAL_DIR)
+            .join(BUCKETS_DIR)
+            .join(format!("{}.{}", bucket, META_EXT)))
+    }
+
+    // bucket subresources, one file each under a directory per bucket
+    fn bucket_settings_path(&self, bucket: &str) -> Result<PathBuf, std::io::Error> {
+        validate_bucket_name(bucket).map_err(S3Error::into_io)?;
+        Ok(self.base_path.join(INTERNAL_DIR).join(SETTINGS_DIR).join(bucket))
+    }
+
+    fn bucket_setting_path(&self, bucket: &str, name: &str) -> Result<PathBuf, std::io::Error> {
+        validate_setting_name(name).map_err(S3Error::into_io)?;
+        Ok(self.bucket_settings_path(bucket)?.join(format!("{}.{}", name, META_EXT)))
+    }
+
+    // staging names are never seen by clients, so they don't draw from the seeded generator
+    fn staging_path(&self) -> PathBuf {
+        self.base_path
+            .join(INTERNAL_DIR)
+            .join(STAGING_DIR)
+            .join(uuid::Uuid::new_v4().to_string())
+    }
+
+    fn upload_path(&self, upload_id: &str) -> Result<PathBuf, std::io::Error> {
+        // upload ids come back from clients, keep them to the characters we hand out
+        if upload_id.is_empty() || !upload_id.bytes().all(|b| b.is_ascii_alphanumeric() || b == b'-') {
+            return Err(S3Error::no_such_upload().into_io());
+        }
+        Ok(self
+            .base_path
+            .join(INTERNAL_DIR)
+            .join(MULTIPART_DIR)
+            .join(upload_id))
+    }
+
+    async fn read_upload(&self, bucket: &str, key: &str, upload_id: &str) -> Result<MultipartUpload, std::io::Error> {
+        let path = self.upload_path(upload_id)?.join(UPLOAD_FILE);
+        let upload: MultipartUpload = match async_fs::read(&path).await {
+            Ok(content) => serde_json::from_slice(&content)?,
+            Err(e) if e.kind() == std::io::ErrorKind::NotFound => {
+                return Err(S3Error::no_such_upload().into_io());
+            }
+            Err(e) => return Err(e),
+        };
+
+        if upload.bucket != bucket || upload.key != key {
+            return Err(S3Error::no_such_upload().into_io());
+        }
+        Ok(upload)
+    }
+
+    async fn read_parts(&self, upload_id: &str) -> Result<Vec<PartInfo>, std::io::Error> {
+        let dir = self.upload_path(upload_id)?;
+        let mut parts = Vec::new();
+        let mut entries = async_fs::read_dir(&dir).await?;
+
+        while let Some(entry) = entries.next_entry().await? {
+            let name = entry.file_name().to_string_lossy().to_string();
+            if let Some(number) = name.strip_suffix(".json").and_then(|n| n.parse::<u32>().ok()) {
+                let content = async_fs::read(entry.path()).await?;
+                let part: PartInfo = serde_json::from_slice(&content)?;
+                if part.part_number == number {
+                    parts.push(part);
+                }
+            }
+        }
+
+        parts.sort_by_key(|p| p.part_number);
+        Ok(parts)
+    }
+
+    // move a staged data file and its metadata into place as one step for readers
+    async fn commit(
+        &self,
+        bucket: &str,
+        key: &str,
+        data_tmp: &Path,
+        metadata: &ObjectMetadata,
+    ) -> Result<(), std::io::Error> {
+        let object_path = self.object_path(bucket, key)?;
+        let metadata_path = self.metadata_path(bucket, key)?;
+
+        let meta_tmp = self.staging_path();
+        if let Err(e) = write_synced(&meta_tmp, &serde_json::to_vec(metadata)?).await {
+            let _ = async_fs::remove_file(&meta_tmp).await;
+            return Err(e);
+        }
+
+        let _guard = self.locks.write(bucket, key).await;
+        let committed = async {
+            if !self.bucket_exists(bucket).await {
+                return Err(S3Error::no_such_bucket().into_io());
+            }
+            for path in [&object_path, &metadata_path] {
+                if let Some(parent) = path.parent() {
+                    async_fs::create_dir_all(parent).await?;
+                }
+            }
+            self.move_into_place(&[(data_tmp, &object_path), (&meta_tmp, &metadata_path)])
+                .await
+        }
+        .await;
+        if committed.is_err() {
+            let _ = async_fs::remove_file(&meta_tmp).await;
+        }
+
+        committed
+    }
+
+    // rename staged files over their destinations so they all change or none do. the
+    // files being replaced are kept aside until every rename is made, to put back if one
+    // fails; an intent written first lets the next start finish the renames after a crash
+    async fn move_into_place(&self, moves: &[(&Path, &Path)]) -> Result<(), std::io::Error> {
+        let mut kept = Vec::new();
+        let moved = async {
+            for (_, destination) in moves {
+                kept.push(self.keep_aside(destination).await?);
+            }
+
+            let intent = self.write_intent(moves).await?;
+            let mut renamed = 0;
+            let mut result = Ok(());
+            for (staged, destination) in moves {
+                if let Err(e) = async_fs::rename(staged, destination).await {
+                    result = Err(e);
+                    break;
+                }
+                renamed += 1;
+            }
+
+            // the intent goes before anything is undone, so a crash now can't redo it
+            let _ = async_fs::remove_file(&intent).await;
+            if let Err(e) = result {
+                for ((_, destination), previous) in moves.iter().zip(&kept).take(renamed) {
+                    let _ = match previous {
+                        Some(previous) => async_fs::rename(previous, destination).await,
+                        None => async_fs::remove_file(destination).await,
+                    };
+                }
+                return Err(e);
+            }
+
+            for (_, destination) in moves {
+                sync_parent(destination).await?;
+            }
+            Ok(())
+        }
+        .await;
+
+        for previous in kept.into_iter().flatten() {
+            let _ = async_fs::remove_file(previous).await;
+        }
+        moved
+    }
+
+    // a second name in staging for a file about to be replaced, or none if there's no file
+    async fn keep_aside(&self, path: &Path) -> Result<Option<PathBuf>, std::io::Error> {
+        let kept = self.staging_path();
+        let linked = match async_fs::hard_link(path, &kept).await {
+            Err(e) if e.kind() != std::io::ErrorKind::NotFound => {
+                // filesystems without hard links
+                async_fs::copy(path, &kept).await.map(|_| ())
+            }
+            linked => linked,
+        };
+        match linked {
+            Ok(()) => Ok(Some(kept)),
+            Err(e) if e.kind() == std::io::ErrorKind::NotFound => Ok(None),
+            Err(e) => {
+                let _ = async_fs::remove_file(&kept).await;
+                Err(e)
+            }
+        }
+    }
+
+    async fn write_intent(&self, moves: &[(&Path, &Path)]) -> Result<PathBuf, std::io::Error> {
+        let intent = Intent {
+            moves: moves
+                .iter()
+                .map(|(staged, destination)| (self.relative(staged), self.relative(destination)))
+                .collect(),
+        };
+        let dir = self.base_path.join(INTERNAL_DIR).join(COMMITS_DIR);
+        let path = dir.join(format!("{}.{}", uuid::Uuid::new_v4(), META_EXT));
+
+        let written = async {
+            async_fs::create_dir_all(&dir).await?;
+            write_synced(&path, &serde_json::to_vec(&intent)?).await?;
+            sync_parent(&path).await
+        }
+        .await;
+        match written {
+            Ok(()) => Ok(path),
+            Err(e) => {
+                let _ = async_fs::remove_file(&path).await;
+                Err(e)
+            }
+        }
+    }
+
+    // metadata for a file that has none, or none that can be trusted
+    fn derive_metadata(
+        &self,
+        key: &str,
+        data: &[u8],
+        file: &fs::Metadata,
+        content_type: Option<String>,
+    ) -> ObjectMetadata {
+        ObjectMetadata {
+            key: key.to_string(),
+            size: data.len() as u64,
+            last_modified: file
+                .modified()
+                .ok()
+                .and_then(|t| t.duration_since(std::time::UNIX_EPOCH).ok())
+                .and_then(|t| DateTime::from_timestamp(t.as_secs() as i64, 0))
+                .unwrap_or_else(|| self.clock.now()),
+            etag: format!("{:x}", md5::compute(data)),
+            content_type,
+        }
+    }
+
+    // a whole file replaced in one step, by staging it and renaming it over the old one
+    async fn write_into_place(&self, path: &Path, data: &[u8]) -> Result<(), std::io::Error> {
+        let staged = self.stage(data).await?;
+        let moved = async {
+            if let Some(parent) = path.parent() {
+                async_fs::create_dir_all(parent).await?;
+            }
+            async_fs::rename(&staged, path).await?;
+            sync_parent(path).await
+        }
+        .await;
+        if moved.is_err() {
+            let _ = async_fs::remove_file(&staged).await;
+        }
+        moved
+    }
+
+    fn relative(&self, path: &Path) -> String {
+        path.strip_prefix(&self.base_path)
+            .unwrap_or(path)
+            .to_string_lossy()
+            .to_string()
+    }
+
+    // the key a file under a bucket (or its metadata, by the same relative path) holds:
+    // from its name, or from the metadata for a hashed name
+    async fn stored_key(&self, bucket: &str, relative: &Path) -> Option<String> {
+        if !is_hashed(relative) {
+            return decode_key(relative);
+        }
+
+        let mut path = self.bucket_metadata_path(bucket).ok()?.join(relative).into_os_string();
+        path.push(".");
+        path.push(META_EXT);
+        let metadata: ObjectMetadata = serde_json::from_slice(&async_fs::read(path).await.ok()?).ok()?;
+        (encode_key(&metadata.key) == relative).then_some(metadata.key)
+    }
+
+    // every key in a bucket with data or metadata, each checked under its lock so a
+    // repair can't race a put moving its files into place
+    async fn fsck_bucket(&self, bucket: &str, repair: bool, report: &mut FsckReport) -> Result<(), std::io::Error> {
+        let mut files = Vec::new();
+        scan_files(&self.bucket_path(bucket)?, PathBuf::new(), &mut files).await?;
+        let mut keys = BTreeSet::new();
+        for file in &files {
+            match self.stored_key(bucket, file).await {
+                Some(key) => {
+                    keys.insert(key);
+                }
+                None => {
+                    let (finding, moved) = self.fsck_unregistered(bucket, file, repair).await?;
+                    report.findings.push(finding);
+                    keys.extend(moved);
+                }
+            }
+        }
+
+        let metadata_dir = self.bucket_metadata_path(bucket)?;
+        if metadata_dir.exists() {
+            let bucket_path = self.bucket_path(bucket)?;
+            let mut files = Vec::new();
+            scan_files(&metadata_dir, PathBuf::new(), &mut files).await?;
+            for file in files {
+                let key = match metadata_name(&file) {
+                    Some(name) => match self.stored_key(bucket, &name).await {
+                        Some(key) => Some(key),
+                        // kept for an unregistered file, and reported along with it
+                        None if bucket_path.join(&name).is_file() => continue,
+                        None => None,
+                    },
+                    None => None,
+                };
+                match key {
+                    Some(key) => {
+                        keys.insert(key);
+                    }
+                    None => {
+                        let path = metadata_dir.join(&file);
+                        if repair {
+                            async_fs::remove_file(&path).await?;
+                            remove_empty_parents(&path, &metadata_dir);
+                        }
+                        report.findings.push(Finding {
+                            problem: Problem::OrphanedMetadata,
+                            path: self.relative(&path),
+                            bucket: Some(bucket.to_string()),
+                            key: None,
+                            detail: "not for any key".to_string(),
+                        });
+                    }
+                }
+            }
+        }
+
+        for key in keys {
+            report.keys += 1;
+            if let Some(finding) = self.fsck_object(bucket, &key, repair).await? {
+                report.findings.push(finding);
+            }
+        }
+        Ok(())
+    }
+
+    // a file no key is stored as. repair moves it to where the key its path names is
+    // stored, and returns that key; one that can't go there is quarantined instead
+    async fn fsck_unregistered(
+        &self,
+        bucket: &str,
+        relative: &Path,
+        repair: bool,
+    ) -> Result<(Finding, Option<String>), std::io::Error> {
+        let hashed = is_hashed(relative);
+        let key = legacy_key(relative).filter(|_| !hashed);
+        let detail = match &key {
+            None if hashed => "hashed name with no metadata to say its key".to_string(),
+            None => "name isn't a valid key".to_string(),
+            Some(key) if self.object_path(bucket, key)?.exists() => format!("key {} is already stored", key),
+            Some(key) => format!("not where key {} is stored", key),
+        };
+
+        let mut moved = None;
+        if repair {
+            if let Some(key) = &key {
+                let _guard = self.locks.write(bucket, key).await;
+                moved = self.relocate(bucket, relative)?;
+            }
+            if moved.is_none() {
+                self.quarantine(bucket, relative).await?;
+            }
+        }
+
+        let finding = Finding {
+            problem: Problem::UnregisteredFile,
+            path: self.relative(&self.bucket_path(bucket)?.join(relative)),
+            bucket: Some(bucket.to_string()),
+            key,
+            detail,
+        };
+        Ok((finding, moved))
+    }
+
+    // out of the bucket into .six7/quarantine/<bucket>/, at the same relative path
+    async fn quarantine(&self, bucket: &str, relative: &Path) -> Result<(), std::io::Error> {
+        let bucket_path = self.bucket_path(bucket)?;
+        let from = bucket_path.join(relative);
+        let to = self
+            .base_path
+            .join(INTERNAL_DIR)
+            .join(QUARANTINE_DIR)
+            .join(bucket)
+            .join(relative);
+        if let Some(parent) = to.parent() {
+            async_fs::create_dir_all(parent).await?;
+        }
+        async_fs::rename(&from, &to).await?;
+        remove_empty_parents(&from, &bucket_path);
+        Ok(())
+    }
+
+    async fn fsck_object(&self, bucket: &str, key: &str, repair: bool) -> Result<Option<Finding>, std::io::Error> {
+        let object_path = self.object_path(bucket, key)?;
+        let metadata_path = self.metadata_path(bucket, key)?;
+        let _guard = self.locks.write(bucket, key).await;
+
+        let data = read_if_exists(&object_path).await?;
+        let stored = read_if_exists(&metadata_path)
+            .await?
+            .map(|content| serde_json::from_slice::<ObjectMetadata>(&content));
+        let (problem, detail) = match (&data, &stored) {
+            (None, None) => return Ok(None),
+            (None, Some(_)) => (Problem::OrphanedMetadata, "no data".to_string()),
+            (Some(_), None) => (Problem::OrphanedData, "no metadata".to_string()),
+            (Some(_), Some(Err(_))) => (Problem::Mismatch, "metadata doesn't parse".to_string()),
+            (Some(data), Some(Ok(stored))) => match mismatch(stored, key, data) {
+                Some(detail) => (Problem::Mismatch, detail),
+                None => return Ok(None),
+            },
+        };
+
+        if repair {
+            match &data {
+                None => async_fs::remove_file(&metadata_path).await?,
+                Some(data) => {
+                    let content_type = match stored {
+                        Some(Ok(stored)) => stored.content_type,
+                        _ => None,
+                    };
+                    let file = async_fs::metadata(&object_path).await?;
+                    let metadata = self.derive_metadata(key, data, &file, content_type);
+                    self.write_into_place(&metadata_path, &serde_json::to_vec(&metadata)?)
+                        .await?;
+                }
+            }
+        }
+
+        Ok(Some(Finding {
+            problem,
+            path: self.relative(match problem {
+                Problem::OrphanedData => &object_path,
+                _ => &metadata_path,
+            }),
+            bucket: Some(bucket.to_string()),
+            key: Some(key.to_string()),
+            detail,
+        }))
+    }
+
+    // metadata, settings and creation dates left behind by buckets whose directory is gone
+    async fn fsck_leftovers(&self, repair: bool, report: &mut FsckReport) -> Result<(), std::io::Error> {
+        let internal = self.base_path.join(INTERNAL_DIR);
+        for dir in [META_DIR, SETTINGS_DIR, BUCKETS_DIR] {
+            let dir = internal.join(dir);
+            if !dir.exists() {
+                continue;
+            }
+
+            let mut entries = async_fs::read_dir(&dir).await?;
+            while let Some(entry) = entries.next_entry().await? {
+                let name = entry.file_name().to_string_lossy().to_string();
+                let file_type = entry.file_type().await?;
+                let bucket = match file_type.is_dir() {
+                    true => name.clone(),
+                    false => name
+                        .strip_suffix(&format!(".{}", META_EXT))
+                        .unwrap_or(&name)
+                        .to_string(),
+                };
+                if self.bucket_exists(&bucket).await {
+                    continue;
+                }
+
+                if repair {
+                    match file_type.is_dir() {
+                        true => async_fs::remove_dir_all(entry.path()).await?,
+                        false => async_fs::remove_file(entry.path()).await?,
+                    }
+                }
+                report.findings.push(Finding {
+                    problem: Problem::OrphanedMetadata,
+                    path: self.relative(&entry.path()),
+                    bucket: Some(bucket),
+                    key: None,
+                    detail: "no bucket".to_string(),
+                });
+            }
+        }
+        Ok(())
+    }
+
+    async fn fsck_uploads(&self, options: &FsckOptions, report: &mut FsckReport) -> Result<(), std::io::Error> {
+        let root = self.base_path.join(INTERNAL_DIR).join(MULTIPART_DIR);
+        if !root.exists() {
+            return Ok(());
+        }
+
+        let mut entries = async_fs::read_dir(&root).await?;
+        while let Some(entry) = entries.next_entry().await? {
+            report.uploads += 1;
+            let upload = read_if_exists(&entry.path().join(UPLOAD_FILE))
+                .await?
+                .and_then(|content| serde_json::from_slice::<MultipartUpload>(&content).ok());
+            let detail = match &upload {
+                // a record that's missing when the directory is new is still being written
+                None if age(&entry.metadata().await?) >= options.abandoned_after => "no upload record".to_string(),
+                None => continue,
+                Some(upload) if !self.bucket_exists(&upload.bucket).await => "no bucket".to_string(),
+                Some(upload) => {
+                    let started = (self.clock.now() - upload.initiated).to_std().unwrap_or_default();
+                    if started < options.abandoned_after {
+                        continue;
+                    }
+                    format!("started {}", upload.initiated.to_rfc3339())
+                }
+            };
+
+            if options.repair {
+                async_fs::remove_dir_all(entry.path()).await?;
+            }
+            report.findings.push(Finding {
+                problem: Problem::AbandonedUpload,
+                path: self.relative(&entry.path()),
+                bucket: upload.as_ref().map(|u| u.bucket.clone()),
+                key: upload.map(|u| u.key),
+                detail,
+            });
+        }
+        Ok(())
+    }
+
+    // staging is cleared on start, so anything old in it belongs to a write that failed
+    // without cleaning up after itself
+    async fn fsck_staging(&self, options: &FsckOptions, report: &mut FsckReport) -> Result<(), std::io::Error> {
+        let staging = self.base_path.join(INTERNAL_DIR).join(STAGING_DIR);
+        if !staging.exists() {
+            return Ok(());
+        }
+
+        let mut entries = async_fs::read_dir(&staging).await?;
+        while let Some(entry) = entries.next_entry().await? {
+            let metadata = entry.metadata().await?;
+            if age(&metadata) < options.abandoned_after {
+                continue;
+            }
+
+            if options.repair {
+                match metadata.is_dir() {
+                    true => async_fs::remove_dir_all(entry.path()).await?,
+                    false => async_fs::remove_file(entry.path()).await?,
+                }
+            }
+            report.findings.push(Finding {
+                problem: Problem::StagingFile,
+                path: self.relative(&entry.path()),
+                bucket: None,
+                key: None,
+                detail: format!("{} bytes", metadata.len()),
+            });
+        }
+        Ok(())
+    }
+
+    // every object and pending upload in a bucket; callers hold the store exclusively
+    async fn clear_bucket(&self, bucket: &str) -> Result<(), std::io::Error> {
+        for upload in self.list_multipart_uploads(bucket).await? {
+            self.remove_upload(&upload.upload_id).await?;
+        }
+
+        let metadata_path = self.bucket_metadata_path(bucket)?;
+        if metadata_path.exists() {
+            async_fs::remove_dir_all(metadata_path).await?;
+        }
+
+        let mut entries = async_fs::read_dir(self.bucket_path(bucket)?).await?;
+        while let Some(entry) = entries.next_entry().await? {
+            if entry.file_type().await?.is_dir() {
+                async_fs::remove_dir_all(entry.path()).await?;
+            } else {
+                async_fs::remove_file(entry.path()).await?;
+            }
+        }
+        Ok(())
+    }
+
+    // without the upload's lock, for callers holding the store exclusively
+    async fn remove_upload(&self, upload_id: &str) -> Result<(), std::io::Error> {
+        match async_fs::remove_dir_all(self.upload_path(upload_id)?).await {
+            Err(e) if e.kind() != std::io::ErrorKind::NotFound => Err(e),
+            _ => Ok(()),
+        }
+    }
+
+    // a bucket and everything kept for it, once it's empty
+    async fn remove_bucket(&self, bucket: &str) -> Result<(), std::io::Error> {
+        for upload in self.list_multipart_uploads(bucket).await? {
+            self.remove_upload(&upload.upload_id).await?;
+        }
+
+        let metadata_path = self.bucket_metadata_path(bucket)?;
+        if metadata_path.exists() {
+            async_fs::remove_dir_all(metadata_path).await?;
+        }
+        match async_fs::remove_file(self.bucket_info_path(bucket)?).await {
+            Err(e) if e.kind() != std::io::ErrorKind::NotFound => return Err(e),
+            _ => {}
+        }
+        let settings_path = self.bucket_settings_path(bucket)?;
+        if settings_path.exists() {
+            async_fs::remove_dir_all(settings_path).await?;
+        }
+        async_fs::remove_dir_all(self.bucket_path(bucket)?).await
+    }
+
+    async fn stage(&self, data: &[u8]) -> Result<PathBuf, std::io::Error> {
+        let path = self.staging_path();
+        let staged = async {
+            if let Some(staging) = path.parent() {
+                async_fs::create_dir_all(staging).await?;
+            }
+            write_synced(&path, data).await
+        }
+        .await;
+
+        match staged {
+            Ok(()) => Ok(path),
+            Err(e) => {
+                let _ = async_fs::remove_file(&path).await;
+                Err(e)
+            }
+        }
+    }
+}
+
+#[async_trait]
+impl StorageBackend for Storage {
+    fn clock(&self) -> &Clock {
+        &self.clock
+    }
+
+    fn entropy(&self) -> &Entropy {
+        &self.entropy
+    }
+
+    async fn create_bucket(&self, bucket: &str) -> Result<(), std::io::Error> {
+        let bucket_path = self.bucket_path(bucket)?;
+        async_fs::create_dir_all(bucket_path).await?;
+
+        let info_path = self.bucket_info_path(bucket)?;
+        if !info_path.exists() {
+            let info = BucketInfo {
+                name: bucket.to_string(),
+                creation_date: self.clock.now(),
+            };
+            if let Some(parent) = info_path.parent() {
+                async_fs::create_dir_all(parent).await?;
+            }
+            write_synced(&info_path, &serde_json::to_vec(&info)?).await?;
+        }
+        Ok(())
+    }
+
+    async fn delete_bucket(&self, bucket: &str) -> Result<(), std::io::Error> {
+        if !self.bucket_exists(bucket).await {
+            return Err(S3Error::no_such_bucket().into_io());
+        }
+        if !self.list_objects(bucket, None).await?.is_empty() {
+            return Err(S3Error::bucket_not_empty().into_io());
+        }
+
+        self.remove_bucket(bucket).await
+    }
+
+    async fn bucket_exists(&self, bucket: &str) -> bool {
+        self.bucket_path(bucket).map(|p| p.is_dir()).unwrap_or(false)
+    }
+
+    async fn list_buckets(&self) -> Result<Vec<BucketInfo>, std::io::Error> {
+        let mut buckets = Vec::new();
+        let mut entries = async_fs::read_dir(&self.base_path).await?;
+
+        while let Some(entry) = entries.next_entry().await? {
+            let name = entry.file_name().to_string_lossy().to_string();
+            if !entry.file_type().await?.is_dir() || validate_bucket_name(&name).is_err() {
+                continue;
+            }
+
+            if let Ok(content) = async_fs::read(self.bucket_info_path(&name)?).await {
+                if let Ok(info) = serde_json::from_slice::<BucketInfo>(&content) {
+                    buckets.push(info);
+                    continue;
+                }
+            }
+
+            // a directory made by hand, or by a version that kept no bucket info
+            let metadata = entry.metadata().await?;
+            let created = metadata.created().or_else(|_| metadata.modified()).ok();
+            buckets.push(BucketInfo {
+                name,
+                creation_date: created.map(DateTime::<Utc>::from).unwrap_or_else(|| self.clock.now()),
+            });
+        }
+
+        buckets.sort_by(|a, b| a.name.cmp(&b.name));
+        Ok(buckets)
+    }
+
+    async fn put_object(
+        &self,
+        bucket: &str,
+        key: &str,
+        data: Bytes,
+        content_type: Option<String>,
+    ) -> Result<ObjectMetadata, std::io::Error> {
+        validate_key(key).map_err(S3Error::into_io)?;
+        // the bucket is checked in commit, under the lock, so a restore or reset in
+        // progress can't make it look missing
+        let metadata = ObjectMetadata {
+            key: key.to_string(),
+            size: data.len() as u64,
+            last_modified: self.clock.now(),
+            etag: format!("{:x}", md5::compute(&data)),
+            content_type,
+        };
+
+        // stage the data before touching the destination so readers never see a partial write
+        let data_tmp = self.stage(&data).await?;
+        if let Err(e) = self.commit(bucket, key, &data_tmp, &metadata).await {
+            let _ = async_fs::remove_file(&data_tmp).await;
+            return Err(e);
+        }
+
+        Ok(metadata)
+    }
+
+    async fn get_object(&self, bucket: &str, key: &str) -> Result<Bytes, std::io::Error> {
+        let object_path = self.object_path(bucket, key)?;
+        let _guard = self.locks.read(bucket, key).await;
+        let data = async_fs::read(object_path).await?;
+        Ok(Bytes::from(data))
+    }
+
+    async fn head_object(&self, bucket: &str, key: &str) -> Result<ObjectMetadata, std::io::Error> {
+        let object_path = self.object_path(bucket, key)?;
+        let metadata_path = self.metadata_path(bucket, key)?;
+        let _guard = self.locks.read(bucket, key).await;
+        let metadata = async_fs::metadata(&object_path).await?;
+        if !metadata.is_file() {
+            return Err(std::io::ErrorKind::NotFound.into());
+        }
+
+        if let Ok(content) = async_fs::read(metadata_path).await {
+            if let Ok(stored) = serde_json::from_slice::<ObjectMetadata>(&content) {
+                return Ok(stored);
+            }
+        }
+
+        // no stored metadata (file placed on disk by hand), derive it from the data
+        let data = async_fs::read(&object_path).await?;
+        Ok(self.derive_metadata(key, &data, &metadata, None))
+    }
+
+    async fn delete_object(&self, bucket: &str, key: &str) -> Result<(), std::io::Error> {
+        let object_path = self.object_path(bucket, key)?;
+        let metadata_path = self.metadata_path(bucket, key)?;
+        let _guard = self.locks.write(bucket, key).await;
+        async_fs::remove_file(object_path).await?;
+
+        match async_fs::remove_file(metadata_path).await {
+            Err(e) if e.kind() != std::io::ErrorKind::NotFound => Err(e),
+            _ => Ok(()),
+        }
+    }
+
+    async fn list_objects(
+        &self,
+        bucket: &str,
+        prefix: Option<&str>,
+    ) -> Result<Vec<ObjectMetadata>, std::io::Error> {
+        let bucket_path = self.bucket_path(bucket)?;
+        let mut files = Vec::new();
+        scan_files(&bucket_path, PathBuf::new(), &mut files).await?;
+
+        // anything that doesn't decode wasn't written by us, so it isn't an object
+        let mut objects = Vec::new();
+        for file in &files {
+            let Some(key) = self.stored_key(bucket, file).await else {
+                continue;
+            };
+            if let Some(p) = prefix {
+                if !key.starts_with(p) {
+                    continue;
+                }
+            }
+
+            // the object may have been deleted between the scan and now
+            if let Ok(metadata) = self.head_object(bucket, &key).await {
+                objects.push(metadata);
+            }
+        }
+
+        objects.sort_by(|a, b| a.key.cmp(&b.key));
+        Ok(objects)
+    }
+
+    async fn create_multipart_upload(
+        &self,
+        bucket: &str,
+        key: &str,
+        content_type: Option<String>,
+    ) -> Result<MultipartUpload, std::io::Error> {
+        validate_key(key).map_err(S3Error::into_io)?;
+        if !self.bucket_exists(bucket).await {
+            return Err(S3Error::no_such_bucket().into_io());
+        }
+
+        let upload = MultipartUpload {
+            upload_id: self.entropy.uuid().simple().to_string(),
+            bucket: bucket.to_string(),
+            key: key.to_string(),
+            content_type,
+            initiated: self.clock.now(),
+        };
+
+        let dir = self.upload_path(&upload.upload_id)?;
+        async_fs::create_dir_all(&dir).await?;
+        write_synced(&dir.join(UPLOAD_FILE), &serde_json::to_vec(&upload)?).await?;
+
+        Ok(upload)
+    }
+
+    async fn upload_part(
+        &self,
+        bucket: &str,
+        key: &str,
+        upload_id: &str,
+        part_number: u32,
+        data: Bytes,
+    ) -> Result<PartInfo, std::io::Error> {
+        validate_part_number(part_number).map_err(S3Error::into_io)?;
+        let _guard = self.locks.upload(upload_id).await;
+        self.read_upload(bucket, key, upload_id).await?;
+
+        let part = PartInfo {
+            part_number,
+            etag: format!("{:x}", md5::compute(&data)),
+            size: data.len() as u64,
+        };
+
+        // same staging dance as objects so a re-uploaded part replaces the old one cleanly
+        let dir = self.upload_path(upload_id)?;
+        let data_tmp = self.stage(&data).await?;
+        let info_tmp = self.stage(&serde_json::to_vec(&part)?).await?;
+        let moved = self
+            .move_into_place(&[
+                (&data_tmp, &dir.join(part_number.to_string())),
+                (&info_tmp, &dir.join(format!("{}.json", part_number))),
+            ])
+            .await;
+        if let Err(e) = moved {
+            let _ = async_fs::remove_file(&data_tmp).await;
+            let _ = async_fs::remove_file(&info_tmp).await;
+            return Err(e);
+        }
+
+        Ok(part)
+    }
+
+    async fn complete_multipart_upload(
+        &self,
+        bucket: &str,
+        key: &str,
+        upload_id: &str,
+        parts: &[(u32, String)],
+    ) -> Result<ObjectMetadata, std::io::Error> {
+        // parts can't change while they're put together, but the upload's lock is let go
+        // before the object's is taken, as no two locks are ever held at once
+        let guard = self.locks.upload(upload_id).await;
+        let upload = self.read_upload(bucket, key, upload_id).await?;
+        let uploaded = self.read_parts(upload_id).await?;
+        let etag = resolve_parts(&uploaded, parts).map_err(S3Error::into_io)?;
+
+        let dir = self.upload_path(upload_id)?;
+        let data_tmp = self.staging_path();
+        let staged = async {
+            if let Some(staging) = data_tmp.parent() {
+                async_fs::create_dir_all(staging).await?;
+            }
+            let mut out = async_fs::File::create(&data_tmp).await?;
+            for (part_number, _) in parts {
+                let mut part = async_fs::File::open(dir.join(part_number.to_string())).await?;
+                tokio::io::copy(&mut part, &mut out).await?;
+            }
+            out.flush().await?;
+            out.sync_all().await
+        }
+        .await;
+        if let Err(e) = staged {
+            let _ = async_fs::remove_file(&data_tmp).await;
+            return Err(e);
+        }
+
+        let size = parts
+            .iter()
+            .filter_map(|(n, _)| uploaded.iter().find(|p| p.part_number == *n))
+            .map(|p| p.size)
+            .sum();
+        let metadata = ObjectMetadata {
+            key: key.to_string(),
+            size,
+            last_modified: self.clock.now(),
+            etag,
+            content_type: upload.content_type,
+        };
+        drop(guard);
+
+        if let Err(e) = self.commit(bucket, key, &data_tmp, &metadata).await {
+            let _ = async_fs::remove_file(&data_tmp).await;
+            return Err(e);
+        }
+
+        let _guard = self.locks.upload(upload_id).await;
+        self.remove_upload(upload_id).await?;
+        Ok(metadata)
+    }
+
+    async fn abort_multipart_upload(
+        &self,
+        bucket: &str,
+        key: &str,
+        upload_id: &str,
+    ) -> Result<(), std::io::Error> {
+        let _guard = self.locks.upload(upload_id).await;
+        self.read_upload(bucket, key, upload_id).await?;
+        async_fs::remove_dir_all(self.upload_path(upload_id)?).await
+    }
+
+    // drops the bucket's directory contents wholesale instead of deleting key by key
+    async fn empty_bucket(&self, bucket: &str) -> Result<(), std::io::Error> {
+        let _all = self.locks.exclusive().await;
+        if !self.bucket_exists(bucket).await {
+            return Err(S3Error::no_such_bucket().into_io());
+        }
+        self.clear_bucket(bucket).await
+    }
+
+    async fn reset(&self, keep: &[String]) -> Result<(), std::io::Error> {
+        let _all = self.locks.exclusive().await;
+        for bucket in self.list_buckets().await? {
+            self.clear_bucket(&bucket.name).await?;
+            if !keep.contains(&bucket.name) {
+                self.remove_bucket(&bucket.name).await?;
+            }
+        }
+        for bucket in keep {
+            self.create_bucket(bucket).await?;
+        }
+        Ok(())
+    }
+
+    async fn create_snapshot(&self, name: &str) -> Result<SnapshotInfo, std::io::Error> {
+        let _all = self.locks.exclusive().await;
+        let base = self.base_path.clone();
+        let name = name.to_string();
+        let created = self.clock.now();
+        blocking(move || snapshot::create(&base, &name, created)).await
+    }
+
+    async fn restore_snapshot(&self, name: &str) -> Result<(), std::io::Error> {
+        let _all = self.locks.exclusive().await;
+        let base = self.base_path.clone();
+        let name = name.to_string();
+        blocking(move || snapshot::restore(&base, &name)).await
+    }
+
+    async fn list_snapshots(&self) -> Result<Vec<SnapshotInfo>, std::io::Error> {
+        let base = self.base_path.clone();
+        blocking(move || snapshot::list(&base)).await
+    }
+
+    async fn delete_snapshot(&self, name: &str) -> Result<(), std::io::Error> {
+        let base = self.base_path.clone();
+        let name = name.to_string();
+        blocking(move || snapshot::delete(&base, &name)).await
+    }
+
+    async fn check_writable(&self) -> Result<(), std::io::Error> {
+        // a file in staging, where a crash would leave it to be cleared on the next start;
+        // not named from the seeded entropy, since probes come whenever they come
+        let probe = self
+            .base_path
+            .join(INTERNAL_DIR)
+            .join(STAGING_DIR)
+            .join(format!("ready-{}", uuid::Uuid::new_v4().simple()));
+        async_fs::write(&probe, b"ok").await?;
+        async_fs::remove_file(&probe).await
+    }
+
+    async fn list_multipart_uploads(&self, bucket: &str) -> Result<Vec<MultipartUpload>, std::io::Error> {
+        validate_bucket_name(bucket).map_err(S3Error::into_io)?;
+        if !self.bucket_exists(bucket).await {
+            return Err(S3Error::no_such_bucket().into_io());
+        }
+
+        let root = self.base_path.join(INTERNAL_DIR).join(MULTIPART_DIR);
+        let mut uploads = Vec::new();
+        if !root.exists() {
+            return Ok(uploads);
+        }
+
+        let mut entries = async_fs::read_dir(&root).await?;
+        while let Some(entry) = entries.next_entry().await? {
+            let Ok(content) = async_fs::read(entry.path().join(UPLOAD_FILE)).await else {
+                continue;
+            };
+            if let Ok(upload) = serde_json::from_slice::<MultipartUpload>(&content) {
+                if upload.bucket == bucket {
+                    uploads.push(upload);
+                }
+            }
+        }
+
+        uploads.sort_by(|a, b| {
+            a.key
+                .cmp(&b.key)
+                .then(a.initiated.cmp(&b.initiated))
+                .then(a.upload_id.cmp(&b.upload_id))
+        });
+        Ok(uploads)
+    }
+
+    async fn get_bucket_setting(&self, bucket: &str, name: &str) -> Result<Option<String>, std::io::Error> {
+        let path = self.bucket_setting_path(bucket, name)?;
+        if !self.bucket_exists(bucket).await {
+            return Err(S3Error::no_such_bucket().into_io());
+        }
+        match async_fs::read_to_string(path).await {
+            Ok(value) => Ok(Some(value)),
+            Err(e) if e.kind() == std::io::ErrorKind::NotFound => Ok(None),
+            Err(e) => Err(e),
+        }
+    }
+
+    async fn put_bucket_setting(
+        &self,
+        bucket: &str,
+        name: &str,
+        value: Option<String>,
+    ) -> Result<(), std::io::Error> {
+        let path = self.bucket_setting_path(bucket, name)?;
+        if !self.bucket_exists(bucket).await {
+            return Err(S3Error::no_such_bucket().into_io());
+        }
+        let Some(value) = value else {
+            return match async_fs::remove_file(path).await {
+                Err(e) if e.kind() != std::io::ErrorKind::NotFound => Err(e),
+                _ => Ok(()),
+            };
+        };
+
+        self.write_into_place(&path, value.as_bytes()).await
+    }
+
+    async fn fsck(&self, options: &FsckOptions) -> Result<FsckReport, std::io::Error> {
+        let mut report = FsckReport {
+            repaired: options.repair,
+            ..FsckReport::default()
+        };
+        for bucket in self.list_buckets().await? {
+            self.fsck_bucket(&bucket.name, options.repair, &mut report).await?;
+        }
+        self.fsck_leftovers(options.repair, &mut report).await?;
+        self.fsck_uploads(options, &mut report).await?;
+        self.fsck_staging(options, &mut report).await?;
+        Ok(report)
+    }
+}
+
+// redo the renames of every write that was stopped after its intent was written; a
+// staged file that's gone was already moved
+fn recover(base: &Path) -> Result<(), std::io::Error> {
+    let dir = base.join(INTERNAL_DIR).join(COMMITS_DIR);
+    let entries = match fs::read_dir(&dir) {
+        Ok(entries) => entries,
+        Err(e) if e.kind() == std::io::ErrorKind::NotFound => return Ok(()),
+        Err(e) => return Err(e),
+    };
+
+    for entry in entries {
+        let path = entry?.path();
+        // an intent that doesn't parse was cut short before any rename was made
+        let intent = fs::read(&path)
+            .ok()
+            .and_then(|content| serde_json::from_slice::<Intent>(&content).ok());
+        for (staged, destination) in intent.map(|i| i.moves).unwrap_or_default() {
+            let (staged, destination) = (base.join(staged), base.join(destination));
+            if !staged.exists() {
+                continue;
+            }
+            let moved = destination
+                .parent()
+                .map_or(Ok(()), fs::create_dir_all)
+                .and_then(|()| fs::rename(&staged, &destination));
+            match moved {
+                Ok(()) => tracing::info!("finished an interrupted write to {}", destination.display()),
+                Err(e) => tracing::warn!("can't finish an interrupted write to {}: {}", destination.display(), e),
+            }
+        }
+        fs::remove_file(&path)?;
+    }
+    Ok(())
+}
+
+// the directories a file was moved out of, up to root, if that left them empty
+fn remove_empty_parents(path: &Path, root: &Path) {
+    for dir in path.ancestors().skip(1) {
+        if dir == root || !dir.starts_with(root) || fs::remove_dir(dir).is_err() {
+            break;
+        }
+    }
+}
+
+// every file under dir, by its path relative to dir
+fn scan_files<'a>(
+    dir: &'a Path,
+    relative: PathBuf,
+    files: &'a mut Vec<PathBuf>,
+) -> Pin<Box<dyn Future<Output = Result<(), std::io::Error>> + Send + 'a>> {
+    Box::pin(async move {
+        let mut entries = async_fs::read_dir(dir).await?;
+
+        while let Some(entry) = entries.next_entry().await? {
+            let file_type = entry.file_type().await?;
+            let relative_path = relative.join(entry.file_name());
+
+            if file_type.is_file() {
+                files.push(relative_path);
+            } else if file_type.is_dir() {
+                scan_files(&entry.path(), relative_path, files).await?;
+            }
+        }
+
+        Ok(())
+    })
+}
+
+// the path of the file a metadata file under a bucket's metadata dir is for
+fn metadata_name(path: &Path) -> Option<PathBuf> {
+    let name = path.file_name()?.to_str()?.strip_suffix(&format!(".{}", META_EXT))?;
+    Some(path.with_file_name(name))
+}
+
+// how stored metadata differs from the data it describes; a multipart etag isn't the
+// md5 of the whole object, so only the size is checked against those
+fn mismatch(stored: &ObjectMetadata, key: &str, data: &[u8]) -> Option<String> {
+    if stored.key != key {
+        return Some(format!("metadata is for key {}", stored.key));
+    }
+    if stored.size != data.len() as u64 {
+        return Some(format!("size {} in metadata, {} on disk", stored.size, data.len()));
+    }
+    let etag = format!("{:x}", md5::compute(data));
+    if !stored.etag.contains('-') && stored.etag != etag {
+        return Some(format!("etag {} in metadata, {} on disk", stored.etag, etag));
+    }
+    None
+}
+
+async fn read_if_exists(path: &Path) -> Result<Option<Vec<u8>>, std::io::Error> {
+    match async_fs::read(path).await {
+        Ok(content) => Ok(Some(content)),
+        Err(e) if e.kind() == std::io::ErrorKind::NotFound => Ok(None),
+        Err(e) => Err(e),
+    }
+}
+
+// how long since a file or directory was last written
+fn age(metadata: &fs::Metadata) -> Duration {
+    metadata
+        .modified()
+        .ok()
+        .and_then(|t| t.elapsed().ok())
+        .unwrap_or_default()
+}
+
+// whole-tree work like snapshots is a lot of small syscalls; keep it off the runtime threads
+async fn blocking<T, F>(f: F) -> Result<T, std::io::Error>
+where
+    F: FnOnce() -> Result<T, std::io::Error> + Send + 'static,
+    T: Send + 'static,
+{
+    tokio::task::spawn_blocking(f)
+        .await
+        .map_err(std::io::Error::other)?
+}
+
+async fn write_synced(path: &Path, data: &[u8]) -> Result<(), std::io::Error> {
+    let mut file = async_fs::File::create(path).await?;
+    file.write_all(data).await?;
+    file.flush().await?;
+    file.sync_all().await
+}
+
+// make a rename durable by syncing the directory entry as well
+#[cfg(unix)]
+async fn sync_parent(path: &Path) -> Result<(), std::io::Error> {
+    match path.parent() {
+        Some(parent) => async_fs::File::open(parent).await?.sync_all().await,
+        None => Ok(()),
+    }
+}
+
+#[cfg(not(unix))]
+async fn sync_parent(_path: &Path) -> Result<(), std::io::Error> {
+    Ok(())
+}
+
+// per-key reader/writer locks so concurrent writers to one key are applied one at a time.
+// every key lock is taken under a shared hold of one lock for the whole store, which
+// snapshots, restores and resets take alone so no request sees them half done
+#[derive(Default)]
+struct KeyLocks {
+    all: Arc<RwLock<()>>,
+    locks: Mutex<HashMap<String, Arc<RwLock<()>>>>,
+}
+
+// a key's lock, held along with the store's; the key's is released first
+struct KeyGuard<G> {
+    _key: G,
+    _all: OwnedRwLockReadGuard<()>,
+}
+
+impl KeyLocks {
+    fn lock_for(&self, bucket: &str, key: &str) -> Arc<RwLock<()>> {
+        let mut locks = self.locks.lock().unwrap();
+
+        // drop locks nobody is holding so the map doesn't grow with every key ever written
+        if locks.len() > 1024 {
+            locks.retain(|_, lock| Arc::strong_count(lock) > 1);
+        }
+
+        locks
+            .entry(format!("{}/{}", bucket, key))
+            .or_default()
+            .clone()
+    }
+
+    async fn read(&self, bucket: &str, key: &str) -> KeyGuard<OwnedRwLockReadGuard<()>> {
+        let all = self.all.clone().read_owned().await;
+        KeyGuard {
+            _key: self.lock_for(bucket, key).read_owned().await,
+            _all: all,
+        }
+    }
+
+    async fn write(&self, bucket: &str, key: &str) -> KeyGuard<OwnedRwLockWriteGuard<()>> {
+        let all = self.all.clone().read_owned().await;
+        KeyGuard {
+            _key: self.lock_for(bucket, key).write_owned().await,
+            _all: all,
+        }
+    }
+
+    // a multipart upload's parts, by upload id; apart from every key, since no bucket
+    // name starts with a dot
+    async fn upload(&self, upload_id: &str) -> KeyGuard<OwnedRwLockWriteGuard<()>> {
+        self.write(".uploads", upload_id).await
+    }
+
+    // the whole store, once every key lock held now is released. it isn't reentrant:
+    // nothing that takes a key lock may be called while holding it
+    async fn exclusive(&self) -> OwnedRwLockWriteGuard<()> {
+        self.all.clone().write_owned().await
+    }
+}
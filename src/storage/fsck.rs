@@ -0,0 +1,74 @@
+use serde::{Deserialize, Serialize};
+use std::fmt;
+use std::time::Duration;
+
+// uploads and staged writes this old are taken to be abandoned rather than in progress
+pub const DEFAULT_ABANDONED_AFTER: Duration = Duration::from_secs(24 * 60 * 60);
+
+#[derive(Debug, Clone)]
+pub struct FsckOptions {
+    pub repair: bool,
+    pub abandoned_after: Duration,
+}
+
+impl Default for FsckOptions {
+    fn default() -> Self {
+        FsckOptions {
+            repair: false,
+            abandoned_after: DEFAULT_ABANDONED_AFTER,
+        }
+    }
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum Problem {
+    // a file under a bucket with no metadata; repair derives it from the data
+    OrphanedData,
+    // metadata for a file or bucket that's gone; repair deletes it
+    OrphanedMetadata,
+    // metadata that doesn't parse, or whose size or etag the file doesn't match; repair
+    // derives it again, keeping the content type
+    Mismatch,
+    // a multipart upload older than abandoned_after, or whose bucket or record is gone;
+    // repair aborts it
+    AbandonedUpload,
+    // a write staged but never moved into place; repair deletes it
+    StagingFile,
+    // a file in a bucket that isn't stored as any key, such as bucket/dir/file.txt from
+    // before keys were encoded; repair moves it to where its key is stored, or to
+    // .six7/quarantine when it has no key or that key is already stored
+    UnregisteredFile,
+}
+
+impl fmt::Display for Problem {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.pad(match self {
+            Problem::OrphanedData => "orphaned_data",
+            Problem::OrphanedMetadata => "orphaned_metadata",
+            Problem::Mismatch => "mismatch",
+            Problem::AbandonedUpload => "abandoned_upload",
+            Problem::StagingFile => "staging_file",
+            Problem::UnregisteredFile => "unregistered_file",
+        })
+    }
+}
+
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct Finding {
+    pub problem: Problem,
+    // relative to the data dir
+    pub path: String,
+    pub bucket: Option<String>,
+    pub key: Option<String>,
+    pub detail: String,
+}
+
+#[derive(Debug, Clone, Default, Serialize, Deserialize)]
+pub struct FsckReport {
+    // keys with data or metadata, and multipart uploads, that were looked at
+    pub keys: u64,
+    pub uploads: u64,
+    pub findings: Vec<Finding>,
+    pub repaired: bool,
+}
@@ -0,0 +1,417 @@
+use async_trait::async_trait;
+use bytes::{Bytes, BytesMut};
+use chrono::{DateTime, Utc};
+use std::collections::{BTreeMap, HashMap};
+use std::sync::RwLock;
+
+use super::snapshot::validate_snapshot_name;
+use super::{
+    resolve_parts, validate_bucket_name, validate_key, validate_part_number, validate_setting_name,
+    BucketInfo,
+    MultipartUpload, ObjectMetadata, PartInfo, SnapshotInfo, StorageBackend,
+};
+use crate::clock::Clock;
+use crate::entropy::Entropy;
+use crate::error::S3Error;
+
+// keeps everything in process memory; nothing touches disk and nothing outlives the value
+#[derive(Default)]
+pub struct MemoryStorage {
+    state: RwLock<MemoryState>,
+    // object data is reference counted, so a snapshot shares it rather than copying it
+    snapshots: RwLock<BTreeMap<String, (SnapshotInfo, MemoryState)>>,
+    clock: Clock,
+    entropy: Entropy,
+}
+
+#[derive(Default, Clone)]
+struct MemoryState {
+    buckets: BTreeMap<String, MemoryBucket>,
+    uploads: HashMap<String, MemoryUpload>,
+}
+
+#[derive(Clone)]
+struct MemoryBucket {
+    created: DateTime<Utc>,
+    objects: BTreeMap<String, (Bytes, ObjectMetadata)>,
+    settings: BTreeMap<String, String>,
+}
+
+#[derive(Clone)]
+struct MemoryUpload {
+    upload: MultipartUpload,
+    parts: BTreeMap<u32, (Bytes, PartInfo)>,
+}
+
+impl MemoryStorage {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    pub fn with_clock(mut self, clock: Clock) -> Self {
+        self.clock = clock;
+        self
+    }
+
+    pub fn with_entropy(mut self, entropy: Entropy) -> Self {
+        self.entropy = entropy;
+        self
+    }
+}
+
+impl MemoryState {
+    fn bucket(&self, bucket: &str) -> Result<&MemoryBucket, std::io::Error> {
+        validate_bucket_name(bucket).map_err(S3Error::into_io)?;
+        self.buckets
+            .get(bucket)
+            .ok_or_else(|| S3Error::no_such_bucket().into_io())
+    }
+
+    fn bucket_mut(&mut self, bucket: &str) -> Result<&mut MemoryBucket, std::io::Error> {
+        validate_bucket_name(bucket).map_err(S3Error::into_io)?;
+        self.buckets
+            .get_mut(bucket)
+            .ok_or_else(|| S3Error::no_such_bucket().into_io())
+    }
+
+    fn upload_mut(&mut self, bucket: &str, key: &str, upload_id: &str) -> Result<&mut MemoryUpload, std::io::Error> {
+        match self.uploads.get_mut(upload_id) {
+            Some(upload) if upload.upload.bucket == bucket && upload.upload.key == key => Ok(upload),
+            _ => Err(S3Error::no_such_upload().into_io()),
+        }
+    }
+}
+
+#[async_trait]
+impl StorageBackend for MemoryStorage {
+    fn clock(&self) -> &Clock {
+        &self.clock
+    }
+
+    fn entropy(&self) -> &Entropy {
+        &self.entropy
+    }
+
+    async fn create_bucket(&self, bucket: &str) -> Result<(), std::io::Error> {
+        validate_bucket_name(bucket).map_err(S3Error::into_io)?;
+        let mut state = self.state.write().unwrap();
+        state
+            .buckets
+            .entry(bucket.to_string())
+            .or_insert_with(|| MemoryBucket {
+                created: self.clock.now(),
+                objects: BTreeMap::new(),
+                settings: BTreeMap::new(),
+            });
+        Ok(())
+    }
+
+    async fn delete_bucket(&self, bucket: &str) -> Result<(), std::io::Error> {
+        let mut state = self.state.write().unwrap();
+        if !state.bucket(bucket)?.objects.is_empty() {
+            return Err(S3Error::bucket_not_empty().into_io());
+        }
+
+        state.buckets.remove(bucket);
+        state.uploads.retain(|_, u| u.upload.bucket != bucket);
+        Ok(())
+    }
+
+    async fn bucket_exists(&self, bucket: &str) -> bool {
+        self.state.read().unwrap().bucket(bucket).is_ok()
+    }
+
+    async fn list_buckets(&self) -> Result<Vec<BucketInfo>, std::io::Error> {
+        let state = self.state.read().unwrap();
+        Ok(state
+            .buckets
+            .iter()
+            .map(|(name, bucket)| BucketInfo {
+                name: name.clone(),
+                creation_date: bucket.created,
+            })
+            .collect())
+    }
+
+    async fn put_object(
+        &self,
+        bucket: &str,
+        key: &str,
+        data: Bytes,
+        content_type: Option<String>,
+    ) -> Result<ObjectMetadata, std::io::Error> {
+        validate_key(key).map_err(S3Error::into_io)?;
+
+        let metadata = ObjectMetadata {
+            key: key.to_string(),
+            size: data.len() as u64,
+            last_modified: self.clock.now(),
+            etag: format!("{:x}", md5::compute(&data)),
+            content_type,
+        };
+
+        let mut state = self.state.write().unwrap();
+        state
+            .bucket_mut(bucket)?
+            .objects
+            .insert(key.to_string(), (data, metadata.clone()));
+        Ok(metadata)
+    }
+
+    async fn get_object(&self, bucket: &str, key: &str) -> Result<Bytes, std::io::Error> {
+        validate_key(key).map_err(S3Error::into_io)?;
+        let state = self.state.read().unwrap();
+        state
+            .bucket(bucket)?
+            .objects
+            .get(key)
+            .map(|(data, _)| data.clone())
+            .ok_or_else(|| std::io::ErrorKind::NotFound.into())
+    }
+
+    async fn head_object(&self, bucket: &str, key: &str) -> Result<ObjectMetadata, std::io::Error> {
+        validate_key(key).map_err(S3Error::into_io)?;
+        let state = self.state.read().unwrap();
+        state
+            .bucket(bucket)?
+            .objects
+            .get(key)
+            .map(|(_, metadata)| metadata.clone())
+            .ok_or_else(|| std::io::ErrorKind::NotFound.into())
+    }
+
+    async fn delete_object(&self, bucket: &str, key: &str) -> Result<(), std::io::Error> {
+        validate_key(key).map_err(S3Error::into_io)?;
+        let mut state = self.state.write().unwrap();
+        match state.bucket_mut(bucket)?.objects.remove(key) {
+            Some(_) => Ok(()),
+            None => Err(std::io::ErrorKind::NotFound.into()),
+        }
+    }
+
+    async fn list_objects(
+        &self,
+        bucket: &str,
+        prefix: Option<&str>,
+    ) -> Result<Vec<ObjectMetadata>, std::io::Error> {
+        let state = self.state.read().unwrap();
+        let prefix = prefix.unwrap_or("");
+        Ok(state
+            .bucket(bucket)?
+            .objects
+            .range(prefix.to_string()..)
+            .take_while(|(key, _)| key.starts_with(prefix))
+            .map(|(_, (_, metadata))| metadata.clone())
+            .collect())
+    }
+
+    async fn create_multipart_upload(
+        &self,
+        bucket: &str,
+        key: &str,
+        content_type: Option<String>,
+    ) -> Result<MultipartUpload, std::io::Error> {
+        validate_key(key).map_err(S3Error::into_io)?;
+        let mut state = self.state.write().unwrap();
+        state.bucket(bucket)?;
+
+        let upload = MultipartUpload {
+            upload_id: self.entropy.uuid().simple().to_string(),
+            bucket: bucket.to_string(),
+            key: key.to_string(),
+            content_type,
+            initiated: self.clock.now(),
+        };
+        state.uploads.insert(
+            upload.upload_id.clone(),
+            MemoryUpload {
+                upload: upload.clone(),
+                parts: BTreeMap::new(),
+            },
+        );
+        Ok(upload)
+    }
+
+    async fn upload_part(
+        &self,
+        bucket: &str,
+        key: &str,
+        upload_id: &str,
+        part_number: u32,
+        data: Bytes,
+    ) -> Result<PartInfo, std::io::Error> {
+        validate_part_number(part_number).map_err(S3Error::into_io)?;
+
+        let part = PartInfo {
+            part_number,
+            etag: format!("{:x}", md5::compute(&data)),
+            size: data.len() as u64,
+        };
+
+        let mut state = self.state.write().unwrap();
+        state
+            .upload_mut(bucket, key, upload_id)?
+            .parts
+            .insert(part_number, (data, part.clone()));
+        Ok(part)
+    }
+
+    async fn complete_multipart_upload(
+        &self,
+        bucket: &str,
+        key: &str,
+        upload_id: &str,
+        parts: &[(u32, String)],
+    ) -> Result<ObjectMetadata, std::io::Error> {
+        let mut state = self.state.write().unwrap();
+        let upload = state.upload_mut(bucket, key, upload_id)?;
+
+        let uploaded: Vec<PartInfo> = upload.parts.values().map(|(_, p)| p.clone()).collect();
+        let etag = resolve_parts(&uploaded, parts).map_err(S3Error::into_io)?;
+
+        let mut data = BytesMut::new();
+        for (part_number, _) in parts {
+            data.extend_from_slice(&upload.parts[part_number].0);
+        }
+
+        let metadata = ObjectMetadata {
+            key: key.to_string(),
+            size: data.len() as u64,
+            last_modified: self.clock.now(),
+            etag,
+            content_type: upload.upload.content_type.clone(),
+        };
+
+        state
+            .bucket_mut(bucket)?
+            .objects
+            .insert(key.to_string(), (data.freeze(), metadata.clone()));
+        state.uploads.remove(upload_id);
+        Ok(metadata)
+    }
+
+    async fn abort_multipart_upload(
+        &self,
+        bucket: &str,
+        key: &str,
+        upload_id: &str,
+    ) -> Result<(), std::io::Error> {
+        let mut state = self.state.write().unwrap();
+        state.upload_mut(bucket, key, upload_id)?;
+        state.uploads.remove(upload_id);
+        Ok(())
+    }
+
+    async fn empty_bucket(&self, bucket: &str) -> Result<(), std::io::Error> {
+        let mut state = self.state.write().unwrap();
+        state.bucket_mut(bucket)?.objects.clear();
+        state.uploads.retain(|_, u| u.upload.bucket != bucket);
+        Ok(())
+    }
+
+    async fn reset(&self, keep: &[String]) -> Result<(), std::io::Error> {
+        for bucket in keep {
+            validate_bucket_name(bucket).map_err(S3Error::into_io)?;
+        }
+
+        let mut state = self.state.write().unwrap();
+        state.uploads.clear();
+        state.buckets.retain(|name, _| keep.contains(name));
+        for bucket in state.buckets.values_mut() {
+            bucket.objects.clear();
+        }
+        for bucket in keep {
+            state.buckets.entry(bucket.clone()).or_insert_with(|| MemoryBucket {
+                created: self.clock.now(),
+                objects: BTreeMap::new(),
+                settings: BTreeMap::new(),
+            });
+        }
+        Ok(())
+    }
+
+    async fn create_snapshot(&self, name: &str) -> Result<SnapshotInfo, std::io::Error> {
+        validate_snapshot_name(name).map_err(S3Error::into_io)?;
+
+        let state = self.state.read().unwrap().clone();
+        let objects = state.buckets.values().flat_map(|b| b.objects.values());
+        let info = SnapshotInfo {
+            name: name.to_string(),
+            created: self.clock.now(),
+            objects: objects.clone().count() as u64,
+            bytes: objects.map(|(data, _)| data.len() as u64).sum(),
+        };
+
+        self.snapshots
+            .write()
+            .unwrap()
+            .insert(name.to_string(), (info.clone(), state));
+        Ok(info)
+    }
+
+    async fn restore_snapshot(&self, name: &str) -> Result<(), std::io::Error> {
+        validate_snapshot_name(name).map_err(S3Error::into_io)?;
+
+        let snapshots = self.snapshots.read().unwrap();
+        let (_, state) = snapshots
+            .get(name)
+            .ok_or_else(|| S3Error::no_such_snapshot().into_io())?;
+        *self.state.write().unwrap() = state.clone();
+        Ok(())
+    }
+
+    async fn list_snapshots(&self) -> Result<Vec<SnapshotInfo>, std::io::Error> {
+        let snapshots = self.snapshots.read().unwrap();
+        Ok(snapshots.values().map(|(info, _)| info.clone()).collect())
+    }
+
+    async fn delete_snapshot(&self, name: &str) -> Result<(), std::io::Error> {
+        validate_snapshot_name(name).map_err(S3Error::into_io)?;
+
+        match self.snapshots.write().unwrap().remove(name) {
+            Some(_) => Ok(()),
+            None => Err(S3Error::no_such_snapshot().into_io()),
+        }
+    }
+
+    async fn list_multipart_uploads(&self, bucket: &str) -> Result<Vec<MultipartUpload>, std::io::Error> {
+        let state = self.state.read().unwrap();
+        state.bucket(bucket)?;
+
+        let mut uploads: Vec<MultipartUpload> = state
+            .uploads
+            .values()
+            .filter(|u| u.upload.bucket == bucket)
+            .map(|u| u.upload.clone())
+            .collect();
+        uploads.sort_by(|a, b| {
+            a.key
+                .cmp(&b.key)
+                .then(a.initiated.cmp(&b.initiated))
+                .then(a.upload_id.cmp(&b.upload_id))
+        });
+        Ok(uploads)
+    }
+
+    async fn get_bucket_setting(&self, bucket: &str, name: &str) -> Result<Option<String>, std::io::Error> {
+        validate_setting_name(name).map_err(S3Error::into_io)?;
+        let state = self.state.read().unwrap();
+        Ok(state.bucket(bucket)?.settings.get(name).cloned())
+    }
+
+    async fn put_bucket_setting(
+        &self,
+        bucket: &str,
+        name: &str,
+        value: Option<String>,
+    ) -> Result<(), std::io::Error> {
+        validate_setting_name(name).map_err(S3Error::into_io)?;
+        let mut state = self.state.write().unwrap();
+        let settings = &mut state.bucket_mut(bucket)?.settings;
+        match value {
+            Some(value) => settings.insert(name.to_string(), value),
+            None => settings.remove(name),
+        };
+        Ok(())
+    }
+}
@@ -0,0 +1,241 @@
+use chrono::{DateTime, Utc};
+use std::fs;
+use std::io;
+use std::path::{Path, PathBuf};
+
+use super::fs::{COMMITS_DIR, INTERNAL_DIR, STAGING_DIR};
+use super::SnapshotInfo;
+use crate::error::S3Error;
+
+// snapshots of a data dir live inside it, in <base>/.six7/snapshots/<name>/, as a copy
+// of the tree (buckets, metadata, uploads) under data/ next to an info file
+const SNAPSHOT_DIR: &str = "snapshots";
+const DATA_DIR: &str = "data";
+const INFO_FILE: &str = "snapshot.json";
+const MAX_NAME_LEN: usize = 64;
+
+pub fn validate_snapshot_name(name: &str) -> Result<(), S3Error> {
+    let valid = !name.is_empty()
+        && name.len() <= MAX_NAME_LEN
+        && !name.starts_with('.')
+        && name
+            .bytes()
+            .all(|b| b.is_ascii_alphanumeric() || b == b'.' || b == b'-' || b == b'_');
+    if !valid {
+        return Err(S3Error::invalid_argument(format!(
+            "snapshot names are 1-{} letters, digits, '.', '-' or '_', not starting with '.': {}",
+            MAX_NAME_LEN, name
+        )));
+    }
+    Ok(())
+}
+
+fn snapshots_path(base: &Path) -> PathBuf {
+    base.join(INTERNAL_DIR).join(SNAPSHOT_DIR)
+}
+
+fn snapshot_path(base: &Path, name: &str) -> io::Result<PathBuf> {
+    validate_snapshot_name(name).map_err(S3Error::into_io)?;
+    Ok(snapshots_path(base).join(name))
+}
+
+// internal paths that are never part of a snapshot: in-flight writes and the snapshots
+fn excluded(base: &Path) -> [PathBuf; 3] {
+    let internal = base.join(INTERNAL_DIR);
+    [internal.join(STAGING_DIR), internal.join(COMMITS_DIR), internal.join(SNAPSHOT_DIR)]
+}
+
+pub fn create(base: &Path, name: &str, created: DateTime<Utc>) -> io::Result<SnapshotInfo> {
+    let target = snapshot_path(base, name)?;
+
+    // build it in staging and move it into place, so a failed snapshot leaves nothing behind
+    let building = base
+        .join(INTERNAL_DIR)
+        .join(STAGING_DIR)
+        .join(format!("snapshot-{}", uuid::Uuid::new_v4()));
+    let built = (|| {
+        let mut info = SnapshotInfo {
+            name: name.to_string(),
+            created,
+            objects: 0,
+            bytes: 0,
+        };
+        copy_tree(base, &building.join(DATA_DIR), &excluded(base), Some(&mut info))?;
+        fs::write(building.join(INFO_FILE), serde_json::to_vec_pretty(&info)?)?;
+        Ok::<_, io::Error>(info)
+    })();
+
+    let info = match built {
+        Ok(info) => info,
+        Err(e) => {
+            let _ = fs::remove_dir_all(&building);
+            return Err(e);
+        }
+    };
+
+    fs::create_dir_all(snapshots_path(base))?;
+    if target.exists() {
+        fs::remove_dir_all(&target)?;
+    }
+    fs::rename(&building, &target)?;
+    Ok(info)
+}
+
+pub fn restore(base: &Path, name: &str) -> io::Result<()> {
+    let source = snapshot_path(base, name)?.join(DATA_DIR);
+    if !source.is_dir() {
+        return Err(S3Error::no_such_snapshot().into_io());
+    }
+
+    // copy the snapshot into staging first, so a copy that fails leaves the data dir as it
+    // was; only then are the live entries renamed out of the way and the copy's renamed in
+    let work = base
+        .join(INTERNAL_DIR)
+        .join(STAGING_DIR)
+        .join(format!("restore-{}", uuid::Uuid::new_v4()));
+    let incoming = work.join("new");
+    let outgoing = work.join("old");
+    if let Err(e) = copy_tree(&source, &incoming, &[], None) {
+        let _ = fs::remove_dir_all(&work);
+        return Err(e);
+    }
+
+    match swap(base, &incoming, &outgoing) {
+        Ok(()) => {
+            // staging is cleared on start anyway, should this fail
+            let _ = fs::remove_dir_all(&work);
+            Ok(())
+        }
+        Err(e) => {
+            let _ = fs::remove_dir_all(&incoming);
+            Err(e)
+        }
+    }
+}
+
+pub fn list(base: &Path) -> io::Result<Vec<SnapshotInfo>> {
+    let entries = match fs::read_dir(snapshots_path(base)) {
+        Ok(entries) => entries,
+        Err(e) if e.kind() == io::ErrorKind::NotFound => return Ok(Vec::new()),
+        Err(e) => return Err(e),
+    };
+
+    let mut snapshots = Vec::new();
+    for entry in entries {
+        let info_path = entry?.path().join(INFO_FILE);
+        // a snapshot without an info file was never finished
+        if let Ok(content) = fs::read(&info_path) {
+            snapshots.push(serde_json::from_slice::<SnapshotInfo>(&content)?);
+        }
+    }
+
+    snapshots.sort_by(|a, b| a.name.cmp(&b.name));
+    Ok(snapshots)
+}
+
+pub fn delete(base: &Path, name: &str) -> io::Result<()> {
+    let path = snapshot_path(base, name)?;
+    if !path.is_dir() {
+        return Err(S3Error::no_such_snapshot().into_io());
+    }
+    fs::remove_dir_all(path)
+}
+
+// move what's live under base to outgoing and what's under incoming into its place,
+// renaming each bucket and each entry of .six7 but staging, commits and the snapshots.
+// if a rename fails, the ones already made are undone
+fn swap(base: &Path, incoming: &Path, outgoing: &Path) -> io::Result<()> {
+    let live = entries(base)?;
+    let restored = entries(incoming)?;
+
+    let mut done = Vec::new();
+    let swapped = (|| {
+        for relative in &live {
+            rename_into(&base.join(relative), &outgoing.join(relative), &mut done)?;
+        }
+        for relative in &restored {
+            rename_into(&incoming.join(relative), &base.join(relative), &mut done)?;
+        }
+        Ok(())
+    })();
+
+    if swapped.is_err() {
+        for (from, to) in done.into_iter().rev() {
+            if let Err(e) = fs::rename(&to, &from) {
+                tracing::error!("can't move {} back to {}: {}", to.display(), from.display(), e);
+            }
+        }
+    }
+    swapped
+}
+
+fn rename_into(from: &Path, to: &Path, done: &mut Vec<(PathBuf, PathBuf)>) -> io::Result<()> {
+    if let Some(parent) = to.parent() {
+        fs::create_dir_all(parent)?;
+    }
+    fs::rename(from, to)?;
+    done.push((from.to_path_buf(), to.to_path_buf()));
+    Ok(())
+}
+
+// the entries of a data dir a restore replaces, relative to it
+fn entries(root: &Path) -> io::Result<Vec<PathBuf>> {
+    let excluded = excluded(root);
+    let internal = root.join(INTERNAL_DIR);
+
+    let mut found = Vec::new();
+    for dir in [root, internal.as_path()] {
+        let entries = match fs::read_dir(dir) {
+            Ok(entries) => entries,
+            Err(e) if e.kind() == io::ErrorKind::NotFound => continue,
+            Err(e) => return Err(e),
+        };
+        for entry in entries {
+            let path = entry?.path();
+            if path == internal || excluded.contains(&path) {
+                continue;
+            }
+            if let Ok(relative) = path.strip_prefix(root) {
+                found.push(relative.to_path_buf());
+            }
+        }
+    }
+    Ok(found)
+}
+
+// mirror src into dst, skipping the excluded paths; counts objects when given an info
+fn copy_tree(src: &Path, dst: &Path, excluded: &[PathBuf], mut info: Option<&mut SnapshotInfo>) -> io::Result<()> {
+    fs::create_dir_all(dst)?;
+
+    for entry in fs::read_dir(src)? {
+        let entry = entry?;
+        let path = entry.path();
+        if excluded.contains(&path) {
+            continue;
+        }
+
+        let target = dst.join(entry.file_name());
+        if entry.file_type()?.is_dir() {
+            // only files under bucket directories are objects; .six7 holds bookkeeping
+            let counting = entry.file_name() != INTERNAL_DIR;
+            copy_tree(&path, &target, excluded, info.as_deref_mut().filter(|_| counting))?;
+        } else {
+            link_or_copy(&path, &target)?;
+            if let Some(info) = info.as_deref_mut() {
+                info.objects += 1;
+                info.bytes += entry.metadata()?.len();
+            }
+        }
+    }
+    Ok(())
+}
+
+// every write in the data dir renames a new file into place and never modifies one, so a
+// hard link is as good as a copy. across filesystems fall back to copying, which linux
+// turns into a reflink where the filesystem supports it
+fn link_or_copy(src: &Path, dst: &Path) -> io::Result<()> {
+    match fs::hard_link(src, dst) {
+        Ok(()) => Ok(()),
+        Err(_) => fs::copy(src, dst).map(|_| ()),
+    }
+}
@@ -0,0 +1,236 @@
+use axum::serve::Listener;
+use chrono::{Datelike, Duration as Days, Utc};
+use rcgen::{
+    BasicConstraints, CertificateParams, DnType, ExtendedKeyUsagePurpose, IsCa, KeyPair,
+    KeyUsagePurpose,
+};
+use rustls::pki_types::pem::PemObject;
+use rustls::pki_types::{CertificateDer, PrivateKeyDer};
+use std::fs;
+use std::io::{self, Write};
+use std::net::SocketAddr;
+use std::path::Path;
+use std::sync::Arc;
+use std::time::Duration;
+use tokio::net::{TcpListener, TcpStream};
+use tokio::sync::mpsc;
+use tokio::task::JoinHandle;
+use tokio_rustls::server::TlsStream;
+use tokio_rustls::TlsAcceptor;
+
+use crate::config::{ServerConfig, TlsConfig};
+
+pub const CA_CERT_FILE: &str = "ca.pem";
+const CA_KEY_FILE: &str = "ca-key.pem";
+const CERT_FILE: &str = "cert.pem";
+const KEY_FILE: &str = "key.pem";
+const CA_NAME: &str = "six7 local CA";
+
+// a client that connects and never finishes the handshake shouldn't hold a task forever
+const HANDSHAKE_TIMEOUT: Duration = Duration::from_secs(10);
+
+// rustls config from the configured pem files, or from a certificate issued by the local ca
+pub fn server_config(tls: &TlsConfig, names: &[String]) -> io::Result<Arc<rustls::ServerConfig>> {
+    let (cert_pem, key_pem) = match (&tls.cert, &tls.key) {
+        (Some(cert), Some(key)) => (read(cert)?, read(key)?),
+        _ => issue(Path::new(&tls.dir), names)?,
+    };
+
+    let certs = CertificateDer::pem_slice_iter(cert_pem.as_bytes())
+        .collect::<Result<Vec<_>, _>>()
+        .map_err(|e| invalid_data(format!("bad certificate: {}", e)))?;
+    if certs.is_empty() {
+        return Err(invalid_data("no certificate found"));
+    }
+    let key = PrivateKeyDer::from_pem_slice(key_pem.as_bytes())
+        .map_err(|e| invalid_data(format!("bad private key: {}", e)))?;
+
+    let provider = Arc::new(rustls::crypto::ring::default_provider());
+    let mut config = rustls::ServerConfig::builder_with_provider(provider)
+        .with_safe_default_protocol_versions()
+        .map_err(io::Error::other)?
+        .with_no_client_auth()
+        .with_single_cert(certs, key)
+        .map_err(|e| invalid_data(e.to_string()))?;
+    config.alpn_protocols = vec![b"http/1.1".to_vec()];
+
+    Ok(Arc::new(config))
+}
+
+// host names the generated certificate is valid for: loopback, the bind address, every
+// base domain with its wildcard, and <bucket>.<domain> for the buckets known at startup,
+// since clients refuse a wildcard directly under a single label like *.localhost
+pub fn subject_names(server: &ServerConfig, buckets: &[String]) -> Vec<String> {
+    let mut names = vec!["127.0.0.1".to_string(), "::1".to_string()];
+    if !["0.0.0.0", "::"].contains(&server.host.as_str()) {
+        names.push(server.host.clone());
+    }
+    for domain in &server.domains {
+        let domain = domain.strip_prefix("*.").unwrap_or(domain);
+        names.push(domain.to_string());
+        names.push(format!("*.{}", domain));
+        names.extend(buckets.iter().map(|bucket| format!("{}.{}", bucket, domain)));
+    }
+
+    let mut unique = Vec::with_capacity(names.len());
+    for name in names {
+        if !unique.contains(&name) {
+            unique.push(name);
+        }
+    }
+    unique
+}
+
+// load the ca from dir (creating it on first run) and sign a fresh certificate for names
+fn issue(dir: &Path, names: &[String]) -> io::Result<(String, String)> {
+    fs::create_dir_all(dir)?;
+
+    let ca_key_path = dir.join(CA_KEY_FILE);
+    let ca_cert_path = dir.join(CA_CERT_FILE);
+    let ca_key = match fs::read_to_string(&ca_key_path) {
+        Ok(pem) => KeyPair::from_pem(&pem).map_err(|e| invalid_data(format!("{}: {}", ca_key_path.display(), e)))?,
+        Err(e) if e.kind() == io::ErrorKind::NotFound => {
+            let key = KeyPair::generate().map_err(io::Error::other)?;
+            write_private(&ca_key_path, &key.serialize_pem())?;
+            key
+        }
+        Err(e) => return Err(e),
+    };
+
+    // only the name, key and usages of the issuer go into a signature, so rebuilding the
+    // ca from its key signs certificates that chain to the ca.pem written on first run
+    let ca = ca_params().self_signed(&ca_key).map_err(io::Error::other)?;
+    if !ca_cert_path.exists() {
+        fs::write(&ca_cert_path, ca.pem())?;
+        tracing::info!("tls: created local ca {}; add it to your client's trust store", ca_cert_path.display());
+    }
+
+    let mut params = CertificateParams::new(names.to_vec()).map_err(|e| invalid_data(e.to_string()))?;
+    params.distinguished_name.push(DnType::CommonName, "six7");
+    params.extended_key_usages = vec![ExtendedKeyUsagePurpose::ServerAuth];
+    params.key_usages = vec![KeyUsagePurpose::DigitalSignature, KeyUsagePurpose::KeyEncipherment];
+    params.use_authority_key_identifier_extension = true;
+    valid_for(&mut params, 365);
+
+    let key = KeyPair::generate().map_err(io::Error::other)?;
+    let cert = params.signed_by(&key, &ca, &ca_key).map_err(io::Error::other)?;
+
+    let (cert_pem, key_pem) = (cert.pem(), key.serialize_pem());
+    fs::write(dir.join(CERT_FILE), &cert_pem)?;
+    write_private(&dir.join(KEY_FILE), &key_pem)?;
+    Ok((cert_pem, key_pem))
+}
+
+fn ca_params() -> CertificateParams {
+    let mut params = CertificateParams::default();
+    params.distinguished_name = rcgen::DistinguishedName::new();
+    params.distinguished_name.push(DnType::CommonName, CA_NAME);
+    params.distinguished_name.push(DnType::OrganizationName, "six7");
+    params.is_ca = IsCa::Ca(BasicConstraints::Constrained(0));
+    params.key_usages = vec![
+        KeyUsagePurpose::KeyCertSign,
+        KeyUsagePurpose::CrlSign,
+        KeyUsagePurpose::DigitalSignature,
+    ];
+    valid_for(&mut params, 10 * 365);
+    params
+}
+
+// from yesterday, so a clock that's slightly behind still accepts it
+fn valid_for(params: &mut CertificateParams, days: i64) {
+    let date = |offset: i64| {
+        let date = (Utc::now() + Days::days(offset)).date_naive();
+        rcgen::date_time_ymd(date.year(), date.month() as u8, date.day() as u8)
+    };
+    params.not_before = date(-1);
+    params.not_after = date(days);
+}
+
+fn read(path: &str) -> io::Result<String> {
+    fs::read_to_string(path).map_err(|e| io::Error::new(e.kind(), format!("{}: {}", path, e)))
+}
+
+// private keys are only readable by the owner
+fn write_private(path: &Path, content: &str) -> io::Result<()> {
+    let mut options = fs::OpenOptions::new();
+    options.write(true).create(true).truncate(true);
+    #[cfg(unix)]
+    {
+        use std::os::unix::fs::OpenOptionsExt;
+        options.mode(0o600);
+    }
+    options.open(path)?.write_all(content.as_bytes())
+}
+
+fn invalid_data(message: impl Into<String>) -> io::Error {
+    io::Error::new(io::ErrorKind::InvalidData, message.into())
+}
+
+// accepts tcp connections and hands them to axum once the tls handshake is done;
+// handshakes run on their own tasks so one slow client doesn't stall the others
+pub struct TlsListener {
+    addr: SocketAddr,
+    incoming: mpsc::Receiver<(TlsStream<TcpStream>, SocketAddr)>,
+    task: JoinHandle<()>,
+}
+
+impl TlsListener {
+    pub fn new(listener: TcpListener, config: Arc<rustls::ServerConfig>) -> io::Result<Self> {
+        let addr = listener.local_addr()?;
+        let (tx, incoming) = mpsc::channel(64);
+        let task = tokio::spawn(accept_loop(listener, TlsAcceptor::from(config), tx));
+
+        Ok(TlsListener { addr, incoming, task })
+    }
+}
+
+async fn accept_loop(
+    listener: TcpListener,
+    acceptor: TlsAcceptor,
+    tx: mpsc::Sender<(TlsStream<TcpStream>, SocketAddr)>,
+) {
+    while !tx.is_closed() {
+        let (stream, peer) = match listener.accept().await {
+            Ok(conn) => conn,
+            Err(e) => {
+                tracing::debug!("tls accept failed: {}", e);
+                tokio::time::sleep(Duration::from_millis(50)).await;
+                continue;
+            }
+        };
+
+        let acceptor = acceptor.clone();
+        let tx = tx.clone();
+        tokio::spawn(async move {
+            match tokio::time::timeout(HANDSHAKE_TIMEOUT, acceptor.accept(stream)).await {
+                Ok(Ok(stream)) => {
+                    let _ = tx.send((stream, peer)).await;
+                }
+                Ok(Err(e)) => tracing::debug!("tls handshake with {} failed: {}", peer, e),
+                Err(_) => tracing::debug!("tls handshake with {} timed out", peer),
+            }
+        });
+    }
+}
+
+impl Listener for TlsListener {
+    type Io = TlsStream<TcpStream>;
+    type Addr = SocketAddr;
+
+    async fn accept(&mut self) -> (Self::Io, Self::Addr) {
+        match self.incoming.recv().await {
+            Some(conn) => conn,
+            None => std::future::pending().await,
+        }
+    }
+
+    fn local_addr(&self) -> io::Result<Self::Addr> {
+        Ok(self.addr)
+    }
+}
+
+impl Drop for TlsListener {
+    fn drop(&mut self) {
+        self.task.abort();
+    }
+}
@@ -0,0 +1,21 @@
+use axum::{
+    extract::State,
+    http::{header, StatusCode},
+    response::{IntoResponse, Response},
+};
+use std::sync::Arc;
+
+use crate::admin;
+use crate::handlers::AppState;
+
+// the whole console is one page, built into the binary; it asks for the admin token and
+// drives the admin api with it
+const PAGE: &str = include_str!("ui/index.html");
+
+// 404 while the admin api is disabled, like the rest of it
+pub async fn page(State(state): State<Arc<AppState>>) -> Response {
+    if !state.config().admin.enabled {
+        return admin::json_error(StatusCode::NOT_FOUND, "NotFound", "admin api is disabled");
+    }
+    ([(header::CONTENT_TYPE, "text/html; charset=utf-8")], PAGE).into_response()
+}
@@ -0,0 +1,143 @@
+use axum::{
+    extract::{Request, State},
+    middleware::Next,
+    response::Response,
+};
+use chrono::{DateTime, Utc};
+use serde::{Deserialize, Serialize};
+use std::sync::atomic::{AtomicU64, Ordering};
+use std::future::Future;
+use std::sync::Arc;
+use std::time::Instant;
+use tokio::sync::{broadcast, watch};
+
+use crate::handlers::{AppState, RequestIds};
+use crate::notifications::Event;
+use crate::operation;
+
+// how far a watcher can fall behind before it starts missing activity
+const CAPACITY: usize = 1024;
+
+// one s3 request as watchers see it, with the event its handler raised if it changed an
+// object
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct Activity {
+    pub seq: u64,
+    pub time: DateTime<Utc>,
+    pub operation: String,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub bucket: Option<String>,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub key: Option<String>,
+    pub method: String,
+    pub status: u16,
+    // until the response started; the body may still be on its way
+    pub duration_ms: f64,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub request_id: Option<String>,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub event: Option<Event>,
+}
+
+// which activity a watcher wants; unset fields match everything
+#[derive(Debug, Clone, Default, Serialize, Deserialize)]
+pub struct WatchFilter {
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub bucket: Option<String>,
+    // of the key
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub prefix: Option<String>,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub operation: Option<String>,
+}
+
+impl WatchFilter {
+    pub fn matches(&self, activity: &Activity) -> bool {
+        self.bucket.as_ref().is_none_or(|b| activity.bucket.as_ref() == Some(b))
+            && self
+                .prefix
+                .as_ref()
+                .is_none_or(|p| activity.key.as_ref().is_some_and(|k| k.starts_with(p.as_str())))
+            && self.operation.as_ref().is_none_or(|o| *o == activity.operation)
+    }
+}
+
+// fans activity out to everyone watching; nothing is kept for later watchers
+pub struct Watchers {
+    sender: broadcast::Sender<Arc<Activity>>,
+    next_seq: AtomicU64,
+    // set once the server starts shutting down, ending every watch
+    closed: watch::Sender<bool>,
+}
+
+impl Default for Watchers {
+    fn default() -> Self {
+        Watchers {
+            sender: broadcast::channel(CAPACITY).0,
+            next_seq: AtomicU64::new(0),
+            closed: watch::channel(false).0,
+        }
+    }
+}
+
+impl Watchers {
+    pub fn new() -> Self {
+        Watchers::default()
+    }
+
+    pub fn subscribe(&self) -> broadcast::Receiver<Arc<Activity>> {
+        self.sender.subscribe()
+    }
+
+    // end every watch, and any started from now on, so a shutdown needn't wait for them
+    pub fn close(&self) {
+        self.closed.send_replace(true);
+    }
+
+    // resolves once the watchers are closed
+    pub fn closed(&self) -> impl Future<Output = ()> + Send + 'static {
+        let mut closed = self.closed.subscribe();
+        async move {
+            let _ = closed.wait_for(|closed| *closed).await;
+        }
+    }
+
+    pub fn is_watched(&self) -> bool {
+        self.sender.receiver_count() > 0
+    }
+
+    fn publish(&self, activity: Activity) {
+        // fails only when the last watcher left in the meantime
+        let _ = self.sender.send(Arc::new(activity));
+    }
+}
+
+// publishes every s3 request once its response starts, but only while someone's watching
+pub async fn observe(State(state): State<Arc<AppState>>, request: Request, next: Next) -> Response {
+    if !state.watchers.is_watched() {
+        return next.run(request).await;
+    }
+    let Some(op) = operation::classify(request.method(), request.uri(), request.headers()) else {
+        return next.run(request).await;
+    };
+
+    let started = Instant::now();
+    let seq = state.watchers.next_seq.fetch_add(1, Ordering::Relaxed) + 1;
+    let method = request.method().to_string();
+    let request_id = request.extensions().get::<RequestIds>().map(|ids| ids.request_id.clone());
+
+    let response = next.run(request).await;
+    state.watchers.publish(Activity {
+        seq,
+        time: state.clock().now(),
+        operation: op.name.to_string(),
+        bucket: op.bucket,
+        key: op.key,
+        method,
+        status: response.status().as_u16(),
+        duration_ms: started.elapsed().as_secs_f64() * 1000.0,
+        request_id,
+        event: response.extensions().get::<Event>().cloned(),
+    });
+    response
+}
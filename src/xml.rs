@@ -0,0 +1,60 @@
+// just enough xml to read the small request documents s3 clients send
+
+pub fn escape(s: &str) -> String {
+    s.replace('&', "&amp;")
+        .replace('<', "&lt;")
+        .replace('>', "&gt;")
+        .replace('"', "&quot;")
+        .replace('\'', "&apos;")
+}
+
+pub fn unescape(s: &str) -> String {
+    s.replace("&lt;", "<")
+        .replace("&gt;", ">")
+        .replace("&quot;", "\"")
+        .replace("&apos;", "'")
+        .replace("&amp;", "&")
+}
+
+// inner text of every <tag>...</tag>, in document order
+pub fn elements<'a>(xml: &'a str, tag: &str) -> Vec<&'a str> {
+    let open = format!("<{}", tag);
+    let close = format!("</{}>", tag);
+    let mut found = Vec::new();
+    let mut rest = xml;
+
+    while let Some(start) = rest.find(&open) {
+        let after = &rest[start + open.len()..];
+        // skip <tagSomethingElse> matches, allow attributes
+        let Some(end_of_open) = after.find('>') else {
+            break;
+        };
+        let head = &after[..end_of_open];
+        if !(head.is_empty() || head.starts_with(char::is_whitespace) || head == "/") {
+            rest = after;
+            continue;
+        }
+        if head.ends_with('/') {
+            found.push("");
+            rest = &after[end_of_open + 1..];
+            continue;
+        }
+
+        let body = &after[end_of_open + 1..];
+        let Some(end) = body.find(&close) else {
+            break;
+        };
+        found.push(&body[..end]);
+        rest = &body[end + close.len()..];
+    }
+
+    found
+}
+
+pub fn element<'a>(xml: &'a str, tag: &str) -> Option<&'a str> {
+    elements(xml, tag).into_iter().next()
+}
+
+pub fn text(xml: &str, tag: &str) -> Option<String> {
+    element(xml, tag).map(|v| unescape(v.trim()))
+}
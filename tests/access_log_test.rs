@@ -0,0 +1,170 @@
+mod common;
+
+use axum::{
+    body::Body,
+    http::{Request, StatusCode},
+    Router,
+};
+use common::{admin, body_string};
+use six7::handlers::AppState;
+use six7::storage::MemoryStorage;
+use std::sync::Arc;
+use tower::ServiceExt;
+
+const ENABLE: &str = r#"<BucketLoggingStatus xmlns="http://doc.s3.amazonaws.com/2006-03-01">
+  <LoggingEnabled>
+    <TargetBucket>logs</TargetBucket>
+    <TargetPrefix>access/</TargetPrefix>
+  </LoggingEnabled>
+</BucketLoggingStatus>"#;
+
+async fn create_app() -> (Router, Arc<AppState>) {
+    let (app, state) = common::create_app(common::admin_config(), MemoryStorage::new()).await;
+    state.storage.create_bucket("logs").await.unwrap();
+    (app, state)
+}
+
+// the status and body, once the body has been sent and any record buffered
+async fn send(app: &Router, request: Request<Body>) -> (StatusCode, String) {
+    let response = app.clone().oneshot(request).await.unwrap();
+    (response.status(), body_string(response).await)
+}
+
+fn request(method: &str, uri: &str, body: &'static str) -> Request<Body> {
+    Request::builder().method(method).uri(uri).body(Body::from(body)).unwrap()
+}
+
+// every record written to the logs bucket so far, split into fields
+async fn records(state: &AppState) -> Vec<Vec<String>> {
+    let mut records = Vec::new();
+    for object in state.storage.list_objects("logs", Some("access/")).await.unwrap() {
+        let body = state.storage.get_object("logs", &object.key).await.unwrap();
+        for line in String::from_utf8(body.to_vec()).unwrap().lines() {
+            records.push(fields(line));
+        }
+    }
+    records
+}
+
+// fields are separated by spaces, except inside quotes or brackets
+fn fields(line: &str) -> Vec<String> {
+    let mut fields = Vec::new();
+    let mut current = String::new();
+    let mut closing = None;
+    for c in line.chars() {
+        match (closing, c) {
+            (None, ' ') => fields.push(std::mem::take(&mut current)),
+            (None, '"') => closing = Some('"'),
+            (None, '[') => closing = Some(']'),
+            (Some(end), c) if c == end => closing = None,
+            (_, c) => current.push(c),
+        }
+    }
+    fields.push(current);
+    fields
+}
+
+#[tokio::test]
+async fn test_put_and_get_bucket_logging() {
+    let (app, _) = create_app().await;
+
+    let (status, body) = send(&app, request("GET", "/bucket?logging", "")).await;
+    assert_eq!(status, StatusCode::OK);
+    assert!(body.contains("<BucketLoggingStatus"));
+    assert!(!body.contains("LoggingEnabled"));
+
+    let (status, _) = send(&app, request("PUT", "/bucket?logging", ENABLE)).await;
+    assert_eq!(status, StatusCode::OK);
+    let (_, body) = send(&app, request("GET", "/bucket?logging", "")).await;
+    assert!(body.contains("<TargetBucket>logs</TargetBucket><TargetPrefix>access/</TargetPrefix>"));
+
+    let missing = ENABLE.replace("<TargetBucket>logs", "<TargetBucket>nowhere");
+    let missing = Request::builder().method("PUT").uri("/bucket?logging").body(Body::from(missing)).unwrap();
+    let (status, body) = send(&app, missing).await;
+    assert_eq!(status, StatusCode::BAD_REQUEST);
+    assert!(body.contains("InvalidTargetBucketForLogging"));
+
+    let disable = "<BucketLoggingStatus xmlns=\"http://doc.s3.amazonaws.com/2006-03-01\" />";
+    let (status, _) = send(&app, request("PUT", "/bucket?logging", disable)).await;
+    assert_eq!(status, StatusCode::OK);
+    let (_, body) = send(&app, request("GET", "/bucket?logging", "")).await;
+    assert!(!body.contains("LoggingEnabled"));
+
+    let (status, body) = send(&app, request("GET", "/missing?logging", "")).await;
+    assert_eq!(status, StatusCode::NOT_FOUND);
+    assert!(body.contains("NoSuchBucket"));
+}
+
+#[tokio::test]
+async fn test_records_in_s3_format() {
+    let (app, state) = create_app().await;
+    send(&app, request("PUT", "/bucket?logging", ENABLE)).await;
+
+    let put = Request::builder()
+        .method("PUT")
+        .uri("/bucket/dir/a%20b.txt")
+        .header("host", "localhost:4040")
+        .header("user-agent", "test-agent")
+        .body(Body::from("hello"))
+        .unwrap();
+    send(&app, put).await;
+    send(&app, request("GET", "/bucket/dir/a%20b.txt", "")).await;
+    send(&app, request("GET", "/bucket/missing.txt", "")).await;
+    send(&app, request("GET", "/bucket?list-type=2", "")).await;
+    // nothing's written until a flush
+    assert!(records(&state).await.is_empty());
+
+    six7::access_log::flush(&state).await;
+    let records = records(&state).await;
+    assert_eq!(records.len(), 4);
+    for record in &records {
+        assert_eq!(record.len(), 26, "{:?}", record);
+    }
+
+    let put = &records[0];
+    assert_eq!(put[0], "local");
+    assert_eq!(put[1], "bucket");
+    assert!(put[2].ends_with(" +0000"));
+    assert_eq!(put[3], "127.0.0.1");
+    assert_eq!(put[4], "-");
+    assert!(!put[5].is_empty() && put[5] != "-");
+    assert_eq!(put[6], "REST.PUT.OBJECT");
+    assert_eq!(put[7], "dir/a%20b.txt");
+    assert_eq!(put[8], "PUT /bucket/dir/a%20b.txt HTTP/1.1");
+    assert_eq!(put[9], "200");
+    assert_eq!(put[10], "-");
+    assert_eq!(put[12], "5");
+    assert_eq!(put[16], "test-agent");
+    assert_eq!(put[22], "localhost:4040");
+
+    let get = &records[1];
+    assert_eq!((get[6].as_str(), get[9].as_str()), ("REST.GET.OBJECT", "200"));
+    assert_eq!((get[11].as_str(), get[12].as_str()), ("5", "5"));
+
+    let missing = &records[2];
+    assert_eq!((missing[9].as_str(), missing[10].as_str()), ("404", "NoSuchKey"));
+    assert_eq!(missing[12], "-");
+
+    let list = &records[3];
+    assert_eq!((list[6].as_str(), list[7].as_str()), ("REST.GET.BUCKET", "-"));
+
+    // flushed records aren't written twice
+    six7::access_log::flush(&state).await;
+    assert_eq!(state.storage.list_objects("logs", None).await.unwrap().len(), 1);
+}
+
+#[tokio::test]
+async fn test_flush_through_admin_api() {
+    let (app, state) = create_app().await;
+    send(&app, request("PUT", "/bucket?logging", ENABLE)).await;
+    send(&app, request("PUT", "/bucket/a.txt", "hello")).await;
+
+    let (status, _) = send(&app, admin("POST", "/_six7/access-logs/flush", "")).await;
+    assert_eq!(status, StatusCode::NO_CONTENT);
+    assert_eq!(records(&state).await.len(), 1);
+
+    // buckets without logging aren't logged
+    send(&app, request("PUT", "/logs/other.txt", "x")).await;
+    six7::access_log::flush(&state).await;
+    assert_eq!(records(&state).await.len(), 1);
+}
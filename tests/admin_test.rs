@@ -0,0 +1,222 @@
+use axum::{
+    body::Body,
+    http::{Request, StatusCode},
+    Router,
+};
+use six7::config::{BucketConfig, Config};
+use six7::handlers::AppState;
+use six7::server::router;
+use six7::storage::{MemoryStorage, StorageBackend};
+use std::sync::Arc;
+use tower::ServiceExt;
+
+const TOKEN: &str = "test-token";
+
+async fn create_admin_app() -> (Router, Arc<AppState>) {
+    let mut config = Config::default();
+    config.admin.enabled = true;
+    config.admin.token = Some(TOKEN.to_string());
+    config.buckets.push(BucketConfig::new("configured"));
+
+    let storage = Arc::new(MemoryStorage::new());
+    storage.create_bucket("configured").await.unwrap();
+    let state = Arc::new(AppState::from_parts(storage, config));
+    (router(state.clone()), state)
+}
+
+fn admin(method: &str, uri: &str, body: &'static str) -> Request<Body> {
+    Request::builder()
+        .method(method)
+        .uri(uri)
+        .header("authorization", format!("Bearer {}", TOKEN))
+        .body(Body::from(body))
+        .unwrap()
+}
+
+async fn json(response: axum::response::Response) -> serde_json::Value {
+    let body = axum::body::to_bytes(response.into_body(), usize::MAX).await.unwrap();
+    serde_json::from_slice(&body).unwrap()
+}
+
+#[tokio::test]
+async fn test_admin_disabled_by_default() {
+    let app = router(Arc::new(AppState::new(MemoryStorage::new())));
+
+    let response = app.oneshot(admin("GET", "/_six7/config", "")).await.unwrap();
+    assert_eq!(response.status(), StatusCode::NOT_FOUND);
+}
+
+#[tokio::test]
+async fn test_admin_requires_token() {
+    let (app, _) = create_admin_app().await;
+
+    let response = app
+        .clone()
+        .oneshot(Request::builder().uri("/_six7/config").body(Body::empty()).unwrap())
+        .await
+        .unwrap();
+    assert_eq!(response.status(), StatusCode::UNAUTHORIZED);
+
+    let response = app
+        .oneshot(
+            Request::builder()
+                .uri("/_six7/config")
+                .header("authorization", "Bearer wrong")
+                .body(Body::empty())
+                .unwrap(),
+        )
+        .await
+        .unwrap();
+    assert_eq!(response.status(), StatusCode::UNAUTHORIZED);
+}
+
+#[tokio::test]
+async fn test_seed_and_list_objects() {
+    let (app, state) = create_admin_app().await;
+
+    let response = app
+        .clone()
+        .oneshot(admin("PUT", "/_six7/objects/seeded/dir/a.txt", "hello"))
+        .await
+        .unwrap();
+    assert_eq!(response.status(), StatusCode::CREATED);
+    assert!(state.storage.bucket_exists("seeded").await);
+
+    let response = app.oneshot(admin("GET", "/_six7/objects", "")).await.unwrap();
+    assert_eq!(response.status(), StatusCode::OK);
+    let objects = json(response).await;
+    assert_eq!(objects.as_array().unwrap().len(), 1);
+    assert_eq!(objects[0]["bucket"], "seeded");
+    assert_eq!(objects[0]["key"], "dir/a.txt");
+    assert_eq!(objects[0]["size"], 5);
+    assert_eq!(objects[0]["etag"], format!("{:x}", md5::compute("hello")));
+}
+
+#[tokio::test]
+async fn test_reset() {
+    let (app, state) = create_admin_app().await;
+    let storage = &state.storage;
+    storage.put_object("configured", "a", "1".into(), None).await.unwrap();
+    storage.create_bucket("scratch").await.unwrap();
+    storage.put_object("scratch", "b", "2".into(), None).await.unwrap();
+    storage.create_multipart_upload("configured", "big", None).await.unwrap();
+
+    let response = app.clone().oneshot(admin("POST", "/_six7/reset/scratch", "")).await.unwrap();
+    assert_eq!(response.status(), StatusCode::NO_CONTENT);
+    assert!(storage.list_objects("scratch", None).await.unwrap().is_empty());
+    assert_eq!(storage.list_objects("configured", None).await.unwrap().len(), 1);
+
+    let response = app.clone().oneshot(admin("POST", "/_six7/reset", "")).await.unwrap();
+    assert_eq!(response.status(), StatusCode::NO_CONTENT);
+
+    // configured buckets survive empty, everything else is gone
+    let buckets: Vec<String> = storage.list_buckets().await.unwrap().into_iter().map(|b| b.name).collect();
+    assert_eq!(buckets, vec!["configured"]);
+    assert!(storage.list_objects("configured", None).await.unwrap().is_empty());
+    assert!(storage.list_multipart_uploads("configured").await.unwrap().is_empty());
+
+    let response = app.oneshot(admin("POST", "/_six7/reset/missing-bucket", "")).await.unwrap();
+    assert_eq!(response.status(), StatusCode::NOT_FOUND);
+}
+
+#[tokio::test]
+async fn test_dump_config() {
+    let (app, _) = create_admin_app().await;
+
+    let response = app.oneshot(admin("GET", "/_six7/config", "")).await.unwrap();
+    assert_eq!(response.status(), StatusCode::OK);
+    let config = json(response).await;
+    assert_eq!(config["buckets"][0]["name"], "configured");
+    assert_eq!(config["admin"]["token"], "***");
+    assert_eq!(config["buckets"][0]["secret_key"], "***");
+}
+
+#[tokio::test]
+async fn test_dump_config_has_no_secrets() {
+    let (app, state) = create_admin_app().await;
+    let mut config = (*state.config()).clone();
+    config.buckets.push(BucketConfig {
+        access_key: "visible-key".to_string(),
+        secret_key: "hidden-secret".to_string(),
+        ..BucketConfig::new("private")
+    });
+    state.set_config(config);
+
+    let response = app.oneshot(admin("GET", "/_six7/config", "")).await.unwrap();
+    let dump = json(response).await.to_string();
+    assert!(dump.contains("visible-key"));
+    assert!(!dump.contains("hidden-secret"));
+    assert!(!dump.contains(TOKEN));
+}
+
+#[tokio::test]
+async fn test_fetch_and_remove_objects() {
+    let (app, state) = create_admin_app().await;
+    state
+        .storage
+        .put_object("configured", "dir/a b.txt", "hello".into(), Some("text/plain".to_string()))
+        .await
+        .unwrap();
+    state.storage.put_object("configured", "top.txt", "hi".into(), None).await.unwrap();
+
+    let response = app.clone().oneshot(admin("GET", "/_six7/buckets", "")).await.unwrap();
+    let buckets = json(response).await;
+    assert_eq!(buckets[0]["name"], "configured");
+
+    let response = app
+        .clone()
+        .oneshot(admin("GET", "/_six7/objects?bucket=configured&prefix=dir/", ""))
+        .await
+        .unwrap();
+    let objects = json(response).await;
+    assert_eq!(objects.as_array().unwrap().len(), 1);
+    assert_eq!(objects[0]["key"], "dir/a b.txt");
+
+    let response = app
+        .clone()
+        .oneshot(admin("GET", "/_six7/objects/configured/dir/a%20b.txt?download", ""))
+        .await
+        .unwrap();
+    assert_eq!(response.status(), StatusCode::OK);
+    assert_eq!(response.headers()["content-type"], "text/plain");
+    assert_eq!(response.headers()["content-disposition"], "attachment; filename=\"a b.txt\"");
+    let body = axum::body::to_bytes(response.into_body(), usize::MAX).await.unwrap();
+    assert_eq!(&body[..], b"hello");
+
+    let response = app
+        .clone()
+        .oneshot(admin("DELETE", "/_six7/objects/configured/top.txt", ""))
+        .await
+        .unwrap();
+    assert_eq!(response.status(), StatusCode::NO_CONTENT);
+    assert!(state.storage.head_object("configured", "top.txt").await.is_err());
+
+    let response = app.oneshot(admin("GET", "/_six7/objects/configured/top.txt", "")).await.unwrap();
+    assert_eq!(response.status(), StatusCode::NOT_FOUND);
+    assert_eq!(json(response).await["code"], "NoSuchKey");
+}
+
+#[tokio::test]
+async fn test_ui_page() {
+    let (app, state) = create_admin_app().await;
+
+    // the page needs no token; the api calls it makes do
+    let response = app
+        .clone()
+        .oneshot(Request::builder().uri("/_six7/ui").body(Body::empty()).unwrap())
+        .await
+        .unwrap();
+    assert_eq!(response.status(), StatusCode::OK);
+    assert!(response.headers()["content-type"].to_str().unwrap().starts_with("text/html"));
+    let body = axum::body::to_bytes(response.into_body(), usize::MAX).await.unwrap();
+    assert!(String::from_utf8_lossy(&body).contains("<title>six7</title>"));
+
+    let mut config = (*state.config()).clone();
+    config.admin.enabled = false;
+    state.set_config(config);
+    let response = app
+        .oneshot(Request::builder().uri("/_six7/ui").body(Body::empty()).unwrap())
+        .await
+        .unwrap();
+    assert_eq!(response.status(), StatusCode::NOT_FOUND);
+}
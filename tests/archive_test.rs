@@ -0,0 +1,143 @@
+use six7::archive::{self, Format, MANIFEST};
+use six7::client::Client;
+use six7::config::Config;
+use six7::Server;
+use std::path::Path;
+use tempfile::TempDir;
+
+fn write(dir: &Path, relative: &str, contents: &str) {
+    let path = dir.join(relative);
+    std::fs::create_dir_all(path.parent().unwrap()).unwrap();
+    std::fs::write(path, contents).unwrap();
+}
+
+// a fixture directory and a client on a fresh data dir
+fn setup() -> (TempDir, Client) {
+    let dir = TempDir::new().unwrap();
+    write(dir.path(), "fixtures/a.txt", "hello");
+    write(dir.path(), "fixtures/img/logo.svg", "<svg/>");
+    write(dir.path(), "fixtures/data/rows", "1,2,3");
+    let client = Client::local(dir.path().join("data")).unwrap();
+    (dir, client)
+}
+
+#[tokio::test]
+async fn test_import_a_directory() {
+    let (dir, client) = setup();
+    let imported = client.import("fixtures", &dir.path().join("fixtures")).await.unwrap();
+    assert_eq!(imported.len(), 3);
+
+    let objects = client.list("fixtures", "", true).await.unwrap().objects;
+    let keys: Vec<_> = objects.iter().map(|o| o.key.as_str()).collect();
+    assert_eq!(keys, ["a.txt", "data/rows", "img/logo.svg"]);
+    assert_eq!(objects[0].etag, format!("{:x}", md5::compute("hello")));
+    assert_eq!(objects[0].content_type.as_deref(), Some("text/plain"));
+    assert_eq!(objects[1].content_type, None);
+    assert_eq!(objects[2].content_type.as_deref(), Some("image/svg+xml"));
+
+    // into a bucket that's already there, replacing what's changed
+    write(dir.path(), "fixtures/a.txt", "changed");
+    client.import("fixtures", &dir.path().join("fixtures")).await.unwrap();
+    assert_eq!(client.get("fixtures", "a.txt").await.unwrap(), "changed");
+}
+
+#[tokio::test]
+async fn test_export_and_import_round_trip() {
+    for name in ["out.tar", "out.zip"] {
+        let (dir, client) = setup();
+        client.import("fixtures", &dir.path().join("fixtures")).await.unwrap();
+        // not something an extension would give
+        client.copy(
+            &six7::client::Location::File(dir.path().join("fixtures/a.txt")),
+            &six7::client::Location::parse("s3://fixtures/notes").unwrap(),
+            false,
+        )
+        .await
+        .unwrap();
+
+        let path = dir.path().join(name);
+        let manifest = client.export("fixtures", &path).await.unwrap();
+        assert_eq!(manifest.bucket, "fixtures");
+        assert_eq!(manifest.objects.len(), 4);
+
+        let contents = archive::read(&path).unwrap();
+        assert_eq!(contents.entries.len(), 4);
+        assert_eq!(contents.manifest.unwrap().objects.len(), 4);
+
+        // on another machine
+        let elsewhere = Client::local(dir.path().join("elsewhere")).unwrap();
+        elsewhere.import("copy", &path).await.unwrap();
+        let objects = elsewhere.list("copy", "", true).await.unwrap().objects;
+        assert_eq!(objects.len(), 4);
+        for (original, copy) in manifest.objects.iter().zip(&objects) {
+            assert_eq!(original.key, copy.key);
+            assert_eq!(original.etag, copy.etag);
+            assert_eq!(original.content_type, copy.content_type);
+        }
+        let notes = objects.iter().find(|o| o.key == "notes").unwrap();
+        assert_eq!(notes.content_type.as_deref(), Some("text/plain"));
+    }
+}
+
+#[tokio::test]
+async fn test_import_checks_the_manifest() {
+    let (dir, client) = setup();
+    client.import("fixtures", &dir.path().join("fixtures")).await.unwrap();
+    let path = dir.path().join("out.tar");
+    client.export("fixtures", &path).await.unwrap();
+
+    // a tar with one file edited since the export
+    let contents = archive::read(&path).unwrap();
+    let edited = dir.path().join("edited.tar");
+    let mut writer = archive::Writer::create(&edited, Format::Tar).unwrap();
+    for (key, data) in &contents.entries {
+        let data = if key == "a.txt" { b"edited".to_vec() } else { data.to_vec() };
+        writer.add(key, &data, chrono::Utc::now()).unwrap();
+    }
+    writer.finish(contents.manifest.as_ref().unwrap()).unwrap();
+
+    let err = client.import("copy", &edited).await.unwrap_err();
+    assert!(err.to_string().contains("a.txt doesn't match its etag"), "{}", err);
+    // nothing was stored
+    assert!(client.list_buckets().await.unwrap().iter().all(|b| b.name != "copy"));
+
+    // but an extracted export is a plain directory, free to edit
+    let extracted = dir.path().join("extracted");
+    tar::Archive::new(std::fs::File::open(&path).unwrap()).unpack(&extracted).unwrap();
+    write(&extracted, "a.txt", "edited");
+    let imported = client.import("copy", &extracted).await.unwrap();
+    assert_eq!(imported.len(), 3);
+    assert_eq!(client.get("copy", "a.txt").await.unwrap(), "edited");
+    assert!(client.get("copy", MANIFEST).await.is_err());
+}
+
+#[tokio::test]
+async fn test_export_errors() {
+    let (dir, client) = setup();
+    let err = client.export("missing", &dir.path().join("out.tar")).await.unwrap_err();
+    assert!(err.to_string().contains("NoSuchBucket"), "{}", err);
+
+    client.import("fixtures", &dir.path().join("fixtures")).await.unwrap();
+    let err = client.export("fixtures", &dir.path().join("out.tgz")).await.unwrap_err();
+    assert!(err.to_string().contains(".tar or .zip"), "{}", err);
+
+    let err = client.import("other", &dir.path().join("fixtures/a.txt")).await.unwrap_err();
+    assert!(err.to_string().contains("expected a directory"), "{}", err);
+}
+
+#[tokio::test]
+async fn test_import_and_export_through_a_server() {
+    let mut config = Config::default();
+    config.admin.enabled = true;
+    config.admin.token = Some("secret".to_string());
+    let server = Server::builder().config(config).port(0).in_memory().spawn().await.unwrap();
+    let client = Client::remote(&server.url(), Some("secret".to_string()));
+
+    let (dir, _) = setup();
+    client.import("fixtures", &dir.path().join("fixtures")).await.unwrap();
+    let path = dir.path().join("out.zip");
+    let manifest = client.export("fixtures", &path).await.unwrap();
+    assert_eq!(manifest.objects.len(), 3);
+    assert_eq!(manifest.get("img/logo.svg").unwrap().content_type.as_deref(), Some("image/svg+xml"));
+    assert_eq!(archive::read(&path).unwrap().entries.len(), 3);
+}
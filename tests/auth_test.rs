@@ -1,5 +1,7 @@
 use axum::http::{HeaderMap, HeaderValue};
-use six7::auth::{extract_access_key, hash_payload};
+use chrono::{TimeDelta, TimeZone, Utc};
+use six7::auth::{extract_access_key, hash_payload, presigned_expiry, presigned_url_expired};
+use six7::clock::Clock;
 
 #[test]
 fn test_extract_access_key_v4() {
@@ -44,3 +46,34 @@ fn test_hash_empty_payload() {
         "e3b0c44298fc1c149afbf4c8996fb92427ae41e4649b934ca495991b7852b855"
     );
 }
+
+#[test]
+fn test_presigned_url_expiry() {
+    let query = "X-Amz-Algorithm=AWS4-HMAC-SHA256&X-Amz-Date=20240101T000000Z&X-Amz-Expires=3600&X-Amz-Signature=abc";
+    let issued = Utc.with_ymd_and_hms(2024, 1, 1, 0, 0, 0).unwrap();
+    assert_eq!(presigned_expiry(query).unwrap(), Some(issued + TimeDelta::hours(1)));
+
+    let clock = Clock::frozen_at(issued);
+    assert!(!presigned_url_expired(query, &clock).unwrap());
+    clock.advance(TimeDelta::seconds(3599)).unwrap();
+    assert!(!presigned_url_expired(query, &clock).unwrap());
+    clock.advance(TimeDelta::seconds(1)).unwrap();
+    assert!(presigned_url_expired(query, &clock).unwrap());
+
+    // not presigned
+    assert!(!presigned_url_expired("list-type=2", &clock).unwrap());
+}
+
+#[test]
+fn test_presigned_expiry_out_of_range() {
+    // presigned for longer than s3 allows, or for no time at all, is refused rather than
+    // taken as a url that never expires
+    for expires in ["604801", "999999", "0", "-1", "soon"] {
+        let query = format!("X-Amz-Date=20240101T000000Z&X-Amz-Expires={}", expires);
+        let err = presigned_expiry(&query).unwrap_err();
+        assert_eq!(err.code, "AuthorizationQueryParametersError", "{}", expires);
+        assert!(presigned_url_expired(&query, &Clock::new()).is_err());
+    }
+    assert!(presigned_expiry("X-Amz-Expires=60").is_err());
+    assert!(presigned_expiry("X-Amz-Date=20240101T000000Z&X-Amz-Expires=604800").unwrap().is_some());
+}
@@ -0,0 +1,219 @@
+use clap::Parser;
+use six7::cli::{Cli, Command, SnapshotAction};
+use six7::config::BucketConfig;
+use std::io::Write;
+use tempfile::NamedTempFile;
+
+fn serve_args(args: &[&str]) -> six7::cli::ServeArgs {
+    match Cli::try_parse_from(args).unwrap().into_command() {
+        Command::Serve(args) => args,
+        command => panic!("expected serve, got {:?}", command),
+    }
+}
+
+#[test]
+fn test_flags_layer_over_config_file() {
+    let mut temp_file = NamedTempFile::new().unwrap();
+    temp_file
+        .write_all(
+            br#"
+server:
+  host: "127.0.0.1"
+  port: 9000
+
+storage:
+  path: "./from-file"
+
+buckets:
+  - name: "file-bucket"
+    access_key: "key1"
+    secret_key: "secret1"
+    region: "us-east-1"
+"#,
+        )
+        .unwrap();
+
+    let path = temp_file.path().to_str().unwrap();
+    let args = serve_args(&["six7", "--config", path, "--port", "5050", "--data-dir", "/tmp/six7"]);
+    let config = args.load_config().unwrap();
+
+    assert_eq!(config.server.host, "127.0.0.1");
+    assert_eq!(config.server.port, 5050);
+    assert_eq!(config.storage.path, "/tmp/six7");
+    assert_eq!(config.buckets.len(), 1);
+    assert_eq!(config.buckets[0].access_key, "key1");
+}
+
+#[test]
+fn test_bucket_flags() {
+    let args = serve_args(&["six7", "serve", "-b", "plain", "--bucket", "creds:AKID:s3cr3t", "--in-memory"]);
+    assert!(args.in_memory);
+    assert_eq!(args.buckets.len(), 2);
+    assert_eq!(args.buckets[0].name, "plain");
+    assert_eq!(args.buckets[0].access_key, "minioadmin");
+    assert_eq!(args.buckets[1].access_key, "AKID");
+    assert_eq!(args.buckets[1].secret_key, "s3cr3t");
+
+    assert!(BucketConfig::parse("name:key").is_err());
+    assert!(BucketConfig::parse("").is_err());
+}
+
+#[test]
+fn test_missing_explicit_config_is_an_error() {
+    let args = serve_args(&["six7", "--config", "/nonexistent/six7.yaml"]);
+    assert!(args.load_config().is_err());
+}
+
+#[test]
+fn test_tls_flags() {
+    let args = serve_args(&["six7", "--config", "/nonexistent/six7.yaml", "--tls-port", "8443"]);
+    let mut config = six7::config::Config::default();
+    args.apply(&mut config);
+    let tls = config.server.tls.unwrap();
+    assert_eq!(tls.port, 8443);
+    assert!(tls.cert.is_none());
+
+    assert!(Cli::try_parse_from(["six7", "--tls-cert", "cert.pem"]).is_err());
+}
+
+#[test]
+fn test_snapshot_subcommand() {
+    let cli = Cli::try_parse_from(["six7", "snapshot", "create", "fixtures", "--data-dir", "/tmp/six7"]).unwrap();
+    match cli.into_command() {
+        Command::Snapshot(args) => {
+            assert!(matches!(args.action, SnapshotAction::Create { ref name } if name == "fixtures"));
+            assert_eq!(args.target.data_dir.as_deref(), Some("/tmp/six7"));
+            assert!(args.target.endpoint.is_none());
+        }
+        command => panic!("expected snapshot, got {:?}", command),
+    }
+
+    assert!(Cli::try_parse_from(["six7", "snapshot", "restore"]).is_err());
+}
+
+#[test]
+fn test_journal_flag_and_replay_subcommand() {
+    let args = serve_args(&["six7", "--journal", "requests.jsonl"]);
+    let mut config = six7::config::Config::default();
+    args.apply(&mut config);
+    assert!(config.journal.enabled);
+    assert_eq!(config.journal.path.as_deref(), Some("requests.jsonl"));
+
+    let cli = Cli::try_parse_from(["six7", "replay", "requests.jsonl", "--endpoint", "http://localhost:4040"]).unwrap();
+    match cli.into_command() {
+        Command::Replay(args) => {
+            assert_eq!(args.journal, std::path::PathBuf::from("requests.jsonl"));
+            assert_eq!(args.endpoint.as_deref(), Some("http://localhost:4040"));
+        }
+        command => panic!("expected replay, got {:?}", command),
+    }
+}
+
+#[test]
+fn test_seed_flag() {
+    let mut config = six7::config::Config::default();
+    serve_args(&["six7"]).apply(&mut config);
+    assert!(config.deterministic.is_none());
+
+    serve_args(&["six7", "--seed", "42"]).apply(&mut config);
+    let settings = config.deterministic.unwrap();
+    assert_eq!(settings.seed, 42);
+    assert_eq!(settings.start, six7::config::default_start());
+}
+
+#[test]
+fn test_watch_subcommand() {
+    let cli = Cli::try_parse_from(["six7", "watch", "--bucket", "photos", "--prefix", "2024/", "--json"]).unwrap();
+    match cli.into_command() {
+        Command::Watch(args) => {
+            assert_eq!(args.endpoint, "http://localhost:4040");
+            assert_eq!(args.bucket.as_deref(), Some("photos"));
+            assert_eq!(args.prefix.as_deref(), Some("2024/"));
+            assert!(args.operation.is_none());
+            assert!(args.json);
+        }
+        command => panic!("expected watch, got {:?}", command),
+    }
+}
+
+#[test]
+fn test_client_subcommands() {
+    use six7::client::Location;
+
+    let cli = Cli::try_parse_from(["six7", "cp", "-r", "./site", "s3://web/assets/", "--endpoint", "http://localhost:4040"])
+        .unwrap();
+    match cli.into_command() {
+        Command::Cp(args) => {
+            assert_eq!(args.source, Location::File("./site".into()));
+            assert_eq!(
+                args.dest,
+                Location::S3 {
+                    bucket: "web".to_string(),
+                    key: "assets/".to_string()
+                }
+            );
+            assert!(args.recursive);
+            assert_eq!(args.target.endpoint.as_deref(), Some("http://localhost:4040"));
+        }
+        command => panic!("expected cp, got {:?}", command),
+    }
+
+    match Cli::try_parse_from(["six7", "rb", "s3://web", "--force"]).unwrap().into_command() {
+        Command::Rb(args) => {
+            assert_eq!(args.bucket, "web");
+            assert!(args.force);
+        }
+        command => panic!("expected rb, got {:?}", command),
+    }
+
+    assert!(Cli::try_parse_from(["six7", "ls", "s3:///key"]).is_err());
+    assert!(Cli::try_parse_from(["six7", "mb", "s3://web/key"]).is_err());
+}
+
+#[test]
+fn test_import_and_export_subcommands() {
+    match Cli::try_parse_from(["six7", "import", "--bucket", "s3://fixtures", "./qa"]).unwrap().into_command() {
+        Command::Import(args) => {
+            assert_eq!(args.bucket, "fixtures");
+            assert_eq!(args.source, std::path::PathBuf::from("./qa"));
+        }
+        command => panic!("expected import, got {:?}", command),
+    }
+
+    match Cli::try_parse_from(["six7", "export", "-b", "fixtures", "out.zip", "--data-dir", "./data"])
+        .unwrap()
+        .into_command()
+    {
+        Command::Export(args) => {
+            assert_eq!(args.bucket, "fixtures");
+            assert_eq!(args.dest, std::path::PathBuf::from("out.zip"));
+            assert_eq!(args.target.data_dir.as_deref(), Some("./data"));
+        }
+        command => panic!("expected export, got {:?}", command),
+    }
+
+    assert!(Cli::try_parse_from(["six7", "export", "out.tar"]).is_err());
+}
+
+#[test]
+fn test_fsck_subcommand() {
+    match Cli::try_parse_from(["six7", "fsck"]).unwrap().into_command() {
+        Command::Fsck(args) => {
+            assert!(!args.repair);
+            assert_eq!(args.abandoned_after, 86400);
+        }
+        command => panic!("expected fsck, got {:?}", command),
+    }
+
+    match Cli::try_parse_from(["six7", "fsck", "--repair", "--abandoned-after", "60", "--endpoint", "http://localhost:4040"])
+        .unwrap()
+        .into_command()
+    {
+        Command::Fsck(args) => {
+            assert!(args.repair);
+            assert_eq!(args.abandoned_after, 60);
+            assert_eq!(args.target.endpoint.as_deref(), Some("http://localhost:4040"));
+        }
+        command => panic!("expected fsck, got {:?}", command),
+    }
+}
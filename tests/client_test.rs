@@ -0,0 +1,128 @@
+use six7::client::{Client, Location};
+use six7::config::Config;
+use six7::server::RunningServer;
+use six7::Server;
+use std::path::Path;
+use tempfile::TempDir;
+
+const TOKEN: &str = "secret";
+
+fn s3(url: &str) -> Location {
+    Location::parse(url).unwrap()
+}
+
+fn file(path: &Path) -> Location {
+    Location::File(path.to_path_buf())
+}
+
+fn write(dir: &Path, relative: &str, contents: &str) {
+    let path = dir.join(relative);
+    std::fs::create_dir_all(path.parent().unwrap()).unwrap();
+    std::fs::write(path, contents).unwrap();
+}
+
+async fn start() -> RunningServer {
+    let mut config = Config::default();
+    config.admin.enabled = true;
+    config.admin.token = Some(TOKEN.to_string());
+    Server::builder().config(config).port(0).in_memory().spawn().await.unwrap()
+}
+
+// everything a client does, the same way whether it's on a data dir or a server
+async fn exercise(client: &Client) {
+    let files = TempDir::new().unwrap();
+    write(files.path(), "a.txt", "hello");
+    write(files.path(), "dir/b.json", "{}");
+    write(files.path(), "dir/deeper/c.txt", "c");
+
+    client.create_bucket("bucket").await.unwrap();
+    let buckets = client.list_buckets().await.unwrap();
+    assert_eq!(buckets.len(), 1);
+
+    // copies never create buckets
+    let err = client.copy(&file(&files.path().join("a.txt")), &s3("s3://missing/"), false).await.unwrap_err();
+    assert!(err.to_string().contains("NoSuchBucket"), "{}", err);
+
+    let copied = client.copy(&file(files.path()), &s3("s3://bucket/up"), true).await.unwrap();
+    assert_eq!(copied.len(), 3);
+    assert_eq!(client.get("bucket", "up/dir/deeper/c.txt").await.unwrap(), "c");
+
+    // one file into a prefix keeps its name
+    client.copy(&file(&files.path().join("a.txt")), &s3("s3://bucket/single/"), false).await.unwrap();
+    assert_eq!(client.get("bucket", "single/a.txt").await.unwrap(), "hello");
+    client.copy(&s3("s3://bucket/single/a.txt"), &s3("s3://bucket/renamed.txt"), false).await.unwrap();
+    assert_eq!(client.get("bucket", "renamed.txt").await.unwrap(), "hello");
+
+    let listing = client.list("bucket", "up/", false).await.unwrap();
+    assert_eq!(listing.prefixes, vec!["up/dir/".to_string()]);
+    assert_eq!(listing.objects.len(), 1);
+    assert_eq!(listing.objects[0].key, "up/a.txt");
+    assert_eq!(listing.objects[0].content_type.as_deref(), Some("text/plain"));
+    assert_eq!(client.list("bucket", "up/", true).await.unwrap().objects.len(), 3);
+
+    // only what changed goes again
+    write(files.path(), "a.txt", "changed");
+    write(files.path(), "new.txt", "new");
+    std::fs::remove_file(files.path().join("dir/b.json")).unwrap();
+    let report = client.sync(&file(files.path()), &s3("s3://bucket/up"), false).await.unwrap();
+    assert_eq!(report.copied.len(), 2);
+    assert_eq!(report.unchanged, 1);
+    assert!(report.deleted.is_empty());
+    let report = client.sync(&file(files.path()), &s3("s3://bucket/up"), true).await.unwrap();
+    assert_eq!((report.copied.len(), report.unchanged), (0, 3));
+    assert_eq!(report.deleted, vec![s3("s3://bucket/up/dir/b.json")]);
+
+    // and back down
+    let mirror = TempDir::new().unwrap();
+    let report = client.sync(&s3("s3://bucket/up/"), &file(mirror.path()), false).await.unwrap();
+    assert_eq!(report.copied.len(), 3);
+    assert_eq!(std::fs::read_to_string(mirror.path().join("a.txt")).unwrap(), "changed");
+    assert_eq!(std::fs::read_to_string(mirror.path().join("dir/deeper/c.txt")).unwrap(), "c");
+    let report = client.sync(&s3("s3://bucket/up"), &file(mirror.path()), false).await.unwrap();
+    assert_eq!((report.copied.len(), report.unchanged), (0, 3));
+
+    let removed = client.remove("bucket", "up/", true).await.unwrap();
+    assert_eq!(removed.len(), 3);
+    assert!(client.get("bucket", "up/a.txt").await.is_err());
+
+    assert!(client.remove_bucket("bucket", false).await.is_err());
+    let removed = client.remove_bucket("bucket", true).await.unwrap();
+    assert_eq!(removed.len(), 2);
+    assert!(client.list_buckets().await.unwrap().is_empty());
+}
+
+#[tokio::test]
+async fn test_client_on_a_data_dir() {
+    let dir = TempDir::new().unwrap();
+    let client = Client::local(dir.path()).unwrap();
+    exercise(&client).await;
+}
+
+#[tokio::test]
+async fn test_client_on_a_running_server() {
+    let server = start().await;
+    let client = Client::remote(&server.url(), Some(TOKEN.to_string()));
+    exercise(&client).await;
+
+    let unauthorized = Client::remote(&server.url(), None);
+    assert!(unauthorized.list_buckets().await.is_err());
+}
+
+#[tokio::test]
+async fn test_locations() {
+    assert_eq!(
+        s3("s3://bucket"),
+        Location::S3 {
+            bucket: "bucket".to_string(),
+            key: String::new()
+        }
+    );
+    assert_eq!(s3("s3://bucket/a/b").to_string(), "s3://bucket/a/b");
+    assert!(Location::parse("s3://").is_err());
+    assert_eq!(Location::parse_bucket("s3://bucket/").unwrap(), "bucket");
+    assert!(file(Path::new("x")).s3().is_err());
+
+    let client = Client::local(TempDir::new().unwrap().path()).unwrap();
+    let err = client.copy(&file(Path::new("a")), &file(Path::new("b")), false).await.unwrap_err();
+    assert!(err.to_string().contains("s3://"));
+}
@@ -0,0 +1,157 @@
+mod common;
+
+use axum::{
+    body::Body,
+    http::{Request, StatusCode},
+    Router,
+};
+use chrono::{DateTime, TimeDelta, TimeZone, Utc};
+use six7::clock::Clock;
+use six7::storage::{MemoryStorage, Storage, StorageBackend};
+use tempfile::TempDir;
+use tower::ServiceExt;
+
+fn new_year() -> DateTime<Utc> {
+    Utc.with_ymd_and_hms(2024, 1, 1, 0, 0, 0).unwrap()
+}
+
+async fn create_app(clock: Clock) -> Router {
+    common::create_app(common::admin_config(), MemoryStorage::new().with_clock(clock)).await.0
+}
+
+async fn admin(app: &Router, method: &str, uri: &str, body: &'static str) -> serde_json::Value {
+    let response = app.clone().oneshot(common::admin(method, uri, body)).await.unwrap();
+    assert_eq!(response.status(), StatusCode::OK);
+    let body = axum::body::to_bytes(response.into_body(), usize::MAX).await.unwrap();
+    serde_json::from_slice(&body).unwrap()
+}
+
+#[test]
+fn test_clock_controls() {
+    let clock = Clock::new();
+    assert!((clock.now() - Utc::now()).abs() < TimeDelta::seconds(1));
+
+    clock.freeze();
+    let frozen = clock.now();
+    std::thread::sleep(std::time::Duration::from_millis(5));
+    assert_eq!(clock.now(), frozen);
+
+    clock.set(new_year());
+    assert_eq!(clock.now(), new_year());
+    clock.advance(TimeDelta::days(1)).unwrap();
+    assert_eq!(clock.now(), new_year() + TimeDelta::days(1));
+
+    // running again picks up from the virtual time, not the real one
+    clock.resume();
+    assert!(clock.now() - (new_year() + TimeDelta::days(1)) < TimeDelta::seconds(1));
+    assert!(!clock.status().frozen);
+
+    // clones share the same time
+    let shared = clock.clone();
+    shared.reset();
+    assert!((clock.now() - Utc::now()).abs() < TimeDelta::seconds(1));
+}
+
+#[tokio::test]
+async fn test_storage_uses_clock() {
+    let clock = Clock::frozen_at(new_year());
+
+    let memory = MemoryStorage::new().with_clock(clock.clone());
+    memory.create_bucket("bucket").await.unwrap();
+    let metadata = memory.put_object("bucket", "a", "x".into(), None).await.unwrap();
+    assert_eq!(metadata.last_modified, new_year());
+    assert_eq!(memory.list_buckets().await.unwrap()[0].creation_date, new_year());
+
+    let dir = TempDir::new().unwrap();
+    let disk = Storage::new(dir.path()).unwrap().with_clock(clock.clone());
+    disk.create_bucket("bucket").await.unwrap();
+    clock.advance(TimeDelta::hours(2)).unwrap();
+    disk.put_object("bucket", "a", "x".into(), None).await.unwrap();
+    let metadata = disk.head_object("bucket", "a").await.unwrap();
+    assert_eq!(metadata.last_modified, new_year() + TimeDelta::hours(2));
+
+    let upload = disk.create_multipart_upload("bucket", "b", None).await.unwrap();
+    assert_eq!(upload.initiated, new_year() + TimeDelta::hours(2));
+}
+
+#[tokio::test]
+async fn test_clock_through_admin_api() {
+    let app = create_app(Clock::new()).await;
+
+    let status = admin(&app, "POST", "/_six7/clock/freeze", "").await;
+    assert_eq!(status["frozen"], true);
+
+    let status = admin(&app, "PUT", "/_six7/clock", r#"{"time":"2024-01-01T00:00:00Z"}"#).await;
+    assert_eq!(status["now"], "2024-01-01T00:00:00Z");
+
+    let status = admin(&app, "POST", "/_six7/clock/advance", r#"{"seconds":90}"#).await;
+    assert_eq!(status["now"], "2024-01-01T00:01:30Z");
+
+    // objects written now carry the virtual time
+    let response = app
+        .clone()
+        .oneshot(Request::builder().method("PUT").uri("/bucket/a.txt").body(Body::from("x")).unwrap())
+        .await
+        .unwrap();
+    assert_eq!(response.status(), StatusCode::OK);
+    let response = app
+        .clone()
+        .oneshot(Request::builder().method("HEAD").uri("/bucket/a.txt").body(Body::empty()).unwrap())
+        .await
+        .unwrap();
+    assert_eq!(response.headers()["last-modified"], "Mon, 1 Jan 2024 00:01:30 +0000");
+
+    let status = admin(&app, "DELETE", "/_six7/clock", "").await;
+    assert_eq!(status["frozen"], false);
+    assert_eq!(status["offset_ms"], 0);
+}
+
+#[tokio::test]
+async fn test_presigned_urls_expire_with_the_clock() {
+    let clock = Clock::frozen_at(new_year());
+    let app = create_app(clock.clone()).await;
+    let uri = "/bucket?X-Amz-Date=20240101T000000Z&X-Amz-Expires=60&X-Amz-Signature=abc";
+
+    let get = || Request::builder().uri(uri).body(Body::empty()).unwrap();
+    let response = app.clone().oneshot(get()).await.unwrap();
+    assert_eq!(response.status(), StatusCode::OK);
+
+    clock.advance(TimeDelta::seconds(61)).unwrap();
+    let response = app.clone().oneshot(get()).await.unwrap();
+    assert_eq!(response.status(), StatusCode::FORBIDDEN);
+    let body = axum::body::to_bytes(response.into_body(), usize::MAX).await.unwrap();
+    assert!(String::from_utf8_lossy(&body).contains("Request has expired"));
+}
+
+#[tokio::test]
+async fn test_presigned_expiry_out_of_range_rejected() {
+    let app = create_app(Clock::frozen_at(new_year())).await;
+    let uri = "/bucket?X-Amz-Date=20240101T000000Z&X-Amz-Expires=604801&X-Amz-Signature=abc";
+
+    let response = app.oneshot(Request::builder().uri(uri).body(Body::empty()).unwrap()).await.unwrap();
+    assert_eq!(response.status(), StatusCode::BAD_REQUEST);
+    let body = axum::body::to_bytes(response.into_body(), usize::MAX).await.unwrap();
+    assert!(String::from_utf8_lossy(&body).contains("AuthorizationQueryParametersError"));
+}
+
+#[tokio::test]
+async fn test_advance_out_of_range_rejected() {
+    let clock = Clock::frozen_at(new_year());
+    assert!(clock.advance(TimeDelta::MAX).is_err());
+    assert_eq!(clock.now(), new_year());
+
+    let running = Clock::new();
+    assert!(running.advance(TimeDelta::MAX).is_err());
+    assert!((running.now() - Utc::now()).abs() < TimeDelta::seconds(1));
+
+    let app = create_app(Clock::new()).await;
+    for body in [r#"{"seconds":1e15}"#, r#"{"seconds":-1e300}"#] {
+        let response = app.clone().oneshot(common::admin("POST", "/_six7/clock/advance", body)).await.unwrap();
+        assert_eq!(response.status(), StatusCode::BAD_REQUEST, "{}", body);
+    }
+
+    // the clock still works after the rejected moves
+    let status = admin(&app, "POST", "/_six7/clock/advance", r#"{"seconds":60}"#).await;
+    assert_eq!(status["frozen"], false);
+    assert!((status["offset_ms"].as_i64().unwrap() - 60_000).abs() < 1000);
+}
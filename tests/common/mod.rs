@@ -0,0 +1,50 @@
+// fixtures shared by the tests that drive the router directly; each test crate uses
+// some of them
+#![allow(dead_code)]
+
+use axum::{body::Body, http::Request, response::Response, Router};
+use six7::config::Config;
+use six7::handlers::AppState;
+use six7::server::router;
+use six7::storage::{MemoryStorage, StorageBackend};
+use std::sync::Arc;
+use tower::ServiceExt;
+
+pub const TOKEN: &str = "test-token";
+
+// the default config with the admin api on, behind TOKEN
+pub fn admin_config() -> Config {
+    let mut config = Config::default();
+    config.admin.enabled = true;
+    config.admin.token = Some(TOKEN.to_string());
+    config
+}
+
+// the routes over storage with an empty "bucket" in it
+pub async fn create_app(config: Config, storage: MemoryStorage) -> (Router, Arc<AppState>) {
+    storage.create_bucket("bucket").await.unwrap();
+    let state = Arc::new(AppState::from_parts(Arc::new(storage), config));
+    (router(state.clone()), state)
+}
+
+// an admin api request carrying TOKEN
+pub fn admin(method: &str, uri: &str, body: &'static str) -> Request<Body> {
+    Request::builder()
+        .method(method)
+        .uri(uri)
+        .header("authorization", format!("Bearer {}", TOKEN))
+        .body(Body::from(body))
+        .unwrap()
+}
+
+pub async fn send(app: &Router, method: &str, uri: &str, body: impl Into<Body>) -> Response {
+    app.clone()
+        .oneshot(Request::builder().method(method).uri(uri).body(body.into()).unwrap())
+        .await
+        .unwrap()
+}
+
+pub async fn body_string(response: Response) -> String {
+    let bytes = axum::body::to_bytes(response.into_body(), usize::MAX).await.unwrap();
+    String::from_utf8(bytes.to_vec()).unwrap()
+}
@@ -1,4 +1,4 @@
-use six7::config::Config;
+use six7::config::{Config, ConfigError};
 use std::io::Write;
 use tempfile::NamedTempFile;
 
@@ -112,3 +112,226 @@ buckets:
     let bucket = config.find_bucket_by_access_key("nonexistent");
     assert!(bucket.is_none());
 }
+
+fn env_from(vars: &[(&str, &str)]) -> impl Fn(&str) -> Option<String> {
+    let vars: std::collections::HashMap<String, String> = vars
+        .iter()
+        .map(|(k, v)| (k.to_string(), v.to_string()))
+        .collect();
+    move |name| vars.get(name).cloned()
+}
+
+const BASE_YAML: &str = r#"
+server:
+  host: "127.0.0.1"
+  port: 9000
+
+storage:
+  path: "./test-data"
+
+buckets:
+  - name: "test-bucket"
+    access_key: "test-key"
+    secret_key: "test-secret"
+    region: "us-east-1"
+"#;
+
+#[test]
+fn test_env_overrides() {
+    let env = env_from(&[
+        ("SIX7_PORT", "4141"),
+        ("SIX7_STORAGE_PATH", "/data"),
+        ("SIX7_BUCKETS", "uploads, private:AKID:s3cr3t"),
+        ("SIX7_SECRET_KEY", "rotated"),
+    ]);
+
+    let config = Config::parse_with_env(BASE_YAML, env).unwrap();
+
+    assert_eq!(config.server.host, "127.0.0.1");
+    assert_eq!(config.server.port, 4141);
+    assert_eq!(config.storage.path, "/data");
+    assert_eq!(config.buckets.len(), 3);
+    assert_eq!(config.buckets[2].name, "private");
+    assert_eq!(config.buckets[2].access_key, "AKID");
+    assert!(config.buckets.iter().all(|b| b.secret_key == "rotated"));
+}
+
+#[test]
+fn test_variable_interpolation() {
+    let yaml = r#"
+server:
+  host: "${HOST:-0.0.0.0}"
+  port: ${PORT}
+
+# ${NOT_SET} in a comment is ignored
+storage:
+  path: "${DATA}/six7"
+
+buckets:
+  - name: "test-bucket"
+"#;
+
+    let config = Config::parse_with_env(yaml, env_from(&[("PORT", "8080"), ("DATA", "/var/lib")])).unwrap();
+    assert_eq!(config.server.host, "0.0.0.0");
+    assert_eq!(config.server.port, 8080);
+    assert_eq!(config.storage.path, "/var/lib/six7");
+
+    let err = Config::parse_with_env(yaml, env_from(&[("DATA", "/var/lib")])).unwrap_err();
+    assert!(matches!(err, ConfigError::Interpolation { line: 4, .. }), "{}", err);
+    assert!(err.to_string().contains("PORT"));
+}
+
+#[test]
+fn test_validation_points_at_field() {
+    let cases: &[(&str, &str)] = &[
+        (
+            "buckets:\n  - name: \"Bad_Bucket\"\n",
+            "buckets[0].name",
+        ),
+        (
+            "buckets:\n  - name: \"one-bucket\"\n    access_key: \"k\"\n    secret_key: \"a\"\n  - name: \"two-bucket\"\n    access_key: \"k\"\n    secret_key: \"b\"\n",
+            "buckets[1].access_key",
+        ),
+        (
+            "buckets:\n  - name: \"one-bucket\"\n  - name: \"one-bucket\"\n",
+            "buckets[1].name",
+        ),
+        (
+            "buckets:\n  - name: \"one-bucket\"\n    region: \"moon\"\n",
+            "buckets[0].region",
+        ),
+    ];
+
+    for (buckets, field) in cases {
+        let yaml = format!(
+            "server:\n  host: \"127.0.0.1\"\n  port: 9000\nstorage:\n  path: \"./data\"\n{}",
+            buckets
+        );
+        match Config::parse_with_env(&yaml, env_from(&[])) {
+            Err(ConfigError::Invalid { field: f, .. }) => assert_eq!(&f, field),
+            other => panic!("expected invalid {}, got {:?}", field, other),
+        }
+    }
+}
+
+#[test]
+fn test_port_out_of_range() {
+    let err = Config::parse_with_env(BASE_YAML, env_from(&[("SIX7_PORT", "70000")])).unwrap_err();
+    assert!(matches!(err, ConfigError::Env { var: "SIX7_PORT", .. }));
+
+    let yaml = BASE_YAML.replace("port: 9000", "port: 70000");
+    let err = Config::parse_with_env(&yaml, env_from(&[])).unwrap_err();
+    assert!(err.to_string().contains("server.port"), "{}", err);
+}
+
+#[test]
+fn test_domains() {
+    let config = Config::parse_with_env(
+        "server:\n  host: 127.0.0.1\n  port: 4040\nstorage:\n  path: ./data\nbuckets: []\n",
+        |_| None,
+    )
+    .unwrap();
+    assert_eq!(config.server.domains, vec!["localhost"]);
+
+    let env = |name: &str| (name == "SIX7_DOMAINS").then(|| "s3.localhost, *.s3.example.test".to_string());
+    let config = Config::parse_with_env(
+        "server:\n  host: 127.0.0.1\n  port: 4040\nstorage:\n  path: ./data\nbuckets: []\n",
+        env,
+    )
+    .unwrap();
+    assert_eq!(config.server.domains, vec!["s3.localhost", "*.s3.example.test"]);
+
+    let err = Config::parse_with_env(
+        "server:\n  host: 127.0.0.1\n  port: 4040\n  domains: [\"http://s3.localhost\"]\nstorage:\n  path: ./data\nbuckets: []\n",
+        |_| None,
+    )
+    .unwrap_err();
+    assert!(err.to_string().contains("server.domains[0]"), "{}", err);
+}
+
+#[test]
+fn test_tls_config() {
+    let base = "server:\n  host: 127.0.0.1\n  port: 4040\n";
+    let rest = "storage:\n  path: ./data\nbuckets: []\n";
+
+    let config = Config::parse_with_env(&format!("{}  tls: {{}}\n{}", base, rest), |_| None).unwrap();
+    let tls = config.server.tls.unwrap();
+    assert_eq!(tls.port, 4443);
+    assert_eq!(tls.dir, "./tls");
+    assert!(tls.cert.is_none());
+
+    let config = Config::parse_with_env(&format!("{}{}", base, rest), |_| None).unwrap();
+    assert!(config.server.tls.is_none());
+
+    let err = Config::parse_with_env(&format!("{}  tls:\n    cert: ./cert.pem\n{}", base, rest), |_| None)
+        .unwrap_err();
+    assert!(err.to_string().contains("server.tls.key"), "{}", err);
+
+    let err = Config::parse_with_env(&format!("{}  tls:\n    port: 4040\n{}", base, rest), |_| None)
+        .unwrap_err();
+    assert!(err.to_string().contains("server.tls.port"), "{}", err);
+}
+
+#[test]
+fn test_notifications_config() {
+    let base = "server:\n  host: 127.0.0.1\n  port: 4040\nstorage:\n  path: ./data\nbuckets: []\n";
+
+    let config = Config::parse_with_env(base, |_| None).unwrap();
+    assert!(config.notifications.targets.is_empty());
+    assert_eq!(config.notifications.retries, 3);
+    assert!(config.notifications.dead_letter.is_none());
+
+    let yaml = format!(
+        "{}notifications:\n  targets:\n    - arn: arn:aws:sns:us-east-1:000000000000:uploads\n      url: http://localhost:8080/events\n  retries: 5\n  dead_letter: ./dead.jsonl\n",
+        base
+    );
+    let config = Config::parse_with_env(&yaml, |_| None).unwrap();
+    let target = config.notifications.target("arn:aws:sns:us-east-1:000000000000:uploads").unwrap();
+    assert_eq!(target.url, "http://localhost:8080/events");
+    assert_eq!(config.notifications.retries, 5);
+
+    let err = Config::parse_with_env(&yaml.replace("http://localhost", "ftp://localhost"), |_| None).unwrap_err();
+    assert!(err.to_string().contains("notifications.targets[0].url"), "{}", err);
+    let err = Config::parse_with_env(&yaml.replace("arn:aws:sns", "sns"), |_| None).unwrap_err();
+    assert!(err.to_string().contains("notifications.targets[0].arn"), "{}", err);
+}
+
+#[test]
+fn test_metrics_config() {
+    let base = "server:\n  host: 127.0.0.1\n  port: 4040\nstorage:\n  path: ./data\nbuckets: []\n";
+
+    let config = Config::parse_with_env(base, |_| None).unwrap();
+    assert!(config.metrics.enabled);
+    assert_eq!(config.metrics.path, "/metrics");
+
+    let yaml = format!("{}metrics:\n  path: /_six7/metrics\n", base);
+    let config = Config::parse_with_env(&yaml, |_| None).unwrap();
+    assert!(config.metrics.enabled);
+    assert_eq!(config.metrics.path, "/_six7/metrics");
+
+    let err = Config::parse_with_env(&yaml.replace("/_six7/metrics", "metrics"), |_| None).unwrap_err();
+    assert!(err.to_string().contains("metrics.path"), "{}", err);
+}
+
+#[test]
+fn test_access_logs_config() {
+    let base = "server:\n  host: 127.0.0.1\n  port: 4040\nstorage:\n  path: ./data\nbuckets: []\n";
+
+    let config = Config::parse_with_env(base, |_| None).unwrap();
+    assert_eq!(config.access_logs.flush_secs, 60);
+
+    let yaml = format!("{}access_logs:\n  flush_secs: 5\n", base);
+    assert_eq!(Config::parse_with_env(&yaml, |_| None).unwrap().access_logs.flush_secs, 5);
+
+    let err = Config::parse_with_env(&yaml.replace("5", "0"), |_| None).unwrap_err();
+    assert!(err.to_string().contains("access_logs.flush_secs"), "{}", err);
+}
+
+#[test]
+fn test_drain_secs() {
+    let base = "server:\n  host: 127.0.0.1\n  port: 4040\nstorage:\n  path: ./data\nbuckets: []\n";
+    assert_eq!(Config::parse_with_env(base, |_| None).unwrap().server.drain_secs, 30);
+
+    let yaml = base.replace("port: 4040\n", "port: 4040\n  drain_secs: 5\n");
+    assert_eq!(Config::parse_with_env(&yaml, |_| None).unwrap().server.drain_secs, 5);
+}
@@ -0,0 +1,127 @@
+use six7::config::{Config, DeterministicConfig, ENV_SEED};
+use six7::entropy::Entropy;
+use six7::server::ServerBuilder;
+use six7::Server;
+use std::collections::HashMap;
+use tempfile::TempDir;
+
+// everything a client sees of a response
+type Exchange = (u16, Vec<(String, String)>, Vec<u8>);
+
+async fn run_sequence(server: ServerBuilder) -> Vec<Exchange> {
+    let server = server.port(0).spawn().await.unwrap();
+    let url = server.url();
+    let client = reqwest::Client::new();
+
+    let requests = vec![
+        client.put(format!("{}/fixtures", url)),
+        client.put(format!("{}/fixtures/a/one.txt", url)).body("one"),
+        client.put(format!("{}/fixtures/b/two.txt", url)).body("two"),
+        client.post(format!("{}/fixtures/big.bin?uploads", url)),
+        client.post(format!("{}/fixtures/big.bin?uploads", url)),
+        client.get(format!("{}/fixtures?uploads", url)),
+        client.get(format!("{}/fixtures?list-type=2&delimiter=/", url)),
+        client.get(format!("{}/", url)),
+        client.head(format!("{}/fixtures/a/one.txt", url)),
+        client.get(format!("{}/fixtures/missing", url)),
+    ];
+
+    let mut exchanges = Vec::new();
+    for request in requests {
+        let response = request.send().await.unwrap();
+        let status = response.status().as_u16();
+        let mut headers: Vec<(String, String)> = response
+            .headers()
+            .iter()
+            .map(|(k, v)| (k.to_string(), v.to_str().unwrap().to_string()))
+            .collect();
+        headers.sort();
+        exchanges.push((status, headers, response.bytes().await.unwrap().to_vec()));
+    }
+    exchanges
+}
+
+fn deterministic(seed: u64, dir: &TempDir) -> ServerBuilder {
+    let mut config = Config::default();
+    config.storage.path = dir.path().to_string_lossy().into_owned();
+    config.deterministic = Some(DeterministicConfig::new(seed));
+    Server::builder().config(config)
+}
+
+#[tokio::test]
+async fn test_identical_requests_get_identical_responses() {
+    let (first_dir, second_dir) = (TempDir::new().unwrap(), TempDir::new().unwrap());
+    let first = run_sequence(deterministic(7, &first_dir)).await;
+    let second = run_sequence(deterministic(7, &second_dir)).await;
+    assert_eq!(first, second);
+
+    let headers: HashMap<_, _> = first[1].1.iter().cloned().collect();
+    assert_eq!(headers["date"], "Sat, 01 Jan 2000 00:00:00 GMT");
+    assert_eq!(headers["x-amz-request-id"].len(), 16);
+
+    // and the same again in memory
+    let unused = TempDir::new().unwrap();
+    let in_memory = run_sequence(deterministic(7, &unused).in_memory()).await;
+    assert_eq!(first, in_memory);
+}
+
+#[tokio::test]
+async fn test_seed_changes_ids() {
+    let (first_dir, second_dir) = (TempDir::new().unwrap(), TempDir::new().unwrap());
+    let first = run_sequence(deterministic(1, &first_dir)).await;
+    let second = run_sequence(deterministic(2, &second_dir)).await;
+
+    // same objects, different upload ids
+    let etag = |exchange: &Exchange| exchange.1.iter().find(|(k, _)| k == "etag").cloned();
+    assert_eq!((first[1].0, etag(&first[1])), (second[1].0, etag(&second[1])));
+    assert_ne!(first[3].2, second[3].2);
+}
+
+#[tokio::test]
+async fn test_ids_differ_without_seed() {
+    let (first_dir, second_dir) = (TempDir::new().unwrap(), TempDir::new().unwrap());
+    let builder = |dir: &TempDir| {
+        let mut config = Config::default();
+        config.storage.path = dir.path().to_string_lossy().into_owned();
+        Server::builder().config(config)
+    };
+
+    let first = run_sequence(builder(&first_dir)).await;
+    let second = run_sequence(builder(&second_dir)).await;
+    assert_ne!(first[3].2, second[3].2);
+}
+
+#[test]
+fn test_entropy_is_reproducible() {
+    let (a, b) = (Entropy::seeded(42), Entropy::seeded(42));
+    assert_eq!(a.uuid(), b.uuid());
+    assert_eq!(a.hex_id(16), b.hex_id(16));
+    assert_eq!(a.uuid().get_version_num(), 4);
+    assert_ne!(Entropy::new().uuid(), Entropy::new().uuid());
+}
+
+#[test]
+fn test_deterministic_config() {
+    let yaml = r#"
+server:
+  host: 127.0.0.1
+  port: 4040
+storage:
+  path: ./data
+buckets: []
+deterministic:
+  seed: 9
+  start: 2024-06-01T12:00:00Z
+"#;
+    let config = Config::parse_with_env(yaml, |_| None).unwrap();
+    let settings = config.deterministic.unwrap();
+    assert_eq!(settings.seed, 9);
+    assert_eq!(settings.start.to_rfc3339(), "2024-06-01T12:00:00+00:00");
+
+    let env = |name: &str| (name == ENV_SEED).then(|| "5".to_string());
+    let config = Config::parse_with_env(yaml, env).unwrap();
+    assert_eq!(config.deterministic.unwrap().seed, 5);
+
+    let env = |name: &str| (name == ENV_SEED).then(|| "five".to_string());
+    assert!(Config::parse_with_env(yaml, env).is_err());
+}
@@ -0,0 +1,266 @@
+mod common;
+
+use axum::{
+    body::Body,
+    http::{HeaderMap, Method, Request, StatusCode, Uri},
+    Router,
+};
+use common::admin;
+use six7::config::Config;
+use six7::faults::{glob_match, Fault, FaultRule};
+use six7::handlers::AppState;
+use six7::operation::classify;
+use six7::storage::MemoryStorage;
+use std::sync::Arc;
+use std::time::{Duration, Instant};
+use tower::ServiceExt;
+
+fn rule(fault: Fault) -> FaultRule {
+    FaultRule {
+        bucket: None,
+        key: None,
+        operation: None,
+        probability: None,
+        every: None,
+        times: None,
+        fault,
+    }
+}
+
+fn internal_error() -> Fault {
+    Fault::Error {
+        code: "InternalError".to_string(),
+    }
+}
+
+async fn create_app(rules: Vec<FaultRule>) -> (Router, Arc<AppState>) {
+    let mut config = common::admin_config();
+    config.faults = rules;
+
+    let (app, state) = common::create_app(config, MemoryStorage::new()).await;
+    state
+        .storage
+        .put_object("bucket", "data/file.txt", "0123456789".into(), None)
+        .await
+        .unwrap();
+    (app, state)
+}
+
+async fn get(app: &Router, uri: &str) -> axum::response::Response {
+    app.clone()
+        .oneshot(Request::builder().uri(uri).body(Body::empty()).unwrap())
+        .await
+        .unwrap()
+}
+
+#[test]
+fn test_glob_match() {
+    assert!(glob_match("*", ""));
+    assert!(glob_match("*.txt", "dir/file.txt"));
+    assert!(glob_match("data/*", "data/a/b"));
+    assert!(glob_match("file?.txt", "file1.txt"));
+    assert!(glob_match("a*b*c", "axxbyyc"));
+    assert!(!glob_match("*.txt", "file.txt.gz"));
+    assert!(!glob_match("file?.txt", "file.txt"));
+    assert!(!glob_match("data/*", "other/file"));
+}
+
+#[test]
+fn test_classify_operations() {
+    let op = |method: Method, uri: &str, headers: HeaderMap| {
+        classify(&method, &uri.parse::<Uri>().unwrap(), &headers).map(|op| op.name)
+    };
+
+    assert_eq!(op(Method::GET, "/", HeaderMap::new()), Some("ListBuckets"));
+    assert_eq!(op(Method::GET, "/bucket?list-type=2", HeaderMap::new()), Some("ListObjectsV2"));
+    assert_eq!(op(Method::GET, "/bucket/a/b.txt", HeaderMap::new()), Some("GetObject"));
+    assert_eq!(op(Method::POST, "/bucket/key?uploads", HeaderMap::new()), Some("CreateMultipartUpload"));
+    assert_eq!(
+        op(Method::PUT, "/bucket/key?partNumber=1&uploadId=x", HeaderMap::new()),
+        Some("UploadPart")
+    );
+
+    let mut headers = HeaderMap::new();
+    headers.insert("x-amz-copy-source", "/bucket/other".parse().unwrap());
+    assert_eq!(op(Method::PUT, "/bucket/key", headers), Some("CopyObject"));
+
+    assert_eq!(op(Method::GET, "/_six7/faults", HeaderMap::new()), None);
+
+    let classified = classify(&Method::GET, &"/bucket/dir/a%20b".parse().unwrap(), &HeaderMap::new()).unwrap();
+    assert_eq!(classified.bucket.as_deref(), Some("bucket"));
+    assert_eq!(classified.key.as_deref(), Some("dir/a b"));
+}
+
+#[test]
+fn test_fault_rules_from_yaml() {
+    let yaml = r#"
+- bucket: bucket
+  key: "*.txt"
+  operation: GetObject
+  every: 3
+  fault: error
+  code: SlowDown
+- probability: 0.5
+  fault: latency
+  delay_ms: 200
+"#;
+    let rules: Vec<FaultRule> = serde_yaml::from_str(yaml).unwrap();
+    assert_eq!(rules.len(), 2);
+    assert_eq!(
+        rules[0].fault,
+        Fault::Error {
+            code: "SlowDown".to_string()
+        }
+    );
+    assert_eq!(rules[0].every, Some(3));
+    assert_eq!(rules[1].fault, Fault::Latency { delay_ms: 200 });
+    assert!(rules.iter().all(|r| r.validate().is_ok()));
+}
+
+#[test]
+fn test_invalid_fault_rules_rejected() {
+    let mut bad_operation = rule(internal_error());
+    bad_operation.operation = Some("GetThing".to_string());
+    assert!(bad_operation.validate().is_err());
+
+    let mut bad_probability = rule(internal_error());
+    bad_probability.probability = Some(1.5);
+    assert!(bad_probability.validate().is_err());
+
+    let bad_code = rule(Fault::Error {
+        code: "NoSuchKey".to_string(),
+    });
+    assert!(bad_code.validate().is_err());
+
+    let mut config = Config::default();
+    config.faults.push(bad_code);
+    let err = config.validate().unwrap_err().to_string();
+    assert!(err.contains("faults[0]"), "{}", err);
+}
+
+#[tokio::test]
+async fn test_error_fault_matches_filters() {
+    let mut slow_down = rule(Fault::Error {
+        code: "SlowDown".to_string(),
+    });
+    slow_down.bucket = Some("bucket".to_string());
+    slow_down.key = Some("data/*".to_string());
+    slow_down.operation = Some("GetObject".to_string());
+    let (app, state) = create_app(vec![slow_down]).await;
+
+    let response = get(&app, "/bucket/data/file.txt").await;
+    assert_eq!(response.status(), StatusCode::SERVICE_UNAVAILABLE);
+    let body = axum::body::to_bytes(response.into_body(), usize::MAX).await.unwrap();
+    assert!(String::from_utf8_lossy(&body).contains("<Code>SlowDown</Code>"));
+
+    // other operations on the same bucket go through
+    let response = get(&app, "/bucket?list-type=2").await;
+    assert_eq!(response.status(), StatusCode::OK);
+    assert_eq!(state.faults.injected(), 1);
+}
+
+#[tokio::test]
+async fn test_every_and_times() {
+    let mut every_other = rule(internal_error());
+    every_other.every = Some(2);
+    every_other.times = Some(2);
+    let (app, _) = create_app(vec![every_other]).await;
+
+    let mut statuses = Vec::new();
+    for _ in 0..6 {
+        statuses.push(get(&app, "/bucket/data/file.txt").await.status().as_u16());
+    }
+    assert_eq!(statuses, vec![200, 500, 200, 500, 200, 200]);
+}
+
+#[tokio::test]
+async fn test_latency_fault() {
+    let (app, _) = create_app(vec![rule(Fault::Latency { delay_ms: 100 })]).await;
+
+    let started = Instant::now();
+    let response = get(&app, "/bucket/data/file.txt").await;
+    assert_eq!(response.status(), StatusCode::OK);
+    assert!(started.elapsed() >= Duration::from_millis(100));
+}
+
+#[tokio::test]
+async fn test_truncate_and_reset_break_the_body() {
+    let (app, _) = create_app(vec![rule(Fault::Truncate { bytes: 4 })]).await;
+
+    let response = get(&app, "/bucket/data/file.txt").await;
+    assert_eq!(response.headers()["content-length"], "10");
+    assert!(axum::body::to_bytes(response.into_body(), usize::MAX).await.is_err());
+
+    let (app, _) = create_app(vec![rule(Fault::Reset)]).await;
+    let response = get(&app, "/bucket/data/file.txt").await;
+    assert!(axum::body::to_bytes(response.into_body(), usize::MAX).await.is_err());
+}
+
+#[tokio::test]
+async fn test_slow_loris_sends_the_whole_body() {
+    let (app, _) = create_app(vec![rule(Fault::SlowLoris {
+        chunk_bytes: 5,
+        interval_ms: 50,
+    })])
+    .await;
+
+    let started = Instant::now();
+    let response = get(&app, "/bucket/data/file.txt").await;
+    let body = axum::body::to_bytes(response.into_body(), usize::MAX).await.unwrap();
+    assert_eq!(&body[..], b"0123456789");
+    assert!(started.elapsed() >= Duration::from_millis(50));
+}
+
+#[tokio::test]
+async fn test_manage_faults_through_admin_api() {
+    let (app, _) = create_app(Vec::new()).await;
+
+    let response = app
+        .clone()
+        .oneshot(admin("POST", "/_six7/faults", r#"{"fault":"error","operation":"PutObject"}"#))
+        .await
+        .unwrap();
+    assert_eq!(response.status(), StatusCode::CREATED);
+    let body = axum::body::to_bytes(response.into_body(), usize::MAX).await.unwrap();
+    let created: serde_json::Value = serde_json::from_slice(&body).unwrap();
+    assert_eq!(created["code"], "InternalError");
+    let id = created["id"].as_u64().unwrap();
+
+    let response = app
+        .clone()
+        .oneshot(
+            Request::builder()
+                .method("PUT")
+                .uri("/bucket/new.txt")
+                .body(Body::from("x"))
+                .unwrap(),
+        )
+        .await
+        .unwrap();
+    assert_eq!(response.status(), StatusCode::INTERNAL_SERVER_ERROR);
+
+    // admin requests are never faulted
+    let response = app.clone().oneshot(admin("GET", "/_six7/faults", "")).await.unwrap();
+    let body = axum::body::to_bytes(response.into_body(), usize::MAX).await.unwrap();
+    let listed: serde_json::Value = serde_json::from_slice(&body).unwrap();
+    assert_eq!(listed[0]["fired"], 1);
+
+    let response = app
+        .clone()
+        .oneshot(admin("DELETE", &format!("/_six7/faults/{}", id), ""))
+        .await
+        .unwrap();
+    assert_eq!(response.status(), StatusCode::NO_CONTENT);
+    let response = app
+        .clone()
+        .oneshot(admin("DELETE", &format!("/_six7/faults/{}", id), ""))
+        .await
+        .unwrap();
+    assert_eq!(response.status(), StatusCode::NOT_FOUND);
+
+    let response = app
+        .oneshot(admin("POST", "/_six7/faults", r#"{"fault":"error","probability":2}"#))
+        .await
+        .unwrap();
+    assert_eq!(response.status(), StatusCode::BAD_REQUEST);
+}
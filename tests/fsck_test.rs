@@ -0,0 +1,240 @@
+use axum::{
+    body::Body,
+    http::{Request, StatusCode},
+};
+use bytes::Bytes;
+use six7::config::Config;
+use six7::handlers::AppState;
+use six7::server::router;
+use six7::storage::{FsckOptions, FsckReport, MemoryStorage, Problem, Storage, StorageBackend};
+use std::path::Path;
+use std::sync::Arc;
+use std::time::Duration;
+use tempfile::TempDir;
+use tower::ServiceExt;
+
+const TOKEN: &str = "test-token";
+
+fn options(repair: bool) -> FsckOptions {
+    FsckOptions {
+        repair,
+        abandoned_after: Duration::ZERO,
+    }
+}
+
+fn problems(report: &FsckReport) -> Vec<(Problem, &str)> {
+    report.findings.iter().map(|f| (f.problem, f.path.as_str())).collect()
+}
+
+// a bucket of three objects, then edited behind six7's back
+async fn drifted(dir: &Path) -> Storage {
+    let storage = Storage::new(dir).unwrap();
+    storage.create_bucket("bucket").await.unwrap();
+    for (key, data) in [("a.txt", "one"), ("dir/b.txt", "two"), ("c.txt", "three")] {
+        storage
+            .put_object("bucket", key, Bytes::from(data), Some("text/plain".to_string()))
+            .await
+            .unwrap();
+    }
+
+    std::fs::write(dir.join("bucket/a.txt"), "ONE").unwrap();
+    std::fs::write(dir.join("bucket/dir%2F/b.txt"), "twooo").unwrap();
+    std::fs::remove_file(dir.join("bucket/c.txt")).unwrap();
+    std::fs::write(dir.join("bucket/stray.txt"), "stray").unwrap();
+    storage
+}
+
+#[tokio::test]
+async fn test_fsck_finds_and_repairs_drift() {
+    let dir = TempDir::new().unwrap();
+    let storage = drifted(dir.path()).await;
+
+    let report = storage.fsck(&options(false)).await.unwrap();
+    assert_eq!(report.keys, 4);
+    assert!(!report.repaired);
+    assert_eq!(
+        problems(&report),
+        [
+            (Problem::Mismatch, ".six7/meta/bucket/a.txt.json"),
+            (Problem::OrphanedMetadata, ".six7/meta/bucket/c.txt.json"),
+            (Problem::Mismatch, ".six7/meta/bucket/dir%2F/b.txt.json"),
+            (Problem::OrphanedData, "bucket/stray.txt"),
+        ]
+    );
+    assert!(report.findings[0].detail.contains("etag"));
+    assert!(report.findings[2].detail.contains("size 3 in metadata, 5 on disk"));
+    assert_eq!(report.findings[3].key.as_deref(), Some("stray.txt"));
+
+    // nothing was touched
+    assert_eq!(storage.fsck(&options(false)).await.unwrap().findings.len(), 4);
+
+    let report = storage.fsck(&options(true)).await.unwrap();
+    assert!(report.repaired);
+    assert_eq!(report.findings.len(), 4);
+    assert!(storage.fsck(&options(false)).await.unwrap().findings.is_empty());
+
+    // metadata follows the data again, keeping what the data can't tell
+    let a = storage.head_object("bucket", "a.txt").await.unwrap();
+    assert_eq!(a.etag, format!("{:x}", md5::compute("ONE")));
+    assert_eq!(a.content_type.as_deref(), Some("text/plain"));
+    assert_eq!(storage.head_object("bucket", "dir/b.txt").await.unwrap().size, 5);
+    assert!(!dir.path().join(".six7/meta/bucket/c.txt.json").exists());
+    assert!(dir.path().join(".six7/meta/bucket/stray.txt.json").exists());
+}
+
+#[tokio::test]
+async fn test_fsck_finds_what_deleted_buckets_and_failed_writes_leave() {
+    let dir = TempDir::new().unwrap();
+    let storage = Storage::new(dir.path()).unwrap();
+    storage.create_bucket("kept").await.unwrap();
+    storage.create_bucket("gone").await.unwrap();
+    storage.put_object("gone", "a", Bytes::from("a"), None).await.unwrap();
+    storage.put_bucket_setting("gone", "cors", Some("<x/>".to_string())).await.unwrap();
+    storage.create_multipart_upload("gone", "big", None).await.unwrap();
+    let upload = storage.create_multipart_upload("kept", "big", None).await.unwrap();
+    std::fs::remove_dir_all(dir.path().join("gone")).unwrap();
+    std::fs::write(dir.path().join(".six7/tmp/leftover"), "partial").unwrap();
+
+    // nothing is old enough to count as abandoned yet, except what lost its bucket
+    let report = storage
+        .fsck(&FsckOptions {
+            repair: false,
+            abandoned_after: Duration::from_secs(3600),
+        })
+        .await
+        .unwrap();
+    let mut found: Vec<_> = problems(&report).into_iter().map(|(p, _)| p).collect();
+    found.sort_by_key(|p| p.to_string());
+    assert_eq!(
+        found,
+        [
+            Problem::AbandonedUpload,
+            Problem::OrphanedMetadata,
+            Problem::OrphanedMetadata,
+            Problem::OrphanedMetadata,
+        ]
+    );
+    assert_eq!(report.uploads, 2);
+
+    let report = storage.fsck(&options(true)).await.unwrap();
+    let abandoned: Vec<_> = report
+        .findings
+        .iter()
+        .filter(|f| f.problem == Problem::AbandonedUpload)
+        .collect();
+    assert_eq!(abandoned.len(), 2);
+    assert!(report.findings.iter().any(|f| f.problem == Problem::StagingFile));
+
+    assert!(storage.fsck(&options(false)).await.unwrap().findings.is_empty());
+    assert!(storage.list_multipart_uploads("kept").await.unwrap().is_empty());
+    assert!(storage.abort_multipart_upload("kept", "big", &upload.upload_id).await.is_err());
+    assert!(!dir.path().join(".six7/meta/gone").exists());
+    assert!(!dir.path().join(".six7/settings/gone").exists());
+    assert!(!dir.path().join(".six7/buckets/gone.json").exists());
+}
+
+#[tokio::test]
+async fn test_fsck_finds_and_moves_unregistered_files() {
+    let dir = TempDir::new().unwrap();
+    let storage = Storage::new(dir.path()).unwrap();
+    storage.create_bucket("bucket").await.unwrap();
+    storage.put_object("bucket", "taken/file.txt", Bytes::from("stored"), None).await.unwrap();
+
+    // dropped into the bucket dir by hand, as plain paths
+    let bucket = dir.path().join("bucket");
+    std::fs::create_dir_all(bucket.join("dir")).unwrap();
+    std::fs::create_dir_all(bucket.join("taken")).unwrap();
+    std::fs::write(bucket.join("dir/file.txt"), "nested").unwrap();
+    std::fs::write(bucket.join("taken/file.txt"), "duplicate").unwrap();
+    std::fs::write(bucket.join(format!("%H{}", "0".repeat(64))), "lost").unwrap();
+
+    let storage = Storage::open(dir.path()).unwrap();
+    let report = storage.fsck(&options(false)).await.unwrap();
+    let mut found = problems(&report);
+    found.sort_by_key(|(_, path)| *path);
+    assert_eq!(
+        found,
+        [
+            (Problem::UnregisteredFile, "bucket/%H0000000000000000000000000000000000000000000000000000000000000000"),
+            (Problem::UnregisteredFile, "bucket/dir/file.txt"),
+            (Problem::UnregisteredFile, "bucket/taken/file.txt"),
+        ]
+    );
+    let nested = report.findings.iter().find(|f| f.path == "bucket/dir/file.txt").unwrap();
+    assert_eq!(nested.key.as_deref(), Some("dir/file.txt"));
+    assert_eq!(nested.detail, "not where key dir/file.txt is stored");
+
+    let report = storage.fsck(&options(true)).await.unwrap();
+    assert!(report.repaired);
+    assert!(problems(&report).contains(&(Problem::OrphanedData, "bucket/dir%2F/file.txt")));
+
+    // the nested file is an object now; what couldn't be one is set aside
+    assert_eq!(storage.get_object("bucket", "dir/file.txt").await.unwrap(), Bytes::from("nested"));
+    assert_eq!(storage.get_object("bucket", "taken/file.txt").await.unwrap(), Bytes::from("stored"));
+    let quarantine = dir.path().join(".six7/quarantine/bucket");
+    assert_eq!(std::fs::read(quarantine.join("taken/file.txt")).unwrap(), b"duplicate");
+    assert!(quarantine.join(format!("%H{}", "0".repeat(64))).exists());
+    assert!(!bucket.join("dir").exists());
+
+    assert!(storage.fsck(&options(false)).await.unwrap().findings.is_empty());
+}
+
+#[tokio::test]
+async fn test_open_leaves_staging_alone() {
+    let dir = TempDir::new().unwrap();
+    Storage::new(dir.path()).unwrap();
+    std::fs::write(dir.path().join(".six7/tmp/leftover"), "partial").unwrap();
+
+    let storage = Storage::open(dir.path()).unwrap();
+    let report = storage.fsck(&options(false)).await.unwrap();
+    assert_eq!(problems(&report), [(Problem::StagingFile, ".six7/tmp/leftover")]);
+
+    assert!(Storage::open(dir.path().join("missing")).is_err());
+}
+
+#[tokio::test]
+async fn test_fsck_admin_endpoint() {
+    let dir = TempDir::new().unwrap();
+    let storage = drifted(dir.path()).await;
+    let mut config = Config::default();
+    config.admin.enabled = true;
+    config.admin.token = Some(TOKEN.to_string());
+    let app = router(Arc::new(AppState::from_parts(Arc::new(storage), config)));
+
+    let fsck = |method: &str| {
+        Request::builder()
+            .method(method)
+            .uri("/_six7/fsck?abandoned_after=0")
+            .header("authorization", format!("Bearer {}", TOKEN))
+            .body(Body::empty())
+            .unwrap()
+    };
+    let report = |response: axum::response::Response| async move {
+        assert_eq!(response.status(), StatusCode::OK);
+        let body = axum::body::to_bytes(response.into_body(), usize::MAX).await.unwrap();
+        serde_json::from_slice::<FsckReport>(&body).unwrap()
+    };
+
+    let checked = report(app.clone().oneshot(fsck("GET")).await.unwrap()).await;
+    assert_eq!(checked.findings.len(), 4);
+    assert!(!checked.repaired);
+
+    let repaired = report(app.clone().oneshot(fsck("POST")).await.unwrap()).await;
+    assert_eq!(repaired.findings.len(), 4);
+    assert!(repaired.repaired);
+
+    let clean = report(app.clone().oneshot(fsck("GET")).await.unwrap()).await;
+    assert!(clean.findings.is_empty());
+
+    let unauthorized = Request::builder().uri("/_six7/fsck").body(Body::empty()).unwrap();
+    let response = app.oneshot(unauthorized).await.unwrap();
+    assert_eq!(response.status(), StatusCode::UNAUTHORIZED);
+}
+
+#[tokio::test]
+async fn test_fsck_in_memory_finds_nothing() {
+    let storage = MemoryStorage::new();
+    storage.create_bucket("bucket").await.unwrap();
+    let report = storage.fsck(&options(true)).await.unwrap();
+    assert!(report.findings.is_empty());
+}
@@ -0,0 +1,131 @@
+use axum::{
+    body::Body,
+    http::{Request, StatusCode},
+    Router,
+};
+use six7::config::Config;
+use six7::faults::{Fault, FaultRule};
+use six7::handlers::AppState;
+use six7::server::{router, RunningServer};
+use six7::storage::{MemoryStorage, Storage};
+use six7::Server;
+use std::sync::Arc;
+use std::time::{Duration, Instant};
+use tempfile::TempDir;
+use tower::ServiceExt;
+
+async fn get(app: &Router, uri: &str) -> (StatusCode, serde_json::Value) {
+    let response = app
+        .clone()
+        .oneshot(Request::builder().uri(uri).body(Body::empty()).unwrap())
+        .await
+        .unwrap();
+    let status = response.status();
+    let body = axum::body::to_bytes(response.into_body(), usize::MAX).await.unwrap();
+    (status, serde_json::from_slice(&body).unwrap())
+}
+
+// a server whose GETs under slow/ take delay_ms to answer
+async fn start_slow(delay_ms: u64, drain_secs: u64) -> RunningServer {
+    let mut config = Config::default();
+    config.server.drain_secs = drain_secs;
+    config.faults.push(FaultRule {
+        bucket: None,
+        key: Some("slow/*".to_string()),
+        operation: Some("GetObject".to_string()),
+        probability: None,
+        every: None,
+        times: None,
+        fault: Fault::Latency { delay_ms },
+    });
+    Server::builder()
+        .config(config)
+        .port(0)
+        .bucket("bucket")
+        .in_memory()
+        .spawn()
+        .await
+        .unwrap()
+}
+
+#[tokio::test]
+async fn test_probes_need_no_admin_api() {
+    // the admin api is disabled by default
+    let app = router(Arc::new(AppState::new(MemoryStorage::new())));
+
+    let (status, body) = get(&app, "/_six7/health").await;
+    assert_eq!(status, StatusCode::OK);
+    assert_eq!(body["status"], "ok");
+
+    let (status, body) = get(&app, "/_six7/ready").await;
+    assert_eq!(status, StatusCode::OK);
+    assert_eq!(body["status"], "ready");
+
+    let (status, body) = get(&app, "/_six7/version").await;
+    assert_eq!(status, StatusCode::OK);
+    assert_eq!(body["name"], "six7");
+    assert_eq!(body["version"], env!("CARGO_PKG_VERSION"));
+
+    // everything else still is
+    let response = app
+        .oneshot(Request::builder().uri("/_six7/config").body(Body::empty()).unwrap())
+        .await
+        .unwrap();
+    assert_eq!(response.status(), StatusCode::NOT_FOUND);
+}
+
+#[tokio::test]
+async fn test_ready_needs_writable_storage() {
+    let dir = TempDir::new().unwrap();
+    let app = router(Arc::new(AppState::new(Storage::new(dir.path()).unwrap())));
+    let (status, _) = get(&app, "/_six7/ready").await;
+    assert_eq!(status, StatusCode::OK);
+    // no probe is left behind
+    assert_eq!(std::fs::read_dir(dir.path().join(".six7/tmp")).unwrap().count(), 0);
+
+    // nowhere to write to
+    std::fs::remove_dir_all(dir.path().join(".six7")).unwrap();
+    let (status, body) = get(&app, "/_six7/ready").await;
+    assert_eq!(status, StatusCode::SERVICE_UNAVAILABLE);
+    assert_eq!(body["status"], "unavailable");
+
+    // still alive, though
+    let (status, _) = get(&app, "/_six7/health").await;
+    assert_eq!(status, StatusCode::OK);
+}
+
+#[tokio::test]
+async fn test_shutdown_lets_requests_finish() {
+    let server = start_slow(500, 30).await;
+    let url = format!("{}/bucket/slow/a.txt", server.url());
+    let client = reqwest::Client::new();
+    client.put(&url).body("hello").send().await.unwrap();
+
+    let request = tokio::spawn(async move {
+        let response = client.get(&url).send().await.unwrap();
+        (response.status(), response.text().await.unwrap())
+    });
+    tokio::time::sleep(Duration::from_millis(100)).await;
+    server.shutdown().await.unwrap();
+
+    let (status, body) = request.await.unwrap();
+    assert_eq!(status, 200);
+    assert_eq!(body, "hello");
+}
+
+#[tokio::test]
+async fn test_shutdown_gives_up_after_the_drain_timeout() {
+    let server = start_slow(30_000, 1).await;
+    let url = format!("{}/bucket/slow/a.txt", server.url());
+    let client = reqwest::Client::new();
+    client.put(&url).body("hello").send().await.unwrap();
+
+    tokio::spawn(async move { client.get(&url).send().await });
+    tokio::time::sleep(Duration::from_millis(100)).await;
+
+    let started = Instant::now();
+    server.shutdown().await.unwrap();
+    let took = started.elapsed();
+    assert!(took >= Duration::from_millis(900), "{:?}", took);
+    assert!(took < Duration::from_secs(10), "{:?}", took);
+}
@@ -4,57 +4,12 @@ use axum::{
     Router,
 };
 use bytes::Bytes;
-use six7::{config::Config, handlers::AppState, storage::Storage};
+use six7::{handlers::AppState, server::router, storage::MemoryStorage};
 use std::sync::Arc;
-use tempfile::{NamedTempFile, TempDir};
 use tower::ServiceExt;
-use std::io::Write;
 
 fn create_test_app() -> Router {
-    let temp_dir = TempDir::new().unwrap();
-    let yaml_content = format!(
-        r#"
-server:
-  host: "127.0.0.1"
-  port: 9000
-
-storage:
-  path: "{}"
-
-buckets:
-  - name: "test-bucket"
-    access_key: "minioadmin"
-    secret_key: "minioadmin"
-    region: "us-east-1"
-"#,
-        temp_dir.path().display()
-    );
-
-    let mut temp_file = NamedTempFile::new().unwrap();
-    temp_file.write_all(yaml_content.as_bytes()).unwrap();
-    temp_file.flush().unwrap();
-
-    let config = Config::load(temp_file.path()).unwrap();
-    let storage = Storage::new(&config.storage.path).unwrap();
-
-    let state = Arc::new(AppState { storage });
-
-    use axum::routing::{delete, get, put};
-    use tower_http::cors::{Any, CorsLayer};
-
-    let cors = CorsLayer::new()
-        .allow_origin(Any)
-        .allow_methods(Any)
-        .allow_headers(Any);
-
-    Router::new()
-        .route("/", get(six7::handlers::list_buckets))
-        .route("/{bucket}", put(six7::handlers::create_bucket))
-        .route("/{bucket}/{*key}", put(six7::handlers::put_object))
-        .route("/{bucket}/{*key}", get(six7::handlers::get_object))
-        .route("/{bucket}/{*key}", delete(six7::handlers::delete_object))
-        .layer(cors)
-        .with_state(state)
+    router(Arc::new(AppState::new(MemoryStorage::new())))
 }
 
 #[tokio::test]
@@ -193,3 +148,68 @@ async fn test_delete_object() {
         .unwrap();
     assert_eq!(response.status(), StatusCode::NOT_FOUND);
 }
+
+#[tokio::test]
+async fn test_create_bucket_invalid_name() {
+    let app = create_test_app();
+
+    let response = app
+        .oneshot(
+            Request::builder()
+                .method("PUT")
+                .uri("/Invalid_Bucket")
+                .body(Body::empty())
+                .unwrap(),
+        )
+        .await
+        .unwrap();
+
+    assert_eq!(response.status(), StatusCode::BAD_REQUEST);
+    let body = axum::body::to_bytes(response.into_body(), usize::MAX).await.unwrap();
+    assert!(String::from_utf8_lossy(&body).contains("<Code>InvalidBucketName</Code>"));
+}
+
+#[tokio::test]
+async fn test_virtual_hosted_style() {
+    let app = create_test_app();
+
+    let request = |method: &str, host: &str, uri: &str, body: &'static str| {
+        Request::builder()
+            .method(method)
+            .uri(uri)
+            .header("host", host)
+            .body(Body::from(body))
+            .unwrap()
+    };
+
+    let response = app
+        .clone()
+        .oneshot(request("PUT", "vhost-bucket.localhost:4040", "/", ""))
+        .await
+        .unwrap();
+    assert_eq!(response.status(), StatusCode::OK);
+
+    let response = app
+        .clone()
+        .oneshot(request("PUT", "vhost-bucket.localhost:4040", "/dir/file.txt?x-id=PutObject", "hello"))
+        .await
+        .unwrap();
+    assert_eq!(response.status(), StatusCode::OK);
+
+    // the same object is reachable path style
+    let response = app
+        .clone()
+        .oneshot(request("GET", "localhost:4040", "/vhost-bucket/dir/file.txt", ""))
+        .await
+        .unwrap();
+    assert_eq!(response.status(), StatusCode::OK);
+    let body = axum::body::to_bytes(response.into_body(), usize::MAX).await.unwrap();
+    assert_eq!(body, Bytes::from("hello"));
+
+    // hosts outside the base domains stay path style
+    let response = app
+        .oneshot(request("GET", "example.com", "/vhost-bucket/dir/file.txt", ""))
+        .await
+        .unwrap();
+    assert_eq!(response.status(), StatusCode::OK);
+}
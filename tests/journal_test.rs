@@ -0,0 +1,227 @@
+mod common;
+
+use axum::{
+    body::Body,
+    http::{Request, StatusCode},
+    Router,
+};
+use common::admin;
+use six7::config::Config;
+use six7::faults::{Fault, FaultRule};
+use six7::handlers::AppState;
+use six7::journal::{self, replay, JournalEntry, JournalFilter};
+use six7::storage::MemoryStorage;
+use six7::Server;
+use std::sync::Arc;
+use tempfile::TempDir;
+use tower::ServiceExt;
+
+async fn create_app(configure: impl FnOnce(&mut Config)) -> (Router, Arc<AppState>) {
+    let mut config = common::admin_config();
+    config.journal.enabled = true;
+    configure(&mut config);
+    common::create_app(config, MemoryStorage::new()).await
+}
+
+async fn send(app: &Router, method: &str, uri: &str, body: &'static str) -> StatusCode {
+    let response = app
+        .clone()
+        .oneshot(Request::builder().method(method).uri(uri).body(Body::from(body)).unwrap())
+        .await
+        .unwrap();
+    let status = response.status();
+    // the entry is recorded once the response body has been sent
+    axum::body::to_bytes(response.into_body(), usize::MAX).await.unwrap();
+    status
+}
+
+async fn query(app: &Router, query: &str) -> Vec<JournalEntry> {
+    let response = app
+        .clone()
+        .oneshot(admin("GET", &format!("/_six7/journal{}", query), ""))
+        .await
+        .unwrap();
+    assert_eq!(response.status(), StatusCode::OK);
+    let body = axum::body::to_bytes(response.into_body(), usize::MAX).await.unwrap();
+    serde_json::from_slice(&body).unwrap()
+}
+
+#[tokio::test]
+async fn test_records_requests() {
+    let (app, state) = create_app(|c| c.journal.body_hashes = true).await;
+
+    send(&app, "PUT", "/bucket/a.txt", "hello").await;
+    send(&app, "GET", "/bucket/a.txt", "").await;
+    send(&app, "GET", "/bucket/missing.txt", "").await;
+
+    let entries = state.journal.entries(&JournalFilter::default());
+    assert_eq!(entries.len(), 3);
+    assert_eq!(entries[0].operation, "PutObject");
+    assert_eq!(entries[0].bucket.as_deref(), Some("bucket"));
+    assert_eq!(entries[0].key.as_deref(), Some("a.txt"));
+    assert_eq!(entries[0].request_bytes, 5);
+    assert_eq!(
+        entries[0].request_sha256.as_deref(),
+        Some("2cf24dba5fb0a30e26e83b2ac5b9e29e1b161e5c1fa7425e73043362938b9824")
+    );
+    assert_eq!(entries[1].response_bytes, 5);
+    assert_eq!(entries[1].response_sha256, entries[0].request_sha256);
+    assert_eq!(entries[2].status, 404);
+    assert!(entries.windows(2).all(|w| w[0].seq < w[1].seq));
+}
+
+#[tokio::test]
+async fn test_disabled_by_default() {
+    let (app, state) = create_app(|c| c.journal.enabled = false).await;
+
+    send(&app, "PUT", "/bucket/a.txt", "hello").await;
+    assert!(state.journal.entries(&JournalFilter::default()).is_empty());
+}
+
+#[tokio::test]
+async fn test_query_through_admin_api() {
+    let (app, _) = create_app(|_| {}).await;
+
+    send(&app, "PUT", "/bucket/x", "1").await;
+    send(&app, "PUT", "/bucket/y", "2").await;
+    send(&app, "GET", "/bucket/x", "").await;
+
+    let puts = query(&app, "?operation=PutObject&key=x").await;
+    assert_eq!(puts.len(), 1);
+    assert_eq!(puts[0].uri, "/bucket/x");
+
+    let since = query(&app, &format!("?since={}", puts[0].seq)).await;
+    assert_eq!(since.len(), 2);
+
+    // admin requests aren't journaled
+    assert_eq!(query(&app, "").await.len(), 3);
+
+    let response = app.clone().oneshot(admin("DELETE", "/_six7/journal", "")).await.unwrap();
+    assert_eq!(response.status(), StatusCode::NO_CONTENT);
+    assert!(query(&app, "").await.is_empty());
+}
+
+#[tokio::test]
+async fn test_records_injected_faults() {
+    let (app, state) = create_app(|c| {
+        c.faults.push(FaultRule {
+            bucket: None,
+            key: None,
+            operation: Some("GetObject".to_string()),
+            probability: None,
+            every: None,
+            times: None,
+            fault: Fault::Error {
+                code: "SlowDown".to_string(),
+            },
+        })
+    })
+    .await;
+
+    send(&app, "GET", "/bucket/a.txt", "").await;
+    let entries = state.journal.entries(&JournalFilter::default());
+    assert_eq!(entries[0].status, 503);
+}
+
+#[tokio::test]
+async fn test_memory_limit() {
+    let (app, state) = create_app(|c| c.journal.limit = 2).await;
+
+    for _ in 0..4 {
+        send(&app, "HEAD", "/bucket", "").await;
+    }
+    let entries = state.journal.entries(&JournalFilter::default());
+    assert_eq!(entries.iter().map(|e| e.seq).collect::<Vec<_>>(), vec![3, 4]);
+}
+
+#[tokio::test]
+async fn test_write_and_replay_journal() {
+    let dir = TempDir::new().unwrap();
+    let path = dir.path().join("journal.jsonl");
+
+    let mut config = Config::default();
+    config.journal.enabled = true;
+    config.journal.path = Some(path.to_string_lossy().into_owned());
+    config.journal.request_bodies = true;
+    config.journal.body_hashes = true;
+    let recorded = Server::builder()
+        .config(config)
+        .port(0)
+        .bucket("bucket")
+        .storage(MemoryStorage::new())
+        .spawn()
+        .await
+        .unwrap();
+
+    let client = reqwest::Client::new();
+    let url = recorded.url();
+    client.put(format!("{}/bucket/plain.txt", url)).body("plain").send().await.unwrap();
+
+    let created = client
+        .post(format!("{}/bucket/big.bin?uploads", url))
+        .send()
+        .await
+        .unwrap()
+        .text()
+        .await
+        .unwrap();
+    let upload_id = six7::xml::text(&created, "UploadId").unwrap();
+    let part = client
+        .put(format!("{}/bucket/big.bin?partNumber=1&uploadId={}", url, upload_id))
+        .body("part one")
+        .send()
+        .await
+        .unwrap();
+    let etag = part.headers()["etag"].to_str().unwrap().to_string();
+    let complete = format!(
+        "<CompleteMultipartUpload><Part><PartNumber>1</PartNumber><ETag>{}</ETag></Part></CompleteMultipartUpload>",
+        etag
+    );
+    let response = client
+        .post(format!("{}/bucket/big.bin?uploadId={}", url, upload_id))
+        .body(complete)
+        .send()
+        .await
+        .unwrap();
+    assert_eq!(response.status(), 200);
+    response.bytes().await.unwrap();
+    let response = client.get(format!("{}/bucket/big.bin", url)).send().await.unwrap();
+    assert_eq!(response.text().await.unwrap(), "part one");
+    recorded.shutdown().await.unwrap();
+
+    let entries = journal::read(&path).unwrap();
+    let operations: Vec<&str> = entries.iter().map(|e| e.operation.as_str()).collect();
+    assert_eq!(
+        operations,
+        vec![
+            "PutObject",
+            "CreateMultipartUpload",
+            "UploadPart",
+            "CompleteMultipartUpload",
+            "GetObject"
+        ]
+    );
+
+    let fresh = Server::builder().bucket("bucket").spawn().await.unwrap();
+    let report = replay::replay(&entries, &fresh.url()).await.unwrap();
+    assert_eq!(report.replayed, 5);
+    assert!(report.is_clean(), "{:?}", report);
+
+    let response = client.get(format!("{}/bucket/plain.txt", fresh.url())).send().await.unwrap();
+    assert_eq!(response.text().await.unwrap(), "plain");
+}
+
+#[tokio::test]
+async fn test_replay_reports_mismatches() {
+    let (app, state) = create_app(|_| {}).await;
+    send(&app, "PUT", "/bucket/a.txt", "").await;
+    send(&app, "GET", "/bucket/a.txt", "").await;
+    let entries = state.journal.entries(&JournalFilter::default());
+
+    // no bucket on the fresh server, so both requests fail
+    let fresh = Server::builder().spawn().await.unwrap();
+    let report = replay::replay(&entries, &fresh.url()).await.unwrap();
+    assert_eq!(report.mismatches.len(), 2);
+    assert_eq!(report.mismatches[0].expected, "status 200");
+    assert_eq!(report.mismatches[0].actual, "status 404");
+}
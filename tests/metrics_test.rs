@@ -0,0 +1,99 @@
+mod common;
+
+use axum::{body::Body, http::StatusCode, Router};
+use common::{body_string, create_app, send};
+use six7::config::Config;
+use six7::faults::{Fault, FaultRule};
+use six7::storage::MemoryStorage;
+
+async fn scrape(app: &Router) -> String {
+    let response = send(app, "GET", "/metrics", Body::empty()).await;
+    assert_eq!(response.status(), StatusCode::OK);
+    assert_eq!(response.headers()["content-type"], six7::metrics::CONTENT_TYPE);
+    body_string(response).await
+}
+
+// the value of the sample with exactly this name and these labels
+fn value(metrics: &str, sample: &str) -> Option<f64> {
+    metrics
+        .lines()
+        .find_map(|line| line.strip_prefix(sample)?.strip_prefix(' ')?.parse().ok())
+}
+
+#[tokio::test]
+async fn test_request_counts_latency_and_bytes() {
+    let (app, _) = create_app(Config::default(), MemoryStorage::new()).await;
+
+    for _ in 0..2 {
+        let response = send(&app, "PUT", "/bucket/a.txt", "hello").await;
+        body_string(response).await;
+    }
+    body_string(send(&app, "GET", "/bucket/a.txt", Body::empty()).await).await;
+    body_string(send(&app, "GET", "/bucket/missing.txt", Body::empty()).await).await;
+
+    let metrics = scrape(&app).await;
+    let put = r#"operation="PutObject",bucket="bucket",status="200""#;
+    assert_eq!(value(&metrics, &format!("six7_requests_total{{{}}}", put)), Some(2.0));
+    assert_eq!(value(&metrics, &format!("six7_received_bytes_total{{{}}}", put)), Some(10.0));
+    assert_eq!(value(&metrics, &format!("six7_request_duration_seconds_count{{{}}}", put)), Some(2.0));
+    let inf = format!("six7_request_duration_seconds_bucket{{{},le=\"+Inf\"}}", put);
+    assert_eq!(value(&metrics, &inf), Some(2.0));
+
+    let get = r#"operation="GetObject",bucket="bucket",status="200""#;
+    assert_eq!(value(&metrics, &format!("six7_sent_bytes_total{{{}}}", get)), Some(5.0));
+    let missing = r#"six7_requests_total{operation="GetObject",bucket="bucket",status="404"}"#;
+    assert_eq!(value(&metrics, missing), Some(1.0));
+
+    // scrapes aren't s3 requests
+    assert!(!metrics.contains(r#"bucket="metrics""#));
+    assert!(metrics.contains("# TYPE six7_request_duration_seconds histogram"));
+}
+
+#[tokio::test]
+async fn test_bucket_gauges_and_faults() {
+    let mut config = Config::default();
+    config.faults.push(FaultRule {
+        bucket: None,
+        key: Some("broken/*".to_string()),
+        operation: None,
+        probability: None,
+        every: None,
+        times: None,
+        fault: Fault::Error {
+            code: "SlowDown".to_string(),
+        },
+    });
+    let (app, _) = create_app(config, MemoryStorage::new()).await;
+
+    send(&app, "PUT", "/bucket/a.txt", "hello").await;
+    send(&app, "PUT", "/bucket/b.txt", "hi").await;
+    send(&app, "POST", "/bucket/big.bin?uploads", Body::empty()).await;
+    send(&app, "GET", "/bucket/broken/a", Body::empty()).await;
+
+    let metrics = scrape(&app).await;
+    assert_eq!(value(&metrics, r#"six7_bucket_objects{bucket="bucket"}"#), Some(2.0));
+    assert_eq!(value(&metrics, r#"six7_bucket_bytes{bucket="bucket"}"#), Some(7.0));
+    assert_eq!(value(&metrics, r#"six7_multipart_uploads{bucket="bucket"}"#), Some(1.0));
+    let faults = r#"six7_faults_injected_total{operation="GetObject",fault="error"}"#;
+    assert_eq!(value(&metrics, faults), Some(1.0));
+    let faulted = r#"six7_requests_total{operation="GetObject",bucket="bucket",status="503"}"#;
+    assert_eq!(value(&metrics, faulted), Some(1.0));
+}
+
+#[tokio::test]
+async fn test_metrics_path_and_disabling() {
+    let mut config = Config::default();
+    config.metrics.path = "/_six7/metrics".to_string();
+    let (app, state) = create_app(config.clone(), MemoryStorage::new()).await;
+
+    // /metrics is a bucket again
+    let response = send(&app, "GET", "/metrics", Body::empty()).await;
+    assert_eq!(response.status(), StatusCode::NOT_FOUND);
+    let response = send(&app, "GET", "/_six7/metrics", Body::empty()).await;
+    assert_eq!(response.status(), StatusCode::OK);
+
+    config.metrics.enabled = false;
+    state.set_config(config);
+    let response = send(&app, "GET", "/_six7/metrics", Body::empty()).await;
+    assert_ne!(response.status(), StatusCode::OK);
+}
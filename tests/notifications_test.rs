@@ -0,0 +1,310 @@
+mod common;
+
+use axum::{
+    body::Body,
+    extract::State,
+    http::{Request, StatusCode},
+    routing::post,
+    Router,
+};
+use common::{body_string, send};
+use serde_json::Value;
+use six7::config::{Config, NotificationTarget};
+use six7::notifications::{Event, EventName, NotificationConfiguration};
+use six7::storage::MemoryStorage;
+use std::sync::atomic::{AtomicUsize, Ordering};
+use std::sync::{Arc, Mutex};
+use std::time::Duration;
+use tempfile::TempDir;
+use tower::ServiceExt;
+
+const TOPIC: &str = "arn:aws:sns:us-east-1:000000000000:uploads";
+
+// a webhook that records what it's sent, failing the first few deliveries
+#[derive(Default)]
+struct Hook {
+    received: Mutex<Vec<Value>>,
+    failures: AtomicUsize,
+}
+
+async fn receive(State(hook): State<Arc<Hook>>, body: String) -> StatusCode {
+    if hook
+        .failures
+        .fetch_update(Ordering::SeqCst, Ordering::SeqCst, |n| n.checked_sub(1))
+        .is_ok()
+    {
+        return StatusCode::INTERNAL_SERVER_ERROR;
+    }
+    hook.received.lock().unwrap().push(serde_json::from_str(&body).unwrap());
+    StatusCode::OK
+}
+
+async fn start_hook(failures: usize) -> (String, Arc<Hook>) {
+    let hook = Arc::new(Hook {
+        failures: AtomicUsize::new(failures),
+        ..Hook::default()
+    });
+    let app = Router::new().route("/events", post(receive)).with_state(hook.clone());
+    let listener = tokio::net::TcpListener::bind("127.0.0.1:0").await.unwrap();
+    let url = format!("http://{}/events", listener.local_addr().unwrap());
+    tokio::spawn(async move { axum::serve(listener, app).await });
+    (url, hook)
+}
+
+async fn create_app(config: Config) -> Router {
+    let (app, state) = common::create_app(config, MemoryStorage::new()).await;
+    state
+        .storage
+        .put_object("bucket", "source.txt", "source".into(), None)
+        .await
+        .unwrap();
+    app
+}
+
+fn config_with_target(url: &str) -> Config {
+    let mut config = Config::default();
+    config.notifications.targets.push(NotificationTarget {
+        arn: TOPIC.to_string(),
+        url: url.to_string(),
+    });
+    config.notifications.backoff_ms = 10;
+    config
+}
+
+fn topic_configuration(destination: &str, events: &[&str], filters: &[(&str, &str)]) -> String {
+    let events: String = events.iter().map(|e| format!("<Event>{}</Event>", e)).collect();
+    let filters: String = filters
+        .iter()
+        .map(|(name, value)| format!("<FilterRule><Name>{}</Name><Value>{}</Value></FilterRule>", name, value))
+        .collect();
+    format!(
+        r#"<NotificationConfiguration xmlns="http://s3.amazonaws.com/doc/2006-03-01/">
+  <TopicConfiguration>
+    <Id>uploads</Id>
+    <Topic>{}</Topic>
+    {}
+    <Filter><S3Key>{}</S3Key></Filter>
+  </TopicConfiguration>
+</NotificationConfiguration>"#,
+        destination, events, filters
+    )
+}
+
+// deliveries happen in the background; wait until the hook has seen this many
+async fn wait_for(hook: &Hook, count: usize) -> Vec<Value> {
+    for _ in 0..200 {
+        let received = hook.received.lock().unwrap().clone();
+        if received.len() >= count {
+            return received;
+        }
+        tokio::time::sleep(Duration::from_millis(10)).await;
+    }
+    panic!("expected {} events, got {:?}", count, hook.received.lock().unwrap());
+}
+
+fn record(message: &Value) -> &Value {
+    &message["Records"][0]
+}
+
+#[tokio::test]
+async fn test_events_from_put_copy_multipart_and_delete() {
+    let (url, hook) = start_hook(0).await;
+    let app = create_app(config_with_target(&url)).await;
+
+    let configuration = topic_configuration(TOPIC, &["s3:ObjectCreated:*", "s3:ObjectRemoved:*"], &[]);
+    let response = send(&app, "PUT", "/bucket?notification", configuration).await;
+    assert_eq!(response.status(), StatusCode::OK);
+
+    let response = send(&app, "PUT", "/bucket/photos/my%20cat.jpg", "meow").await;
+    assert_eq!(response.status(), StatusCode::OK);
+    let request_id = response.headers()["x-amz-request-id"].to_str().unwrap().to_string();
+    let received = wait_for(&hook, 1).await;
+
+    let put = record(&received[0]);
+    assert_eq!(put["eventVersion"], "2.1");
+    assert_eq!(put["eventSource"], "aws:s3");
+    assert_eq!(put["awsRegion"], "us-east-1");
+    assert_eq!(put["eventName"], "ObjectCreated:Put");
+    assert_eq!(put["responseElements"]["x-amz-request-id"], request_id.as_str());
+    assert_eq!(put["s3"]["configurationId"], "uploads");
+    assert_eq!(put["s3"]["bucket"]["name"], "bucket");
+    assert_eq!(put["s3"]["bucket"]["arn"], "arn:aws:s3:::bucket");
+    assert_eq!(put["s3"]["object"]["key"], "photos/my+cat.jpg");
+    assert_eq!(put["s3"]["object"]["size"], 4);
+    assert_eq!(put["s3"]["object"]["eTag"], format!("{:x}", md5::compute("meow")));
+
+    let copy = Request::builder()
+        .method("PUT")
+        .uri("/bucket/copy.txt")
+        .header("x-amz-copy-source", "bucket/source.txt")
+        .body(Body::empty())
+        .unwrap();
+    assert_eq!(app.clone().oneshot(copy).await.unwrap().status(), StatusCode::OK);
+    wait_for(&hook, 2).await;
+
+    let created = body_string(send(&app, "POST", "/bucket/big.bin?uploads", Body::empty()).await).await;
+    let upload_id = six7::xml::text(&created, "UploadId").unwrap();
+    let part = send(&app, "PUT", &format!("/bucket/big.bin?uploadId={}&partNumber=1", upload_id), "part").await;
+    let etag = part.headers()["etag"].to_str().unwrap().to_string();
+    let complete = format!(
+        "<CompleteMultipartUpload><Part><PartNumber>1</PartNumber><ETag>{}</ETag></Part></CompleteMultipartUpload>",
+        etag
+    );
+    let response = send(&app, "POST", &format!("/bucket/big.bin?uploadId={}", upload_id), complete).await;
+    assert_eq!(response.status(), StatusCode::OK);
+    wait_for(&hook, 3).await;
+
+    let response = send(&app, "DELETE", "/bucket/copy.txt", Body::empty()).await;
+    assert_eq!(response.status(), StatusCode::NO_CONTENT);
+    let received = wait_for(&hook, 4).await;
+
+    let mut names: Vec<String> = received
+        .iter()
+        .map(|m| record(m)["eventName"].as_str().unwrap().to_string())
+        .collect();
+    names.sort();
+    assert_eq!(
+        names,
+        [
+            "ObjectCreated:CompleteMultipartUpload",
+            "ObjectCreated:Copy",
+            "ObjectCreated:Put",
+            "ObjectRemoved:Delete"
+        ]
+    );
+    let delete = received
+        .iter()
+        .map(record)
+        .find(|r| r["eventName"] == "ObjectRemoved:Delete")
+        .unwrap();
+    assert_eq!(delete["s3"]["object"]["key"], "copy.txt");
+    assert!(delete["s3"]["object"].get("size").is_none());
+}
+
+#[tokio::test]
+async fn test_filters_and_event_types() {
+    let (url, hook) = start_hook(0).await;
+    let app = create_app(config_with_target(&url)).await;
+
+    let configuration = topic_configuration(TOPIC, &["s3:ObjectCreated:Put"], &[("prefix", "images/"), ("suffix", ".png")]);
+    send(&app, "PUT", "/bucket?notification", configuration).await;
+
+    send(&app, "PUT", "/bucket/images/a.jpg", "x").await;
+    send(&app, "PUT", "/bucket/docs/b.png", "x").await;
+    send(&app, "DELETE", "/bucket/images/old.png", Body::empty()).await;
+    send(&app, "PUT", "/bucket/images/c.png", "x").await;
+
+    let received = wait_for(&hook, 1).await;
+    tokio::time::sleep(Duration::from_millis(100)).await;
+    assert_eq!(hook.received.lock().unwrap().len(), 1);
+    assert_eq!(record(&received[0])["s3"]["object"]["key"], "images/c.png");
+}
+
+#[tokio::test]
+async fn test_configuration_round_trip_and_validation() {
+    let app = create_app(config_with_target("http://127.0.0.1:9/events")).await;
+
+    let response = send(&app, "GET", "/bucket?notification", Body::empty()).await;
+    assert_eq!(response.status(), StatusCode::OK);
+    assert!(!body_string(response).await.contains("TopicConfiguration"));
+
+    let configuration = topic_configuration(TOPIC, &["s3:ObjectCreated:*"], &[("Prefix", "logs/")]);
+    assert_eq!(send(&app, "PUT", "/bucket?notification", configuration).await.status(), StatusCode::OK);
+    let xml = body_string(send(&app, "GET", "/bucket?notification", Body::empty()).await).await;
+    assert!(xml.contains(&format!("<Topic>{}</Topic>", TOPIC)));
+    assert!(xml.contains("<Event>s3:ObjectCreated:*</Event>"));
+    assert!(xml.contains("<Name>prefix</Name><Value>logs/</Value>"));
+
+    // destinations that resolve to nothing are refused, like s3 refuses ones it can't reach
+    let unknown = topic_configuration("arn:aws:sns:us-east-1:000000000000:nope", &["s3:ObjectCreated:*"], &[]);
+    let response = send(&app, "PUT", "/bucket?notification", unknown).await;
+    assert_eq!(response.status(), StatusCode::BAD_REQUEST);
+    assert!(body_string(response).await.contains("Unable to validate"));
+
+    let bad_event = topic_configuration(TOPIC, &["s3:ObjectTouched:*"], &[]);
+    assert_eq!(send(&app, "PUT", "/bucket?notification", bad_event).await.status(), StatusCode::BAD_REQUEST);
+
+    let response = send(&app, "PUT", "/missing?notification", topic_configuration(TOPIC, &["s3:ObjectCreated:*"], &[])).await;
+    assert_eq!(response.status(), StatusCode::NOT_FOUND);
+
+    // an empty configuration turns notifications off
+    let empty = "<NotificationConfiguration/>";
+    assert_eq!(send(&app, "PUT", "/bucket?notification", empty).await.status(), StatusCode::OK);
+    let xml = body_string(send(&app, "GET", "/bucket?notification", Body::empty()).await).await;
+    assert!(!xml.contains("TopicConfiguration"));
+}
+
+#[tokio::test]
+async fn test_url_destinations_and_retries() {
+    let (url, hook) = start_hook(2).await;
+    let mut config = Config::default();
+    config.notifications.backoff_ms = 10;
+    let app = create_app(config).await;
+
+    let configuration = format!(
+        "<NotificationConfiguration><QueueConfiguration><Queue>{}</Queue><Event>s3:ObjectCreated:*</Event></QueueConfiguration></NotificationConfiguration>",
+        url
+    );
+    assert_eq!(send(&app, "PUT", "/bucket?notification", configuration).await.status(), StatusCode::OK);
+
+    send(&app, "PUT", "/bucket/retried.txt", "x").await;
+    let received = wait_for(&hook, 1).await;
+    assert_eq!(record(&received[0])["s3"]["object"]["key"], "retried.txt");
+    // rules sent without an id get one
+    assert!(!record(&received[0])["s3"]["configurationId"].as_str().unwrap().is_empty());
+}
+
+#[tokio::test]
+async fn test_undeliverable_events_go_to_the_dead_letter_log() {
+    let dir = TempDir::new().unwrap();
+    let dead_letter = dir.path().join("dead-letters.jsonl");
+    let (url, hook) = start_hook(usize::MAX).await;
+    let mut config = config_with_target(&url);
+    config.notifications.retries = 2;
+    config.notifications.dead_letter = Some(dead_letter.to_string_lossy().into_owned());
+    let app = create_app(config).await;
+
+    send(&app, "PUT", "/bucket?notification", topic_configuration(TOPIC, &["s3:ObjectCreated:*"], &[])).await;
+    send(&app, "PUT", "/bucket/lost.txt", "x").await;
+
+    let mut letters = String::new();
+    for _ in 0..200 {
+        letters = std::fs::read_to_string(&dead_letter).unwrap_or_default();
+        if !letters.is_empty() {
+            break;
+        }
+        tokio::time::sleep(Duration::from_millis(10)).await;
+    }
+    let letter: Value = serde_json::from_str(letters.lines().next().unwrap()).unwrap();
+    assert_eq!(letter["arn"], TOPIC);
+    assert_eq!(letter["attempts"], 3);
+    assert!(letter["error"].as_str().unwrap().contains("500"));
+    assert_eq!(record(&letter["message"])["s3"]["object"]["key"], "lost.txt");
+    assert!(hook.received.lock().unwrap().is_empty());
+}
+
+#[test]
+fn test_rule_matching() {
+    let configuration = NotificationConfiguration::parse(
+        &topic_configuration(TOPIC, &["s3:ObjectRemoved:*"], &[("suffix", ".log")]),
+        || "generated".to_string(),
+    )
+    .unwrap();
+    let rule = &configuration.rules[0];
+    assert_eq!(rule.id, "uploads");
+
+    let event = |name, key: &str| Event {
+        name,
+        bucket: "bucket".to_string(),
+        key: key.to_string(),
+        size: None,
+        etag: None,
+        time: chrono::Utc::now(),
+        request_id: String::new(),
+        host_id: String::new(),
+        sequencer: "0000000000000001".to_string(),
+    };
+    assert!(rule.matches(&event(EventName::ObjectRemovedDelete, "app.log")));
+    assert!(!rule.matches(&event(EventName::ObjectRemovedDelete, "app.txt")));
+    assert!(!rule.matches(&event(EventName::ObjectCreatedPut, "app.log")));
+}
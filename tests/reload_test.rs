@@ -0,0 +1,167 @@
+use axum::body::Body;
+use axum::http::Request;
+use six7::config::{BucketConfig, Config};
+use six7::handlers::AppState;
+use six7::reload::{self, ConfigDiff, Overrides};
+use six7::storage::{MemoryStorage, StorageBackend};
+use std::sync::Arc;
+use tempfile::TempDir;
+use tower::ServiceExt;
+
+const HEADER: &str = "server:\n  host: 127.0.0.1\n  port: 4040\nstorage:\n  path: ./data\n";
+
+fn write_config(path: &std::path::Path, body: &str) {
+    std::fs::write(path, format!("{}{}", HEADER, body)).unwrap();
+}
+
+fn config_with(buckets: &[(&str, &str, &str)]) -> Config {
+    let mut config = Config::default();
+    for (name, key, secret) in buckets {
+        config.buckets.push(BucketConfig {
+            access_key: key.to_string(),
+            secret_key: secret.to_string(),
+            ..BucketConfig::new(*name)
+        });
+    }
+    config
+}
+
+#[test]
+fn test_diff_between_configs() {
+    let old = config_with(&[("kept", "k", "s"), ("rotated", "k2", "s2"), ("gone", "k3", "s3")]);
+    let mut new = config_with(&[("kept", "k", "s"), ("rotated", "k2", "new"), ("fresh", "k4", "s4")]);
+    new.server.port = 9999;
+
+    let diff = ConfigDiff::between(&old, &new);
+    assert_eq!(diff.added_buckets, vec!["fresh"]);
+    assert_eq!(diff.removed_buckets, vec!["gone"]);
+    assert_eq!(diff.changed_credentials, vec!["rotated"]);
+    assert!(diff.changed_regions.is_empty());
+    assert_eq!(diff.restart_required, vec!["server.port"]);
+    assert_eq!(
+        diff.to_string(),
+        "+bucket: fresh; -bucket: gone; ~credentials: rotated; needs restart: server.port"
+    );
+
+    assert!(ConfigDiff::between(&old, &old).is_empty());
+}
+
+#[test]
+fn test_diff_covers_every_section() {
+    let old = Config::default();
+    let mut new = old.clone();
+    new.server.domains.push("s3.test".to_string());
+    new.server.drain_secs += 1;
+    new.admin.enabled = !old.admin.enabled;
+    new.journal.limit += 1;
+    new.notifications.retries += 1;
+    new.metrics.path = "/other".to_string();
+    new.access_logs.flush_secs += 1;
+    new.cors.max_age_secs = Some(600);
+    new.server.tls = Some(serde_yaml::from_str("port: 8443").unwrap());
+    new.storage.path = "./elsewhere".to_string();
+
+    let diff = ConfigDiff::between(&old, &new);
+    assert_eq!(
+        diff.changed_settings,
+        ["server.domains", "server.drain_secs", "admin", "journal", "notifications", "metrics", "access_logs", "cors"]
+    );
+    assert_eq!(diff.restart_required, ["server.tls", "storage"]);
+    assert!(diff.to_string().starts_with("~settings: server.domains, "));
+}
+
+#[tokio::test]
+async fn test_reload_creates_buckets_and_swaps_credentials() {
+    let dir = TempDir::new().unwrap();
+    let path = dir.path().join("six7.yaml");
+    write_config(&path, "buckets:\n  - name: first\n    access_key: old\n    secret_key: old-secret\n");
+
+    let initial = Config::load(&path).unwrap();
+    let storage: Arc<dyn StorageBackend> = Arc::new(MemoryStorage::new());
+    storage.create_bucket("first").await.unwrap();
+    let state = AppState::from_parts(storage, initial);
+    let overrides: Overrides = Arc::new(|_: &mut Config| {});
+
+    write_config(
+        &path,
+        "buckets:\n  - name: first\n    access_key: new\n    secret_key: new-secret\n  - name: second\n",
+    );
+
+    let diff = reload::reload(&state, &path, &overrides).await.unwrap();
+    assert_eq!(diff.added_buckets, vec!["second"]);
+    assert_eq!(diff.changed_credentials, vec!["first"]);
+
+    assert!(state.storage.bucket_exists("second").await);
+    let config = state.config();
+    assert_eq!(config.get_bucket("first").unwrap().access_key, "new");
+}
+
+#[tokio::test]
+async fn test_invalid_reload_keeps_previous_config() {
+    let dir = TempDir::new().unwrap();
+    let path = dir.path().join("six7.yaml");
+    write_config(&path, "buckets:\n  - name: first\n");
+
+    let state = AppState::from_parts(Arc::new(MemoryStorage::new()), Config::load(&path).unwrap());
+    let overrides: Overrides = Arc::new(|_: &mut Config| {});
+
+    write_config(&path, "buckets:\n  - name: Not_Valid\n");
+    assert!(reload::reload(&state, &path, &overrides).await.is_err());
+    assert!(state.config().get_bucket("first").is_some());
+}
+
+#[tokio::test]
+async fn test_overrides_apply_on_reload() {
+    let dir = TempDir::new().unwrap();
+    let path = dir.path().join("six7.yaml");
+    write_config(&path, "buckets: []\n");
+
+    let state = AppState::new(MemoryStorage::new());
+    let overrides: Overrides = Arc::new(|config: &mut Config| config.server.port = 5050);
+
+    reload::reload(&state, &path, &overrides).await.unwrap();
+    assert_eq!(state.config().server.port, 5050);
+}
+
+#[tokio::test]
+async fn test_reload_changes_cors() {
+    let dir = TempDir::new().unwrap();
+    let path = dir.path().join("six7.yaml");
+    write_config(&path, "buckets:\n  - name: first\n");
+
+    let state = Arc::new(AppState::from_parts(Arc::new(MemoryStorage::new()), Config::load(&path).unwrap()));
+    state.storage.create_bucket("first").await.unwrap();
+    let app = six7::server::router(state.clone());
+    let overrides: Overrides = Arc::new(|_: &mut Config| {});
+
+    let preflight = || {
+        Request::builder()
+            .method("OPTIONS")
+            .uri("/first/a.txt")
+            .header("origin", "https://app.test")
+            .header("access-control-request-method", "PUT")
+            .body(Body::empty())
+            .unwrap()
+    };
+    let response = app.clone().oneshot(preflight()).await.unwrap();
+    assert_eq!(response.headers()["access-control-allow-origin"], "*");
+
+    write_config(
+        &path,
+        "buckets:\n  - name: first\ncors:\n  allowed_origins: [https://other.test]\n  allowed_methods: [GET]\n",
+    );
+    let diff = reload::reload(&state, &path, &overrides).await.unwrap();
+    assert_eq!(diff.changed_settings, vec!["cors"]);
+
+    let response = app.clone().oneshot(preflight()).await.unwrap();
+    assert!(response.headers().get("access-control-allow-origin").is_none());
+
+    let request = Request::builder()
+        .uri("/first")
+        .header("origin", "https://other.test")
+        .body(Body::empty())
+        .unwrap();
+    let response = app.oneshot(request).await.unwrap();
+    assert_eq!(response.headers()["access-control-allow-origin"], "https://other.test");
+    assert_eq!(response.headers()["vary"], "origin");
+}
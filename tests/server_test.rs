@@ -0,0 +1,140 @@
+use six7::config::{BucketConfig, Config};
+use six7::server::bucket_from_host;
+use six7::storage::MemoryStorage;
+use six7::Server;
+
+#[tokio::test]
+async fn test_spawn_on_ephemeral_port() {
+    let server = Server::builder()
+        .bucket("test-bucket")
+        .spawn()
+        .await
+        .unwrap();
+
+    assert_ne!(server.addr().port(), 0);
+
+    let client = reqwest::Client::new();
+    let response = client
+        .put(format!("{}/test-bucket/hello.txt", server.url()))
+        .body("hello world")
+        .send()
+        .await
+        .unwrap();
+    assert_eq!(response.status(), 200);
+
+    let response = client
+        .get(format!("{}/test-bucket/hello.txt", server.url()))
+        .send()
+        .await
+        .unwrap();
+    assert_eq!(response.status(), 200);
+    assert_eq!(response.text().await.unwrap(), "hello world");
+
+    server.shutdown().await.unwrap();
+}
+
+#[tokio::test]
+async fn test_builder_buckets_are_configured() {
+    let mut config = Config::default();
+    config.admin.enabled = true;
+    config.admin.token = Some("token".to_string());
+    config.buckets.push(BucketConfig::new("from-config"));
+    let server = Server::builder()
+        .config(config)
+        .in_memory()
+        .port(0)
+        .bucket("from-builder")
+        .spawn()
+        .await
+        .unwrap();
+
+    let client = reqwest::Client::new();
+    let response = client
+        .post(format!("{}/_six7/reset", server.url()))
+        .bearer_auth("token")
+        .send()
+        .await
+        .unwrap();
+    assert_eq!(response.status(), 204);
+
+    let response = client
+        .put(format!("{}/from-builder/after-reset.txt", server.url()))
+        .body("still here")
+        .send()
+        .await
+        .unwrap();
+    assert_eq!(response.status(), 200);
+
+    let response = client
+        .get(format!("{}/_six7/config", server.url()))
+        .bearer_auth("token")
+        .send()
+        .await
+        .unwrap();
+    let config: serde_json::Value = serde_json::from_slice(&response.bytes().await.unwrap()).unwrap();
+    let buckets: Vec<&str> = config["buckets"]
+        .as_array()
+        .unwrap()
+        .iter()
+        .map(|b| b["name"].as_str().unwrap())
+        .collect();
+    assert_eq!(buckets, ["from-config", "from-builder"]);
+    assert_eq!(config["server"]["port"], server.addr().port());
+
+    server.shutdown().await.unwrap();
+}
+
+#[tokio::test]
+async fn test_servers_are_isolated() {
+    let first = Server::builder()
+        .bucket("test-bucket")
+        .storage(MemoryStorage::new())
+        .spawn()
+        .await
+        .unwrap();
+    let second = Server::builder()
+        .bucket("test-bucket")
+        .spawn()
+        .await
+        .unwrap();
+
+    assert_ne!(first.addr(), second.addr());
+
+    let client = reqwest::Client::new();
+    client
+        .put(format!("{}/test-bucket/only-here.txt", first.url()))
+        .body("data")
+        .send()
+        .await
+        .unwrap();
+
+    let response = client
+        .get(format!("{}/test-bucket/only-here.txt", second.url()))
+        .send()
+        .await
+        .unwrap();
+    assert_eq!(response.status(), 404);
+}
+
+#[tokio::test]
+async fn test_shutdown_releases_port() {
+    let server = Server::builder().spawn().await.unwrap();
+    let addr = server.addr();
+    server.shutdown().await.unwrap();
+
+    assert!(reqwest::get(format!("http://{}/", addr)).await.is_err());
+}
+
+#[test]
+fn test_bucket_from_host() {
+    let domains = vec!["localhost".to_string(), "*.s3.example.test".to_string()];
+
+    assert_eq!(bucket_from_host("photos.localhost:4040", &domains).as_deref(), Some("photos"));
+    assert_eq!(bucket_from_host("My.Photos.localhost", &domains).as_deref(), Some("my.photos"));
+    assert_eq!(bucket_from_host("logs.s3.example.test", &domains).as_deref(), Some("logs"));
+    assert_eq!(bucket_from_host("localhost:4040", &domains), None);
+    assert_eq!(bucket_from_host("127.0.0.1:4040", &domains), None);
+    assert_eq!(bucket_from_host("[::1]:4040", &domains), None);
+    assert_eq!(bucket_from_host("Not_A_Bucket.localhost", &domains), None);
+    assert_eq!(bucket_from_host("photos.otherlocalhost", &domains), None);
+}
@@ -0,0 +1,208 @@
+use axum::{
+    body::Body,
+    http::{Request, StatusCode},
+};
+use bytes::Bytes;
+use six7::config::Config;
+use six7::handlers::AppState;
+use six7::server::router;
+use six7::storage::{snapshot, MemoryStorage, Storage, StorageBackend};
+use std::sync::Arc;
+use tempfile::TempDir;
+use tower::ServiceExt;
+
+async fn exercise_snapshots(storage: &dyn StorageBackend) {
+    storage.create_bucket("fixtures").await.unwrap();
+    storage.put_object("fixtures", "a/one.txt", Bytes::from("one"), Some("text/plain".into())).await.unwrap();
+    storage.put_object("fixtures", "two.txt", Bytes::from("two!"), None).await.unwrap();
+    let upload = storage.create_multipart_upload("fixtures", "big", None).await.unwrap();
+
+    let info = storage.create_snapshot("baseline").await.unwrap();
+    assert_eq!(info.name, "baseline");
+    assert_eq!(info.objects, 2);
+    assert_eq!(info.bytes, 7);
+
+    // change everything after the snapshot
+    storage.put_object("fixtures", "two.txt", Bytes::from("changed"), None).await.unwrap();
+    storage.delete_object("fixtures", "a/one.txt").await.unwrap();
+    storage.abort_multipart_upload("fixtures", "big", &upload.upload_id).await.unwrap();
+    storage.create_bucket("later").await.unwrap();
+
+    storage.restore_snapshot("baseline").await.unwrap();
+
+    assert!(!storage.bucket_exists("later").await);
+    assert_eq!(storage.get_object("fixtures", "two.txt").await.unwrap(), Bytes::from("two!"));
+    assert_eq!(storage.get_object("fixtures", "a/one.txt").await.unwrap(), Bytes::from("one"));
+    let metadata = storage.head_object("fixtures", "a/one.txt").await.unwrap();
+    assert_eq!(metadata.content_type.as_deref(), Some("text/plain"));
+    assert_eq!(storage.list_multipart_uploads("fixtures").await.unwrap().len(), 1);
+
+    // restoring twice works: writing after a restore doesn't reach into the snapshot
+    storage.put_object("fixtures", "two.txt", Bytes::from("again"), None).await.unwrap();
+    storage.restore_snapshot("baseline").await.unwrap();
+    assert_eq!(storage.get_object("fixtures", "two.txt").await.unwrap(), Bytes::from("two!"));
+
+    let names: Vec<String> = storage.list_snapshots().await.unwrap().into_iter().map(|s| s.name).collect();
+    assert_eq!(names, vec!["baseline"]);
+
+    storage.delete_snapshot("baseline").await.unwrap();
+    assert!(storage.list_snapshots().await.unwrap().is_empty());
+    assert!(storage.restore_snapshot("baseline").await.is_err());
+    assert!(storage.create_snapshot("../escape").await.is_err());
+}
+
+#[tokio::test]
+async fn test_snapshots_on_disk() {
+    let temp_dir = TempDir::new().unwrap();
+    let storage = Storage::new(temp_dir.path()).unwrap();
+    exercise_snapshots(&storage).await;
+
+    // snapshots never show up as buckets
+    assert_eq!(storage.list_buckets().await.unwrap().len(), 1);
+}
+
+#[tokio::test]
+async fn test_snapshots_in_memory() {
+    exercise_snapshots(&MemoryStorage::new()).await;
+}
+
+#[cfg(unix)]
+#[tokio::test(flavor = "multi_thread", worker_threads = 4)]
+async fn test_restore_is_isolated_from_writes() {
+    let temp_dir = TempDir::new().unwrap();
+    let storage = Arc::new(Storage::new(temp_dir.path()).unwrap());
+    storage.create_bucket("fixtures").await.unwrap();
+    for i in 0..20 {
+        let key = format!("dir/{}.txt", i);
+        storage.put_object("fixtures", &key, Bytes::from("baseline"), None).await.unwrap();
+    }
+    storage.create_snapshot("baseline").await.unwrap();
+
+    let writers: Vec<_> = (0..4)
+        .map(|w| {
+            let storage = storage.clone();
+            tokio::spawn(async move {
+                for i in 0..50 {
+                    let key = format!("dir/{}.txt", i % 20);
+                    let data = Bytes::from(format!("writer {} round {}", w, i));
+                    storage.put_object("fixtures", &key, data, None).await.unwrap();
+                    // a reset may have removed it since, but never leaves part of it
+                    match storage.get_object("fixtures", &key).await {
+                        Ok(data) => assert!(data.starts_with(b"writer") || data == "baseline"),
+                        Err(e) => assert_eq!(e.kind(), std::io::ErrorKind::NotFound),
+                    }
+                }
+            })
+        })
+        .collect();
+    for _ in 0..10 {
+        storage.restore_snapshot("baseline").await.unwrap();
+        storage.reset(&["fixtures".to_string()]).await.unwrap();
+        storage.restore_snapshot("baseline").await.unwrap();
+    }
+    for writer in writers {
+        writer.await.unwrap();
+    }
+
+    // every object is whole, with metadata that matches it
+    let report = storage.fsck(&Default::default()).await.unwrap();
+    assert!(report.findings.is_empty(), "{:?}", report.findings);
+    assert_eq!(report.keys, 20);
+}
+
+#[tokio::test]
+async fn test_snapshot_hard_links_objects() {
+    use std::os::unix::fs::MetadataExt;
+
+    let temp_dir = TempDir::new().unwrap();
+    let storage = Storage::new(temp_dir.path()).unwrap();
+    storage.create_bucket("fixtures").await.unwrap();
+    storage.put_object("fixtures", "data.bin", Bytes::from(vec![7u8; 4096]), None).await.unwrap();
+
+    storage.create_snapshot("linked").await.unwrap();
+
+    let object = storage.object_path("fixtures", "data.bin").unwrap();
+    assert_eq!(std::fs::metadata(object).unwrap().nlink(), 2);
+}
+
+#[tokio::test]
+async fn test_snapshot_without_a_server() {
+    let temp_dir = TempDir::new().unwrap();
+    {
+        let storage = Storage::new(temp_dir.path()).unwrap();
+        storage.create_bucket("offline").await.unwrap();
+        storage.put_object("offline", "k", Bytes::from("v"), None).await.unwrap();
+    }
+
+    let info = snapshot::create(temp_dir.path(), "cold", chrono::Utc::now()).unwrap();
+    assert_eq!(info.objects, 1);
+    assert_eq!(snapshot::list(temp_dir.path()).unwrap().len(), 1);
+
+    std::fs::remove_dir_all(temp_dir.path().join("offline")).unwrap();
+    snapshot::restore(temp_dir.path(), "cold").unwrap();
+
+    let storage = Storage::new(temp_dir.path()).unwrap();
+    assert_eq!(storage.get_object("offline", "k").await.unwrap(), Bytes::from("v"));
+}
+
+#[tokio::test]
+async fn test_failed_restore_keeps_current_data() {
+    let temp_dir = TempDir::new().unwrap();
+    let storage = Storage::new(temp_dir.path()).unwrap();
+    storage.create_bucket("kept").await.unwrap();
+    storage.put_object("kept", "before", Bytes::from("old"), None).await.unwrap();
+    storage.create_snapshot("baseline").await.unwrap();
+    storage.put_object("kept", "after", Bytes::from("new"), None).await.unwrap();
+
+    // the copy is made in staging, so with staging unusable it fails before anything moves
+    let staging = temp_dir.path().join(".six7").join("tmp");
+    std::fs::remove_dir_all(&staging).unwrap();
+    std::fs::write(&staging, "").unwrap();
+    assert!(snapshot::restore(temp_dir.path(), "baseline").is_err());
+
+    assert_eq!(storage.get_object("kept", "before").await.unwrap(), Bytes::from("old"));
+    assert_eq!(storage.get_object("kept", "after").await.unwrap(), Bytes::from("new"));
+
+    std::fs::remove_file(&staging).unwrap();
+    std::fs::create_dir(&staging).unwrap();
+    storage.restore_snapshot("baseline").await.unwrap();
+    assert!(storage.get_object("kept", "after").await.is_err());
+    assert_eq!(std::fs::read_dir(&staging).unwrap().count(), 0);
+}
+
+#[tokio::test]
+async fn test_snapshot_admin_api() {
+    let mut config = Config::default();
+    config.admin.enabled = true;
+    config.admin.token = Some("t".to_string());
+    let storage = Arc::new(MemoryStorage::new());
+    storage.create_bucket("fixtures").await.unwrap();
+    let app = router(Arc::new(AppState::from_parts(storage.clone(), config)));
+
+    let request = |method: &str, uri: &str| {
+        Request::builder()
+            .method(method)
+            .uri(uri)
+            .header("authorization", "Bearer t")
+            .body(Body::empty())
+            .unwrap()
+    };
+
+    let response = app.clone().oneshot(request("POST", "/_six7/snapshots/empty")).await.unwrap();
+    assert_eq!(response.status(), StatusCode::CREATED);
+
+    storage.put_object("fixtures", "k", Bytes::from("v"), None).await.unwrap();
+    let response = app.clone().oneshot(request("POST", "/_six7/snapshots/empty/restore")).await.unwrap();
+    assert_eq!(response.status(), StatusCode::NO_CONTENT);
+    assert!(storage.list_objects("fixtures", None).await.unwrap().is_empty());
+
+    let response = app.clone().oneshot(request("GET", "/_six7/snapshots")).await.unwrap();
+    let body = axum::body::to_bytes(response.into_body(), usize::MAX).await.unwrap();
+    let snapshots: serde_json::Value = serde_json::from_slice(&body).unwrap();
+    assert_eq!(snapshots[0]["name"], "empty");
+
+    let response = app.clone().oneshot(request("DELETE", "/_six7/snapshots/empty")).await.unwrap();
+    assert_eq!(response.status(), StatusCode::NO_CONTENT);
+    let response = app.oneshot(request("POST", "/_six7/snapshots/empty/restore")).await.unwrap();
+    assert_eq!(response.status(), StatusCode::NOT_FOUND);
+}
@@ -0,0 +1,249 @@
+mod common;
+
+use axum::{
+    body::Body,
+    http::{Request, StatusCode},
+    Router,
+};
+use chrono::{TimeZone, TimeDelta, Utc};
+use common::{body_string, send};
+use serde_json::{json, Value};
+use six7::clock::Clock;
+use six7::config::Config;
+use six7::storage::MemoryStorage;
+use std::time::{Duration, Instant};
+use tower::ServiceExt;
+
+const QUEUE_URL: &str = "http://localhost:4040/000000000000/jobs";
+const QUEUE_ARN: &str = "arn:aws:sqs:us-east-1:000000000000:jobs";
+
+async fn create_app(clock: Clock) -> Router {
+    common::create_app(Config::default(), MemoryStorage::new().with_clock(clock)).await.0
+}
+
+// a json-protocol call, as current sdks make them
+async fn call(app: &Router, action: &str, params: Value) -> (StatusCode, Value) {
+    let request = Request::builder()
+        .method("POST")
+        .uri("/")
+        .header("host", "localhost:4040")
+        .header("content-type", "application/x-amz-json-1.0")
+        .header("x-amz-target", format!("AmazonSQS.{}", action))
+        .body(Body::from(params.to_string()))
+        .unwrap();
+    let response = app.clone().oneshot(request).await.unwrap();
+    let status = response.status();
+    (status, serde_json::from_str(&body_string(response).await).unwrap())
+}
+
+// a query-protocol call, sent to the given path
+async fn query(app: &Router, path: &str, form: &str) -> (StatusCode, String) {
+    let request = Request::builder()
+        .method("POST")
+        .uri(path)
+        .header("host", "localhost:4040")
+        .header("content-type", "application/x-www-form-urlencoded")
+        .body(Body::from(form.to_string()))
+        .unwrap();
+    let response = app.clone().oneshot(request).await.unwrap();
+    let status = response.status();
+    (status, body_string(response).await)
+}
+
+#[tokio::test]
+async fn test_queue_lifecycle() {
+    let app = create_app(Clock::new()).await;
+
+    let (status, body) = call(&app, "CreateQueue", json!({ "QueueName": "jobs" })).await;
+    assert_eq!(status, StatusCode::OK);
+    assert_eq!(body["QueueUrl"], QUEUE_URL);
+
+    // creating it again with the same attributes is fine, different ones aren't
+    let (status, _) = call(&app, "CreateQueue", json!({ "QueueName": "jobs" })).await;
+    assert_eq!(status, StatusCode::OK);
+    let params = json!({ "QueueName": "jobs", "Attributes": { "VisibilityTimeout": "5" } });
+    let (status, body) = call(&app, "CreateQueue", params).await;
+    assert_eq!(status, StatusCode::BAD_REQUEST);
+    assert_eq!(body["__type"], "com.amazonaws.sqs#QueueNameExists");
+
+    let (_, body) = call(&app, "GetQueueUrl", json!({ "QueueName": "jobs" })).await;
+    assert_eq!(body["QueueUrl"], QUEUE_URL);
+    call(&app, "CreateQueue", json!({ "QueueName": "other" })).await;
+    let (_, body) = call(&app, "ListQueues", json!({ "QueueNamePrefix": "jo" })).await;
+    assert_eq!(body["QueueUrls"], json!([QUEUE_URL]));
+
+    let params = json!({ "QueueUrl": QUEUE_URL, "AttributeNames": ["All"] });
+    let (_, body) = call(&app, "GetQueueAttributes", params).await;
+    assert_eq!(body["Attributes"]["QueueArn"], QUEUE_ARN);
+    assert_eq!(body["Attributes"]["VisibilityTimeout"], "30");
+    assert_eq!(body["Attributes"]["ApproximateNumberOfMessages"], "0");
+
+    let (status, _) = call(&app, "DeleteQueue", json!({ "QueueUrl": QUEUE_URL })).await;
+    assert_eq!(status, StatusCode::OK);
+    let (status, body) = call(&app, "GetQueueUrl", json!({ "QueueName": "jobs" })).await;
+    assert_eq!(status, StatusCode::BAD_REQUEST);
+    assert_eq!(body["__type"], "com.amazonaws.sqs#QueueDoesNotExist");
+
+    let (status, body) = call(&app, "CreateQueue", json!({ "QueueName": "jobs.fifo" })).await;
+    assert_eq!(status, StatusCode::BAD_REQUEST);
+    assert_eq!(body["__type"], "com.amazonaws.sqs#InvalidParameterValue");
+    let (_, body) = call(&app, "SendMessageBatch", json!({ "QueueUrl": QUEUE_URL })).await;
+    assert_eq!(body["__type"], "com.amazonaws.sqs#UnsupportedOperation");
+}
+
+#[tokio::test]
+async fn test_send_receive_delete() {
+    let app = create_app(Clock::new()).await;
+    call(&app, "CreateQueue", json!({ "QueueName": "jobs" })).await;
+
+    let params = json!({ "QueueUrl": QUEUE_URL, "MessageBody": "hello" });
+    let (status, sent) = call(&app, "SendMessage", params).await;
+    assert_eq!(status, StatusCode::OK);
+    assert_eq!(sent["MD5OfMessageBody"], "5d41402abc4b2a76b9719d911017c592");
+
+    let params = json!({ "QueueUrl": QUEUE_URL, "MaxNumberOfMessages": 10, "AttributeNames": ["All"] });
+    let (_, body) = call(&app, "ReceiveMessage", params.clone()).await;
+    let messages = body["Messages"].as_array().unwrap();
+    assert_eq!(messages.len(), 1);
+    assert_eq!(messages[0]["MessageId"], sent["MessageId"]);
+    assert_eq!(messages[0]["Body"], "hello");
+    assert_eq!(messages[0]["Attributes"]["ApproximateReceiveCount"], "1");
+
+    // in flight, so nobody else gets it
+    let (_, body) = call(&app, "ReceiveMessage", params.clone()).await;
+    assert!(body.get("Messages").is_none());
+
+    let handle = messages[0]["ReceiptHandle"].clone();
+    let (status, _) = call(&app, "DeleteMessage", json!({ "QueueUrl": QUEUE_URL, "ReceiptHandle": handle })).await;
+    assert_eq!(status, StatusCode::OK);
+    let (_, body) = call(&app, "GetQueueAttributes", json!({ "QueueUrl": QUEUE_URL, "AttributeNames": ["All"] })).await;
+    assert_eq!(body["Attributes"]["ApproximateNumberOfMessages"], "0");
+    assert_eq!(body["Attributes"]["ApproximateNumberOfMessagesNotVisible"], "0");
+}
+
+#[tokio::test]
+async fn test_visibility_timeout() {
+    let clock = Clock::frozen_at(Utc.with_ymd_and_hms(2030, 1, 1, 0, 0, 0).unwrap());
+    let app = create_app(clock.clone()).await;
+    let params = json!({ "QueueName": "jobs", "Attributes": { "VisibilityTimeout": "60" } });
+    call(&app, "CreateQueue", params).await;
+    call(&app, "SendMessage", json!({ "QueueUrl": QUEUE_URL, "MessageBody": "work" })).await;
+
+    let receive = json!({ "QueueUrl": QUEUE_URL, "AttributeNames": ["ApproximateReceiveCount"] });
+    let (_, body) = call(&app, "ReceiveMessage", receive.clone()).await;
+    let first = body["Messages"][0]["ReceiptHandle"].clone();
+
+    clock.advance(TimeDelta::seconds(59)).unwrap();
+    let (_, body) = call(&app, "ReceiveMessage", receive.clone()).await;
+    assert!(body.get("Messages").is_none());
+
+    // a consumer that didn't delete it in time loses it to the next one
+    clock.advance(TimeDelta::seconds(1)).unwrap();
+    let (_, body) = call(&app, "ReceiveMessage", receive.clone()).await;
+    assert_eq!(body["Messages"][0]["Body"], "work");
+    assert_eq!(body["Messages"][0]["Attributes"]["ApproximateReceiveCount"], "2");
+    let second = body["Messages"][0]["ReceiptHandle"].clone();
+    assert_ne!(first, second);
+
+    // the stale handle can't extend it
+    let params = json!({ "QueueUrl": QUEUE_URL, "ReceiptHandle": first, "VisibilityTimeout": 600 });
+    let (status, body) = call(&app, "ChangeMessageVisibility", params).await;
+    assert_eq!(status, StatusCode::BAD_REQUEST);
+    assert_eq!(body["__type"], "com.amazonaws.sqs#MessageNotInflight");
+
+    // zero hands it straight back
+    let params = json!({ "QueueUrl": QUEUE_URL, "ReceiptHandle": second, "VisibilityTimeout": 0 });
+    let (status, _) = call(&app, "ChangeMessageVisibility", params).await;
+    assert_eq!(status, StatusCode::OK);
+    let (_, body) = call(&app, "ReceiveMessage", receive).await;
+    assert_eq!(body["Messages"][0]["Attributes"]["ApproximateReceiveCount"], "3");
+}
+
+#[tokio::test]
+async fn test_long_poll_wakes_on_send() {
+    let app = create_app(Clock::new()).await;
+    call(&app, "CreateQueue", json!({ "QueueName": "jobs" })).await;
+
+    let poller = app.clone();
+    let started = Instant::now();
+    let poll = tokio::spawn(async move {
+        let params = json!({ "QueueUrl": QUEUE_URL, "WaitTimeSeconds": 10 });
+        call(&poller, "ReceiveMessage", params).await
+    });
+    tokio::time::sleep(Duration::from_millis(200)).await;
+    call(&app, "SendMessage", json!({ "QueueUrl": QUEUE_URL, "MessageBody": "late" })).await;
+
+    let (_, body) = poll.await.unwrap();
+    assert_eq!(body["Messages"][0]["Body"], "late");
+    assert!(started.elapsed() < Duration::from_secs(5));
+
+    let (status, _) = call(&app, "ReceiveMessage", json!({ "QueueUrl": QUEUE_URL, "WaitTimeSeconds": 21 })).await;
+    assert_eq!(status, StatusCode::BAD_REQUEST);
+}
+
+#[tokio::test]
+async fn test_query_protocol() {
+    let app = create_app(Clock::new()).await;
+
+    let form = "Action=CreateQueue&QueueName=jobs&Attribute.1.Name=VisibilityTimeout&Attribute.1.Value=45";
+    let (status, body) = query(&app, "/", form).await;
+    assert_eq!(status, StatusCode::OK);
+    assert!(body.contains(&format!("<CreateQueueResult><QueueUrl>{}</QueueUrl></CreateQueueResult>", QUEUE_URL)));
+    assert!(body.contains("<RequestId>"));
+
+    // sent to the queue url, which names the queue
+    let (status, body) = query(&app, "/000000000000/jobs", "Action=SendMessage&MessageBody=a%26b").await;
+    assert_eq!(status, StatusCode::OK);
+    assert!(body.contains("<MD5OfMessageBody>"));
+
+    let form = "Action=ReceiveMessage&AttributeName.1=SentTimestamp";
+    let (_, body) = query(&app, "/000000000000/jobs", form).await;
+    assert!(body.contains("<Body>a&amp;b</Body>"));
+    assert!(body.contains("<Attribute><Name>SentTimestamp</Name>"));
+
+    let form = format!("Action=GetQueueAttributes&QueueUrl={}&AttributeName.1=VisibilityTimeout", QUEUE_URL);
+    let (_, body) = query(&app, "/", &form).await;
+    assert!(body.contains("<Attribute><Name>VisibilityTimeout</Name><Value>45</Value></Attribute>"));
+
+    let (status, body) = query(&app, "/", "Action=GetQueueUrl&QueueName=missing").await;
+    assert_eq!(status, StatusCode::BAD_REQUEST);
+    assert!(body.contains("<Code>AWS.SimpleQueueService.NonExistentQueue</Code>"));
+
+    let (_, body) = query(&app, "/", "Action=DeleteQueue&QueueUrl=jobs").await;
+    assert!(body.contains("<DeleteQueueResponse"));
+    assert!(!body.contains("<DeleteQueueResult"));
+}
+
+#[tokio::test]
+async fn test_bucket_notifications_to_queue() {
+    let app = create_app(Clock::new()).await;
+    let configuration = format!(
+        r#"<NotificationConfiguration xmlns="http://s3.amazonaws.com/doc/2006-03-01/">
+  <QueueConfiguration>
+    <Id>uploads</Id>
+    <Queue>{}</Queue>
+    <Event>s3:ObjectCreated:*</Event>
+  </QueueConfiguration>
+</NotificationConfiguration>"#,
+        QUEUE_ARN
+    );
+
+    // the queue has to exist for the configuration to validate
+    let response = send(&app, "PUT", "/bucket?notification", configuration.clone()).await;
+    assert_eq!(response.status(), StatusCode::BAD_REQUEST);
+    call(&app, "CreateQueue", json!({ "QueueName": "jobs" })).await;
+    let response = send(&app, "PUT", "/bucket?notification", configuration).await;
+    assert_eq!(response.status(), StatusCode::OK);
+
+    let response = send(&app, "PUT", "/bucket/photos/cat.jpg", "meow").await;
+    assert_eq!(response.status(), StatusCode::OK);
+
+    // already queued by the time the upload returns
+    let (_, body) = call(&app, "ReceiveMessage", json!({ "QueueUrl": QUEUE_URL })).await;
+    let message: Value = serde_json::from_str(body["Messages"][0]["Body"].as_str().unwrap()).unwrap();
+    let record = &message["Records"][0];
+    assert_eq!(record["eventName"], "ObjectCreated:Put");
+    assert_eq!(record["s3"]["bucket"]["name"], "bucket");
+    assert_eq!(record["s3"]["object"]["key"], "photos/cat.jpg");
+    assert_eq!(record["s3"]["configurationId"], "uploads");
+}
@@ -1,5 +1,7 @@
 use bytes::Bytes;
-use six7::storage::Storage;
+use six7::storage::{
+    decode_key, encode_key, validate_bucket_name, FsckOptions, MemoryStorage, Storage, StorageBackend,
+};
 use tempfile::TempDir;
 
 #[tokio::test]
@@ -98,3 +100,479 @@ async fn test_head_object() {
     assert_eq!(metadata.key, "test.txt");
     assert_eq!(metadata.size, 11);
 }
+
+#[tokio::test]
+async fn test_metadata_persisted_with_object() {
+    let temp_dir = TempDir::new().unwrap();
+    let storage = Storage::new(temp_dir.path()).unwrap();
+
+    storage.create_bucket("test-bucket").await.unwrap();
+    storage
+        .put_object("test-bucket", "page.html", Bytes::from("<p>hi</p>"), Some("text/html".to_string()))
+        .await
+        .unwrap();
+
+    let metadata = storage.head_object("test-bucket", "page.html").await.unwrap();
+    assert_eq!(metadata.content_type.as_deref(), Some("text/html"));
+
+    let reopened = Storage::new(temp_dir.path()).unwrap();
+    let metadata = reopened.head_object("test-bucket", "page.html").await.unwrap();
+    assert_eq!(metadata.content_type.as_deref(), Some("text/html"));
+    assert_eq!(metadata.size, 9);
+}
+
+#[tokio::test]
+async fn test_concurrent_writers_leave_consistent_object() {
+    let temp_dir = TempDir::new().unwrap();
+    let storage = std::sync::Arc::new(Storage::new(temp_dir.path()).unwrap());
+
+    storage.create_bucket("test-bucket").await.unwrap();
+
+    let mut tasks = Vec::new();
+    for i in 0..16 {
+        let storage = storage.clone();
+        tasks.push(tokio::spawn(async move {
+            let data = Bytes::from(vec![b'a' + i as u8; 1024 * (i + 1)]);
+            storage.put_object("test-bucket", "shared.bin", data, None).await.unwrap();
+        }));
+    }
+    for task in tasks {
+        task.await.unwrap();
+    }
+
+    let data = storage.get_object("test-bucket", "shared.bin").await.unwrap();
+    let metadata = storage.head_object("test-bucket", "shared.bin").await.unwrap();
+    assert_eq!(metadata.size, data.len() as u64);
+    assert_eq!(metadata.etag, format!("{:x}", md5::compute(&data)));
+    assert!(data.iter().all(|b| *b == data[0]));
+}
+
+#[tokio::test]
+async fn test_concurrent_part_uploads_leave_consistent_part() {
+    let temp_dir = TempDir::new().unwrap();
+    let storage = std::sync::Arc::new(Storage::new(temp_dir.path()).unwrap());
+
+    storage.create_bucket("test-bucket").await.unwrap();
+    let upload = storage.create_multipart_upload("test-bucket", "big.bin", None).await.unwrap();
+
+    let mut tasks = Vec::new();
+    for i in 0..16 {
+        let storage = storage.clone();
+        let upload_id = upload.upload_id.clone();
+        tasks.push(tokio::spawn(async move {
+            let data = Bytes::from(vec![b'a' + i as u8; 1024 * (i + 1)]);
+            storage.upload_part("test-bucket", "big.bin", &upload_id, 1, data).await.unwrap()
+        }));
+    }
+    let mut etags = Vec::new();
+    for task in tasks {
+        etags.push(task.await.unwrap().etag);
+    }
+
+    // only the part that ended up stored completes, and its data is what its etag says
+    let mut completed = 0;
+    for etag in etags {
+        let parts = [(1, etag.clone())];
+        if storage.complete_multipart_upload("test-bucket", "big.bin", &upload.upload_id, &parts).await.is_ok() {
+            let data = storage.get_object("test-bucket", "big.bin").await.unwrap();
+            assert_eq!(format!("{:x}", md5::compute(&data)), etag.trim_matches('"'));
+            assert!(data.iter().all(|b| *b == data[0]));
+            completed += 1;
+        }
+    }
+    assert_eq!(completed, 1);
+}
+
+#[tokio::test]
+async fn test_failed_write_leaves_object_unchanged() {
+    let temp_dir = TempDir::new().unwrap();
+    let storage = Storage::new(temp_dir.path()).unwrap();
+
+    storage.create_bucket("test-bucket").await.unwrap();
+    storage
+        .put_object("test-bucket", "a.txt", Bytes::from("first"), None)
+        .await
+        .unwrap();
+    let before = storage.head_object("test-bucket", "a.txt").await.unwrap();
+
+    // a non-empty directory where each metadata file goes makes its rename fail after
+    // the data has been moved
+    for key in ["a.txt", "b.txt"] {
+        let metadata_path = storage.metadata_path("test-bucket", key).unwrap();
+        std::fs::remove_file(&metadata_path).ok();
+        std::fs::create_dir_all(metadata_path.join("blocker")).unwrap();
+    }
+
+    assert!(storage
+        .put_object("test-bucket", "a.txt", Bytes::from("second"), None)
+        .await
+        .is_err());
+    assert!(storage
+        .put_object("test-bucket", "b.txt", Bytes::from("new"), None)
+        .await
+        .is_err());
+
+    assert_eq!(storage.get_object("test-bucket", "a.txt").await.unwrap(), Bytes::from("first"));
+    assert!(storage.get_object("test-bucket", "b.txt").await.is_err());
+    assert!(!storage.object_path("test-bucket", "b.txt").unwrap().exists());
+    let keys: Vec<String> = storage
+        .list_objects("test-bucket", None)
+        .await
+        .unwrap()
+        .into_iter()
+        .map(|o| o.key)
+        .collect();
+    assert!(!keys.contains(&"b.txt".to_string()));
+
+    let metadata_path = storage.metadata_path("test-bucket", "a.txt").unwrap();
+    std::fs::remove_dir_all(&metadata_path).unwrap();
+    std::fs::write(&metadata_path, serde_json::to_vec(&before).unwrap()).unwrap();
+    assert_eq!(storage.head_object("test-bucket", "a.txt").await.unwrap().etag, before.etag);
+}
+
+#[tokio::test]
+async fn test_interrupted_write_finished_on_start() {
+    let temp_dir = TempDir::new().unwrap();
+    let storage = Storage::new(temp_dir.path()).unwrap();
+
+    storage.create_bucket("test-bucket").await.unwrap();
+    storage
+        .put_object("test-bucket", "a.txt", Bytes::from("first"), None)
+        .await
+        .unwrap();
+    let mut metadata = storage.head_object("test-bucket", "a.txt").await.unwrap();
+
+    // a crash after the data was renamed: only the metadata is still staged
+    let internal = temp_dir.path().join(".six7");
+    std::fs::write(temp_dir.path().join("test-bucket/a.txt"), "second").unwrap();
+    metadata.size = 6;
+    metadata.etag = format!("{:x}", md5::compute("second"));
+    std::fs::write(internal.join("tmp/meta"), serde_json::to_vec(&metadata).unwrap()).unwrap();
+    std::fs::create_dir_all(internal.join("commits")).unwrap();
+    std::fs::write(
+        internal.join("commits/write.json"),
+        r#"{"moves":[[".six7/tmp/data","test-bucket/a.txt"],[".six7/tmp/meta",".six7/meta/test-bucket/a.txt.json"]]}"#,
+    )
+    .unwrap();
+
+    let reopened = Storage::new(temp_dir.path()).unwrap();
+    assert_eq!(reopened.get_object("test-bucket", "a.txt").await.unwrap(), Bytes::from("second"));
+    assert_eq!(reopened.head_object("test-bucket", "a.txt").await.unwrap().etag, metadata.etag);
+    assert!(!internal.join("commits/write.json").exists());
+}
+
+#[tokio::test]
+async fn test_staging_not_listed_as_objects() {
+    let temp_dir = TempDir::new().unwrap();
+    let storage = Storage::new(temp_dir.path()).unwrap();
+
+    storage.create_bucket("test-bucket").await.unwrap();
+    storage
+        .put_object("test-bucket", "file.txt", Bytes::from("data"), None)
+        .await
+        .unwrap();
+
+    let objects = storage.list_objects("test-bucket", None).await.unwrap();
+    assert_eq!(objects.len(), 1);
+    assert_eq!(objects[0].key, "file.txt");
+}
+
+#[tokio::test]
+async fn test_awkward_keys_round_trip() {
+    let temp_dir = TempDir::new().unwrap();
+    let storage = Storage::new(temp_dir.path()).unwrap();
+
+    storage.create_bucket("test-bucket").await.unwrap();
+
+    let keys = ["a", "a/b", "a/", "a//b", "../escape", "x/../../y", ".", "/leading", "50%/off\\here"];
+    for key in keys {
+        storage
+            .put_object("test-bucket", key, Bytes::from(key.to_string()), None)
+            .await
+            .unwrap();
+    }
+
+    for key in keys {
+        let data = storage.get_object("test-bucket", key).await.unwrap();
+        assert_eq!(data, Bytes::from(key.to_string()));
+    }
+
+    let mut listed: Vec<String> = storage
+        .list_objects("test-bucket", None)
+        .await
+        .unwrap()
+        .into_iter()
+        .map(|o| o.key)
+        .collect();
+    let mut expected: Vec<String> = keys.iter().map(|k| k.to_string()).collect();
+    listed.sort();
+    expected.sort();
+    assert_eq!(listed, expected);
+
+    // nothing may land outside the bucket directory
+    assert!(!temp_dir.path().join("escape").exists());
+    assert!(!temp_dir.path().join("y").exists());
+}
+
+#[tokio::test]
+async fn test_longest_keys_round_trip() {
+    let temp_dir = TempDir::new().unwrap();
+    let storage = Storage::new(temp_dir.path()).unwrap();
+    storage.create_bucket("test-bucket").await.unwrap();
+
+    let keys = [
+        "k".repeat(1024),
+        "a/".repeat(512),
+        "%/".repeat(512),
+        format!("dir/{}", "\u{1}".repeat(1020)),
+        format!("{}/{}", "x".repeat(200), "y".repeat(823)),
+    ];
+    for key in &keys {
+        assert_eq!(key.len(), 1024);
+        storage
+            .put_object("test-bucket", key, Bytes::from(key.clone()), None)
+            .await
+            .unwrap();
+    }
+
+    for key in &keys {
+        assert_eq!(storage.get_object("test-bucket", key).await.unwrap(), Bytes::from(key.clone()));
+        assert_eq!(&storage.head_object("test-bucket", key).await.unwrap().key, key);
+    }
+
+    let mut listed: Vec<String> = storage
+        .list_objects("test-bucket", None)
+        .await
+        .unwrap()
+        .into_iter()
+        .map(|o| o.key)
+        .collect();
+    let mut expected = keys.to_vec();
+    listed.sort();
+    expected.sort();
+    assert_eq!(listed, expected);
+    let report = storage.fsck(&FsckOptions::default()).await.unwrap();
+    assert_eq!(report.keys, keys.len() as u64);
+    assert!(report.findings.is_empty());
+
+    storage.delete_object("test-bucket", &keys[0]).await.unwrap();
+    assert!(storage.get_object("test-bucket", &keys[0]).await.is_err());
+}
+
+#[tokio::test]
+async fn test_plain_layout_migrated_on_start() {
+    let temp_dir = TempDir::new().unwrap();
+    let base = temp_dir.path();
+
+    // files as they were stored before keys were encoded: the key as a plain path
+    std::fs::create_dir_all(base.join("test-bucket/folder/nested")).unwrap();
+    std::fs::write(base.join("test-bucket/folder/file.txt"), "file").unwrap();
+    std::fs::write(base.join("test-bucket/folder/nested/deep.txt"), "deep").unwrap();
+    std::fs::write(base.join("test-bucket/100%.txt"), "percent").unwrap();
+    std::fs::write(base.join("test-bucket/plain.txt"), "plain").unwrap();
+    let legacy_metadata = base.join(".six7/meta/test-bucket/folder/file.txt.json");
+    std::fs::create_dir_all(legacy_metadata.parent().unwrap()).unwrap();
+    std::fs::write(
+        &legacy_metadata,
+        r#"{"key":"folder/file.txt","size":4,"last_modified":"2024-01-01T00:00:00Z","etag":"8c7dd922ad47494fc02c388e12c00eac","content_type":"text/plain"}"#,
+    )
+    .unwrap();
+
+    let storage = Storage::new(base).unwrap();
+    let mut listed: Vec<String> = storage
+        .list_objects("test-bucket", None)
+        .await
+        .unwrap()
+        .into_iter()
+        .map(|o| o.key)
+        .collect();
+    listed.sort();
+    assert_eq!(listed, ["100%.txt", "folder/file.txt", "folder/nested/deep.txt", "plain.txt"]);
+
+    assert_eq!(storage.get_object("test-bucket", "folder/nested/deep.txt").await.unwrap(), Bytes::from("deep"));
+    let metadata = storage.head_object("test-bucket", "folder/file.txt").await.unwrap();
+    assert_eq!(metadata.content_type.as_deref(), Some("text/plain"));
+    assert!(!base.join("test-bucket/folder").exists());
+    assert!(!base.join(".six7/meta/test-bucket/folder").exists());
+}
+
+#[tokio::test]
+async fn test_plain_layout_conflicts_left_in_place() {
+    let temp_dir = TempDir::new().unwrap();
+    let storage = Storage::new(temp_dir.path()).unwrap();
+    storage.create_bucket("test-bucket").await.unwrap();
+    storage
+        .put_object("test-bucket", "folder/file.txt", Bytes::from("encoded"), None)
+        .await
+        .unwrap();
+
+    let legacy = temp_dir.path().join("test-bucket/folder/file.txt");
+    std::fs::create_dir_all(legacy.parent().unwrap()).unwrap();
+    std::fs::write(&legacy, "legacy").unwrap();
+
+    let storage = Storage::new(temp_dir.path()).unwrap();
+    assert!(legacy.exists());
+    assert_eq!(storage.get_object("test-bucket", "folder/file.txt").await.unwrap(), Bytes::from("encoded"));
+}
+
+#[test]
+fn test_only_canonical_paths_decode() {
+    for path in ["a%41", "folder/file.txt", "a%2F", "%2e"] {
+        assert_eq!(decode_key(std::path::Path::new(path)), None, "{}", path);
+    }
+    assert_eq!(decode_key(std::path::Path::new("folder%2F/file.txt")).as_deref(), Some("folder/file.txt"));
+}
+
+#[test]
+fn test_key_encoding_stays_inside_bucket() {
+    for key in ["..", "../../etc/passwd", "/abs/path", "a/./b"] {
+        let path = encode_key(key);
+        assert!(path
+            .components()
+            .all(|c| matches!(c, std::path::Component::Normal(_))));
+        assert_eq!(decode_key(&path).as_deref(), Some(key));
+    }
+}
+
+#[tokio::test]
+async fn test_invalid_bucket_names_rejected() {
+    let temp_dir = TempDir::new().unwrap();
+    let storage = Storage::new(temp_dir.path()).unwrap();
+
+    for name in ["..", "ab", "UPPER", "-bucket", "bucket-", "a..b", "192.168.1.1", "xn--bucket", ".six7", "../x"] {
+        assert!(validate_bucket_name(name).is_err(), "{} should be invalid", name);
+        let err = storage.create_bucket(name).await.unwrap_err();
+        assert_eq!(err.kind(), std::io::ErrorKind::InvalidInput);
+    }
+
+    for name in ["abc", "my.bucket", "bucket-1", "0-bucket"] {
+        assert!(validate_bucket_name(name).is_ok(), "{} should be valid", name);
+    }
+}
+
+async fn exercise_multipart(storage: &dyn StorageBackend) {
+    storage.create_bucket("test-bucket").await.unwrap();
+
+    let upload = storage
+        .create_multipart_upload("test-bucket", "big.bin", Some("application/zip".to_string()))
+        .await
+        .unwrap();
+    assert_eq!(storage.list_multipart_uploads("test-bucket").await.unwrap().len(), 1);
+
+    let first = storage
+        .upload_part("test-bucket", "big.bin", &upload.upload_id, 1, Bytes::from("hello "))
+        .await
+        .unwrap();
+    let second = storage
+        .upload_part("test-bucket", "big.bin", &upload.upload_id, 2, Bytes::from("world"))
+        .await
+        .unwrap();
+
+    let metadata = storage
+        .complete_multipart_upload(
+            "test-bucket",
+            "big.bin",
+            &upload.upload_id,
+            &[(1, first.etag.clone()), (2, format!("\"{}\"", second.etag))],
+        )
+        .await
+        .unwrap();
+
+    assert!(metadata.etag.ends_with("-2"));
+    assert_eq!(metadata.size, 11);
+    assert_eq!(metadata.content_type.as_deref(), Some("application/zip"));
+    assert_eq!(storage.get_object("test-bucket", "big.bin").await.unwrap(), Bytes::from("hello world"));
+    assert!(storage.list_multipart_uploads("test-bucket").await.unwrap().is_empty());
+
+    let err = storage
+        .upload_part("test-bucket", "big.bin", &upload.upload_id, 3, Bytes::from("late"))
+        .await
+        .unwrap_err();
+    assert_eq!(err.kind(), std::io::ErrorKind::InvalidInput);
+
+    let err = storage.list_multipart_uploads("missing-bucket").await.unwrap_err();
+    assert!(err.to_string().contains("NoSuchBucket"));
+}
+
+async fn exercise_buckets(storage: &dyn StorageBackend) {
+    storage.create_bucket("bucket-b").await.unwrap();
+    storage.create_bucket("bucket-a").await.unwrap();
+
+    let names: Vec<String> = storage.list_buckets().await.unwrap().into_iter().map(|b| b.name).collect();
+    assert_eq!(names, vec!["bucket-a", "bucket-b"]);
+
+    storage.put_object("bucket-a", "file.txt", Bytes::from("x"), None).await.unwrap();
+    assert!(storage.delete_bucket("bucket-a").await.is_err());
+
+    // bucket settings live and die with the bucket
+    assert_eq!(storage.get_bucket_setting("bucket-a", "notification").await.unwrap(), None);
+    storage
+        .put_bucket_setting("bucket-a", "notification", Some("{}".to_string()))
+        .await
+        .unwrap();
+    assert_eq!(storage.get_bucket_setting("bucket-a", "notification").await.unwrap().as_deref(), Some("{}"));
+    assert!(storage.get_bucket_setting("bucket-a", "../escape").await.is_err());
+    assert!(storage.get_bucket_setting("missing", "notification").await.is_err());
+
+    storage.delete_object("bucket-a", "file.txt").await.unwrap();
+    storage.delete_bucket("bucket-a").await.unwrap();
+    assert!(!storage.bucket_exists("bucket-a").await);
+    storage.create_bucket("bucket-a").await.unwrap();
+    assert_eq!(storage.get_bucket_setting("bucket-a", "notification").await.unwrap(), None);
+    storage.delete_bucket("bucket-a").await.unwrap();
+    assert!(storage.put_object("bucket-a", "file.txt", Bytes::from("x"), None).await.is_err());
+}
+
+#[tokio::test]
+async fn test_multipart_upload() {
+    let temp_dir = TempDir::new().unwrap();
+    exercise_multipart(&Storage::new(temp_dir.path()).unwrap()).await;
+    exercise_multipart(&MemoryStorage::new()).await;
+}
+
+#[tokio::test]
+async fn test_bucket_lifecycle() {
+    let temp_dir = TempDir::new().unwrap();
+    exercise_buckets(&Storage::new(temp_dir.path()).unwrap()).await;
+    exercise_buckets(&MemoryStorage::new()).await;
+}
+
+#[tokio::test]
+async fn test_memory_storage_objects() {
+    let storage = MemoryStorage::new();
+    storage.create_bucket("test-bucket").await.unwrap();
+
+    for key in ["a", "a/b", "b/c", "ab"] {
+        storage.put_object("test-bucket", key, Bytes::from(key), None).await.unwrap();
+    }
+
+    let keys: Vec<String> = storage
+        .list_objects("test-bucket", Some("a"))
+        .await
+        .unwrap()
+        .into_iter()
+        .map(|o| o.key)
+        .collect();
+    assert_eq!(keys, vec!["a", "a/b", "ab"]);
+
+    let copied = storage.copy_object("test-bucket", "a/b", "test-bucket", "copy").await.unwrap();
+    assert_eq!(copied.etag, format!("{:x}", md5::compute("a/b")));
+    assert_eq!(storage.get_object("test-bucket", "copy").await.unwrap(), Bytes::from("a/b"));
+}
+
+#[tokio::test]
+async fn test_empty_bucket_on_disk() {
+    let temp_dir = TempDir::new().unwrap();
+    let storage = Storage::new(temp_dir.path()).unwrap();
+    storage.create_bucket("full-bucket").await.unwrap();
+    storage.put_object("full-bucket", "a/b/c.txt", Bytes::from("x"), None).await.unwrap();
+    storage.put_object("full-bucket", "top.txt", Bytes::from("y"), None).await.unwrap();
+    storage.create_multipart_upload("full-bucket", "big", None).await.unwrap();
+
+    storage.empty_bucket("full-bucket").await.unwrap();
+
+    assert!(storage.bucket_exists("full-bucket").await);
+    assert!(storage.list_objects("full-bucket", None).await.unwrap().is_empty());
+    assert!(storage.list_multipart_uploads("full-bucket").await.unwrap().is_empty());
+    storage.delete_bucket("full-bucket").await.unwrap();
+}
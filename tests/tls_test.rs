@@ -0,0 +1,122 @@
+use rustls::pki_types::pem::PemObject;
+use rustls::pki_types::{CertificateDer, ServerName};
+use six7::config::TlsConfig;
+use six7::Server;
+use std::net::SocketAddr;
+use std::path::Path;
+use std::sync::Arc;
+use tempfile::TempDir;
+use tokio::io::{AsyncReadExt, AsyncWriteExt};
+use tokio::net::TcpStream;
+use tokio_rustls::TlsConnector;
+
+fn generated(dir: &Path) -> TlsConfig {
+    TlsConfig {
+        port: 0,
+        dir: dir.to_str().unwrap().to_string(),
+        ..TlsConfig::default()
+    }
+}
+
+// a plain http/1.1 GET over tls, trusting only the given ca
+async fn get(addr: SocketAddr, host: &str, path: &str, ca: &Path) -> String {
+    let mut roots = rustls::RootCertStore::empty();
+    for cert in CertificateDer::pem_file_iter(ca).unwrap() {
+        roots.add(cert.unwrap()).unwrap();
+    }
+    let provider = Arc::new(rustls::crypto::ring::default_provider());
+    let config = rustls::ClientConfig::builder_with_provider(provider)
+        .with_safe_default_protocol_versions()
+        .unwrap()
+        .with_root_certificates(roots)
+        .with_no_client_auth();
+
+    let tcp = TcpStream::connect(addr).await.unwrap();
+    let name = ServerName::try_from(host.to_string()).unwrap();
+    let mut stream = TlsConnector::from(Arc::new(config)).connect(name, tcp).await.unwrap();
+
+    let request = format!("GET {} HTTP/1.1\r\nHost: {}\r\nConnection: close\r\n\r\n", path, host);
+    stream.write_all(request.as_bytes()).await.unwrap();
+    let mut response = String::new();
+    stream.read_to_string(&mut response).await.unwrap();
+    response
+}
+
+#[tokio::test]
+async fn test_generated_certificate() {
+    let dir = TempDir::new().unwrap();
+    let server = Server::builder()
+        .bucket("secure-bucket")
+        .tls(generated(dir.path()))
+        .spawn()
+        .await
+        .unwrap();
+
+    let ca = dir.path().join("ca.pem");
+    assert!(ca.exists());
+    assert!(server.tls_url().unwrap().starts_with("https://"));
+
+    let tls_addr = server.tls_addr().unwrap();
+    let response = get(tls_addr, "localhost", "/", &ca).await;
+    assert!(response.starts_with("HTTP/1.1 200"), "{}", response);
+    assert!(response.contains("secure-bucket"));
+
+    // virtual-hosted-style names are covered by the wildcard
+    let response = get(tls_addr, "secure-bucket.localhost", "/", &ca).await;
+    assert!(response.starts_with("HTTP/1.1 200"), "{}", response);
+
+    // plain http keeps working alongside
+    let response = reqwest::get(format!("{}/", server.url())).await.unwrap();
+    assert_eq!(response.status(), 200);
+}
+
+#[tokio::test]
+async fn test_ca_is_reused_across_restarts() {
+    let dir = TempDir::new().unwrap();
+    let first = Server::builder().tls(generated(dir.path())).spawn().await.unwrap();
+    let ca = std::fs::read(dir.path().join("ca.pem")).unwrap();
+    first.shutdown().await.unwrap();
+
+    let second = Server::builder().tls(generated(dir.path())).spawn().await.unwrap();
+    assert_eq!(std::fs::read(dir.path().join("ca.pem")).unwrap(), ca);
+
+    let response = get(second.tls_addr().unwrap(), "localhost", "/", &dir.path().join("ca.pem")).await;
+    assert!(response.starts_with("HTTP/1.1 200"), "{}", response);
+}
+
+#[tokio::test]
+async fn test_configured_pem_files() {
+    let generated_dir = TempDir::new().unwrap();
+    let first = Server::builder().tls(generated(generated_dir.path())).spawn().await.unwrap();
+    first.shutdown().await.unwrap();
+
+    let path = |name: &str| generated_dir.path().join(name).to_str().unwrap().to_string();
+    let server = Server::builder()
+        .tls(TlsConfig {
+            port: 0,
+            cert: Some(path("cert.pem")),
+            key: Some(path("key.pem")),
+            dir: "/nonexistent".to_string(),
+        })
+        .spawn()
+        .await
+        .unwrap();
+
+    let response = get(server.tls_addr().unwrap(), "localhost", "/", &generated_dir.path().join("ca.pem")).await;
+    assert!(response.starts_with("HTTP/1.1 200"), "{}", response);
+}
+
+#[tokio::test]
+async fn test_missing_pem_files() {
+    let result = Server::builder()
+        .tls(TlsConfig {
+            port: 0,
+            cert: Some("/nonexistent/cert.pem".to_string()),
+            key: Some("/nonexistent/key.pem".to_string()),
+            ..TlsConfig::default()
+        })
+        .spawn()
+        .await;
+
+    assert!(result.is_err());
+}
@@ -0,0 +1,125 @@
+use six7::config::Config;
+use six7::remote::{AdminClient, WatchEvent};
+use six7::server::RunningServer;
+use six7::watch::{Activity, WatchFilter};
+use six7::Server;
+use std::time::Duration;
+use tokio::sync::mpsc;
+
+const TOKEN: &str = "secret";
+
+async fn start() -> RunningServer {
+    let mut config = Config::default();
+    config.admin.enabled = true;
+    config.admin.token = Some(TOKEN.to_string());
+    Server::builder()
+        .config(config)
+        .port(0)
+        .bucket("bucket")
+        .in_memory()
+        .spawn()
+        .await
+        .unwrap()
+}
+
+// a watcher on its own task, passing on what it sees
+async fn watch(url: &str, filter: WatchFilter) -> mpsc::UnboundedReceiver<Activity> {
+    let (sender, receiver) = mpsc::unbounded_channel();
+    let client = AdminClient::new(url, Some(TOKEN.to_string()));
+    tokio::spawn(async move {
+        client
+            .watch(&filter, |event| match event {
+                WatchEvent::Activity(activity) => sender.send(*activity).is_ok(),
+                WatchEvent::Lagged(_) => true,
+            })
+            .await
+    });
+    // the subscription is made once the request reaches the server
+    tokio::time::sleep(Duration::from_millis(200)).await;
+    receiver
+}
+
+async fn next(receiver: &mut mpsc::UnboundedReceiver<Activity>) -> Activity {
+    tokio::time::timeout(Duration::from_secs(5), receiver.recv())
+        .await
+        .expect("no activity")
+        .unwrap()
+}
+
+#[tokio::test]
+async fn test_watch_every_operation() {
+    let server = start().await;
+    let url = server.url();
+    let mut activity = watch(&url, WatchFilter::default()).await;
+
+    let client = reqwest::Client::new();
+    let response = client.put(format!("{}/bucket/a.txt", url)).body("hello").send().await.unwrap();
+    let request_id = response.headers()["x-amz-request-id"].to_str().unwrap().to_string();
+    client.get(format!("{}/bucket/a.txt", url)).send().await.unwrap();
+    client.get(format!("{}/bucket/missing.txt", url)).send().await.unwrap();
+    client.delete(format!("{}/bucket/a.txt", url)).send().await.unwrap();
+
+    let put = next(&mut activity).await;
+    assert_eq!(put.operation, "PutObject");
+    assert_eq!(put.bucket.as_deref(), Some("bucket"));
+    assert_eq!(put.key.as_deref(), Some("a.txt"));
+    assert_eq!(put.status, 200);
+    assert_eq!(put.request_id.as_deref(), Some(request_id.as_str()));
+    let event = put.event.unwrap();
+    assert_eq!(event.name.as_str(), "ObjectCreated:Put");
+    assert_eq!(event.size, Some(5));
+
+    let get = next(&mut activity).await;
+    assert_eq!((get.operation.as_str(), get.status), ("GetObject", 200));
+    assert!(get.event.is_none());
+    let missing = next(&mut activity).await;
+    assert_eq!(missing.status, 404);
+    let delete = next(&mut activity).await;
+    assert_eq!(delete.event.unwrap().name.as_str(), "ObjectRemoved:Delete");
+    assert!(delete.seq > put.seq);
+}
+
+#[tokio::test]
+async fn test_watch_filters() {
+    let server = start().await;
+    let url = server.url();
+    let filter = WatchFilter {
+        bucket: Some("bucket".to_string()),
+        prefix: Some("logs/".to_string()),
+        ..WatchFilter::default()
+    };
+    let mut activity = watch(&url, filter).await;
+
+    let client = reqwest::Client::new();
+    client.put(format!("{}/other", url)).send().await.unwrap();
+    client.put(format!("{}/other/logs/a", url)).body("x").send().await.unwrap();
+    client.put(format!("{}/bucket/images/a", url)).body("x").send().await.unwrap();
+    client.get(format!("{}/bucket?prefix=logs/", url)).send().await.unwrap();
+    client.put(format!("{}/bucket/logs/a", url)).body("x").send().await.unwrap();
+
+    let seen = next(&mut activity).await;
+    assert_eq!(seen.key.as_deref(), Some("logs/a"));
+    assert_eq!(seen.bucket.as_deref(), Some("bucket"));
+}
+
+#[tokio::test]
+async fn test_watch_needs_the_admin_token() {
+    let server = start().await;
+    let client = AdminClient::new(server.url(), Some("wrong".to_string()));
+    let result = client.watch(&WatchFilter::default(), |_| false).await;
+    assert!(result.is_err());
+}
+
+#[tokio::test]
+async fn test_shutdown_ends_watches() {
+    let server = start().await;
+    let mut activity = watch(&server.url(), WatchFilter::default()).await;
+
+    // the drain timeout is 30 seconds; an open watch mustn't hold shutdown for it
+    tokio::time::timeout(Duration::from_secs(5), server.shutdown())
+        .await
+        .expect("shutdown waited on the watch")
+        .unwrap();
+    let ended = tokio::time::timeout(Duration::from_secs(5), activity.recv()).await;
+    assert!(ended.expect("the watch is still open").is_none());
+}